@@ -60,7 +60,7 @@ class BatchImportS3SourceCreateSerializer(BatchImportSerializer):
     """Serializer for creating BatchImports with config builder methods"""
 
     content_type = serializers.ChoiceField(
-        choices=["mixpanel", "captured", "amplitude"],
+        choices=["mixpanel", "captured", "amplitude", "segment"],
         write_only=True,
         required=True,
     )
@@ -116,6 +116,7 @@ class BatchImportS3SourceCreateSerializer(BatchImportSerializer):
             "mixpanel": ContentType.MIXPANEL,
             "amplitude": ContentType.AMPLITUDE,
             "captured": ContentType.CAPTURED,
+            "segment": ContentType.SEGMENT,
         }
 
         content_type = content_type_map[validated_data["content_type"]]
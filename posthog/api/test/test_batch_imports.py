@@ -18,6 +18,8 @@ class TestBatchImportModel(BaseTest):
         self.assertEqual(ContentType.MIXPANEL.value, "mixpanel")
         self.assertEqual(ContentType.CAPTURED.value, "captured")
         self.assertEqual(ContentType.AMPLITUDE.value, "amplitude")
+        self.assertEqual(ContentType.SEGMENT.value, "segment")
+        self.assertEqual(ContentType.HEAP.value, "heap")
 
         self.assertEqual(ContentType.MIXPANEL.serialize(), {"type": "mixpanel"})
 
@@ -34,6 +36,39 @@ class TestBatchImportConfigBuilder(BaseTest):
         self.assertEqual(self.batch_import.import_config, expected)
         self.assertIsInstance(config, BatchImportConfigBuilder)
 
+    def test_csv_configuration(self):
+        columns = [{"column": "ts", "field": "timestamp"}, {"column": "price", "coerce": "number"}]
+        self.batch_import.config.csv(ContentType.CAPTURED, columns)
+
+        expected = {
+            "data_format": {
+                "type": "csv",
+                "delimiter": ",",
+                "has_header": True,
+                "columns": columns,
+                "content": {"type": "captured"},
+            }
+        }
+        self.assertEqual(self.batch_import.import_config, expected)
+
+    def test_parquet_configuration(self):
+        self.batch_import.config.parquet(ContentType.AMPLITUDE)
+
+        expected = {"data_format": {"type": "parquet", "columns": [], "content": {"type": "amplitude"}}}
+        self.assertEqual(self.batch_import.import_config, expected)
+
+    def test_transformation_configuration(self):
+        self.batch_import.config.with_transformation(["_H", 1, 31, 38], max_errors=10)
+
+        expected = {"transformation": {"bytecode": ["_H", 1, 31, 38], "max_errors": 10}}
+        self.assertEqual(self.batch_import.import_config, expected)
+
+    def test_dry_run_configuration(self):
+        self.batch_import.config.dry_run(max_keys=1)
+
+        expected = {"dry_run": {"max_keys": 1, "max_bytes": 10_000_000, "sample_size": 10}}
+        self.assertEqual(self.batch_import.import_config, expected)
+
     def test_from_s3_configuration(self):
         self.batch_import.config.from_s3(
             bucket="my-bucket",
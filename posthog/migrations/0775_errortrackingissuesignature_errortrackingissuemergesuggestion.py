@@ -0,0 +1,76 @@
+# Generated by Django 4.2.18 on 2025-06-12 09:30
+
+import django.contrib.postgres.fields
+import django.contrib.postgres.indexes
+from django.db import migrations, models
+import django.db.models.deletion
+import posthog.models.utils
+
+
+class Migration(migrations.Migration):
+    dependencies = [("posthog", "0774_batchimport_display_status_message")]
+
+    operations = [
+        migrations.CreateModel(
+            name="ErrorTrackingIssueSignature",
+            fields=[
+                (
+                    "issue",
+                    models.OneToOneField(
+                        on_delete=django.db.models.deletion.CASCADE,
+                        primary_key=True,
+                        related_name="signature",
+                        serialize=False,
+                        to="posthog.errortrackingissue",
+                    ),
+                ),
+                (
+                    "signature",
+                    django.contrib.postgres.fields.ArrayField(base_field=models.BigIntegerField(), size=None),
+                ),
+                ("bands", django.contrib.postgres.fields.ArrayField(base_field=models.BigIntegerField(), size=None)),
+                ("created_at", models.DateTimeField(auto_now_add=True)),
+                ("team", models.ForeignKey(on_delete=django.db.models.deletion.CASCADE, to="posthog.team")),
+            ],
+            options={
+                "indexes": [
+                    django.contrib.postgres.indexes.GinIndex(fields=["bands"], name="error_tracking_sig_bands_idx")
+                ],
+            },
+        ),
+        migrations.CreateModel(
+            name="ErrorTrackingIssueMergeSuggestion",
+            fields=[
+                (
+                    "id",
+                    models.UUIDField(
+                        default=posthog.models.utils.UUIDT, editable=False, primary_key=True, serialize=False
+                    ),
+                ),
+                ("similarity", models.FloatField()),
+                ("created_at", models.DateTimeField(auto_now_add=True)),
+                (
+                    "issue",
+                    models.ForeignKey(
+                        on_delete=django.db.models.deletion.CASCADE,
+                        related_name="merge_suggestions",
+                        to="posthog.errortrackingissue",
+                    ),
+                ),
+                (
+                    "similar_issue",
+                    models.ForeignKey(
+                        on_delete=django.db.models.deletion.CASCADE,
+                        related_name="+",
+                        to="posthog.errortrackingissue",
+                    ),
+                ),
+                ("team", models.ForeignKey(on_delete=django.db.models.deletion.CASCADE, to="posthog.team")),
+            ],
+            options={
+                "constraints": [
+                    models.UniqueConstraint(fields=("issue", "similar_issue"), name="unique_merge_suggestion_per_pair")
+                ],
+            },
+        ),
+    ]
@@ -0,0 +1,55 @@
+# Generated by Django 4.2.18 on 2025-06-14 11:00
+
+from django.db import migrations, models
+import django.db.models.deletion
+import posthog.models.utils
+
+
+class Migration(migrations.Migration):
+    dependencies = [("posthog", "0775_errortrackingissuesignature_errortrackingissuemergesuggestion")]
+
+    operations = [
+        migrations.CreateModel(
+            name="ErrorTrackingIssueRelease",
+            fields=[
+                (
+                    "id",
+                    models.UUIDField(
+                        default=posthog.models.utils.UUIDT, editable=False, primary_key=True, serialize=False
+                    ),
+                ),
+                ("first_seen", models.DateTimeField()),
+                ("last_seen", models.DateTimeField()),
+                ("occurrences", models.BigIntegerField()),
+                (
+                    "kind",
+                    models.CharField(
+                        blank=True,
+                        choices=[("new", "New"), ("regressed", "Regressed")],
+                        max_length=32,
+                        null=True,
+                    ),
+                ),
+                (
+                    "issue",
+                    models.ForeignKey(
+                        on_delete=django.db.models.deletion.CASCADE,
+                        related_name="releases",
+                        to="posthog.errortrackingissue",
+                    ),
+                ),
+                (
+                    "release",
+                    models.ForeignKey(
+                        on_delete=django.db.models.deletion.CASCADE,
+                        related_name="issues",
+                        to="posthog.errortrackingrelease",
+                    ),
+                ),
+                ("team", models.ForeignKey(on_delete=django.db.models.deletion.CASCADE, to="posthog.team")),
+            ],
+            options={
+                "constraints": [models.UniqueConstraint(fields=("issue", "release"), name="unique_issue_release")],
+            },
+        ),
+    ]
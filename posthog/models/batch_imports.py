@@ -18,6 +18,8 @@ class ContentType(str, Enum):
     MIXPANEL = "mixpanel"
     CAPTURED = "captured"
     AMPLITUDE = "amplitude"
+    SEGMENT = "segment"
+    HEAP = "heap"
 
     def serialize(self) -> dict:
         return {"type": self.value}
@@ -71,6 +73,44 @@ class BatchImportConfigBuilder:
         }
         return self
 
+    def csv(
+        self, content_type: ContentType, columns: list[dict], has_header: bool = True, delimiter: str = ","
+    ) -> Self:
+        # Each column is a dict of "column", and optionally the "field" to map it to and the type to "coerce" it to
+        self.batch_import.import_config["data_format"] = {
+            "type": "csv",
+            "delimiter": delimiter,
+            "has_header": has_header,
+            "columns": columns,
+            "content": content_type.serialize(),
+        }
+        return self
+
+    def parquet(self, content_type: ContentType, columns: list[dict] | None = None) -> Self:
+        self.batch_import.import_config["data_format"] = {
+            "type": "parquet",
+            "columns": columns or [],
+            "content": content_type.serialize(),
+        }
+        return self
+
+    def with_transformation(self, bytecode: list, max_errors: int = 0) -> Self:
+        # Compiled hog, run against each event after it's parsed
+        self.batch_import.import_config["transformation"] = {
+            "bytecode": bytecode,
+            "max_errors": max_errors,
+        }
+        return self
+
+    def dry_run(self, max_keys: int = 10, max_bytes: int = 10_000_000, sample_size: int = 10) -> Self:
+        # Parse the start of the source and write a report to the job state, without importing anything
+        self.batch_import.import_config["dry_run"] = {
+            "max_keys": max_keys,
+            "max_bytes": max_bytes,
+            "sample_size": sample_size,
+        }
+        return self
+
     def from_folder(self, path: str) -> Self:
         self.batch_import.import_config["source"] = {"type": "folder", "path": path}
         return self
@@ -1,5 +1,6 @@
 from django.db import models, transaction
 from django.contrib.postgres.fields import ArrayField
+from django.contrib.postgres.indexes import GinIndex
 from django.conf import settings
 from rest_framework.exceptions import ValidationError
 
@@ -83,6 +84,38 @@ class ErrorTrackingIssueFingerprintV2(UUIDModel):
         constraints = [models.UniqueConstraint(fields=["team", "fingerprint"], name="unique_fingerprint_for_team")]
 
 
+class ErrorTrackingIssueSignature(models.Model):
+    # A MinHash signature of an issue's exception types and in-app function names, computed by cymbal when
+    # the issue is created, and used to find similar issues to suggest merging it with
+    issue = models.OneToOneField(
+        ErrorTrackingIssue, on_delete=models.CASCADE, primary_key=True, related_name="signature"
+    )
+    team = models.ForeignKey(Team, on_delete=models.CASCADE)
+    signature = ArrayField(models.BigIntegerField(), null=False)
+    # Hashes of fixed-size slices of the signature - issues sharing a band are candidates for comparison
+    bands = ArrayField(models.BigIntegerField(), null=False)
+    created_at = models.DateTimeField(auto_now_add=True)
+
+    class Meta:
+        indexes = [
+            GinIndex(fields=["bands"], name="error_tracking_sig_bands_idx"),
+        ]
+
+
+class ErrorTrackingIssueMergeSuggestion(UUIDModel):
+    team = models.ForeignKey(Team, on_delete=models.CASCADE)
+    # Suggestions always point from the newer issue to the older one it looks like
+    issue = models.ForeignKey(ErrorTrackingIssue, on_delete=models.CASCADE, related_name="merge_suggestions")
+    similar_issue = models.ForeignKey(ErrorTrackingIssue, on_delete=models.CASCADE, related_name="+")
+    similarity = models.FloatField()
+    created_at = models.DateTimeField(auto_now_add=True)
+
+    class Meta:
+        constraints = [
+            models.UniqueConstraint(fields=["issue", "similar_issue"], name="unique_merge_suggestion_per_pair"),
+        ]
+
+
 class ErrorTrackingRelease(UUIDModel):
     team = models.ForeignKey(Team, on_delete=models.CASCADE)
     # On upload, users can provide a hash of some key identifiers, e.g. "git repo, commit, branch"
@@ -107,6 +140,27 @@ class ErrorTrackingRelease(UUIDModel):
         ]
 
 
+class ErrorTrackingIssueRelease(UUIDModel):
+    # Maintained by cymbal - the occurrences of an issue in each release it's been seen in
+    class Kind(models.TextChoices):
+        NEW = "new", "New"
+        REGRESSED = "regressed", "Regressed"
+
+    team = models.ForeignKey(Team, on_delete=models.CASCADE)
+    issue = models.ForeignKey(ErrorTrackingIssue, on_delete=models.CASCADE, related_name="releases")
+    release = models.ForeignKey(ErrorTrackingRelease, on_delete=models.CASCADE, related_name="issues")
+    first_seen = models.DateTimeField()
+    last_seen = models.DateTimeField()
+    occurrences = models.BigIntegerField()
+    # How the issue related to the release when it was first seen in it, if notably
+    kind = models.CharField(max_length=32, choices=Kind.choices, null=True, blank=True)
+
+    class Meta:
+        constraints = [
+            models.UniqueConstraint(fields=["issue", "release"], name="unique_issue_release"),
+        ]
+
+
 class ErrorTrackingSymbolSet(UUIDModel):
     # Derived from the symbol set reference
     ref = models.TextField(null=False, blank=False)
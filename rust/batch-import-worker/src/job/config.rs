@@ -12,7 +12,7 @@ use crate::{
     context::AppContext,
     emit::{kafka::KafkaEmitter, Emitter, FileEmitter, NoOpEmitter, StdoutEmitter},
     extractor::ExtractorType,
-    parse::format::FormatConfig,
+    parse::{format::FormatConfig, transformation::TransformationConfig},
     source::{
         date_range_export::{AuthConfig, DateRangeExportSource},
         folder::FolderSource,
@@ -22,7 +22,7 @@ use crate::{
     },
 };
 
-use super::model::JobModel;
+use super::{dry_run::DryRunConfig, model::JobModel};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -30,7 +30,13 @@ pub struct JobConfig {
     pub source: SourceConfig,
     // What format is the data in, e.g. Mixpanel events stored in json-lines
     pub data_format: FormatConfig,
+    // An optional hog program to run against each event, after it's been parsed
+    #[serde(default)]
+    pub transformation: Option<TransformationConfig>,
     pub sink: SinkConfig,
+    // If set, we parse the start of the source and report on it, rather than importing anything
+    #[serde(default)]
+    pub dry_run: Option<DryRunConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
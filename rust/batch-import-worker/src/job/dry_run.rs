@@ -0,0 +1,172 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use common_types::{InternallyCapturedEvent, RawEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// A dry run reads and parses the start of the source, without emitting anything, and writes a report
+// of what would have been imported back to the job state, so it can be checked before the real import
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DryRunConfig {
+    #[serde(default = "DryRunConfig::default_max_keys")]
+    pub max_keys: usize,
+    // We never read past this, so the last chunk read is cut short to fit, and a record it cuts off
+    // isn't counted
+    #[serde(default = "DryRunConfig::default_max_bytes")]
+    pub max_bytes: u64,
+    // How many transformed events, and parse errors, to keep as samples
+    #[serde(default = "DryRunConfig::default_sample_size")]
+    pub sample_size: usize,
+}
+
+impl DryRunConfig {
+    fn default_max_keys() -> usize {
+        10
+    }
+
+    fn default_max_bytes() -> u64 {
+        10_000_000 // ~10MB
+    }
+
+    fn default_sample_size() -> usize {
+        10
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DryRunReport {
+    pub keys_read: usize,
+    pub bytes_read: u64,
+    pub event_count: u64,
+    pub event_counts: HashMap<String, u64>,
+    pub parse_error_count: u64,
+    pub parse_error_samples: Vec<String>,
+    pub earliest_timestamp: Option<DateTime<Utc>>,
+    pub latest_timestamp: Option<DateTime<Utc>>,
+    pub distinct_id_count: usize,
+    pub sample_events: Vec<Value>,
+    // Used to calculate the distinct_id count, but too big to keep around in the job state
+    #[serde(skip)]
+    distinct_ids: HashSet<String>,
+}
+
+impl DryRunReport {
+    pub fn record_events(&mut self, events: &[InternallyCapturedEvent], sample_size: usize) {
+        for event in events {
+            self.event_count += 1;
+            self.distinct_ids.insert(event.inner.distinct_id.clone());
+
+            // Every parser produces events from RawEvents, so this should never fail, but we don't
+            // want the report to hide events if it does
+            let Ok(raw) = serde_json::from_str::<RawEvent>(&event.inner.data) else {
+                *self.event_counts.entry("unknown".to_string()).or_default() += 1;
+                continue;
+            };
+
+            *self.event_counts.entry(raw.event.clone()).or_default() += 1;
+
+            let timestamp = raw
+                .timestamp
+                .as_deref()
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.with_timezone(&Utc));
+            if let Some(timestamp) = timestamp {
+                self.earliest_timestamp = Some(
+                    self.earliest_timestamp
+                        .map_or(timestamp, |t| t.min(timestamp)),
+                );
+                self.latest_timestamp = Some(
+                    self.latest_timestamp
+                        .map_or(timestamp, |t| t.max(timestamp)),
+                );
+            }
+
+            if self.sample_events.len() < sample_size {
+                if let Ok(sample) = serde_json::to_value(&raw) {
+                    self.sample_events.push(sample);
+                }
+            }
+        }
+
+        self.distinct_id_count = self.distinct_ids.len();
+    }
+
+    pub fn record_error(&mut self, error: &anyhow::Error, sample_size: usize) {
+        self.parse_error_count += 1;
+        if self.parse_error_samples.len() < sample_size {
+            self.parse_error_samples.push(format!("{:#}", error));
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Dry run complete: parsed {} events from {} keys, with {} parse errors",
+            self.event_count, self.keys_read, self.parse_error_count
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common_types::CapturedEvent;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn event(name: &str, distinct_id: &str, timestamp: &str) -> InternallyCapturedEvent {
+        let raw = json!({
+            "event": name,
+            "distinct_id": distinct_id,
+            "timestamp": timestamp,
+            "properties": {}
+        });
+
+        InternallyCapturedEvent {
+            team_id: 1,
+            inner: CapturedEvent {
+                uuid: Uuid::now_v7(),
+                distinct_id: distinct_id.to_string(),
+                ip: "127.0.0.1".to_string(),
+                data: raw.to_string(),
+                now: timestamp.to_string(),
+                sent_at: None,
+                token: "token".to_string(),
+                is_cookieless_mode: false,
+            },
+        }
+    }
+
+    #[test]
+    fn test_report_records_events_and_errors() {
+        let mut report = DryRunReport::default();
+
+        report.record_events(
+            &[
+                event("$pageview", "a", "2024-01-02T00:00:00Z"),
+                event("$pageview", "b", "2024-01-01T00:00:00Z"),
+                event("signup", "a", "2024-01-03T00:00:00Z"),
+            ],
+            2,
+        );
+        report.record_error(&anyhow::Error::msg("bad line"), 2);
+
+        assert_eq!(report.event_count, 3);
+        assert_eq!(report.event_counts["$pageview"], 2);
+        assert_eq!(report.event_counts["signup"], 1);
+        assert_eq!(report.distinct_id_count, 2);
+        assert_eq!(report.sample_events.len(), 2);
+        assert_eq!(
+            report.earliest_timestamp.unwrap().to_rfc3339(),
+            "2024-01-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            report.latest_timestamp.unwrap().to_rfc3339(),
+            "2024-01-03T00:00:00+00:00"
+        );
+        assert_eq!(report.parse_error_count, 1);
+        assert_eq!(report.parse_error_samples, vec!["bad line".to_string()]);
+    }
+}
@@ -1,22 +1,27 @@
-use std::sync::{atomic::Ordering, Arc};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 use anyhow::{Context, Error};
 
 use common_types::InternallyCapturedEvent;
+use dry_run::{DryRunConfig, DryRunReport};
 use model::{JobModel, JobState, PartState};
 use tokio::sync::Mutex;
 use tracing::{debug, error, info, warn};
 
 use crate::{
     context::AppContext,
-    emit::Emitter,
+    emit::{Emitter, NoOpEmitter},
     error::get_user_message,
-    parse::{format::ParserFn, Parsed},
+    parse::{format::ParserFn, Chunk, Parsed},
     source::DataSource,
     spawn_liveness_loop,
 };
 
 pub mod config;
+pub mod dry_run;
 pub mod model;
 
 pub struct Job {
@@ -33,10 +38,16 @@ pub struct Job {
 
     pub source: Box<dyn DataSource>,
     pub transform: Arc<ParserFn>,
+    // How many events the transformation has failed on, including in chunks we've parsed but not yet
+    // committed. Seeded from the job state, so the error limit holds across restarts
+    transformation_errors: Arc<AtomicU64>,
 
     // We keep a mutex here so we can mutably borrow this and the job state at the same time
     pub sink: Mutex<Box<dyn Emitter>>,
 
+    // If set, we only parse the start of the source, and report on it, rather than importing it
+    dry_run: Option<DryRunConfig>,
+
     // We want to fetch data and send it at the same time, and this acts as a temporary store
     // for the data we've fetched, but not yet sent
     checkpoint: Mutex<Option<Checkpoint>>,
@@ -45,6 +56,8 @@ pub struct Job {
 struct Checkpoint {
     key: String,
     data: Parsed<Vec<InternallyCapturedEvent>>,
+    // The transformation error count once this chunk was parsed, to be persisted when it's committed
+    transformation_errors: u64,
 }
 
 impl Job {
@@ -61,26 +74,42 @@ impl Job {
         // Some sources need to prepare for the job before we can start processing it
         source.prepare_for_job().await?;
 
-        let transform = Box::new(
+        let mut transform = model
+            .import_config
+            .data_format
+            .get_parser(&model, context.clone())
+            .await?;
+
+        let transformation_errors = Arc::new(AtomicU64::new(
             model
-                .import_config
-                .data_format
-                .get_parser(&model, context.clone())
-                .await?,
-        );
+                .state
+                .as_ref()
+                .map_or(0, |state| state.transformation_errors),
+        ));
+        if let Some(transformation) = &model.import_config.transformation {
+            transform =
+                transformation.wrap_parser(transform, model.id, transformation_errors.clone())?;
+        }
 
-        let sink = model
-            .import_config
-            .sink
-            .construct(context.clone(), &model)
-            .await
-            .with_context(|| format!("Failed to construct sink for job {}", model.id))?;
+        let dry_run = model.import_config.dry_run.clone();
 
-        let mut state = model
-            .state
-            .as_ref()
-            .cloned()
-            .unwrap_or_else(|| JobState { parts: vec![] });
+        // Dry runs never emit anything, so there's no reason to set up the real sink
+        let sink: Box<dyn Emitter> = if dry_run.is_some() {
+            Box::new(NoOpEmitter {})
+        } else {
+            model
+                .import_config
+                .sink
+                .construct(context.clone(), &model)
+                .await
+                .with_context(|| format!("Failed to construct sink for job {}", model.id))?
+        };
+
+        let mut state = model.state.as_ref().cloned().unwrap_or_else(|| JobState {
+            parts: vec![],
+            dry_run_report: None,
+            transformation_errors: 0,
+        });
 
         if state.parts.is_empty() {
             info!("Found job with no parts, initializing parts list");
@@ -114,12 +143,20 @@ impl Job {
             state: Mutex::new(state),
             source,
             transform: Arc::new(transform),
+            transformation_errors,
             sink: Mutex::new(sink),
+            dry_run,
             checkpoint: Mutex::new(None),
         })
     }
 
     pub async fn process(self) -> Result<Option<Self>, Error> {
+        if let Some(config) = self.dry_run.clone() {
+            // Dry runs are done in one go, and never return the job to be processed again
+            self.do_dry_run(config).await?;
+            return Ok(None);
+        }
+
         let next_chunk_fut = self.get_next_chunk();
         let next_commit_fut = self.do_commit();
 
@@ -167,6 +204,7 @@ impl Job {
         *checkpoint = Some(Checkpoint {
             key: next.0,
             data: next.1,
+            transformation_errors: self.transformation_errors.load(Ordering::Relaxed),
         });
 
         drop(checkpoint);
@@ -239,6 +277,13 @@ impl Job {
         };
 
         let chunk_bytes = next_chunk.len();
+        let next_chunk = Chunk {
+            at_end_of_part: match next_part.total_size {
+                Some(total_size) => next_part.current_offset + chunk_bytes as u64 >= total_size,
+                None => chunk_bytes < self.context.config.chunk_size,
+            },
+            data: next_chunk,
+        };
 
         info!("Fetched part chunk {:?}", next_part);
         let m_tf = self.transform.clone();
@@ -254,7 +299,9 @@ impl Job {
 
         // If this is the last chunk, and we didn't consume all of it, or we didn't manage to
         // consume any of this chunk, we've got a bad chunk, and should pause the job with an error.
-        if parsed.consumed < chunk_bytes && is_last_chunk || parsed.data.is_empty() {
+        // Consuming bytes without producing any events is still progress - a CSV chunk might only
+        // hold the header row, or a parquet file might have no rows at all.
+        if parsed.consumed < chunk_bytes && is_last_chunk || parsed.consumed == 0 {
             return Err(Error::msg(format!(
                 "Failed to parse any data from part {} at offset {}",
                 next_part.key, next_part.current_offset
@@ -277,7 +324,11 @@ impl Job {
             return Ok(()); // We've got no checkpointed data to commit, so we're done
         };
 
-        let (key, parsed) = (checkpoint.key, checkpoint.data);
+        let (key, parsed, transformation_errors) = (
+            checkpoint.key,
+            checkpoint.data,
+            checkpoint.transformation_errors,
+        );
 
         info!("Committing part {} consumed {} bytes", key, parsed.consumed);
         info!("Committing {} events", parsed.data.len());
@@ -298,7 +349,8 @@ impl Job {
         // looking at logs, or both). The jobs status message is set to enable this kind of debugging.
         self.shutdown_guard()?; // This is the last time we call this during the commit - if we get this far, we want to commit fully if at all possible
         info!("Beginning PG part commit");
-        self.begin_part_commit(&key, parsed.consumed).await?;
+        self.begin_part_commit(&key, parsed.consumed, transformation_errors)
+            .await?;
         info!("Beginning emitter part commit");
 
         let to_sleep = txn.commit_write().await?;
@@ -312,6 +364,129 @@ impl Job {
         Ok(())
     }
 
+    async fn do_dry_run(self, config: DryRunConfig) -> Result<(), Error> {
+        let result = self.build_dry_run_report(&config).await;
+
+        if let Err(e) = self.source.cleanup_after_job().await {
+            warn!("Failed to cleanup after job: {:?}", e);
+        }
+
+        let report = match result {
+            Ok(report) => report,
+            Err(e) => {
+                // As with a regular run, failing to fetch data needs manual intervention
+                let user_facing_error_message = get_user_message(&e);
+                error!("Failed to complete dry run: {:?}", e);
+                self.model
+                    .lock()
+                    .await
+                    .pause(
+                        self.context.clone(),
+                        format!("Failed to complete dry run: {:?}", e),
+                        user_facing_error_message.to_string(),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        info!("{}", report.summary());
+
+        let mut model = self.model.lock().await;
+        model.display_status_message = Some(report.summary());
+        model
+            .state
+            .get_or_insert_with(|| JobState {
+                parts: vec![],
+                dry_run_report: None,
+                transformation_errors: 0,
+            })
+            .dry_run_report = Some(report);
+        model.complete(&self.context.db).await
+    }
+
+    // Reads and parses the first few parts of the source, recording what we would have emitted. Parse
+    // errors are part of the report, rather than failing the dry run, but errors fetching data aren't
+    async fn build_dry_run_report(&self, config: &DryRunConfig) -> Result<DryRunReport, Error> {
+        let parts = self.state.lock().await.parts.clone();
+        let mut report = DryRunReport::default();
+
+        for part in parts.iter().take(config.max_keys) {
+            if report.bytes_read >= config.max_bytes {
+                break;
+            }
+
+            self.source.prepare_key(&part.key).await?;
+            report.keys_read += 1;
+
+            let total_size = match part.total_size {
+                Some(size) => Some(size),
+                None => self.source.size(&part.key).await?,
+            };
+
+            let mut offset = 0;
+            while report.bytes_read < config.max_bytes {
+                if let Some(size) = total_size {
+                    if offset >= size {
+                        break;
+                    }
+                }
+
+                // Only fetch as much as we have left to read, rather than a whole chunk
+                let chunk_size = self.context.config.chunk_size as u64;
+                let requested = chunk_size.min(config.max_bytes - report.bytes_read);
+                let data = self
+                    .source
+                    .get_chunk(&part.key, offset, requested)
+                    .await
+                    .with_context(|| format!("Fetching part {} at offset {}", part.key, offset))?;
+                if data.is_empty() {
+                    break;
+                }
+                let at_end_of_part = match total_size {
+                    Some(size) => offset + data.len() as u64 >= size,
+                    None => (data.len() as u64) < requested,
+                };
+                let cut_short = requested < chunk_size && !at_end_of_part;
+
+                let m_tf = self.transform.clone();
+                let chunk = Chunk {
+                    data,
+                    at_end_of_part,
+                };
+                let parsed = tokio::task::spawn_blocking(move || (m_tf)(chunk)).await?;
+
+                match parsed {
+                    Ok(parsed) if parsed.consumed > 0 => {
+                        report.record_events(&parsed.data, config.sample_size);
+                        report.bytes_read += parsed.consumed as u64;
+                        offset += parsed.consumed as u64;
+                    }
+                    // If we cut the chunk short to stay within max_bytes, we can't tell a bad record
+                    // from one we cut off, so we stop here rather than report an error
+                    _ if cut_short => break,
+                    Ok(_) => {
+                        let e = Error::msg(format!(
+                            "Failed to parse any data from part {} at offset {}",
+                            part.key, offset
+                        ));
+                        report.record_error(&e, config.sample_size);
+                        break;
+                    }
+                    // We don't know how far into the chunk the error was, so we skip the rest of the part
+                    Err(e) => {
+                        let e =
+                            e.context(format!("Parsing part {} at offset {}", part.key, offset));
+                        report.record_error(&e, config.sample_size);
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
     async fn successfully_complete(self) -> Result<(), Error> {
         let mut model = self.model.lock().await;
         model.complete(&self.context.db).await
@@ -319,12 +494,19 @@ impl Job {
 
     // Writes the new partstate to the DB, and sets the job status to paused, such that if there's an issue with the sink commit, the job
     // will be paused, and manual intervention will be required to resume it
-    async fn begin_part_commit(&self, key: &str, consumed: usize) -> Result<(), Error> {
+    async fn begin_part_commit(
+        &self,
+        key: &str,
+        consumed: usize,
+        transformation_errors: u64,
+    ) -> Result<(), Error> {
         let mut model = self.model.lock().await;
         let Some(model_state) = &mut model.state else {
             return Err(Error::msg("No model state found"));
         };
 
+        model_state.transformation_errors = transformation_errors;
+
         // Iterate through the parts list and update the relevant part
         let Some(part) = model_state.parts.iter_mut().find(|p| p.key == key) else {
             return Err(Error::msg(format!("No part found with key {}", key)));
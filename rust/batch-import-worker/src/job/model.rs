@@ -9,7 +9,10 @@ use uuid::Uuid;
 
 use crate::context::AppContext;
 
-use super::config::{JobConfig, JobSecrets};
+use super::{
+    config::{JobConfig, JobSecrets},
+    dry_run::DryRunReport,
+};
 
 #[derive(Debug, Clone)]
 pub struct JobModel {
@@ -51,6 +54,12 @@ pub struct JobState {
     // Parts are sorted, and we iterate through them in order, to let us import
     // from oldest to newest
     pub parts: Vec<PartState>,
+    // Only set for dry runs, once they're done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dry_run_report: Option<DryRunReport>,
+    // How many events the job's transformation has failed on, across the chunks committed so far
+    #[serde(default)]
+    pub transformation_errors: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -264,7 +273,11 @@ impl TryFrom<(JobRow, &[String], String)> for JobModel {
         let (row, keys, lease_id) = input;
         let state = match row.state {
             Some(s) => serde_json::from_value(s).context("Parsing state")?,
-            None => JobState { parts: vec![] },
+            None => JobState {
+                parts: vec![],
+                dry_run_report: None,
+                transformation_errors: 0,
+            },
         };
 
         let import_config = serde_json::from_value(row.import_config).context("Parsing config")?;
@@ -0,0 +1,178 @@
+use anyhow::{Context, Error};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Maps a column of a tabular input (a csv or parquet file) onto a field of the json object
+// that's handed to the content type, e.g. the "ts" column onto "timestamp", or the "browser"
+// column onto "properties.$browser"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    pub column: String,
+    // Dot-separated path of the field to write the value to. Defaults to the column name
+    #[serde(default)]
+    pub field: Option<String>,
+    // If unset, the value is passed through as-is, meaning csv values are always strings
+    #[serde(default)]
+    pub coerce: Option<Coercion>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Coercion {
+    String,
+    Number,
+    Boolean,
+    Json,
+}
+
+impl ColumnMapping {
+    pub fn field(&self) -> &str {
+        self.field.as_deref().unwrap_or(&self.column)
+    }
+}
+
+impl Coercion {
+    pub fn apply(&self, value: Value) -> Result<Value, Error> {
+        match (self, value) {
+            (Coercion::String, Value::String(s)) => Ok(Value::String(s)),
+            (Coercion::String, other) => Ok(Value::String(other.to_string())),
+            (Coercion::Number, Value::Number(n)) => Ok(Value::Number(n)),
+            (Coercion::Number, Value::String(s)) => parse_number(s.trim())
+                .ok_or_else(|| Error::msg(format!("Could not coerce \"{}\" to a number", s))),
+            (Coercion::Boolean, Value::Bool(b)) => Ok(Value::Bool(b)),
+            (Coercion::Boolean, Value::String(s)) => match s.trim().to_lowercase().as_str() {
+                "true" | "1" => Ok(Value::Bool(true)),
+                "false" | "0" => Ok(Value::Bool(false)),
+                _ => Err(Error::msg(format!(
+                    "Could not coerce \"{}\" to a boolean",
+                    s
+                ))),
+            },
+            (Coercion::Json, Value::String(s)) => {
+                serde_json::from_str(&s).context("Could not coerce value to json")
+            }
+            (Coercion::Json, other) => Ok(other),
+            (coercion, other) => Err(Error::msg(format!(
+                "Could not coerce {} to {:?}",
+                other, coercion
+            ))),
+        }
+    }
+}
+
+// Build the object for a single row, from (column, value) pairs. Null values and empty strings
+// are treated as missing, and left unset, so optional fields (like a uuid) can be left blank
+pub fn map_row<'a>(
+    columns: &[ColumnMapping],
+    mut get: impl FnMut(&str) -> Option<&'a Value>,
+) -> Result<Value, Error> {
+    let mut row = Value::Object(Map::new());
+    for mapping in columns {
+        let Some(value) = get(&mapping.column) else {
+            continue;
+        };
+        if value.is_null() || value.as_str().is_some_and(str::is_empty) {
+            continue;
+        }
+        let value = match mapping.coerce {
+            Some(coercion) => coercion
+                .apply(value.clone())
+                .with_context(|| format!("Failed to coerce column {}", mapping.column))?,
+            None => value.clone(),
+        };
+        set_path(&mut row, mapping.field(), value)?;
+    }
+    Ok(row)
+}
+
+fn set_path(target: &mut Value, path: &str, value: Value) -> Result<(), Error> {
+    let mut current = target;
+    let mut parts = path.split('.').peekable();
+    while let Some(part) = parts.next() {
+        let Value::Object(object) = current else {
+            return Err(Error::msg(format!(
+                "Cannot set field {}, {} is not an object",
+                path, part
+            )));
+        };
+        if parts.peek().is_none() {
+            object.insert(part.to_string(), value);
+            return Ok(());
+        }
+        current = object
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+    Ok(())
+}
+
+fn parse_number(s: &str) -> Option<Value> {
+    if let Ok(n) = s.parse::<u64>() {
+        Some(Value::from(n))
+    } else if let Ok(n) = s.parse::<i64>() {
+        Some(Value::from(n))
+    } else {
+        s.parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn mapping(column: &str, field: Option<&str>, coerce: Option<Coercion>) -> ColumnMapping {
+        ColumnMapping {
+            column: column.to_string(),
+            field: field.map(String::from),
+            coerce,
+        }
+    }
+
+    #[test]
+    fn test_map_row() {
+        let columns = vec![
+            mapping("event", None, None),
+            mapping("user", Some("distinct_id"), None),
+            mapping("price", Some("properties.price"), Some(Coercion::Number)),
+            mapping("paid", Some("properties.paid"), Some(Coercion::Boolean)),
+            mapping("extra", Some("properties.extra"), Some(Coercion::Json)),
+            mapping("uuid", None, None),
+        ];
+        let values: HashMap<String, Value> = [
+            ("event", json!("purchase")),
+            ("user", json!("user-1")),
+            ("price", json!("9.5")),
+            ("paid", json!("TRUE")),
+            ("extra", json!("{\"a\": [1]}")),
+            ("uuid", json!("")),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+
+        let row = map_row(&columns, |c| values.get(c)).unwrap();
+
+        assert_eq!(
+            row,
+            json!({
+                "event": "purchase",
+                "distinct_id": "user-1",
+                "properties": {"price": 9.5, "paid": true, "extra": {"a": [1]}}
+            })
+        );
+    }
+
+    #[test]
+    fn test_coercion_errors() {
+        assert!(Coercion::Number.apply(json!("abc")).is_err());
+        assert!(Coercion::Boolean.apply(json!("maybe")).is_err());
+        assert!(Coercion::Number.apply(json!(true)).is_err());
+        assert_eq!(Coercion::String.apply(json!(12)).unwrap(), json!("12"));
+        assert_eq!(Coercion::Number.apply(json!("-3")).unwrap(), json!(-3));
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use common_types::{CapturedEvent, InternallyCapturedEvent, RawEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{id_string, TransformContext};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct HeapContentConfig {
+    #[serde(default)]
+    pub table: HeapTable,
+    // Heap exports each event type to its own table, so rows from those tables don't carry the event
+    // name. Only the all_events table has an event_table_name column.
+    pub event_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeapTable {
+    #[default]
+    Events,
+    Users,
+}
+
+// Heap connect tables share a user_id column, but are otherwise made up of whichever columns the
+// table has, including any custom properties, so we just keep them all around
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeapRow {
+    pub user_id: Value,
+    #[serde(flatten)]
+    pub columns: HashMap<String, Value>,
+}
+
+impl HeapRow {
+    pub fn parse_fn(
+        context: TransformContext,
+        config: HeapContentConfig,
+        event_transform: impl Fn(RawEvent) -> Result<Option<RawEvent>, Error>,
+    ) -> impl Fn(Self) -> Result<Option<InternallyCapturedEvent>, Error> {
+        move |row| {
+            let token = context.token.clone();
+            let team_id = context.team_id;
+
+            let heap_user_id = id_string(&row.user_id).ok_or(Error::msg("No user_id found"))?;
+            let mut columns = row.columns;
+            columns.retain(|_, v| !v.is_null());
+
+            let event_uuid = Uuid::now_v7();
+            let mut properties = HashMap::new();
+            let mut set = HashMap::new();
+
+            let (event, distinct_id, timestamp) = match config.table {
+                HeapTable::Events => {
+                    let event_name = match (&config.event_name, columns.remove("event_table_name"))
+                    {
+                        (Some(name), _) => name.clone(),
+                        (None, Some(Value::String(name))) => name,
+                        _ => return Err(Error::msg("No event name found")),
+                    };
+                    let event = match event_name.as_str() {
+                        // Sessions are derived from the events in them, so we don't import them
+                        "sessions" => return Ok(None),
+                        "pageviews" => "$pageview".to_string(),
+                        _ => event_name,
+                    };
+
+                    let timestamp = columns
+                        .remove("time")
+                        .ok_or(Error::msg("No time found"))
+                        .and_then(|t| parse_timestamp(&t))?;
+
+                    for (from, to) in EVENT_PROP_MAPPINGS {
+                        if let Some(value) = columns.remove(*from) {
+                            properties.insert(to.to_string(), value);
+                        }
+                    }
+                    properties.extend(columns);
+
+                    (event, heap_user_id.clone(), timestamp)
+                }
+                HeapTable::Users => {
+                    let timestamp = match columns.get("last_modified").or(columns.get("joindate")) {
+                        Some(t) => parse_timestamp(t)?,
+                        None => Utc::now(),
+                    };
+
+                    // Heap's user ids are internal, with the identity being what the user was
+                    // identified as, so we merge the heap user into the identified one
+                    let (event, distinct_id) =
+                        match columns.remove("identity").as_ref().and_then(id_string) {
+                            Some(identity) => {
+                                properties.insert(
+                                    "$anon_distinct_id".to_string(),
+                                    Value::String(heap_user_id.clone()),
+                                );
+                                ("$identify".to_string(), identity)
+                            }
+                            None => ("$set".to_string(), heap_user_id.clone()),
+                        };
+
+                    set.extend(columns);
+                    set.insert(
+                        "$heap_user_id".to_string(),
+                        Value::String(heap_user_id.clone()),
+                    );
+
+                    (event, distinct_id, timestamp)
+                }
+            };
+
+            properties.insert("$heap_user_id".to_string(), Value::String(heap_user_id));
+            properties.insert("historical_migration".to_string(), Value::Bool(true));
+            properties.insert(
+                "analytics_source".to_string(),
+                Value::String("heap".to_string()),
+            );
+
+            let ip = properties
+                .get("$ip")
+                .and_then(|ip| ip.as_str())
+                .unwrap_or("127.0.0.1")
+                .to_string();
+
+            let raw_event = RawEvent {
+                token: Some(token.clone()),
+                distinct_id: Some(Value::String(distinct_id.clone())),
+                uuid: Some(event_uuid),
+                event,
+                properties,
+                timestamp: Some(timestamp.to_rfc3339()),
+                set: if set.is_empty() { None } else { Some(set) },
+                set_once: None,
+                offset: None,
+            };
+
+            let Some(raw_event) = event_transform(raw_event)? else {
+                return Ok(None);
+            };
+
+            let inner = CapturedEvent {
+                uuid: event_uuid,
+                distinct_id,
+                ip,
+                data: serde_json::to_string(&raw_event)?,
+                now: Utc::now().to_rfc3339(),
+                sent_at: None,
+                token,
+                is_cookieless_mode: false,
+            };
+
+            Ok(Some(InternallyCapturedEvent { team_id, inner }))
+        }
+    }
+}
+
+const EVENT_PROP_MAPPINGS: &[(&str, &str)] = &[
+    ("event_id", "$heap_event_id"),
+    ("session_id", "$heap_session_id"),
+    ("href", "$current_url"),
+    ("domain", "$host"),
+    ("path", "$pathname"),
+    ("title", "$title"),
+    ("referrer", "$referrer"),
+    ("browser", "$browser"),
+    ("device_type", "$device_type"),
+    ("platform", "$os"),
+    ("ip", "$ip"),
+    ("country", "$geoip_country_name"),
+    ("region", "$geoip_subdivision_1_name"),
+    ("city", "$geoip_city_name"),
+    ("target_text", "$el_text"),
+];
+
+// Depending on how the table was exported, times are either strings or microseconds since the epoch
+fn parse_timestamp(value: &Value) -> Result<DateTime<Utc>, Error> {
+    let timestamp = match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .map(|t| t.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+                    .ok()
+                    .map(|t| t.and_utc())
+            }),
+        Value::Number(n) => n.as_i64().and_then(DateTime::<Utc>::from_timestamp_micros),
+        _ => None,
+    };
+
+    timestamp.ok_or(Error::msg("Invalid timestamp format"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transform(table: HeapTable, row: Value) -> Option<RawEvent> {
+        let context = TransformContext {
+            team_id: 1,
+            token: "token".to_string(),
+        };
+        let config = HeapContentConfig {
+            table,
+            event_name: None,
+        };
+        let parse = HeapRow::parse_fn(context, config, |e| Ok(Some(e)));
+        let row: HeapRow = serde_json::from_value(row).unwrap();
+        parse(row)
+            .unwrap()
+            .map(|e| serde_json::from_str(&e.inner.data).unwrap())
+    }
+
+    #[test]
+    fn test_events_table() {
+        let event = transform(
+            HeapTable::Events,
+            json!({
+                "user_id": 1234,
+                "event_id": 5678,
+                "time": "2024-01-01 12:00:00.123",
+                "event_table_name": "pageviews",
+                "path": "/pricing",
+                "plan": null,
+                "custom_prop": "value"
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(event.event, "$pageview");
+        assert_eq!(event.distinct_id, Some(json!("1234")));
+        assert_eq!(
+            event.timestamp.as_deref(),
+            Some("2024-01-01T12:00:00.123+00:00")
+        );
+        assert_eq!(event.properties["$pathname"], json!("/pricing"));
+        assert_eq!(event.properties["$heap_event_id"], json!(5678));
+        assert_eq!(event.properties["custom_prop"], json!("value"));
+        assert!(!event.properties.contains_key("plan"));
+
+        let session = transform(
+            HeapTable::Events,
+            json!({"user_id": 1234, "time": 1704110400000000i64, "event_table_name": "sessions"}),
+        );
+        assert!(session.is_none());
+    }
+
+    #[test]
+    fn test_users_table() {
+        let event = transform(
+            HeapTable::Users,
+            json!({
+                "user_id": 1234,
+                "identity": "user@example.com",
+                "joindate": "2024-01-01T12:00:00Z",
+                "plan": "enterprise"
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(event.event, "$identify");
+        assert_eq!(event.distinct_id, Some(json!("user@example.com")));
+        assert_eq!(event.properties["$anon_distinct_id"], json!("1234"));
+        let set = event.set.unwrap();
+        assert_eq!(set["plan"], json!("enterprise"));
+        assert_eq!(set["$heap_user_id"], json!("1234"));
+
+        let event = transform(HeapTable::Users, json!({"user_id": 1234, "plan": "free"})).unwrap();
+        assert_eq!(event.event, "$set");
+        assert_eq!(event.distinct_id, Some(json!("1234")));
+    }
+}
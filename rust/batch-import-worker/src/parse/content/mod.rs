@@ -1,9 +1,14 @@
+use heap::HeapContentConfig;
 use mixpanel::MixpanelContentConfig;
+use segment::SegmentContentConfig;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 pub mod amplitude;
 pub mod captured;
+pub mod heap;
 pub mod mixpanel;
+pub mod segment;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
@@ -11,6 +16,8 @@ pub enum ContentType {
     Mixpanel(MixpanelContentConfig), // From a mixpanel export
     Amplitude,
     Captured, // Each json object structured as if it was going to be sent to the capture endpoint
+    Segment(SegmentContentConfig), // Segment messages, e.g. from a warehouse or S3 archive
+    Heap(HeapContentConfig), // Rows of a Heap Connect export table
 }
 
 // All /extra/ information needed to go from any input format to an InternallyCapturedEvent,
@@ -20,3 +27,12 @@ pub struct TransformContext {
     pub team_id: i32,
     pub token: String,
 }
+
+// Ids are sometimes numbers rather than strings in exported data, so we accept either
+pub fn id_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
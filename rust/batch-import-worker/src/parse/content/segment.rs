@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use common_types::{CapturedEvent, InternallyCapturedEvent, RawEvent};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{id_string, TransformContext};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SegmentContentConfig {
+    // Segment has no notion of group types, so group calls are all mapped to this one
+    #[serde(default = "SegmentContentConfig::default_group_type")]
+    pub group_type: String,
+}
+
+impl SegmentContentConfig {
+    fn default_group_type() -> String {
+        "company".to_string()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentMessageType {
+    Track,
+    Identify,
+    Page,
+    Screen,
+    Group,
+    Alias,
+}
+
+// Based on the segment spec: https://segment.com/docs/connections/spec/common/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentMessage {
+    #[serde(rename = "type")]
+    pub message_type: SegmentMessageType,
+    pub message_id: Option<String>,
+    pub user_id: Option<Value>,
+    pub anonymous_id: Option<Value>,
+    pub timestamp: Option<String>,
+    pub original_timestamp: Option<String>,
+    pub received_at: Option<String>,
+    // Set for track calls
+    pub event: Option<String>,
+    // Set for page and screen calls
+    pub name: Option<String>,
+    // Set for group calls
+    pub group_id: Option<Value>,
+    // Set for alias calls
+    pub previous_id: Option<Value>,
+    #[serde(default)]
+    pub properties: HashMap<String, Value>,
+    #[serde(default)]
+    pub traits: HashMap<String, Value>,
+    #[serde(default)]
+    pub context: SegmentContext,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SegmentContext {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub locale: Option<String>,
+    pub group_id: Option<Value>,
+    #[serde(default)]
+    pub page: HashMap<String, Value>,
+    #[serde(default)]
+    pub campaign: HashMap<String, Value>,
+    #[serde(default)]
+    pub os: HashMap<String, Value>,
+    #[serde(default)]
+    pub device: HashMap<String, Value>,
+    #[serde(default)]
+    pub screen: HashMap<String, Value>,
+    #[serde(default)]
+    pub app: HashMap<String, Value>,
+}
+
+impl SegmentMessage {
+    pub fn parse_fn(
+        context: TransformContext,
+        group_type: String,
+        event_transform: impl Fn(RawEvent) -> Result<Option<RawEvent>, Error>,
+    ) -> impl Fn(Self) -> Result<Option<InternallyCapturedEvent>, Error> {
+        move |msg| {
+            let token = context.token.clone();
+            let team_id = context.team_id;
+
+            let user_id = msg.user_id.as_ref().and_then(id_string);
+            let anonymous_id = msg.anonymous_id.as_ref().and_then(id_string);
+            let Some(distinct_id) = user_id.clone().or(anonymous_id.clone()) else {
+                return Err(Error::msg("No userId or anonymousId found"));
+            };
+
+            let event_uuid = msg
+                .message_id
+                .as_deref()
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .unwrap_or_else(Uuid::now_v7);
+
+            let timestamp = msg
+                .timestamp
+                .as_deref()
+                .or(msg.original_timestamp.as_deref())
+                .or(msg.received_at.as_deref())
+                .ok_or(Error::msg("No timestamp found"))?;
+            let timestamp = DateTime::parse_from_rfc3339(timestamp)
+                .map_err(|_| Error::msg("Invalid timestamp format"))?
+                .with_timezone(&Utc);
+
+            let mut properties = context_properties(&msg.context);
+            let mut set = None;
+
+            let event = match msg.message_type {
+                SegmentMessageType::Track => {
+                    properties.extend(msg.properties);
+                    msg.event
+                        .ok_or(Error::msg("Track call has no event name"))?
+                }
+                SegmentMessageType::Page => {
+                    map_props(&mut properties, &msg.properties, PAGE_PROP_MAPPINGS);
+                    properties.extend(msg.properties);
+                    if let Some(name) = msg.name {
+                        properties.insert("$page_name".to_string(), Value::String(name));
+                    }
+                    "$pageview".to_string()
+                }
+                SegmentMessageType::Screen => {
+                    properties.extend(msg.properties);
+                    if let Some(name) = msg.name {
+                        properties.insert("$screen_name".to_string(), Value::String(name));
+                    }
+                    "$screen".to_string()
+                }
+                SegmentMessageType::Identify => {
+                    set = Some(msg.traits);
+                    match (&user_id, anonymous_id) {
+                        // Identifying an anonymous user, so we merge them into the identified one
+                        (Some(_), Some(anonymous_id)) => {
+                            properties.insert(
+                                "$anon_distinct_id".to_string(),
+                                Value::String(anonymous_id),
+                            );
+                            "$identify".to_string()
+                        }
+                        (Some(_), None) => "$identify".to_string(),
+                        // Anonymous identify calls only set traits on the anonymous user
+                        (None, _) => "$set".to_string(),
+                    }
+                }
+                SegmentMessageType::Group => {
+                    let group_key = msg
+                        .group_id
+                        .as_ref()
+                        .and_then(id_string)
+                        .ok_or(Error::msg("Group call has no groupId"))?;
+                    properties.insert("$group_type".to_string(), Value::String(group_type.clone()));
+                    properties.insert("$group_key".to_string(), Value::String(group_key));
+                    properties.insert(
+                        "$group_set".to_string(),
+                        Value::Object(msg.traits.into_iter().collect()),
+                    );
+                    "$groupidentify".to_string()
+                }
+                SegmentMessageType::Alias => {
+                    let previous_id = msg
+                        .previous_id
+                        .as_ref()
+                        .and_then(id_string)
+                        .ok_or(Error::msg("Alias call has no previousId"))?;
+                    properties.insert("alias".to_string(), Value::String(previous_id));
+                    "$create_alias".to_string()
+                }
+            };
+
+            // Segment lets track calls be associated with a group via the context
+            if let Some(group_id) = msg.context.group_id.as_ref().and_then(id_string) {
+                let mut groups = serde_json::Map::new();
+                groups.insert(group_type.clone(), Value::String(group_id));
+                properties.insert("$groups".to_string(), Value::Object(groups));
+            }
+
+            if let Some(message_id) = msg.message_id {
+                properties.insert("$segment_message_id".to_string(), Value::String(message_id));
+            }
+            if let Some(anonymous_id) = msg.anonymous_id {
+                properties.insert("$segment_anonymous_id".to_string(), anonymous_id);
+            }
+            properties.insert("historical_migration".to_string(), Value::Bool(true));
+            properties.insert(
+                "analytics_source".to_string(),
+                Value::String("segment".to_string()),
+            );
+
+            let raw_event = RawEvent {
+                token: Some(token.clone()),
+                distinct_id: Some(Value::String(distinct_id.clone())),
+                uuid: Some(event_uuid),
+                event,
+                properties,
+                timestamp: Some(timestamp.to_rfc3339()),
+                set: set.filter(|s| !s.is_empty()),
+                set_once: None,
+                offset: None,
+            };
+
+            let Some(raw_event) = event_transform(raw_event)? else {
+                return Ok(None);
+            };
+
+            let inner = CapturedEvent {
+                uuid: event_uuid,
+                distinct_id,
+                ip: msg.context.ip.unwrap_or_else(|| "127.0.0.1".to_string()),
+                data: serde_json::to_string(&raw_event)?,
+                now: Utc::now().to_rfc3339(),
+                sent_at: None,
+                token,
+                is_cookieless_mode: false,
+            };
+
+            Ok(Some(InternallyCapturedEvent { team_id, inner }))
+        }
+    }
+}
+
+// Page properties from the segment spec, which take precedence over those in the context
+const PAGE_PROP_MAPPINGS: &[(&str, &str)] = &[
+    ("url", "$current_url"),
+    ("path", "$pathname"),
+    ("referrer", "$referrer"),
+    ("title", "$title"),
+];
+
+const CAMPAIGN_PROP_MAPPINGS: &[(&str, &str)] = &[
+    ("name", "utm_campaign"),
+    ("source", "utm_source"),
+    ("medium", "utm_medium"),
+    ("term", "utm_term"),
+    ("content", "utm_content"),
+];
+
+const OS_PROP_MAPPINGS: &[(&str, &str)] = &[("name", "$os"), ("version", "$os_version")];
+
+const DEVICE_PROP_MAPPINGS: &[(&str, &str)] = &[
+    ("id", "$device_id"),
+    ("type", "$device_type"),
+    ("manufacturer", "$device_manufacturer"),
+    ("model", "$device_model"),
+];
+
+const SCREEN_PROP_MAPPINGS: &[(&str, &str)] =
+    &[("width", "$screen_width"), ("height", "$screen_height")];
+
+const APP_PROP_MAPPINGS: &[(&str, &str)] = &[
+    ("name", "$app_name"),
+    ("version", "$app_version"),
+    ("build", "$app_build"),
+];
+
+fn context_properties(context: &SegmentContext) -> HashMap<String, Value> {
+    let mut props = HashMap::new();
+
+    map_props(&mut props, &context.page, PAGE_PROP_MAPPINGS);
+    map_props(&mut props, &context.campaign, CAMPAIGN_PROP_MAPPINGS);
+    map_props(&mut props, &context.os, OS_PROP_MAPPINGS);
+    map_props(&mut props, &context.device, DEVICE_PROP_MAPPINGS);
+    map_props(&mut props, &context.screen, SCREEN_PROP_MAPPINGS);
+    map_props(&mut props, &context.app, APP_PROP_MAPPINGS);
+
+    if let Some(ip) = &context.ip {
+        props.insert("$ip".to_string(), Value::String(ip.clone()));
+    }
+    if let Some(user_agent) = &context.user_agent {
+        props.insert(
+            "$raw_user_agent".to_string(),
+            Value::String(user_agent.clone()),
+        );
+    }
+    if let Some(locale) = &context.locale {
+        props.insert("$locale".to_string(), Value::String(locale.clone()));
+    }
+
+    props
+}
+
+fn map_props(
+    props: &mut HashMap<String, Value>,
+    from: &HashMap<String, Value>,
+    mappings: &[(&str, &str)],
+) {
+    for (from_key, to_key) in mappings {
+        if let Some(value) = from.get(*from_key).filter(|v| !v.is_null()) {
+            props.insert(to_key.to_string(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn transform(message: Value) -> Result<Option<RawEvent>, Error> {
+        let context = TransformContext {
+            team_id: 1,
+            token: "token".to_string(),
+        };
+        let parse = SegmentMessage::parse_fn(context, "company".to_string(), |e| Ok(Some(e)));
+        let message: SegmentMessage = serde_json::from_value(message)?;
+        Ok(parse(message)?.map(|e| serde_json::from_str(&e.inner.data).unwrap()))
+    }
+
+    #[test]
+    fn test_track_and_page() {
+        let event = transform(json!({
+            "type": "track",
+            "event": "Order Completed",
+            "userId": "user-1",
+            "anonymousId": "anon-1",
+            "timestamp": "2024-01-01T12:00:00.000Z",
+            "properties": {"revenue": 10},
+            "context": {"ip": "1.2.3.4", "campaign": {"source": "newsletter"}, "groupId": "acme"}
+        }))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(event.event, "Order Completed");
+        assert_eq!(event.distinct_id, Some(json!("user-1")));
+        assert_eq!(
+            event.timestamp.as_deref(),
+            Some("2024-01-01T12:00:00+00:00")
+        );
+        assert_eq!(event.properties["revenue"], json!(10));
+        assert_eq!(event.properties["utm_source"], json!("newsletter"));
+        assert_eq!(event.properties["$ip"], json!("1.2.3.4"));
+        assert_eq!(event.properties["$groups"], json!({"company": "acme"}));
+
+        let event = transform(json!({
+            "type": "page",
+            "name": "Pricing",
+            "anonymousId": "anon-1",
+            "timestamp": "2024-01-01T12:00:00Z",
+            "properties": {"url": "https://example.com/pricing"},
+            "context": {"page": {"url": "https://example.com/", "path": "/pricing"}}
+        }))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(event.event, "$pageview");
+        assert_eq!(event.distinct_id, Some(json!("anon-1")));
+        assert_eq!(
+            event.properties["$current_url"],
+            json!("https://example.com/pricing")
+        );
+        assert_eq!(event.properties["$pathname"], json!("/pricing"));
+        assert_eq!(event.properties["$page_name"], json!("Pricing"));
+    }
+
+    #[test]
+    fn test_identify_group_and_alias() {
+        let event = transform(json!({
+            "type": "identify",
+            "userId": 42,
+            "anonymousId": "anon-1",
+            "timestamp": "2024-01-01T12:00:00Z",
+            "traits": {"email": "user@example.com"}
+        }))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(event.event, "$identify");
+        assert_eq!(event.distinct_id, Some(json!("42")));
+        assert_eq!(event.properties["$anon_distinct_id"], json!("anon-1"));
+        assert_eq!(event.set.unwrap()["email"], json!("user@example.com"));
+
+        let event = transform(json!({
+            "type": "group",
+            "userId": "user-1",
+            "groupId": "acme",
+            "timestamp": "2024-01-01T12:00:00Z",
+            "traits": {"plan": "enterprise"}
+        }))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(event.event, "$groupidentify");
+        assert_eq!(event.properties["$group_type"], json!("company"));
+        assert_eq!(event.properties["$group_key"], json!("acme"));
+        assert_eq!(
+            event.properties["$group_set"],
+            json!({"plan": "enterprise"})
+        );
+
+        let event = transform(json!({
+            "type": "alias",
+            "userId": "user-1",
+            "previousId": "old-user-1",
+            "timestamp": "2024-01-01T12:00:00Z"
+        }))
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(event.event, "$create_alias");
+        assert_eq!(event.properties["alias"], json!("old-user-1"));
+
+        assert!(transform(json!({
+            "type": "track",
+            "event": "No ids",
+            "timestamp": "2024-01-01T12:00:00Z"
+        }))
+        .is_err());
+    }
+}
@@ -1,20 +1,27 @@
 use std::sync::Arc;
 
 use anyhow::{Context, Error};
-use chrono::Duration;
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
 use common_types::{InternallyCapturedEvent, RawEvent};
+use parquet::{
+    file::reader::{FileReader, SerializedFileReader},
+    record::Field,
+};
 use rayon::iter::IntoParallelIterator;
 use rayon::prelude::*;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use crate::{context::AppContext, job::model::JobModel};
 
 use super::{
+    columns::{map_row, ColumnMapping},
     content::{
-        amplitude::AmplitudeEvent, captured::captured_parse_fn, mixpanel::MixpanelEvent,
-        ContentType, TransformContext,
+        amplitude::AmplitudeEvent, captured::captured_parse_fn, heap::HeapRow,
+        mixpanel::MixpanelEvent, segment::SegmentMessage, ContentType, TransformContext,
     },
-    Parsed,
+    Chunk, Parsed,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,10 +31,29 @@ pub enum FormatConfig {
         skip_blanks: bool,
         content: ContentType,
     },
+    // Each row is mapped to a json object via the column list, which gives the csv columns in order
+    Csv {
+        #[serde(default = "FormatConfig::default_delimiter")]
+        delimiter: char,
+        // Rows exactly matching the column names are skipped, rather than parsed
+        #[serde(default)]
+        has_header: bool,
+        columns: Vec<ColumnMapping>,
+        content: ContentType,
+    },
+    // Parquet files can't be read from an arbitrary byte offset, so each part has to fit in a single chunk
+    Parquet {
+        // If empty, every column is mapped to a field of the same name
+        #[serde(default)]
+        columns: Vec<ColumnMapping>,
+        content: ContentType,
+    },
 }
 
 pub type ParserFn =
-    Box<dyn Fn(Vec<u8>) -> Result<Parsed<Vec<InternallyCapturedEvent>>, Error> + Send + Sync>;
+    Box<dyn Fn(Chunk) -> Result<Parsed<Vec<InternallyCapturedEvent>>, Error> + Send + Sync>;
+
+type RecordParserFn<T> = Box<dyn Fn(Chunk) -> Result<Parsed<Vec<T>>, Error> + Send + Sync>;
 
 impl FormatConfig {
     pub async fn get_parser(
@@ -35,21 +61,14 @@ impl FormatConfig {
         model: &JobModel,
         context: Arc<AppContext>,
     ) -> Result<ParserFn, Error> {
-        // Only support json-lines for now
-        let Self::JsonLines {
-            skip_blanks,
-            content,
-        } = self;
-
         let transform_context = TransformContext {
             team_id: model.team_id,
             token: context.get_token_for_team_id(model.team_id).await?,
         };
+        let chunk_size = context.config.chunk_size;
 
-        match content {
+        match self.content() {
             ContentType::Mixpanel(config) => {
-                let format_parse = json_nd(*skip_blanks);
-
                 let event_transform = MixpanelEvent::parse_fn(
                     transform_context,
                     config.skip_no_distinct_id,
@@ -59,69 +78,137 @@ impl FormatConfig {
                         .unwrap_or_default(),
                     skip_geoip(),
                 );
-
-                let parser = move |data| {
-                    let parsed: Parsed<Vec<MixpanelEvent>> = format_parse(data)?;
-                    let consumed = parsed.consumed;
-                    let result: Result<_, Error> = parsed
-                        .data
-                        .into_par_iter()
-                        .map(&event_transform)
-                        .filter_map(|x| x.transpose())
-                        .collect();
-
-                    Ok(Parsed {
-                        data: result?,
-                        consumed,
-                    })
-                };
-
-                Ok(Box::new(parser))
+                Ok(with_transform(
+                    self.record_parser::<MixpanelEvent>(chunk_size)?,
+                    event_transform,
+                ))
             }
             ContentType::Amplitude => {
-                let format_parse = json_nd(*skip_blanks);
                 let event_transform = AmplitudeEvent::parse_fn(transform_context, skip_geoip());
-                let parser = move |data| {
-                    let parsed: Parsed<Vec<AmplitudeEvent>> = format_parse(data)?;
-                    let consumed = parsed.consumed;
-                    let result: Result<_, Error> = parsed
-                        .data
-                        .into_par_iter()
-                        .map(&event_transform)
-                        .filter_map(|x| x.transpose())
-                        .collect();
-
-                    Ok(Parsed {
-                        data: result?,
-                        consumed,
-                    })
-                };
-
-                Ok(Box::new(parser))
+                Ok(with_transform(
+                    self.record_parser::<AmplitudeEvent>(chunk_size)?,
+                    event_transform,
+                ))
             }
             ContentType::Captured => {
-                let format_parse = json_nd(*skip_blanks);
                 let event_transform = captured_parse_fn(transform_context, skip_geoip());
-                let parser = move |data| {
-                    let parsed: Parsed<Vec<RawEvent>> = format_parse(data)?;
-                    let consumed = parsed.consumed;
-                    let result: Result<_, Error> = parsed
-                        .data
-                        .into_par_iter()
-                        .map(&event_transform)
-                        .filter_map(|x| x.transpose())
-                        .collect();
-
-                    Ok(Parsed {
-                        data: result?,
-                        consumed,
-                    })
-                };
+                Ok(with_transform(
+                    self.record_parser::<RawEvent>(chunk_size)?,
+                    event_transform,
+                ))
+            }
+            ContentType::Segment(config) => {
+                let event_transform = SegmentMessage::parse_fn(
+                    transform_context,
+                    config.group_type.clone(),
+                    skip_geoip(),
+                );
+                Ok(with_transform(
+                    self.record_parser::<SegmentMessage>(chunk_size)?,
+                    event_transform,
+                ))
+            }
+            ContentType::Heap(config) => {
+                let event_transform =
+                    HeapRow::parse_fn(transform_context, config.clone(), skip_geoip());
+                Ok(with_transform(
+                    self.record_parser::<HeapRow>(chunk_size)?,
+                    event_transform,
+                ))
+            }
+        }
+    }
 
-                Ok(Box::new(parser))
+    pub fn content(&self) -> &ContentType {
+        match self {
+            Self::JsonLines { content, .. }
+            | Self::Csv { content, .. }
+            | Self::Parquet { content, .. } => content,
+        }
+    }
+
+    // Returns a function parsing a chunk into records of the content type, before they're transformed
+    fn record_parser<T>(&self, chunk_size: usize) -> Result<RecordParserFn<T>, Error>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        match self {
+            Self::JsonLines { skip_blanks, .. } => {
+                let format_parse = json_nd(*skip_blanks);
+                Ok(Box::new(move |chunk: Chunk| format_parse(chunk.data)))
+            }
+            Self::Csv {
+                delimiter,
+                has_header,
+                columns,
+                ..
+            } => {
+                if !delimiter.is_ascii() {
+                    return Err(Error::msg(format!(
+                        "CSV delimiter must be an ascii character, got {}",
+                        delimiter
+                    )));
+                }
+                if columns.is_empty() {
+                    return Err(Error::msg("CSV format requires at least one column"));
+                }
+                let format_parse = csv_rows(*delimiter as u8, *has_header, columns.clone());
+                Ok(Box::new(move |chunk| from_rows::<T>(format_parse(chunk)?)))
+            }
+            Self::Parquet { columns, .. } => {
+                let format_parse = parquet_rows(columns.clone(), chunk_size);
+                Ok(Box::new(move |chunk| from_rows::<T>(format_parse(chunk)?)))
             }
         }
     }
+
+    fn default_delimiter() -> char {
+        ','
+    }
+}
+
+// Combine a record parser with the transform from a record to the event we emit
+fn with_transform<T, F>(format_parse: RecordParserFn<T>, event_transform: F) -> ParserFn
+where
+    T: Send + 'static,
+    F: Fn(T) -> Result<Option<InternallyCapturedEvent>, Error> + Send + Sync + 'static,
+{
+    let parser = move |chunk| {
+        let parsed = format_parse(chunk)?;
+        let consumed = parsed.consumed;
+        let result: Result<_, Error> = parsed
+            .data
+            .into_par_iter()
+            .map(&event_transform)
+            .filter_map(|x| x.transpose())
+            .collect();
+
+        Ok(Parsed {
+            data: result?,
+            consumed,
+        })
+    };
+
+    Box::new(parser)
+}
+
+fn from_rows<T: DeserializeOwned + Send>(
+    parsed: Parsed<Vec<Value>>,
+) -> Result<Parsed<Vec<T>>, Error> {
+    let data: Result<Vec<T>, Error> = parsed
+        .data
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, row)| {
+            serde_json::from_value(row)
+                .with_context(|| format!("Failed to parse row {} of current chunk", i))
+        })
+        .collect();
+
+    Ok(Parsed {
+        data: data?,
+        consumed: parsed.consumed,
+    })
 }
 
 const NEWLINE_DELIM: u8 = b'\n';
@@ -216,6 +303,152 @@ where
     })
 }
 
+// Splits a chunk into csv rows, mapping each to a json object. As with newline_delim, the chunk may
+// end partway through a row, so we only consume rows we know are complete - those followed by another
+// row, or the last row of a chunk that runs to the end of the part.
+pub fn csv_rows(
+    delimiter: u8,
+    has_header: bool,
+    columns: Vec<ColumnMapping>,
+) -> impl Fn(Chunk) -> Result<Parsed<Vec<Value>>, Error> {
+    move |Chunk {
+              data,
+              at_end_of_part,
+          }| {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(data.as_slice());
+
+        let mut records = Vec::new();
+        loop {
+            let mut record = csv::ByteRecord::new();
+            let more = reader
+                .read_byte_record(&mut record)
+                .context("Failed to read csv row")?;
+            if !more {
+                break;
+            }
+            records.push((reader.position().byte() as usize, record));
+        }
+
+        // A row might be cut off partway through a field, even a quoted one spanning lines, so unless
+        // we know we're at the end of the part, we leave the last row to be read with the next chunk
+        if !at_end_of_part {
+            records.pop();
+        }
+
+        let mut output = Vec::with_capacity(records.len());
+        let mut consumed = 0;
+        for (end, record) in records {
+            let fields = record
+                .iter()
+                .map(std::str::from_utf8)
+                .collect::<Result<Vec<_>, _>>()
+                .context("Failed to parse row as utf8")
+                .with_context(|| format!("Starting at byte {} of current chunk", consumed))?;
+
+            if fields.len() != columns.len() {
+                return Err(Error::msg(format!(
+                    "Expected {} columns, found {}",
+                    columns.len(),
+                    fields.len()
+                ))
+                .context(format!("Starting at byte {} of current chunk", consumed)));
+            }
+
+            let is_header = has_header && fields.iter().zip(&columns).all(|(f, c)| *f == c.column);
+            if !is_header {
+                let values: Vec<Value> = fields.into_iter().map(Value::from).collect();
+                let row = map_row(&columns, |column| {
+                    columns
+                        .iter()
+                        .position(|c| c.column == column)
+                        .map(|i| &values[i])
+                })
+                .with_context(|| format!("Starting at byte {} of current chunk", consumed))?;
+                output.push(row);
+            }
+
+            consumed = end;
+        }
+
+        Ok(Parsed {
+            data: output,
+            consumed,
+        })
+    }
+}
+
+// Reads every row group of a parquet file, mapping each row to a json object. The whole file has to
+// be in the chunk, since the file metadata is stored at the end of it.
+pub fn parquet_rows(
+    columns: Vec<ColumnMapping>,
+    chunk_size: usize,
+) -> impl Fn(Chunk) -> Result<Parsed<Vec<Value>>, Error> {
+    move |Chunk {
+              data,
+              at_end_of_part,
+          }| {
+        let consumed = data.len();
+        let reader = match SerializedFileReader::new(Bytes::from(data)) {
+            Ok(reader) => reader,
+            Err(e) if !at_end_of_part => {
+                return Err(Error::from(e).context(format!(
+                    "Failed to read parquet file, parquet parts must be smaller than the chunk size ({} bytes)",
+                    chunk_size
+                )));
+            }
+            Err(e) => return Err(Error::from(e).context("Failed to read parquet file")),
+        };
+
+        let mut output = Vec::new();
+        for i in 0..reader.num_row_groups() {
+            let row_group = reader
+                .get_row_group(i)
+                .with_context(|| format!("Failed to read row group {}", i))?;
+            let rows = row_group
+                .get_row_iter(None)
+                .with_context(|| format!("Failed to read row group {}", i))?;
+
+            for row in rows {
+                let row = row.with_context(|| format!("Failed to read row in row group {}", i))?;
+                let values: Map<String, Value> = row
+                    .get_column_iter()
+                    .map(|(name, field)| (name.clone(), parquet_value(field)))
+                    .collect();
+
+                let row = if columns.is_empty() {
+                    Value::Object(values)
+                } else {
+                    map_row(&columns, |column| values.get(column))
+                        .with_context(|| format!("Failed to map row in row group {}", i))?
+                };
+                output.push(row);
+            }
+        }
+
+        Ok(Parsed {
+            data: output,
+            consumed,
+        })
+    }
+}
+
+// Parquet timestamps are rendered in their own format by default, but we want rfc3339
+fn parquet_value(field: &Field) -> Value {
+    let timestamp = match field {
+        Field::TimestampMillis(millis) => DateTime::<Utc>::from_timestamp_millis(*millis),
+        Field::TimestampMicros(micros) => DateTime::<Utc>::from_timestamp_micros(*micros),
+        _ => return field.to_json_value(),
+    };
+
+    timestamp
+        .map(|t| Value::String(t.to_rfc3339()))
+        .unwrap_or_else(|| field.to_json_value())
+}
+
 pub fn skip_geoip() -> impl Fn(RawEvent) -> Result<Option<RawEvent>, Error> {
     move |mut event| {
         event
@@ -231,6 +464,7 @@ mod tests {
     use crate::source::{folder::FolderSource, DataSource};
 
     use super::*;
+    use crate::parse::columns::Coercion;
     use serde::Deserialize;
     use std::fs;
     use tempfile::TempDir;
@@ -309,4 +543,186 @@ mod tests {
         // 26 "data" characters, plus the newline
         assert_eq!(parsed.consumed, 27);
     }
+
+    fn end_of_part(data: Vec<u8>) -> Chunk {
+        Chunk {
+            data,
+            at_end_of_part: true,
+        }
+    }
+
+    fn csv_columns() -> Vec<ColumnMapping> {
+        vec![
+            ColumnMapping {
+                column: "id".to_string(),
+                field: None,
+                coerce: Some(Coercion::Number),
+            },
+            ColumnMapping {
+                column: "label".to_string(),
+                field: Some("name".to_string()),
+                coerce: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_csv_parsing() {
+        let data = b"id,label\n1,test1\n2,\"test, two\"\n3,\"test\nthree\"\n".to_vec();
+        let chunk_len = data.len();
+
+        let parsed = csv_rows(b',', true, csv_columns())(end_of_part(data)).unwrap();
+        let rows: Vec<TestData> = parsed
+            .data
+            .into_iter()
+            .map(|row| serde_json::from_value(row).unwrap())
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                TestData {
+                    id: 1,
+                    name: "test1".to_string()
+                },
+                TestData {
+                    id: 2,
+                    name: "test, two".to_string()
+                },
+                TestData {
+                    id: 3,
+                    name: "test\nthree".to_string()
+                },
+            ]
+        );
+        assert_eq!(parsed.consumed, chunk_len);
+    }
+
+    #[test]
+    fn test_csv_header_only() {
+        // A chunk holding only the header row has no rows, but is still consumed
+        let data = b"id,label\n".to_vec();
+        let parsed = csv_rows(b',', true, csv_columns())(end_of_part(data)).unwrap();
+
+        assert!(parsed.data.is_empty());
+        assert_eq!(parsed.consumed, 9);
+    }
+
+    #[test]
+    fn test_csv_partial_row() {
+        // The chunk is cut off inside a quoted field spanning lines
+        let data = b"1,test1\n2,\"test\n".to_vec();
+        let parsed = csv_rows(b',', false, csv_columns())(Chunk {
+            data,
+            at_end_of_part: false,
+        })
+        .unwrap();
+
+        assert_eq!(parsed.data.len(), 1);
+        // 7 "data" characters, plus the newline
+        assert_eq!(parsed.consumed, 8);
+
+        // At the end of the part, a final row without a newline is complete
+        let data = b"1,test1\n2,test2".to_vec();
+        let parsed = csv_rows(b',', false, csv_columns())(end_of_part(data.clone())).unwrap();
+        assert_eq!(parsed.data.len(), 2);
+        assert_eq!(parsed.consumed, 15);
+
+        // But otherwise it's left for the next chunk, however short this one is
+        let parsed = csv_rows(b',', false, csv_columns())(Chunk {
+            data,
+            at_end_of_part: false,
+        })
+        .unwrap();
+        assert_eq!(parsed.data.len(), 1);
+        assert_eq!(parsed.consumed, 8);
+
+        let data = b"1,test1,extra\n".to_vec();
+        assert!(csv_rows(b',', false, csv_columns())(end_of_part(data)).is_err());
+    }
+
+    fn write_parquet_file() -> Vec<u8> {
+        use parquet::{
+            data_type::{ByteArray, ByteArrayType, Int32Type},
+            file::writer::SerializedFileWriter,
+            schema::parser::parse_message_type,
+        };
+
+        let schema = Arc::new(
+            parse_message_type(
+                "message schema { REQUIRED INT32 id; REQUIRED BYTE_ARRAY label (UTF8); }",
+            )
+            .unwrap(),
+        );
+
+        let mut buffer = Vec::new();
+        let mut writer =
+            SerializedFileWriter::new(&mut buffer, schema, Default::default()).unwrap();
+        // Write each row to its own row group, so we read across several
+        for (id, label) in [(1, "test1"), (2, "test2")] {
+            let mut row_group = writer.next_row_group().unwrap();
+
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column
+                .typed::<Int32Type>()
+                .write_batch(&[id], None, None)
+                .unwrap();
+            column.close().unwrap();
+
+            let mut column = row_group.next_column().unwrap().unwrap();
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&[ByteArray::from(label)], None, None)
+                .unwrap();
+            column.close().unwrap();
+
+            row_group.close().unwrap();
+        }
+        writer.close().unwrap();
+
+        buffer
+    }
+
+    #[test]
+    fn test_parquet_parsing() {
+        let data = write_parquet_file();
+        let chunk_len = data.len();
+
+        let parsed = parquet_rows(csv_columns(), 1000)(end_of_part(data.clone())).unwrap();
+        let rows: Vec<TestData> = parsed
+            .data
+            .into_iter()
+            .map(|row| serde_json::from_value(row).unwrap())
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                TestData {
+                    id: 1,
+                    name: "test1".to_string()
+                },
+                TestData {
+                    id: 2,
+                    name: "test2".to_string()
+                },
+            ]
+        );
+        assert_eq!(parsed.consumed, chunk_len);
+
+        // Without a column mapping, columns are passed through by name
+        let parsed = parquet_rows(vec![], 1000)(end_of_part(data.clone())).unwrap();
+        assert_eq!(
+            parsed.data[1],
+            serde_json::json!({"id": 2, "label": "test2"})
+        );
+
+        // A part that doesn't fit in a chunk can't be read
+        let truncated = data[..data.len() - 10].to_vec();
+        assert!(parquet_rows(vec![], truncated.len())(Chunk {
+            data: truncated,
+            at_end_of_part: false,
+        })
+        .is_err());
+    }
 }
@@ -1,5 +1,15 @@
+pub mod columns;
 pub mod content;
 pub mod format;
+pub mod transformation;
+
+// A chunk of a part, as handed to a parser
+pub struct Chunk {
+    pub data: Vec<u8>,
+    // Whether the chunk runs to the end of its part, so that a record cut off at the end of it won't
+    // be completed by the next chunk
+    pub at_end_of_part: bool,
+}
 
 pub struct Parsed<T> {
     pub data: T,
@@ -0,0 +1,283 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use anyhow::{Context, Error};
+use common_types::{InternallyCapturedEvent, RawEvent};
+use hogvm::{sync_execute, ExecutionContext, Program};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::error::UserError;
+
+use super::{format::ParserFn, Parsed};
+
+pub const TRANSFORMATION_ERRORS: &str = "batch_import_transformation_errors";
+
+// The most a job can configure its program to run for, or allocate, per event. The program runs once
+// for every event we import, so we don't let a job use much more than the defaults
+pub const MAX_STEPS: usize = 100_000;
+pub const MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+// A hog program run against every event after it's been parsed, to handle per-job quirks (renaming or
+// dropping properties, fixing up timestamps etc). The program gets the event as the "event" global, and
+// returns either the (modified) event, null to drop it, or a list of events to fan it out into
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TransformationConfig {
+    pub bytecode: Vec<Value>,
+    #[serde(default = "TransformationConfig::default_max_steps")]
+    pub max_steps: usize,
+    #[serde(default = "TransformationConfig::default_max_heap_size")]
+    pub max_heap_size: usize,
+    // How many events the program can fail on before we give up on the job. Failed events are dropped
+    #[serde(default)]
+    pub max_errors: u64,
+}
+
+impl TransformationConfig {
+    // Wraps a parser, running the program against each event it outputs. Failures are added to
+    // `errors`, which the job seeds from, and persists to, its state, so that the limit holds across
+    // restarts and resumes
+    pub fn wrap_parser(
+        &self,
+        parser: ParserFn,
+        job_id: Uuid,
+        errors: Arc<AtomicU64>,
+    ) -> Result<ParserFn, Error> {
+        // Check the bytecode is at least well-formed up-front, rather than failing every event
+        Program::new(self.bytecode.clone()).context("Invalid transformation bytecode")?;
+
+        let mut config = self.clone();
+        config.max_steps = config.max_steps.min(MAX_STEPS);
+        config.max_heap_size = config.max_heap_size.min(MAX_HEAP_SIZE);
+
+        let wrapped = move |chunk| {
+            let parsed: Parsed<Vec<InternallyCapturedEvent>> = parser(chunk)?;
+
+            let results: Vec<_> = parsed
+                .data
+                .into_par_iter()
+                .map(|event| config.transform(event))
+                .collect();
+
+            let mut output = Vec::with_capacity(results.len());
+            let mut failed = 0;
+            for result in results {
+                match result {
+                    Ok(events) => output.extend(events),
+                    Err(e) => {
+                        warn!("Transformation failed for job {}: {:?}", job_id, e);
+                        failed += 1;
+                    }
+                }
+            }
+
+            if failed > 0 {
+                common_metrics::inc(TRANSFORMATION_ERRORS, &[], failed);
+                let total = errors.fetch_add(failed, Ordering::Relaxed) + failed;
+                warn!(
+                    "Transformation failed for {} events in job {}, {} in total",
+                    failed, job_id, total
+                );
+                if total > config.max_errors {
+                    return Err(Error::msg(format!(
+                        "Transformation failed for {} events, more than the {} allowed",
+                        total, config.max_errors
+                    ))
+                    .context(UserError::new(
+                        "The transformation failed for too many events",
+                    )));
+                }
+            }
+
+            Ok(Parsed {
+                data: output,
+                consumed: parsed.consumed,
+            })
+        };
+
+        Ok(Box::new(wrapped))
+    }
+
+    fn transform(
+        &self,
+        event: InternallyCapturedEvent,
+    ) -> Result<Vec<InternallyCapturedEvent>, Error> {
+        let raw: RawEvent = serde_json::from_str(&event.inner.data)?;
+        let original_uuid = raw.uuid;
+
+        // The context isn't Send, so we have to set up a new one for each event
+        let program = Program::new(self.bytecode.clone())?;
+        let context = ExecutionContext::with_defaults(program)
+            .with_globals(json!({ "event": raw }))
+            .with_max_steps(self.max_steps)
+            .with_max_heap_size(self.max_heap_size);
+
+        let result = sync_execute(&context, false).map_err(|failure| {
+            Error::from(failure.error)
+                .context(format!("Transformation failed at step {}", failure.step))
+        })?;
+
+        let results = match result {
+            Value::Null => vec![],
+            Value::Array(events) => events,
+            event => vec![event],
+        };
+
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let mut raw: RawEvent = serde_json::from_value(result)
+                    .context("Transformation returned something other than an event")?;
+
+                // Events fanned out from the same input need their own uuids
+                if raw.uuid.is_none() || (i > 0 && raw.uuid == original_uuid) {
+                    raw.uuid = Some(Uuid::now_v7());
+                }
+                let Some(distinct_id) = raw.extract_distinct_id() else {
+                    return Err(Error::msg("Transformed event has no distinct_id"));
+                };
+
+                let mut inner = event.inner.clone();
+                inner.uuid = raw.uuid.unwrap_or_else(Uuid::now_v7);
+                inner.distinct_id = distinct_id;
+                inner.data = serde_json::to_string(&raw)?;
+
+                Ok(InternallyCapturedEvent {
+                    team_id: event.team_id,
+                    inner,
+                })
+            })
+            .collect()
+    }
+
+    fn default_max_steps() -> usize {
+        10_000
+    }
+
+    fn default_max_heap_size() -> usize {
+        1024 * 1024
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Chunk;
+    use common_types::CapturedEvent;
+
+    fn event(distinct_id: &str) -> InternallyCapturedEvent {
+        let raw = RawEvent {
+            distinct_id: Some(Value::String(distinct_id.to_string())),
+            uuid: Some(Uuid::now_v7()),
+            event: "test".to_string(),
+            ..Default::default()
+        };
+
+        InternallyCapturedEvent {
+            team_id: 1,
+            inner: CapturedEvent {
+                uuid: raw.uuid.unwrap(),
+                distinct_id: distinct_id.to_string(),
+                ip: "127.0.0.1".to_string(),
+                data: serde_json::to_string(&raw).unwrap(),
+                now: "2024-01-01T00:00:00Z".to_string(),
+                sent_at: None,
+                token: "token".to_string(),
+                is_cookieless_mode: false,
+            },
+        }
+    }
+
+    fn run(
+        bytecode: &str,
+        max_errors: u64,
+        events: Vec<InternallyCapturedEvent>,
+    ) -> Result<Vec<RawEvent>, Error> {
+        run_with_errors(bytecode, max_errors, events, Arc::new(AtomicU64::new(0)))
+    }
+
+    fn run_with_errors(
+        bytecode: &str,
+        max_errors: u64,
+        events: Vec<InternallyCapturedEvent>,
+        errors: Arc<AtomicU64>,
+    ) -> Result<Vec<RawEvent>, Error> {
+        let config = TransformationConfig {
+            bytecode: serde_json::from_str(bytecode).unwrap(),
+            max_steps: TransformationConfig::default_max_steps(),
+            max_heap_size: TransformationConfig::default_max_heap_size(),
+            max_errors,
+        };
+        let parser: ParserFn = Box::new(move |_| {
+            Ok(Parsed {
+                data: events.clone(),
+                consumed: 0,
+            })
+        });
+
+        let parsed = config.wrap_parser(parser, Uuid::now_v7(), errors)?(Chunk {
+            data: vec![],
+            at_end_of_part: true,
+        })?;
+        Ok(parsed
+            .data
+            .into_iter()
+            .map(|e| serde_json::from_str(&e.inner.data).unwrap())
+            .collect())
+    }
+
+    #[test]
+    fn test_modifies_events() {
+        // let e := event; e.properties.source := 'hog'; return e
+        let bytecode = r#"["_H", 1, 32, "event", 1, 1, 36, 0, 32, "properties", 45, 32, "source", 32, "hog", 46, 36, 0, 38]"#;
+        let output = run(bytecode, 0, vec![event("a"), event("b")]).unwrap();
+
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].properties["source"], json!("hog"));
+        assert_eq!(output[1].distinct_id, Some(json!("b")));
+    }
+
+    #[test]
+    fn test_drops_and_fans_out_events() {
+        // return null
+        let output = run(r#"["_H", 1, 31, 38]"#, 0, vec![event("a")]).unwrap();
+        assert!(output.is_empty());
+
+        // return [event, event]
+        let bytecode = r#"["_H", 1, 32, "event", 1, 1, 32, "event", 1, 1, 43, 2, 38]"#;
+        let output = run(bytecode, 0, vec![event("a")]).unwrap();
+        assert_eq!(output.len(), 2);
+        assert_ne!(output[0].uuid, output[1].uuid);
+    }
+
+    #[test]
+    fn test_counts_errors() {
+        // return unknown_global
+        let bytecode = r#"["_H", 1, 32, "unknown_global", 1, 1, 38]"#;
+
+        let output = run(bytecode, 2, vec![event("a"), event("b")]).unwrap();
+        assert!(output.is_empty());
+
+        assert!(run(bytecode, 1, vec![event("a"), event("b")]).is_err());
+    }
+
+    #[test]
+    fn test_counts_errors_from_previous_runs() {
+        // return unknown_global
+        let bytecode = r#"["_H", 1, 32, "unknown_global", 1, 1, 38]"#;
+
+        // As if the job had already failed on one event before restarting
+        let errors = Arc::new(AtomicU64::new(1));
+        assert!(
+            run_with_errors(bytecode, 2, vec![event("a"), event("b")], errors.clone()).is_err()
+        );
+        assert_eq!(errors.load(Ordering::Relaxed), 3);
+    }
+}
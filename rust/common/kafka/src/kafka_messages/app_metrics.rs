@@ -23,6 +23,7 @@ pub enum ErrorType {
     ConnectionError,
     BadHttpStatus(u16),
     ParseError,
+    SigningError,
 }
 
 // NOTE: This is stored in Postgres and deserialized by the cleanup/janitor process, so this
@@ -129,6 +130,7 @@ impl TryFrom<String> for ErrorType {
                 Ok(ErrorType::BadHttpStatus(parsed_status))
             }
             "Parse Error" | "ParseError" => Ok(ErrorType::ParseError),
+            "Signing Error" | "SigningError" => Ok(ErrorType::SigningError),
             _ => Err(format!("Unknown ErrorType: {}", s)),
         }
     }
@@ -141,6 +143,7 @@ impl From<ErrorType> for String {
             ErrorType::TimeoutError => "Timeout Error".to_string(),
             ErrorType::BadHttpStatus(s) => format!("Bad HTTP Status: {}", s),
             ErrorType::ParseError => "Parse Error".to_string(),
+            ErrorType::SigningError => "Signing Error".to_string(),
         }
     }
 }
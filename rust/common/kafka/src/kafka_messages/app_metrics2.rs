@@ -8,6 +8,7 @@ use super::{deserialize_datetime, serialize_datetime};
 pub enum Source {
     Hoghooks,
     Cyclotron,
+    Webhooks,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
@@ -15,6 +16,7 @@ pub enum Source {
 pub enum Kind {
     Success,
     Failure,
+    Other,
     Unknown,
 }
 
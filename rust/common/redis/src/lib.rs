@@ -103,6 +103,16 @@ pub trait Client {
     ) -> Result<bool, CustomRedisError>;
     async fn del(&self, k: String) -> Result<(), CustomRedisError>;
     async fn hget(&self, k: String, field: String) -> Result<String, CustomRedisError>;
+    // Increment a hash field, reset the key's TTL, delete `stale_fields`, and return every field
+    // left in the hash, all in a single pipeline
+    async fn hincrby_and_getall(
+        &self,
+        k: String,
+        field: String,
+        count: i32,
+        seconds: u64,
+        stale_fields: Vec<String>,
+    ) -> Result<HashMap<String, String>, CustomRedisError>;
 }
 
 pub struct RedisClient {
@@ -259,6 +269,31 @@ impl Client for RedisClient {
             None => Err(CustomRedisError::NotFound),
         }
     }
+
+    async fn hincrby_and_getall(
+        &self,
+        k: String,
+        field: String,
+        count: i32,
+        seconds: u64,
+        stale_fields: Vec<String>,
+    ) -> Result<HashMap<String, String>, CustomRedisError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let mut pipe = redis::pipe();
+        pipe.hincr(&k, field, count)
+            .ignore()
+            .expire(&k, seconds as usize)
+            .ignore();
+        if !stale_fields.is_empty() {
+            pipe.hdel(&k, stale_fields).ignore();
+        }
+        pipe.hgetall(&k);
+
+        let results = pipe.query_async(&mut conn);
+        let fut: Result<(HashMap<String, String>,), RedisError> =
+            timeout(Duration::from_millis(get_redis_timeout_ms()), results).await?;
+        Ok(fut?.0)
+    }
 }
 
 #[derive(Clone)]
@@ -270,6 +305,7 @@ pub struct MockRedisClient {
     set_nx_ex_ret: HashMap<String, Result<bool, CustomRedisError>>,
     del_ret: HashMap<String, Result<(), CustomRedisError>>,
     hget_ret: HashMap<String, Result<String, CustomRedisError>>,
+    hincrby_and_getall_ret: HashMap<String, Result<HashMap<String, String>, CustomRedisError>>,
     calls: Arc<Mutex<Vec<MockRedisCall>>>,
 }
 
@@ -283,6 +319,7 @@ impl Default for MockRedisClient {
             set_nx_ex_ret: HashMap::new(),
             del_ret: HashMap::new(),
             hget_ret: HashMap::new(),
+            hincrby_and_getall_ret: HashMap::new(),
             calls: Arc::new(Mutex::new(Vec::new())),
         }
     }
@@ -332,6 +369,15 @@ impl MockRedisClient {
         self.clone()
     }
 
+    pub fn hincrby_and_getall_ret(
+        &mut self,
+        key: &str,
+        ret: Result<HashMap<String, String>, CustomRedisError>,
+    ) -> Self {
+        self.hincrby_and_getall_ret.insert(key.to_owned(), ret);
+        self.clone()
+    }
+
     pub fn get_calls(&self) -> Vec<MockRedisCall> {
         self.lock_calls().clone()
     }
@@ -354,6 +400,12 @@ pub enum MockRedisValue {
     MinMax(String, String),
     StringWithFormat(String, RedisValueFormat),
     StringWithTTLAndFormat(String, u64, RedisValueFormat),
+    HashIncrement {
+        field: String,
+        count: i32,
+        seconds: u64,
+        stale_fields: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -539,4 +591,31 @@ impl Client for MockRedisClient {
             None => Err(CustomRedisError::NotFound),
         }
     }
+
+    async fn hincrby_and_getall(
+        &self,
+        key: String,
+        field: String,
+        count: i32,
+        seconds: u64,
+        stale_fields: Vec<String>,
+    ) -> Result<HashMap<String, String>, CustomRedisError> {
+        // Record the call
+        let mut calls = self.lock_calls();
+        calls.push(MockRedisCall {
+            op: "hincrby_and_getall".to_string(),
+            key: key.clone(),
+            value: MockRedisValue::HashIncrement {
+                field,
+                count,
+                seconds,
+                stale_fields,
+            },
+        });
+
+        match self.hincrby_and_getall_ret.get(&key) {
+            Some(result) => result.clone(),
+            None => Err(CustomRedisError::NotFound),
+        }
+    }
 }
@@ -0,0 +1,5 @@
+-- Fair share dequeueing walks the distinct teams with available jobs on a queue, and then takes
+-- each team's oldest jobs, which this index supports without scanning every available job.
+CREATE INDEX idx_cyclotron_jobs_fair_dequeue ON cyclotron_jobs (queue_name, team_id, scheduled)
+WHERE
+    state = 'available';
@@ -0,0 +1,44 @@
+---------------------------------------------------------------------
+-- Recurring jobs
+---------------------------------------------------------------------
+-- A recurring job is a template job plus a schedule. The scheduler (run by the janitor) locks due
+-- recurring jobs, enqueues an instance of the template for each due occurrence, and advances
+-- next_run, all in one transaction, so each occurrence is enqueued exactly once even with
+-- multiple schedulers running.
+CREATE TABLE IF NOT EXISTS cyclotron_recurring_jobs (
+    id UUID PRIMARY KEY,
+    created TIMESTAMPTZ NOT NULL,
+    ---------------------------------------------------------------------
+    -- The template every instance is created from
+    ---------------------------------------------------------------------
+    team_id INT NOT NULL,
+    function_id UUID,
+    queue_name TEXT NOT NULL,
+    priority SMALLINT NOT NULL,
+    vm_state bytea,
+    metadata bytea,
+    parameters bytea,
+    blob bytea,
+    ---------------------------------------------------------------------
+    -- The schedule. Exactly one of cron_expression and interval_seconds is set
+    ---------------------------------------------------------------------
+    cron_expression TEXT,
+    interval_seconds BIGINT,
+    timezone TEXT NOT NULL,
+    -- One of 'latest', 'all' or 'skip' - what to do about occurrences missed while the scheduler was down or the job was paused
+    catch_up_policy TEXT NOT NULL,
+    ---------------------------------------------------------------------
+    -- Scheduling state
+    ---------------------------------------------------------------------
+    paused BOOLEAN NOT NULL DEFAULT FALSE,
+    -- The earliest occurrence not yet enqueued. NULL if the schedule has no more occurrences
+    next_run TIMESTAMPTZ,
+    last_run TIMESTAMPTZ,
+    CONSTRAINT cyclotron_recurring_jobs_one_schedule CHECK ((cron_expression IS NULL) != (interval_seconds IS NULL))
+);
+
+CREATE INDEX idx_cyclotron_recurring_jobs_next_run ON cyclotron_recurring_jobs (next_run)
+WHERE
+    NOT paused;
+
+CREATE INDEX idx_cyclotron_recurring_jobs_team_id ON cyclotron_recurring_jobs (team_id);
@@ -0,0 +1,10 @@
+ALTER TABLE cyclotron_jobs ADD COLUMN IF NOT EXISTS idempotency_key TEXT;
+
+-- A job's idempotency key is unique within its queue until the job completes or fails. Dead lettered
+-- jobs are excluded, as the dead letter queue mixes jobs from every queue. Inserts resolving
+-- conflicts against this index must repeat its predicate exactly.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_cyclotron_jobs_idempotency_key ON cyclotron_jobs (queue_name, idempotency_key)
+WHERE
+    idempotency_key IS NOT NULL
+    AND state IN ('available', 'running', 'paused')
+    AND queue_name != '_cyclotron_dead_letter';
@@ -0,0 +1,3 @@
+-- Jobs with parents wait in this state until all their parents have finished. New enum values
+-- can't be used in the transaction that adds them, so this gets a migration of its own.
+ALTER TYPE JobState ADD VALUE IF NOT EXISTS 'waiting';
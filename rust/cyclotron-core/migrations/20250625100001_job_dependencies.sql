@@ -0,0 +1,28 @@
+---------------------------------------------------------------------
+-- Job dependencies
+---------------------------------------------------------------------
+-- One row per parent of a waiting job. When a parent finishes, its outcome and result (its final
+-- parameters) are copied here, in the same transaction, so children can read them after the
+-- parent itself has been cleaned up. Once every parent of a job has finished, the job is
+-- released, failed or cancelled according to its on_parent_failure policy. Rows are deleted by
+-- the janitor once their child is gone.
+CREATE TABLE IF NOT EXISTS cyclotron_job_dependencies (
+    job_id UUID NOT NULL,
+    parent_id UUID NOT NULL,
+    -- One of 'cancel', 'run' or 'fail'. The same for every parent of a job
+    on_parent_failure TEXT NOT NULL,
+    -- One of 'completed', 'failed', 'cancelled' or 'missing', or NULL if the parent hasn't finished
+    parent_outcome TEXT,
+    parent_result bytea,
+    created TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (job_id, parent_id)
+);
+
+CREATE INDEX idx_cyclotron_job_dependencies_parent_id ON cyclotron_job_dependencies (parent_id)
+WHERE
+    parent_outcome IS NULL;
+
+-- For the janitor's sweep of waiting jobs
+CREATE INDEX idx_cyclotron_jobs_waiting ON cyclotron_jobs (id)
+WHERE
+    state = 'waiting';
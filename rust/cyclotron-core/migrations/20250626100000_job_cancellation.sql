@@ -0,0 +1,7 @@
+-- Set on running jobs that have been cancelled. The job is deleted by its worker's next heartbeat or
+-- flush, or by the janitor if it stops running some other way.
+ALTER TABLE cyclotron_jobs ADD COLUMN IF NOT EXISTS cancel_requested BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_cyclotron_jobs_cancel_requested ON cyclotron_jobs (id)
+WHERE
+    cancel_requested;
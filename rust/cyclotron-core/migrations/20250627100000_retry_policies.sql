@@ -0,0 +1,23 @@
+-- Retry policies for whole queues. Jobs in a queue with no policy, and without one of their own,
+-- are retried according to the default policy
+CREATE TABLE IF NOT EXISTS cyclotron_queue_retry_policies (
+    queue_name TEXT PRIMARY KEY,
+    policy JSONB NOT NULL,
+    updated TIMESTAMPTZ NOT NULL
+);
+
+-- Retry policies set on individual jobs when they're created, taking precedence over their queue's
+CREATE TABLE IF NOT EXISTS cyclotron_job_retry_policies (
+    job_id UUID PRIMARY KEY REFERENCES cyclotron_jobs (id) ON DELETE CASCADE,
+    policy JSONB NOT NULL
+);
+
+-- Every failed attempt reported via a retry, kept until the job is deleted
+CREATE TABLE IF NOT EXISTS cyclotron_job_attempts (
+    job_id UUID NOT NULL REFERENCES cyclotron_jobs (id) ON DELETE CASCADE,
+    attempt INT NOT NULL,
+    error_class TEXT NOT NULL,
+    message TEXT NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (job_id, attempt)
+);
@@ -0,0 +1,9 @@
+-- Jobs waiting on their parents hold their idempotency key too. The index has to be recreated to
+-- change its predicate, and inserts resolving conflicts against it must repeat the new one exactly.
+DROP INDEX IF EXISTS idx_cyclotron_jobs_idempotency_key;
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_cyclotron_jobs_idempotency_key ON cyclotron_jobs (queue_name, idempotency_key)
+WHERE
+    idempotency_key IS NOT NULL
+    AND state IN ('available', 'running', 'paused', 'waiting')
+    AND queue_name != '_cyclotron_dead_letter';
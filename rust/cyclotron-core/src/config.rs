@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use serde::{Deserialize, Serialize};
 use sqlx::{pool::PoolOptions, PgPool};
@@ -46,6 +46,8 @@ pub struct ManagerConfig {
     pub should_compress_vm_state: Option<bool>, // Defaults to "false" for now
     #[serde(alias = "shouldUseBulkJobCopy")]
     pub should_use_bulk_job_copy: Option<bool>, // Defaults to "false" for now
+    #[serde(alias = "shouldNotifyOnInsert")]
+    pub should_notify_on_insert: Option<bool>, // Defaults to "true" - wakes workers waiting on LISTEN
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -62,6 +64,52 @@ pub struct WorkerConfig {
     pub flush_loop_interval_ms: Option<u64>, // Defaults to 10
     #[serde(alias = "shouldCompressVmState")]
     pub should_compress_vm_state: Option<bool>, // Defaults to "false"
+    #[serde(alias = "dequeueMode")]
+    pub dequeue_mode: Option<DequeueMode>, // Defaults to "priority"
+    #[serde(alias = "teamWeights")]
+    pub team_weights: Option<HashMap<i32, u32>>, // Fair share only. Teams not listed have a weight of 1
+    #[serde(alias = "teamConcurrencyLimits")]
+    pub team_concurrency_limits: Option<HashMap<i32, u64>>, // Fair share only. Max running jobs per team, per queue
+    #[serde(alias = "defaultTeamConcurrencyLimit")]
+    pub default_team_concurrency_limit: Option<u64>, // Fair share only. Defaults to no limit
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DequeueMode {
+    // Jobs are dequeued strictly by priority, then scheduled time, regardless of team
+    #[default]
+    Priority,
+    // Each batch is shared out across the teams with available jobs, by weight, so one team
+    // with a huge backlog can't starve the others. Within a team, jobs are still dequeued
+    // by priority, then scheduled time.
+    FairShare,
+}
+
+// The resolved fair share settings a worker dequeues with
+#[derive(Debug, Clone, Default)]
+pub struct FairShareConfig {
+    pub team_weights: HashMap<i32, u32>,
+    pub team_concurrency_limits: HashMap<i32, u64>,
+    pub default_team_concurrency_limit: Option<u64>,
+}
+
+impl FairShareConfig {
+    pub fn weight(&self, team_id: i32) -> u64 {
+        // A weight of 0 would mean a team is never dequeued, which is what pausing is for
+        self.team_weights.get(&team_id).copied().unwrap_or(1).max(1) as u64
+    }
+
+    pub fn concurrency_limit(&self, team_id: i32) -> Option<u64> {
+        self.team_concurrency_limits
+            .get(&team_id)
+            .copied()
+            .or(self.default_team_concurrency_limit)
+    }
+
+    pub fn has_concurrency_limits(&self) -> bool {
+        !self.team_concurrency_limits.is_empty() || self.default_team_concurrency_limit.is_some()
+    }
 }
 
 impl WorkerConfig {
@@ -88,4 +136,16 @@ impl WorkerConfig {
     pub fn should_compress_vm_state(&self) -> bool {
         self.should_compress_vm_state.unwrap_or(false)
     }
+
+    // None if the worker should dequeue in strict priority order
+    pub fn fair_share(&self) -> Option<FairShareConfig> {
+        match self.dequeue_mode.unwrap_or_default() {
+            DequeueMode::Priority => None,
+            DequeueMode::FairShare => Some(FairShareConfig {
+                team_weights: self.team_weights.clone().unwrap_or_default(),
+                team_concurrency_limits: self.team_concurrency_limits.clone().unwrap_or_default(),
+                default_team_concurrency_limit: self.default_team_concurrency_limit,
+            }),
+        }
+    }
 }
@@ -14,18 +14,28 @@ pub enum QueueError {
     CompressionError(String),
     #[error("writing in-mem CSV buffer at {0}: {1}")]
     CsvError(&'static str, csv::Error),
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+    #[error("Invalid job filter: {0}")]
+    InvalidFilter(String),
+    #[error("A job with the same idempotency key was inserted concurrently, try again")]
+    ConcurrentKeyConflict,
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum JobError {
     #[error("Unknown job id: {0}")]
     UnknownJobId(Uuid),
+    #[error("Unknown recurring job id: {0}")]
+    UnknownRecurringJobId(Uuid),
     #[error("Invalid lock id: {0} for job {1}")]
     InvalidLock(Uuid, Uuid),
     #[error("Cannot flush job {0} without a next state")]
     FlushWithoutNextState(Uuid),
     #[error("Deadline to flush update for job {0} exceeded")]
     DeadlineExceeded(Uuid),
+    #[error("Job {0} was cancelled")]
+    Cancelled(Uuid),
     #[error("Update dropped before being flushed.")]
     UpdateDropped,
     #[error("vm_state compression error: {0}")]
@@ -4,8 +4,11 @@ use sqlx::PgPool;
 
 use crate::{
     ops::{
+        bulk::delete_cancelled_jobs,
+        dependencies::{delete_finished_dependencies, resolve_orphaned_dependencies},
         janitor::{delete_completed_and_failed_jobs, detect_poison_pills, reset_stalled_jobs},
         meta::{count_total_waiting_jobs, dead_letter, run_migrations},
+        recurring::schedule_recurring_jobs,
     },
     types::AggregatedDelete,
     PoolConfig, QueueError,
@@ -59,6 +62,36 @@ impl Janitor {
         Ok(poison.len() as u64)
     }
 
+    // Enqueue instances of up to `max` due recurring jobs, returning the number of instances enqueued
+    pub async fn schedule_recurring_jobs(
+        &self,
+        max: usize,
+        should_compress_vm_state: bool,
+    ) -> Result<u64, QueueError> {
+        schedule_recurring_jobs(&self.pool, max, should_compress_vm_state).await
+    }
+
+    // Resolve parents that disappeared without finishing, releasing or cancelling the jobs waiting on
+    // them, and clean up dependencies whose children have been deleted. Returns the number of
+    // orphaned parents resolved.
+    pub async fn resolve_orphaned_dependencies(&self) -> Result<u64, QueueError> {
+        let mut txn = self.pool.begin().await?;
+        let resolved = resolve_orphaned_dependencies(&mut txn).await?;
+        txn.commit().await?;
+
+        delete_finished_dependencies(&self.pool).await?;
+        Ok(resolved)
+    }
+
+    // Delete jobs that were cancelled while running, but stopped running without their worker
+    // noticing, e.g. because they stalled and were reset. Returns the number of jobs deleted.
+    pub async fn delete_cancelled_jobs(&self) -> Result<u64, QueueError> {
+        let mut txn = self.pool.begin().await?;
+        let deleted = delete_cancelled_jobs(&mut txn).await?;
+        txn.commit().await?;
+        Ok(deleted)
+    }
+
     pub async fn waiting_jobs(&self) -> Result<Vec<(u64, String)>, QueueError> {
         count_total_waiting_jobs(&self.pool).await
     }
@@ -5,11 +5,32 @@ mod ops;
 // Types
 mod types;
 pub use types::AggregatedDelete;
+pub use types::Attempt;
+pub use types::AttemptError;
 pub use types::Bytes;
+pub use types::CancelledJobs;
+pub use types::CatchUpPolicy;
+pub use types::DeadLetterEntry;
+pub use types::DeadLetterFilter;
+pub use types::DeadLetterGroup;
 pub use types::Job;
+pub use types::JobFilter;
 pub use types::JobInit;
 pub use types::JobState;
 pub use types::JobUpdate;
+pub use types::OnKeyConflict;
+pub use types::OnParentFailure;
+pub use types::ParentOutcome;
+pub use types::ParentResult;
+pub use types::RecurringJobInit;
+pub use types::ReplayedDeadLetters;
+pub use types::RecurringSchedule;
+pub use types::RetryOutcome;
+pub use types::RetryPolicy;
+
+// Cron and interval schedules for recurring jobs
+mod schedule;
+pub use schedule::CronSchedule;
 
 // Errors
 mod error;
@@ -28,12 +49,17 @@ mod worker;
 pub use worker::FlushHandle;
 pub use worker::Worker;
 
+// LISTEN/NOTIFY wakeups for waiting workers
+mod notify;
+
 // Janitor
 mod janitor;
 pub use janitor::Janitor;
 
 // Config
 mod config;
+pub use config::DequeueMode;
+pub use config::FairShareConfig;
 pub use config::ManagerConfig;
 pub use config::PoolConfig;
 pub use config::WorkerConfig;
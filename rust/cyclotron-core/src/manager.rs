@@ -1,4 +1,4 @@
-use std::sync::atomic::AtomicUsize;
+use std::{collections::HashMap, sync::atomic::AtomicUsize};
 
 use chrono::{DateTime, Duration, Utc};
 use sqlx::PgPool;
@@ -8,10 +8,21 @@ use uuid::Uuid;
 use crate::{
     config::{DEFAULT_QUEUE_DEPTH_LIMIT, DEFAULT_SHARD_HEALTH_CHECK_INTERVAL},
     ops::{
-        manager::{bulk_create_jobs_copy, bulk_create_jobs_upsert, create_job},
+        bulk::{cancel_jobs, pause_jobs, reprioritize_jobs, reschedule_jobs, resume_jobs},
+        dead_letter::{
+            group_dead_letters, list_dead_letters, purge_dead_letters, replay_dead_letters,
+        },
+        dependencies::insert_dependencies,
+        manager::{
+            bulk_create_jobs_copy, bulk_create_jobs_resolved, bulk_create_jobs_upsert, create_job,
+            notify_queues, queues_to_notify, ResolvedJob,
+        },
         meta::count_total_waiting_jobs,
+        recurring::{create_recurring_job, delete_recurring_job, set_recurring_job_paused},
+        retry::{insert_retry_policies, set_queue_retry_policy},
     },
-    JobInit, ManagerConfig, QueueError,
+    CancelledJobs, DeadLetterEntry, DeadLetterFilter, DeadLetterGroup, JobError, JobFilter,
+    JobInit, ManagerConfig, QueueError, RecurringJobInit, ReplayedDeadLetters, RetryPolicy,
 };
 
 pub struct Shard {
@@ -21,6 +32,7 @@ pub struct Shard {
     pub depth_limit: u64,
     pub should_compress_vm_state: bool,
     pub should_use_bulk_job_copy: bool,
+    pub should_notify: bool, // Notify waiting workers when jobs are inserted
 }
 
 pub struct QueueManager {
@@ -41,17 +53,18 @@ impl QueueManager {
         );
         let should_compress_vm_state = config.should_compress_vm_state.unwrap_or(false);
         let should_use_bulk_job_copy = config.should_use_bulk_job_copy.unwrap_or(false);
+        let should_notify = config.should_notify_on_insert.unwrap_or(true);
 
         for shard in config.shards {
             let pool = shard.connect().await.unwrap();
-            let shard = Shard::new(
+            shards.push(Shard::new(
                 pool,
                 depth_limit,
                 check_interval,
                 should_compress_vm_state,
                 should_use_bulk_job_copy,
-            );
-            shards.push(shard);
+                should_notify,
+            ));
         }
         Ok(Self {
             shards: RwLock::new(shards),
@@ -72,6 +85,7 @@ impl QueueManager {
                 Duration::seconds(DEFAULT_SHARD_HEALTH_CHECK_INTERVAL as i64),
                 should_compress_vm_state,
                 should_use_bulk_job_copy,
+                true,
             )]),
             next_shard: AtomicUsize::new(0),
         }
@@ -121,6 +135,191 @@ impl QueueManager {
             .bulk_create_jobs_blocking(inits, timeout)
             .await
     }
+
+    // Recurring jobs live on a single shard, and their instances are enqueued onto that same
+    // shard by its janitor
+    pub async fn create_recurring_job(&self, init: RecurringJobInit) -> Result<Uuid, QueueError> {
+        let next = self
+            .next_shard
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let shards = self.shards.read().await;
+        let shard = &shards[next % shards.len()];
+        create_recurring_job(&shard.pool, init).await
+    }
+
+    pub async fn pause_recurring_job(&self, id: Uuid) -> Result<(), QueueError> {
+        self.set_recurring_job_paused(id, true).await
+    }
+
+    pub async fn resume_recurring_job(&self, id: Uuid) -> Result<(), QueueError> {
+        self.set_recurring_job_paused(id, false).await
+    }
+
+    // Stops any further instances being enqueued. Already enqueued instances are left alone.
+    pub async fn delete_recurring_job(&self, id: Uuid) -> Result<(), QueueError> {
+        // We don't track which shard a recurring job was created on, so we just try all of them
+        let shards = self.shards.read().await;
+        for shard in shards.iter() {
+            if delete_recurring_job(&shard.pool, id).await? {
+                return Ok(());
+            }
+        }
+        Err(JobError::UnknownRecurringJobId(id).into())
+    }
+
+    // Bulk operations apply to matching jobs on every shard, and return the number of jobs affected
+    // across all of them. See `JobFilter` for which jobs each one touches.
+    pub async fn cancel_jobs(&self, filter: &JobFilter) -> Result<CancelledJobs, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = CancelledJobs::default();
+        for shard in shards.iter() {
+            let mut txn = shard.pool.begin().await?;
+            let res = cancel_jobs(&mut txn, filter).await?;
+            txn.commit().await?;
+            total.cancelled += res.cancelled;
+            total.cancel_requested += res.cancel_requested;
+        }
+        Ok(total)
+    }
+
+    pub async fn pause_jobs(&self, filter: &JobFilter) -> Result<u64, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = 0;
+        for shard in shards.iter() {
+            total += pause_jobs(&shard.pool, filter).await?;
+        }
+        Ok(total)
+    }
+
+    pub async fn resume_jobs(&self, filter: &JobFilter) -> Result<u64, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = 0;
+        for shard in shards.iter() {
+            let mut txn = shard.pool.begin().await?;
+            total += resume_jobs(&mut txn, filter).await?;
+            txn.commit().await?;
+        }
+        Ok(total)
+    }
+
+    pub async fn reprioritize_jobs(
+        &self,
+        filter: &JobFilter,
+        priority: i16,
+    ) -> Result<u64, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = 0;
+        for shard in shards.iter() {
+            total += reprioritize_jobs(&shard.pool, filter, priority).await?;
+        }
+        Ok(total)
+    }
+
+    pub async fn reschedule_jobs(
+        &self,
+        filter: &JobFilter,
+        scheduled: DateTime<Utc>,
+    ) -> Result<u64, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = 0;
+        for shard in shards.iter() {
+            total += reschedule_jobs(&shard.pool, filter, scheduled).await?;
+        }
+        Ok(total)
+    }
+
+    // The `limit` most recently dead lettered entries matching the filter, across all shards
+    pub async fn list_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+        limit: usize,
+    ) -> Result<Vec<DeadLetterEntry>, QueueError> {
+        let shards = self.shards.read().await;
+        let mut entries = Vec::new();
+        for shard in shards.iter() {
+            entries.extend(list_dead_letters(&shard.pool, filter, limit).await?);
+        }
+        entries.sort_by(|a, b| b.dlq_time.cmp(&a.dlq_time).then(a.job_id.cmp(&b.job_id)));
+        entries.truncate(limit);
+        Ok(entries)
+    }
+
+    pub async fn group_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+    ) -> Result<Vec<DeadLetterGroup>, QueueError> {
+        let shards = self.shards.read().await;
+        let mut groups: HashMap<(String, i32, Option<Uuid>), DeadLetterGroup> = HashMap::new();
+        for shard in shards.iter() {
+            for group in group_dead_letters(&shard.pool, filter).await? {
+                let key = (group.reason.clone(), group.team_id, group.function_id);
+                match groups.get_mut(&key) {
+                    Some(existing) => {
+                        existing.count += group.count;
+                        existing.oldest = existing.oldest.min(group.oldest);
+                        existing.newest = existing.newest.max(group.newest);
+                    }
+                    None => {
+                        groups.insert(key, group);
+                    }
+                }
+            }
+        }
+        let mut groups: Vec<DeadLetterGroup> = groups.into_values().collect();
+        groups.sort_by(|a, b| b.count.cmp(&a.count));
+        Ok(groups)
+    }
+
+    pub async fn replay_dead_letters(
+        &self,
+        filter: &DeadLetterFilter,
+    ) -> Result<ReplayedDeadLetters, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = ReplayedDeadLetters::default();
+        for shard in shards.iter() {
+            let mut txn = shard.pool.begin().await?;
+            let res = replay_dead_letters(&mut txn, filter).await?;
+            txn.commit().await?;
+            total.replayed += res.replayed;
+            total.skipped += res.skipped;
+        }
+        Ok(total)
+    }
+
+    pub async fn purge_dead_letters(&self, filter: &DeadLetterFilter) -> Result<u64, QueueError> {
+        let shards = self.shards.read().await;
+        let mut total = 0;
+        for shard in shards.iter() {
+            let mut txn = shard.pool.begin().await?;
+            total += purge_dead_letters(&mut txn, filter).await?;
+            txn.commit().await?;
+        }
+        Ok(total)
+    }
+
+    // Set the retry policy for every job in a queue that doesn't have its own. Passing None
+    // reverts the queue to the default policy.
+    pub async fn set_retry_policy(
+        &self,
+        queue_name: &str,
+        policy: Option<RetryPolicy>,
+    ) -> Result<(), QueueError> {
+        let shards = self.shards.read().await;
+        for shard in shards.iter() {
+            set_queue_retry_policy(&shard.pool, queue_name, policy.as_ref()).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_recurring_job_paused(&self, id: Uuid, paused: bool) -> Result<(), QueueError> {
+        let shards = self.shards.read().await;
+        for shard in shards.iter() {
+            if set_recurring_job_paused(&shard.pool, id, paused).await? {
+                return Ok(());
+            }
+        }
+        Err(JobError::UnknownRecurringJobId(id).into())
+    }
 }
 
 impl Shard {
@@ -130,6 +329,7 @@ impl Shard {
         check_interval: Duration,
         should_compress_vm_state: bool,
         should_use_bulk_job_copy: bool,
+        should_notify: bool,
     ) -> Self {
         Self {
             pool,
@@ -138,13 +338,14 @@ impl Shard {
             depth_limit,
             should_compress_vm_state,
             should_use_bulk_job_copy,
+            should_notify,
         }
     }
 
     // Inserts a job, failing if the shard is at capacity
     pub async fn create_job(&self, init: JobInit) -> Result<Uuid, QueueError> {
         self.insert_guard().await?;
-        create_job(&self.pool, init, self.should_compress_vm_state).await
+        self.insert_job(init).await
     }
 
     // Inserts a vec of jobs, failing if the shard is at capacity. Note "capacity" here just
@@ -152,11 +353,7 @@ impl Shard {
     // 1000, we still insert all 1000.
     pub async fn bulk_create_jobs(&self, inits: Vec<JobInit>) -> Result<Vec<Uuid>, QueueError> {
         self.insert_guard().await?;
-        if self.should_use_bulk_job_copy {
-            bulk_create_jobs_copy(&self.pool, inits, self.should_compress_vm_state).await
-        } else {
-            bulk_create_jobs_upsert(&self.pool, inits, self.should_compress_vm_state).await
-        }
+        self.insert_jobs(inits).await
     }
 
     // Inserts a job, blocking until there's capacity (or until the timeout is reached)
@@ -175,7 +372,7 @@ impl Shard {
             }
         }
 
-        create_job(&self.pool, init, self.should_compress_vm_state).await
+        self.insert_job(init).await
     }
 
     // As above, with the same caveats about what "capacity" means
@@ -194,10 +391,65 @@ impl Shard {
             }
         }
 
-        if self.should_use_bulk_job_copy {
-            bulk_create_jobs_copy(&self.pool, inits, self.should_compress_vm_state).await
+        self.insert_jobs(inits).await
+    }
+
+    async fn insert_job(&self, init: JobInit) -> Result<Uuid, QueueError> {
+        let queues = self.queues_to_notify([&init]);
+
+        if !has_extra_rows(&init) {
+            let id = create_job(&self.pool, init, self.should_compress_vm_state).await?;
+            notify_queues(&self.pool, &queues).await?;
+            return Ok(id);
+        }
+
+        // Jobs with parents are inserted alongside their dependencies, so they can't be left waiting
+        // on nothing, and the same goes for jobs with their own retry policy
+        let mut txn = self.pool.begin().await?;
+        let inits = vec![init];
+        let resolved =
+            bulk_create_jobs_resolved(&mut *txn, inits.clone(), self.should_compress_vm_state)
+                .await?;
+        let (ids, owned) = owned_extra_rows(&resolved, &inits);
+        insert_dependencies(&mut txn, &ids, &owned).await?;
+        insert_retry_policies(&mut txn, &ids, &owned).await?;
+        notify_queues(&mut *txn, &queues).await?;
+        txn.commit().await?;
+        Ok(resolved[0].id)
+    }
+
+    async fn insert_jobs(&self, inits: Vec<JobInit>) -> Result<Vec<Uuid>, QueueError> {
+        let queues = self.queues_to_notify(&inits);
+
+        if inits.iter().any(has_extra_rows) {
+            // COPY can't be done inside a transaction we control, so batches containing jobs with
+            // parents or retry policies always take the upsert path
+            let mut txn = self.pool.begin().await?;
+            let resolved =
+                bulk_create_jobs_resolved(&mut *txn, inits.clone(), self.should_compress_vm_state)
+                    .await?;
+            let (ids, owned) = owned_extra_rows(&resolved, &inits);
+            insert_dependencies(&mut txn, &ids, &owned).await?;
+            insert_retry_policies(&mut txn, &ids, &owned).await?;
+            notify_queues(&mut *txn, &queues).await?;
+            txn.commit().await?;
+            return Ok(resolved.into_iter().map(|r| r.id).collect());
+        }
+
+        let ids = if self.should_use_bulk_job_copy {
+            bulk_create_jobs_copy(&self.pool, inits, self.should_compress_vm_state).await?
         } else {
-            bulk_create_jobs_upsert(&self.pool, inits, self.should_compress_vm_state).await
+            bulk_create_jobs_upsert(&self.pool, inits, self.should_compress_vm_state).await?
+        };
+        notify_queues(&self.pool, &queues).await?;
+        Ok(ids)
+    }
+
+    fn queues_to_notify<'a>(&self, inits: impl IntoIterator<Item = &'a JobInit>) -> Vec<String> {
+        if self.should_notify {
+            queues_to_notify(inits)
+        } else {
+            vec![]
         }
     }
 
@@ -236,3 +488,19 @@ impl Shard {
         Ok(is_full)
     }
 }
+
+// Whether a job needs rows in tables other than cyclotron_jobs, inserted in the same transaction
+fn has_extra_rows(init: &JobInit) -> bool {
+    !init.parents.is_empty() || init.retry_policy.is_some()
+}
+
+// Jobs dropped in favour of an existing job with the same idempotency key don't bring their
+// parents or retry policy along with them
+fn owned_extra_rows(resolved: &[ResolvedJob], inits: &[JobInit]) -> (Vec<Uuid>, Vec<JobInit>) {
+    resolved
+        .iter()
+        .zip(inits)
+        .filter(|(r, _)| !r.ignored)
+        .map(|(r, init)| (r.id, init.clone()))
+        .unzip()
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Weak,
+    },
+};
+
+use chrono::Duration;
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::Notify;
+use tracing::error;
+
+use crate::ops::manager::JOBS_AVAILABLE_CHANNEL;
+
+// How often the listener checks whether its worker has been dropped
+const LISTENER_CHECK_INTERVAL_MS: i64 = 1000;
+// How long we wait before trying to re-establish a failed listener connection
+const LISTENER_RECONNECT_DELAY_MS: i64 = 1000;
+
+// Fans notifications from a single LISTEN connection out to everything waiting on a queue. The
+// connection is only opened the first time something waits, so workers that only poll never
+// hold one.
+pub struct QueueNotifier {
+    pool: PgPool,
+    queues: Mutex<HashMap<String, Arc<Notify>>>,
+    started: AtomicBool,
+}
+
+impl QueueNotifier {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            queues: Default::default(),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    // Returns true if we were woken by a notification, false if we timed out. A notification
+    // that arrives while nobody is waiting is held, so the next wait returns immediately - this
+    // means a job inserted between a dequeue and a wait is never missed.
+    pub async fn wait(self: &Arc<Self>, queue: &str, timeout: Duration) -> bool {
+        if !self.started.swap(true, Ordering::SeqCst) {
+            tokio::spawn(listen_loop(self.pool.clone(), Arc::downgrade(self)));
+        }
+
+        let notify = self.queue(queue);
+        tokio::time::timeout(timeout.to_std().unwrap_or_default(), notify.notified())
+            .await
+            .is_ok()
+    }
+
+    fn queue(&self, queue: &str) -> Arc<Notify> {
+        self.queues
+            .lock()
+            .unwrap()
+            .entry(queue.to_string())
+            .or_default()
+            .clone()
+    }
+
+    fn wake(&self, queue: &str) {
+        if let Some(notify) = self.queues.lock().unwrap().get(queue) {
+            notify.notify_one();
+        }
+    }
+
+    // Used when we might have missed notifications, e.g. across a reconnect, so that everyone
+    // waiting goes back to the database to check
+    fn wake_all(&self) {
+        for notify in self.queues.lock().unwrap().values() {
+            notify.notify_one();
+        }
+    }
+}
+
+async fn listen_loop(pool: PgPool, notifier: Weak<QueueNotifier>) {
+    let check_interval = Duration::milliseconds(LISTENER_CHECK_INTERVAL_MS)
+        .to_std()
+        .unwrap();
+    let reconnect_delay = Duration::milliseconds(LISTENER_RECONNECT_DELAY_MS)
+        .to_std()
+        .unwrap();
+    let mut listener: Option<PgListener> = None;
+
+    // TRICKY - we never hold an upgraded notifier across an await point, so that dropping the
+    // worker drops the notifier, and we exit on the next check
+    while notifier.strong_count() > 0 {
+        let Some(active) = listener.as_mut() else {
+            match connect(&pool).await {
+                Ok(l) => {
+                    listener = Some(l);
+                    if let Some(n) = notifier.upgrade() {
+                        n.wake_all();
+                    }
+                }
+                Err(e) => {
+                    error!("Error connecting queue listener: {:?}", e);
+                    tokio::time::sleep(reconnect_delay).await;
+                }
+            }
+            continue;
+        };
+
+        let Ok(received) = tokio::time::timeout(check_interval, active.try_recv()).await else {
+            continue;
+        };
+
+        let Some(n) = notifier.upgrade() else {
+            break;
+        };
+        match received {
+            Ok(Some(notification)) => n.wake(notification.payload()),
+            // The connection was lost, and will be re-established on the next receive. Anything
+            // sent in the meantime is gone, so we wake everyone to go and check.
+            Ok(None) => n.wake_all(),
+            Err(e) => {
+                error!("Error receiving queue notification: {:?}", e);
+                n.wake_all();
+                listener = None;
+            }
+        }
+    }
+}
+
+async fn connect(pool: &PgPool) -> Result<PgListener, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(JOBS_AVAILABLE_CHANNEL).await?;
+    Ok(listener)
+}
@@ -0,0 +1,189 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    error::QueueError,
+    ops::{dependencies::resolve_dependencies, manager::notify_queues},
+    types::{CancelledJobs, JobFilter, JobState, ParentOutcome},
+};
+
+// Jobs that haven't started running yet. These can be cancelled by deleting them outright, and
+// reprioritized or rescheduled without affecting any worker.
+const PENDING_STATES: &[JobState] = &[JobState::Available, JobState::Paused, JobState::Waiting];
+
+// Push the WHERE clause selecting the jobs `filter` matches, out of those in `allowed` states
+fn push_filter(
+    query: &mut QueryBuilder<'_, Postgres>,
+    filter: &JobFilter,
+    allowed: &[JobState],
+) -> Result<(), QueueError> {
+    if filter.team_id.is_none() && filter.function_id.is_none() && filter.queue_name.is_none() {
+        return Err(QueueError::InvalidFilter(
+            "at least one of team_id, function_id or queue_name must be set".to_string(),
+        ));
+    }
+
+    let states: Vec<&str> = allowed
+        .iter()
+        .filter(|s| filter.states.as_ref().map_or(true, |f| f.contains(s)))
+        .map(|s| s.as_str())
+        .collect();
+
+    // An empty list of states matches nothing, which is what we want if the filter's states don't
+    // overlap with the ones the operation applies to
+    query.push(" WHERE state = ANY(");
+    query.push_bind(states);
+    query.push("::TEXT[]::JobState[])");
+
+    if let Some(team_id) = filter.team_id {
+        query.push(" AND team_id = ");
+        query.push_bind(team_id);
+    }
+    if let Some(function_id) = filter.function_id {
+        query.push(" AND function_id = ");
+        query.push_bind(function_id);
+    }
+    if let Some(queue_name) = &filter.queue_name {
+        query.push(" AND queue_name = ");
+        query.push_bind(queue_name.clone());
+    }
+    if let Some(created_before) = filter.created_before {
+        query.push(" AND created < ");
+        query.push_bind(created_before);
+    }
+    if let Some(created_after) = filter.created_after {
+        query.push(" AND created > ");
+        query.push_bind(created_after);
+    }
+
+    Ok(())
+}
+
+// Cancel every job matching the filter. Jobs that aren't running are deleted, and anything waiting
+// on them is resolved as if they'd been cancelled. Running jobs are flagged, and deleted when their
+// worker next heartbeats or flushes an update for them.
+pub async fn cancel_jobs(
+    conn: &mut PgConnection,
+    filter: &JobFilter,
+) -> Result<CancelledJobs, QueueError> {
+    let mut query = QueryBuilder::new("DELETE FROM cyclotron_jobs");
+    push_filter(&mut query, filter, PENDING_STATES)?;
+    query.push(" RETURNING id");
+    let deleted: Vec<Uuid> = query.build_query_scalar().fetch_all(&mut *conn).await?;
+
+    let cancelled = deleted.len() as u64;
+    resolve_dependencies(
+        &mut *conn,
+        deleted
+            .into_iter()
+            .map(|id| (id, ParentOutcome::Cancelled))
+            .collect(),
+    )
+    .await?;
+
+    let mut query = QueryBuilder::new("UPDATE cyclotron_jobs SET cancel_requested = TRUE");
+    push_filter(&mut query, filter, &[JobState::Running])?;
+    query.push(" AND NOT cancel_requested");
+    let res = query.build().execute(&mut *conn).await?;
+
+    Ok(CancelledJobs {
+        cancelled,
+        cancel_requested: res.rows_affected(),
+    })
+}
+
+// Delete jobs that were cancelled while running, but stopped running without their worker noticing,
+// e.g. because they stalled and were reset by the janitor. Returns the number of jobs deleted.
+pub async fn delete_cancelled_jobs(conn: &mut PgConnection) -> Result<u64, QueueError> {
+    let deleted: Vec<Uuid> = sqlx::query_scalar(
+        "DELETE FROM cyclotron_jobs WHERE cancel_requested AND state != 'running' RETURNING id",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let count = deleted.len() as u64;
+    resolve_dependencies(
+        conn,
+        deleted
+            .into_iter()
+            .map(|id| (id, ParentOutcome::Cancelled))
+            .collect(),
+    )
+    .await?;
+
+    Ok(count)
+}
+
+// Move available jobs matching the filter to paused, so workers stop picking them up
+pub async fn pause_jobs<'c, E>(executor: E, filter: &JobFilter) -> Result<u64, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let queues = transition_jobs(executor, filter, JobState::Available, JobState::Paused).await?;
+    Ok(queues.len() as u64)
+}
+
+// Move paused jobs matching the filter back to available, waking any workers waiting on their queues
+pub async fn resume_jobs(conn: &mut PgConnection, filter: &JobFilter) -> Result<u64, QueueError> {
+    let mut queues =
+        transition_jobs(&mut *conn, filter, JobState::Paused, JobState::Available).await?;
+    let resumed = queues.len() as u64;
+
+    queues.sort_unstable();
+    queues.dedup();
+    notify_queues(&mut *conn, &queues).await?;
+
+    Ok(resumed)
+}
+
+// Change the priority of matching jobs that haven't started running yet
+pub async fn reprioritize_jobs<'c, E>(
+    executor: E,
+    filter: &JobFilter,
+    priority: i16,
+) -> Result<u64, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let mut query = QueryBuilder::new("UPDATE cyclotron_jobs SET priority = ");
+    query.push_bind(priority);
+    push_filter(&mut query, filter, PENDING_STATES)?;
+    let res = query.build().execute(executor).await?;
+    Ok(res.rows_affected())
+}
+
+// Change the scheduled time of matching jobs that haven't started running yet
+pub async fn reschedule_jobs<'c, E>(
+    executor: E,
+    filter: &JobFilter,
+    scheduled: DateTime<Utc>,
+) -> Result<u64, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let mut query = QueryBuilder::new("UPDATE cyclotron_jobs SET scheduled = ");
+    query.push_bind(scheduled);
+    push_filter(&mut query, filter, PENDING_STATES)?;
+    let res = query.build().execute(executor).await?;
+    Ok(res.rows_affected())
+}
+
+// Returns the queue of every job transitioned, one entry per job
+async fn transition_jobs<'c, E>(
+    executor: E,
+    filter: &JobFilter,
+    from: JobState,
+    to: JobState,
+) -> Result<Vec<String>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let mut query = QueryBuilder::new("UPDATE cyclotron_jobs SET state = ");
+    query.push_bind(to.as_str());
+    query.push("::JobState, last_transition = NOW(), transition_count = transition_count + 1");
+    push_filter(&mut query, filter, &[from])?;
+    query.push(" RETURNING queue_name");
+    let queues = query.build_query_scalar().fetch_all(executor).await?;
+    Ok(queues)
+}
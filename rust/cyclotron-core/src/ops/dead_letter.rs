@@ -0,0 +1,235 @@
+use sqlx::{PgConnection, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    error::QueueError,
+    ops::{dependencies::resolve_dependencies, manager::notify_queues},
+    types::{
+        DeadLetterEntry, DeadLetterFilter, DeadLetterGroup, ParentOutcome, ReplayedDeadLetters,
+    },
+    DEAD_LETTER_QUEUE,
+};
+
+// Push the WHERE clause joining dead lettered jobs (aliased as `jobs`) to their metadata (aliased
+// as `dlq`), and selecting the entries `filter` matches. Metadata rows for jobs that have since
+// left the dead letter queue are ignored.
+fn push_filter(query: &mut QueryBuilder<'_, Postgres>, filter: &DeadLetterFilter) {
+    query.push(" WHERE jobs.id = dlq.job_id AND jobs.queue_name = ");
+    query.push_bind(DEAD_LETTER_QUEUE);
+
+    if let Some(job_ids) = &filter.job_ids {
+        query.push(" AND jobs.id = ANY(");
+        query.push_bind(job_ids.clone());
+        query.push(")");
+    }
+    if let Some(team_id) = filter.team_id {
+        query.push(" AND jobs.team_id = ");
+        query.push_bind(team_id);
+    }
+    if let Some(function_id) = filter.function_id {
+        query.push(" AND jobs.function_id = ");
+        query.push_bind(function_id);
+    }
+    if let Some(original_queue_name) = &filter.original_queue_name {
+        query.push(" AND dlq.original_queue_name = ");
+        query.push_bind(original_queue_name.clone());
+    }
+    if let Some(reason) = &filter.reason {
+        query.push(" AND dlq.reason = ");
+        query.push_bind(reason.clone());
+    }
+    if let Some(dlq_before) = filter.dlq_before {
+        query.push(" AND dlq.dlq_time < ");
+        query.push_bind(dlq_before);
+    }
+    if let Some(dlq_after) = filter.dlq_after {
+        query.push(" AND dlq.dlq_time > ");
+        query.push_bind(dlq_after);
+    }
+}
+
+fn require_filter(filter: &DeadLetterFilter) -> Result<(), QueueError> {
+    if filter.is_empty() {
+        return Err(QueueError::InvalidFilter(
+            "at least one field must be set to replay or purge dead letters".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// The most recently dead lettered entries matching the filter, newest first
+pub async fn list_dead_letters<'c, E>(
+    executor: E,
+    filter: &DeadLetterFilter,
+    limit: usize,
+) -> Result<Vec<DeadLetterEntry>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let mut query = QueryBuilder::new(
+        r#"
+SELECT
+    jobs.id AS job_id,
+    jobs.team_id,
+    jobs.function_id,
+    dlq.original_queue_name,
+    dlq.reason,
+    dlq.dlq_time,
+    jobs.created
+FROM cyclotron_jobs AS jobs, cyclotron_dead_letter_metadata AS dlq"#,
+    );
+    push_filter(&mut query, filter);
+    query.push(" ORDER BY dlq.dlq_time DESC, jobs.id LIMIT ");
+    query.push_bind(limit as i64);
+
+    Ok(query.build_query_as().fetch_all(executor).await?)
+}
+
+// Count the entries matching the filter by reason, team and function, largest group first
+pub async fn group_dead_letters<'c, E>(
+    executor: E,
+    filter: &DeadLetterFilter,
+) -> Result<Vec<DeadLetterGroup>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let mut query = QueryBuilder::new(
+        r#"
+SELECT
+    dlq.reason,
+    jobs.team_id,
+    jobs.function_id,
+    COUNT(*) AS count,
+    MIN(dlq.dlq_time) AS oldest,
+    MAX(dlq.dlq_time) AS newest
+FROM cyclotron_jobs AS jobs, cyclotron_dead_letter_metadata AS dlq"#,
+    );
+    push_filter(&mut query, filter);
+    query.push(" GROUP BY dlq.reason, jobs.team_id, jobs.function_id ORDER BY count DESC");
+
+    Ok(query.build_query_as().fetch_all(executor).await?)
+}
+
+// Move matching entries back to the queue they were dead lettered from, to be run again as soon as
+// possible. The job's queue bookkeeping (lock, heartbeat, janitor touches, retry attempts) is reset,
+// so it isn't immediately treated as a poison pill or out of retries again, but its data is left as
+// it was.
+//
+// A job whose idempotency key is already held by a job in the queue it'd go back to - or by
+// another job being replayed to that queue - is a duplicate of that job, so it's left in the dead
+// letter queue and counted as skipped, rather than failing the whole replay on the unique index.
+pub async fn replay_dead_letters(
+    conn: &mut PgConnection,
+    filter: &DeadLetterFilter,
+) -> Result<ReplayedDeadLetters, QueueError> {
+    require_filter(filter)?;
+
+    let mut query = QueryBuilder::new(
+        r#"
+WITH candidates AS (
+    SELECT jobs.id, jobs.idempotency_key, dlq.original_queue_name
+    FROM cyclotron_jobs AS jobs, cyclotron_dead_letter_metadata AS dlq"#,
+    );
+    push_filter(&mut query, filter);
+    query.push(
+        r#"
+),
+eligible AS (
+    SELECT DISTINCT ON (original_queue_name, COALESCE(idempotency_key, id::TEXT))
+        id,
+        original_queue_name
+    FROM candidates
+    WHERE
+        idempotency_key IS NULL
+        OR NOT EXISTS (
+            SELECT 1
+            FROM cyclotron_jobs AS live
+            WHERE
+                live.queue_name = candidates.original_queue_name
+                AND live.idempotency_key = candidates.idempotency_key
+                AND live.state IN ('available', 'running', 'paused', 'waiting')
+        )
+    ORDER BY original_queue_name, COALESCE(idempotency_key, id::TEXT), id
+),
+replayed AS (
+    UPDATE cyclotron_jobs AS jobs
+    SET
+        queue_name = eligible.original_queue_name,
+        state = 'available',
+        lock_id = NULL,
+        last_heartbeat = NULL,
+        janitor_touch_count = 0,
+        cancel_requested = FALSE,
+        scheduled = NOW(),
+        last_transition = NOW(),
+        transition_count = jobs.transition_count + 1
+    FROM eligible
+    WHERE jobs.id = eligible.id
+    RETURNING jobs.id, jobs.queue_name
+),
+attempts AS (
+    DELETE FROM cyclotron_job_attempts
+    USING replayed
+    WHERE cyclotron_job_attempts.job_id = replayed.id
+),
+metadata AS (
+    DELETE FROM cyclotron_dead_letter_metadata
+    USING replayed
+    WHERE cyclotron_dead_letter_metadata.job_id = replayed.id
+)
+SELECT
+    (SELECT COUNT(*) FROM replayed) AS replayed,
+    (SELECT COUNT(*) FROM candidates) - (SELECT COUNT(*) FROM replayed) AS skipped,
+    ARRAY(SELECT DISTINCT queue_name FROM replayed) AS queues"#,
+    );
+
+    let (replayed, skipped, queues): (i64, i64, Vec<String>) =
+        query.build_query_as().fetch_one(&mut *conn).await?;
+    notify_queues(&mut *conn, &queues).await?;
+
+    Ok(ReplayedDeadLetters {
+        replayed: replayed as u64,
+        skipped: skipped as u64,
+    })
+}
+
+// Delete matching entries outright. Anything waiting on a purged job is resolved as if it had been
+// cancelled. Returns the number of jobs purged.
+pub async fn purge_dead_letters(
+    conn: &mut PgConnection,
+    filter: &DeadLetterFilter,
+) -> Result<u64, QueueError> {
+    require_filter(filter)?;
+
+    let mut query = QueryBuilder::new(
+        r#"
+WITH purged AS (
+    DELETE FROM cyclotron_jobs AS jobs
+    USING cyclotron_dead_letter_metadata AS dlq"#,
+    );
+    push_filter(&mut query, filter);
+    query.push(
+        r#"
+    RETURNING jobs.id
+),
+metadata AS (
+    DELETE FROM cyclotron_dead_letter_metadata
+    USING purged
+    WHERE cyclotron_dead_letter_metadata.job_id = purged.id
+)
+SELECT id FROM purged"#,
+    );
+
+    let purged: Vec<Uuid> = query.build_query_scalar().fetch_all(&mut *conn).await?;
+    let count = purged.len() as u64;
+    resolve_dependencies(
+        conn,
+        purged
+            .into_iter()
+            .map(|id| (id, ParentOutcome::Cancelled))
+            .collect(),
+    )
+    .await?;
+
+    Ok(count)
+}
@@ -0,0 +1,316 @@
+use std::collections::{BTreeSet, HashSet};
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    error::QueueError,
+    ops::manager::notify_queues,
+    types::{Bytes, JobInit, JobState, ParentOutcome, ParentResult},
+};
+
+#[derive(Debug, sqlx::FromRow)]
+struct FinishedJob {
+    id: Uuid,
+    outcome: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ReleasedJob {
+    id: Uuid,
+    outcome: String,
+    queue_name: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct DependencyRow {
+    parent_id: Uuid,
+    parent_outcome: Option<String>,
+    parent_result: Option<Bytes>,
+}
+
+// Record the parents of newly created jobs. `ids` are the ids the inits were created (or resolved)
+// as. Jobs that aren't waiting are skipped - if an idempotency key resolved to a job that's
+// already running, we don't want to go adding parents to it. Parents that have already finished
+// are resolved straight away.
+//
+// The parents are locked until the caller commits, so one finishing concurrently either does so
+// before we check for finished parents, or only after our dependencies are visible to it - otherwise
+// it could resolve its children without seeing ours, leaving them waiting forever.
+pub async fn insert_dependencies(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+    inits: &[JobInit],
+) -> Result<(), QueueError> {
+    let mut job_ids = Vec::new();
+    let mut parent_ids = Vec::new();
+    let mut policies = Vec::new();
+    for (id, init) in ids.iter().zip(inits) {
+        for parent in &init.parents {
+            job_ids.push(*id);
+            parent_ids.push(*parent);
+            policies.push(init.on_parent_failure.as_str());
+        }
+    }
+
+    if job_ids.is_empty() {
+        return Ok(());
+    }
+
+    // Locked in a consistent order, so concurrent inserts sharing parents can't deadlock
+    sqlx::query("SELECT id FROM cyclotron_jobs WHERE id = ANY($1) ORDER BY id FOR SHARE")
+        .bind(&parent_ids)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(
+        r#"
+INSERT INTO cyclotron_job_dependencies (job_id, parent_id, on_parent_failure, created)
+SELECT new.job_id, new.parent_id, new.on_parent_failure, NOW()
+FROM UNNEST($1::UUID[], $2::UUID[], $3::TEXT[]) AS new(job_id, parent_id, on_parent_failure)
+JOIN cyclotron_jobs ON cyclotron_jobs.id = new.job_id AND cyclotron_jobs.state = 'waiting'
+ON CONFLICT (job_id, parent_id) DO NOTHING
+    "#,
+    )
+    .bind(&job_ids)
+    .bind(&parent_ids)
+    .bind(policies)
+    .execute(&mut *conn)
+    .await?;
+
+    let finished: Vec<FinishedJob> = sqlx::query_as(
+        r#"
+SELECT id, state::TEXT AS outcome
+FROM cyclotron_jobs
+WHERE id = ANY($1) AND state IN ('completed', 'failed')
+    "#,
+    )
+    .bind(&parent_ids)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let finished = finished
+        .into_iter()
+        .filter_map(|f| Some((f.id, f.outcome.parse().ok()?)))
+        .collect();
+
+    resolve_dependencies(conn, finished).await
+}
+
+// Record the outcome of finished jobs against their children, and release any children that are no
+// longer waiting on anything, according to their on_parent_failure policy. Children that are failed
+// or cancelled as a result are themselves resolved against their own children, and so on down the
+// graph. Workers waiting on the queues of released children are woken once the caller commits.
+pub async fn resolve_dependencies(
+    conn: &mut PgConnection,
+    mut finished: Vec<(Uuid, ParentOutcome)>,
+) -> Result<(), QueueError> {
+    // Guards against cycles, which we don't prevent on insert
+    let mut seen = HashSet::new();
+    finished.retain(|(id, _)| seen.insert(*id));
+    let mut queues = BTreeSet::new();
+
+    while !finished.is_empty() {
+        let (ids, outcomes): (Vec<Uuid>, Vec<&str>) =
+            finished.iter().map(|(id, o)| (*id, o.as_str())).unzip();
+
+        let children: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+UPDATE cyclotron_job_dependencies
+SET
+    parent_outcome = finished.outcome,
+    parent_result = parents.parameters
+FROM UNNEST($1::UUID[], $2::TEXT[]) AS finished(id, outcome)
+LEFT JOIN cyclotron_jobs parents ON parents.id = finished.id
+WHERE
+    cyclotron_job_dependencies.parent_id = finished.id
+    AND cyclotron_job_dependencies.parent_outcome IS NULL
+RETURNING cyclotron_job_dependencies.job_id
+        "#,
+        )
+        .bind(&ids)
+        .bind(&outcomes)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        if children.is_empty() {
+            break;
+        }
+
+        // Release the children that aren't waiting on any other parents. If a parent failed, the
+        // child's policy decides whether it runs, fails or is deleted outright.
+        let released: Vec<ReleasedJob> = sqlx::query_as(
+            r#"
+WITH ready AS (
+    SELECT
+        job_id,
+        BOOL_OR(parent_outcome != 'completed') AS parent_failed,
+        MIN(on_parent_failure) AS on_parent_failure
+    FROM cyclotron_job_dependencies
+    WHERE job_id = ANY($1)
+    GROUP BY job_id
+    HAVING BOOL_AND(parent_outcome IS NOT NULL)
+),
+released AS (
+    UPDATE cyclotron_jobs
+    SET
+        state = CASE
+            WHEN ready.parent_failed AND ready.on_parent_failure = 'fail' THEN 'failed'::JobState
+            ELSE 'available'::JobState
+        END,
+        last_transition = NOW(),
+        transition_count = transition_count + 1
+    FROM ready
+    WHERE
+        cyclotron_jobs.id = ready.job_id
+        AND cyclotron_jobs.state = 'waiting'
+        AND NOT (ready.parent_failed AND ready.on_parent_failure = 'cancel')
+    RETURNING cyclotron_jobs.id, cyclotron_jobs.state::TEXT AS outcome, cyclotron_jobs.queue_name
+),
+cancelled AS (
+    DELETE FROM cyclotron_jobs
+    USING ready
+    WHERE
+        cyclotron_jobs.id = ready.job_id
+        AND cyclotron_jobs.state = 'waiting'
+        AND ready.parent_failed
+        AND ready.on_parent_failure = 'cancel'
+    RETURNING cyclotron_jobs.id, 'cancelled'::TEXT AS outcome, cyclotron_jobs.queue_name
+)
+SELECT id, outcome, queue_name FROM released
+UNION ALL
+SELECT id, outcome, queue_name FROM cancelled
+        "#,
+        )
+        .bind(&children)
+        .fetch_all(&mut *conn)
+        .await?;
+
+        // Children that became available don't affect their own children until they run, but
+        // failed or cancelled ones are finished, so we carry on down the graph
+        queues.extend(
+            released
+                .iter()
+                .filter(|r| r.outcome == JobState::Available.as_str())
+                .map(|r| r.queue_name.clone()),
+        );
+
+        finished = released
+            .into_iter()
+            .filter_map(|r| match r.outcome.parse().ok()? {
+                ParentOutcome::Completed => None,
+                outcome => Some((r.id, outcome)),
+            })
+            .filter(|(id, _)| seen.insert(*id))
+            .collect();
+    }
+
+    notify_queues(conn, &queues.into_iter().collect::<Vec<_>>()).await
+}
+
+// The janitor's backstop for the dependency graph. Parents that no longer exist (or never did) without
+// having finished are resolved as missing, and waiting jobs with no parents recorded at all are
+// released. Returns the number of orphaned parents resolved.
+pub async fn resolve_orphaned_dependencies(conn: &mut PgConnection) -> Result<u64, QueueError> {
+    let missing: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+SELECT DISTINCT parent_id
+FROM cyclotron_job_dependencies
+WHERE
+    parent_outcome IS NULL
+    AND NOT EXISTS (SELECT 1 FROM cyclotron_jobs WHERE cyclotron_jobs.id = cyclotron_job_dependencies.parent_id)
+    "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let resolved = missing.len() as u64;
+    resolve_dependencies(
+        conn,
+        missing
+            .into_iter()
+            .map(|id| (id, ParentOutcome::Missing))
+            .collect(),
+    )
+    .await?;
+
+    let queues: Vec<String> = sqlx::query_scalar(
+        r#"
+WITH released AS (
+    UPDATE cyclotron_jobs
+    SET state = 'available', last_transition = NOW(), transition_count = transition_count + 1
+    WHERE
+        state = 'waiting'
+        AND NOT EXISTS (SELECT 1 FROM cyclotron_job_dependencies WHERE cyclotron_job_dependencies.job_id = cyclotron_jobs.id)
+    RETURNING queue_name
+)
+SELECT DISTINCT queue_name FROM released
+    "#,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    notify_queues(&mut *conn, &queues).await?;
+
+    Ok(resolved)
+}
+
+// Dependencies are kept around until their child is deleted, so the child can read its parents'
+// results at any point while it's running
+pub async fn delete_finished_dependencies<'c, E>(executor: E) -> Result<u64, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let res = sqlx::query(
+        r#"
+DELETE FROM cyclotron_job_dependencies
+WHERE
+    parent_outcome IS NOT NULL
+    AND NOT EXISTS (SELECT 1 FROM cyclotron_jobs WHERE cyclotron_jobs.id = cyclotron_job_dependencies.job_id)
+    "#,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+pub async fn get_parent_results<'c, E>(
+    executor: E,
+    job_id: Uuid,
+) -> Result<Vec<ParentResult>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let rows: Vec<DependencyRow> = sqlx::query_as(
+        r#"
+SELECT parent_id, parent_outcome, parent_result
+FROM cyclotron_job_dependencies
+WHERE job_id = $1
+ORDER BY created, parent_id
+    "#,
+    )
+    .bind(job_id)
+    .fetch_all(executor)
+    .await?;
+
+    // A running job's parents have all finished, so an unresolved parent is one still being waited on
+    Ok(rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(ParentResult {
+                parent_id: row.parent_id,
+                outcome: row.parent_outcome?.parse().ok()?,
+                result: row.parent_result,
+            })
+        })
+        .collect())
+}
+
+// The outcome a flushed job's children see, if the flush finished it
+pub fn finished_outcome(state: &JobState) -> Option<ParentOutcome> {
+    match state {
+        JobState::Completed => Some(ParentOutcome::Completed),
+        JobState::Failed => Some(ParentOutcome::Failed),
+        _ => None,
+    }
+}
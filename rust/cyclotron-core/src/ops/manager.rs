@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use sqlx::{postgres::PgPoolCopyExt, Pool, Postgres};
 use uuid::Uuid;
@@ -5,18 +7,179 @@ use uuid::Uuid;
 use crate::{
     error::QueueError,
     ops::compress::compress_vm_state,
-    types::{Bytes, JobInit, JobState},
+    types::{Bytes, JobInit, JobState, OnKeyConflict},
 };
 use common_metrics::inc;
 
 const ESTIMATED_RECORD_SIZE: usize = 1024;
 
+// The channel managers notify on when jobs are inserted, with the queue name as the payload
+pub const JOBS_AVAILABLE_CHANNEL: &str = "cyclotron_jobs_available";
+
 const COPY_IN_STMT: &str = r#"COPY cyclotron_jobs
     (id, team_id, function_id, created, lock_id, last_heartbeat, janitor_touch_count,
      transition_count, last_transition, queue_name, state, scheduled, priority, vm_state,
-     metadata, parameters, blob)
+     metadata, parameters, blob, idempotency_key)
+    FROM STDIN WITH (FORMAT CSV, ENCODING 'UTF8')"#;
+
+// COPY can't resolve conflicts, so batches containing idempotency keys are copied into a
+// temporary staging table, and inserted from there. We only copy in some of the columns, so the
+// staging table needs the defaults of the rest, not just their NOT NULL constraints
+const CREATE_STAGING_TABLE_STMT: &str = r#"CREATE TEMPORARY TABLE cyclotron_jobs_staging
+    (LIKE cyclotron_jobs INCLUDING DEFAULTS) ON COMMIT DROP"#;
+
+const COPY_IN_STAGING_STMT: &str = r#"COPY cyclotron_jobs_staging
+    (id, team_id, function_id, created, lock_id, last_heartbeat, janitor_touch_count,
+     transition_count, last_transition, queue_name, state, scheduled, priority, vm_state,
+     metadata, parameters, blob, idempotency_key)
     FROM STDIN WITH (FORMAT CSV, ENCODING 'UTF8')"#;
 
+const STAGING_SOURCE: &str = r#"
+SELECT staging.*, policies.on_key_conflict
+FROM cyclotron_jobs_staging staging
+JOIN UNNEST($1::UUID[], $2::TEXT[]) AS policies(id, on_key_conflict) USING (id)
+"#;
+
+const UNNEST_SOURCE: &str = r#"
+SELECT *
+FROM UNNEST(
+        $1,
+        $2,
+        $3,
+        $4,
+        $5,
+        $6,
+        $7,
+        $8,
+        $9,
+        $10,
+        $11,
+        $12,
+        $13,
+        $14,
+        $15,
+        $16,
+        $17,
+        $18,
+        $19
+    ) AS input(
+        id,
+        team_id,
+        function_id,
+        created,
+        lock_id,
+        last_heartbeat,
+        janitor_touch_count,
+        transition_count,
+        last_transition,
+        queue_name,
+        state,
+        scheduled,
+        priority,
+        vm_state,
+        metadata,
+        parameters,
+        blob,
+        idempotency_key,
+        on_key_conflict
+    )
+"#;
+
+// Inserts jobs from `{source}`, skipping any whose idempotency key is held by a non-terminal job in
+// the same queue, and resolving those conflicts according to each job's policy. Returns the id
+// of the job each input row resolved to - the row itself if it was inserted, or the conflicting
+// job. The predicate must match that of the unique index on idempotency keys.
+//
+// The conflicting job is read from the statement's snapshot, so if it was committed after the
+// statement started, the input row is skipped without resolving to anything.
+const KEYED_INSERT_STMT: &str = r#"
+WITH input AS ({source}),
+inserted AS (
+    INSERT INTO cyclotron_jobs
+        (
+            id,
+            team_id,
+            function_id,
+            created,
+            lock_id,
+            last_heartbeat,
+            janitor_touch_count,
+            transition_count,
+            last_transition,
+            queue_name,
+            state,
+            scheduled,
+            priority,
+            vm_state,
+            metadata,
+            parameters,
+            blob,
+            idempotency_key
+        )
+    SELECT
+        id,
+        team_id,
+        function_id,
+        created,
+        lock_id,
+        last_heartbeat,
+        janitor_touch_count,
+        transition_count,
+        last_transition,
+        queue_name,
+        state,
+        scheduled,
+        priority,
+        vm_state,
+        metadata,
+        parameters,
+        blob,
+        idempotency_key
+    FROM input
+    ON CONFLICT (queue_name, idempotency_key)
+        WHERE idempotency_key IS NOT NULL
+        AND state IN ('available', 'running', 'paused', 'waiting')
+        AND queue_name != '_cyclotron_dead_letter'
+    DO NOTHING
+    RETURNING id
+),
+conflicted AS (
+    SELECT
+        input.id AS input_id,
+        existing.id AS existing_id,
+        input.on_key_conflict,
+        input.parameters,
+        input.blob
+    FROM input
+    JOIN cyclotron_jobs existing
+        ON existing.queue_name = input.queue_name
+        AND existing.idempotency_key = input.idempotency_key
+        AND existing.state IN ('available', 'running', 'paused', 'waiting')
+    WHERE input.id NOT IN (SELECT id FROM inserted)
+),
+replaced AS (
+    UPDATE cyclotron_jobs
+    SET
+        parameters = conflicted.parameters,
+        blob = conflicted.blob
+    FROM conflicted
+    WHERE
+        cyclotron_jobs.id = conflicted.existing_id
+        AND cyclotron_jobs.state = 'available'
+        AND conflicted.on_key_conflict = 'replace'
+    RETURNING cyclotron_jobs.id
+)
+SELECT id AS input_id, id AS job_id FROM inserted
+UNION ALL
+SELECT input_id, existing_id AS job_id FROM conflicted
+"#;
+
+#[derive(Debug, sqlx::FromRow)]
+struct KeyedInsertResult {
+    input_id: Uuid,
+    job_id: Uuid,
+}
+
 pub async fn create_job<'c, E>(
     executor: E,
     mut data: JobInit,
@@ -25,6 +188,11 @@ pub async fn create_job<'c, E>(
 where
     E: sqlx::Executor<'c, Database = sqlx::Postgres>,
 {
+    if data.idempotency_key.is_some() {
+        let ids = bulk_create_jobs_upsert(executor, vec![data], should_compress_vm_state).await?;
+        return Ok(ids[0]);
+    }
+
     let id = Uuid::now_v7();
 
     if should_compress_vm_state {
@@ -60,7 +228,7 @@ VALUES
         data.team_id,
         data.function_id,
         data.queue_name,
-        JobState::Available as _,
+        initial_state(&data) as _,
         data.scheduled,
         data.priority,
         data.vm_state,
@@ -79,10 +247,25 @@ pub async fn bulk_create_jobs_upsert<'c, E>(
     jobs: Vec<JobInit>,
     should_compress_vm_state: bool,
 ) -> Result<Vec<Uuid>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let resolved = bulk_create_jobs_resolved(executor, jobs, should_compress_vm_state).await?;
+    Ok(resolved.into_iter().map(|r| r.id).collect())
+}
+
+// As above, but also reporting which of the jobs were dropped in favour of an existing job, so
+// callers inserting parents or retry policies alongside the jobs know to leave those out
+pub async fn bulk_create_jobs_resolved<'c, E>(
+    executor: E,
+    jobs: Vec<JobInit>,
+    should_compress_vm_state: bool,
+) -> Result<Vec<ResolvedJob>, QueueError>
 where
     E: sqlx::Executor<'c, Database = sqlx::Postgres>,
 {
     let now = Utc::now();
+    let (jobs, slots) = dedupe_keys(jobs);
     // Flatten these jobs into a series of vecs of arguments PG can unnest
     let mut ids = Vec::with_capacity(jobs.len());
     let mut team_ids = Vec::with_capacity(jobs.len());
@@ -101,6 +284,9 @@ where
     let mut metadatas = Vec::with_capacity(jobs.len());
     let mut parameters = Vec::with_capacity(jobs.len());
     let mut blob = Vec::with_capacity(jobs.len());
+    let mut idempotency_keys = Vec::with_capacity(jobs.len());
+    let mut on_key_conflicts = Vec::with_capacity(jobs.len());
+    let policies: Vec<OnKeyConflict> = jobs.iter().map(|j| j.on_key_conflict).collect();
 
     for d in &jobs {
         let vm_state = d.vm_state.clone();
@@ -120,12 +306,42 @@ where
         transition_counts.push(0);
         last_transitions.push(now);
         queue_names.push(d.queue_name.clone());
-        states.push(JobState::Available);
+        states.push(initial_state(d));
         scheduleds.push(d.scheduled);
         priorities.push(d.priority);
         metadatas.push(d.metadata.clone());
         parameters.push(d.parameters.clone());
         blob.push(d.blob.clone());
+        idempotency_keys.push(d.idempotency_key.clone());
+        on_key_conflicts.push(d.on_key_conflict.as_str());
+    }
+
+    if idempotency_keys.iter().any(Option::is_some) {
+        let results: Vec<KeyedInsertResult> =
+            sqlx::query_as(&KEYED_INSERT_STMT.replace("{source}", UNNEST_SOURCE))
+                .bind(&ids)
+                .bind(team_ids)
+                .bind(function_ids)
+                .bind(created_at)
+                .bind(lock_ids)
+                .bind(last_heartbeats)
+                .bind(janitor_touch_counts)
+                .bind(transition_counts)
+                .bind(last_transitions)
+                .bind(queue_names)
+                .bind(states)
+                .bind(scheduleds)
+                .bind(priorities)
+                .bind(vm_states)
+                .bind(metadatas)
+                .bind(parameters)
+                .bind(blob)
+                .bind(idempotency_keys)
+                .bind(on_key_conflicts)
+                .fetch_all(executor)
+                .await?;
+
+        return resolve_keyed_ids(&ids, &policies, results, &slots);
     }
 
     // Using the "unnest" function to turn an array of rows into a set of rows
@@ -193,7 +409,13 @@ FROM UNNEST(
     .execute(executor)
     .await?;
 
-    Ok(ids)
+    Ok(slots
+        .iter()
+        .map(|slot| ResolvedJob {
+            id: ids[slot.index],
+            ignored: false,
+        })
+        .collect())
 }
 
 // wraps CSV rows to be encoded and batch written
@@ -217,6 +439,7 @@ struct CopyFromJobInit {
     metadata: Option<String>,
     parameters: Option<String>,
     blob: Option<String>,
+    idempotency_key: Option<String>,
 }
 
 pub async fn bulk_create_jobs_copy(
@@ -224,7 +447,11 @@ pub async fn bulk_create_jobs_copy(
     jobs: Vec<JobInit>,
     should_compress_vm_state: bool,
 ) -> Result<Vec<Uuid>, QueueError> {
+    let (jobs, slots) = dedupe_keys(jobs);
     let mut ids = Vec::with_capacity(jobs.len());
+    let mut on_key_conflicts = Vec::with_capacity(jobs.len());
+    let mut policies = Vec::with_capacity(jobs.len());
+    let keyed = jobs.iter().any(|j| j.idempotency_key.is_some());
     let now = Utc::now();
 
     // set up CSV in mem buffer for capturing the row data; try to
@@ -239,6 +466,9 @@ pub async fn bulk_create_jobs_copy(
     for j in jobs {
         let new_id = Uuid::now_v7();
         ids.push(new_id);
+        on_key_conflicts.push(j.on_key_conflict.as_str());
+        policies.push(j.on_key_conflict);
+        let state = initial_state(&j);
 
         let mut vm_state = j.vm_state;
         if should_compress_vm_state {
@@ -256,13 +486,14 @@ pub async fn bulk_create_jobs_copy(
             transition_count: 0,
             last_transition: now,
             queue_name: j.queue_name,
-            job_state: JobState::Available,
+            job_state: state,
             scheduled: j.scheduled,
             priority: j.priority,
             vm_state: encode_pg_bytea(vm_state),
             metadata: encode_pg_bytea(j.metadata),
             parameters: encode_pg_bytea(j.parameters),
             blob: encode_pg_bytea(j.blob),
+            idempotency_key: j.idempotency_key,
         };
 
         csv_writer
@@ -274,6 +505,36 @@ pub async fn bulk_create_jobs_copy(
         .flush()
         .map_err(|e| QueueError::CsvError("csv_flush", e.into()))?;
 
+    if keyed {
+        let mut txn = pool.begin().await?;
+        sqlx::query(CREATE_STAGING_TABLE_STMT)
+            .execute(&mut *txn)
+            .await?;
+
+        let mut stream = txn.copy_in_raw(COPY_IN_STAGING_STMT).await?;
+        let result = stream.send(&csv_writer.get_ref()[..]).await;
+        if let Err(e) = result {
+            let _unused = stream
+                .abort(format!("failed to send COPY IN record: {}", e))
+                .await;
+            return Err(QueueError::SqlxError(e));
+        }
+        stream.finish().await.map_err(QueueError::SqlxError)?;
+
+        let results: Vec<KeyedInsertResult> =
+            sqlx::query_as(&KEYED_INSERT_STMT.replace("{source}", STAGING_SOURCE))
+                .bind(&ids)
+                .bind(on_key_conflicts)
+                .fetch_all(&mut *txn)
+                .await?;
+        txn.commit().await?;
+
+        let rows_affected = results.iter().filter(|r| r.input_id == r.job_id).count() as u64;
+        inc("bulk_create_jobs_copy_rows_affected", &[], rows_affected);
+        let resolved = resolve_keyed_ids(&ids, &policies, results, &slots)?;
+        return Ok(resolved.into_iter().map(|r| r.id).collect());
+    }
+
     let mut stream = pool.copy_in_raw(COPY_IN_STMT).await?;
     let result = stream.send(&csv_writer.get_ref()[..]).await;
     if let Err(e) = result {
@@ -287,7 +548,138 @@ pub async fn bulk_create_jobs_copy(
     let rows_affected = stream.finish().await.map_err(QueueError::SqlxError)?;
     inc("bulk_create_jobs_copy_rows_affected", &[], rows_affected);
 
-    Ok(ids)
+    Ok(slots.iter().map(|slot| ids[slot.index]).collect())
+}
+
+// Where a job passed to a bulk insert ended up - the index of the job it was inserted as, and
+// whether it was dropped in favour of that job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Slot {
+    index: usize,
+    ignored: bool,
+}
+
+// The job a job passed to a bulk insert resolved to. Jobs that were ignored because of an
+// idempotency key conflict resolve to the job they conflicted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedJob {
+    pub id: Uuid,
+    pub ignored: bool,
+}
+
+// Jobs repeating an idempotency key already used earlier in the same batch are folded into that
+// earlier job before we hit the DB, as if it had already been inserted - postgres would otherwise
+// silently skip them, without letting us resolve them against it.
+fn dedupe_keys(jobs: Vec<JobInit>) -> (Vec<JobInit>, Vec<Slot>) {
+    let mut deduped: Vec<JobInit> = Vec::with_capacity(jobs.len());
+    let mut slots = Vec::with_capacity(jobs.len());
+    let mut seen: HashMap<(String, String), usize> = HashMap::new();
+
+    for job in jobs {
+        let Some(key) = job.idempotency_key.clone() else {
+            slots.push(Slot {
+                index: deduped.len(),
+                ignored: false,
+            });
+            deduped.push(job);
+            continue;
+        };
+
+        match seen.get(&(job.queue_name.clone(), key.clone())) {
+            Some(&index) => {
+                if job.on_key_conflict == OnKeyConflict::Replace {
+                    deduped[index].parameters = job.parameters;
+                    deduped[index].blob = job.blob;
+                }
+                slots.push(Slot {
+                    index,
+                    ignored: job.on_key_conflict == OnKeyConflict::Ignore,
+                });
+            }
+            None => {
+                seen.insert((job.queue_name.clone(), key), deduped.len());
+                slots.push(Slot {
+                    index: deduped.len(),
+                    ignored: false,
+                });
+                deduped.push(job);
+            }
+        }
+    }
+
+    (deduped, slots)
+}
+
+// Map the results of a keyed insert back onto the jobs passed in, in order. A job that wasn't
+// inserted, and didn't resolve to an existing job either, lost a race with a concurrent insert
+// of the same key, and the caller has to try again.
+fn resolve_keyed_ids(
+    ids: &[Uuid],
+    policies: &[OnKeyConflict],
+    results: Vec<KeyedInsertResult>,
+    slots: &[Slot],
+) -> Result<Vec<ResolvedJob>, QueueError> {
+    let resolved: HashMap<Uuid, Uuid> = results
+        .into_iter()
+        .map(|r| (r.input_id, r.job_id))
+        .collect();
+
+    slots
+        .iter()
+        .map(|slot| {
+            let input_id = ids[slot.index];
+            let Some(&id) = resolved.get(&input_id) else {
+                return Err(QueueError::ConcurrentKeyConflict);
+            };
+            let conflicted = id != input_id && policies[slot.index] == OnKeyConflict::Ignore;
+            Ok(ResolvedJob {
+                id,
+                ignored: slot.ignored || conflicted,
+            })
+        })
+        .collect()
+}
+
+// Wakes any workers waiting on the given queues. Notifications sent in a transaction are only
+// delivered once it commits, and postgres collapses duplicates within a transaction.
+pub async fn notify_queues<'c, E>(executor: E, queues: &[String]) -> Result<(), QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    if queues.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query("SELECT pg_notify($1, queue) FROM UNNEST($2::TEXT[]) AS queue")
+        .bind(JOBS_AVAILABLE_CHANNEL)
+        .bind(queues)
+        .execute(executor)
+        .await?;
+
+    Ok(())
+}
+
+// The distinct queues that have a job in the batch that can be dequeued right away - there's
+// no point waking workers for jobs that are scheduled in the future, or waiting on parents
+pub fn queues_to_notify<'a>(inits: impl IntoIterator<Item = &'a JobInit>) -> Vec<String> {
+    let now = Utc::now();
+    let mut queues: Vec<String> = inits
+        .into_iter()
+        .filter(|init| initial_state(init) == JobState::Available && init.scheduled <= now)
+        .map(|init| init.queue_name.clone())
+        .collect();
+    queues.sort_unstable();
+    queues.dedup();
+    queues
+}
+
+// Jobs with parents wait for them to finish before becoming available
+fn initial_state(init: &JobInit) -> JobState {
+    if init.parents.is_empty() {
+        JobState::Available
+    } else {
+        JobState::Waiting
+    }
 }
 
 // COPY FROM STDIN with CSV input method must encode BYTEA binary blobs
@@ -3,6 +3,8 @@ use uuid::Uuid;
 
 use crate::{
     error::{JobError, QueueError},
+    ops::dependencies::resolve_dependencies,
+    types::ParentOutcome,
     DEAD_LETTER_QUEUE,
 };
 
@@ -48,10 +50,10 @@ pub async fn run_migrations(pool: &PgPool) {
 /// Move a job into the dead letter queue, also updating the metadata table. Note that this operation does not
 /// require a lock on the job. This is because the janitor needs to DLQ jobs that are stalled. The worker wrapper
 /// around this operation should check that the job is "known" (owned by it) before calling this function.
-pub async fn dead_letter<'c, E>(executor: E, job: Uuid, reason: &str) -> Result<(), QueueError>
-where
-    E: sqlx::Executor<'c, Database = sqlx::Postgres> + Clone,
-{
+/// Any children waiting on the job are resolved as if it had failed.
+pub async fn dead_letter(pool: &PgPool, job: Uuid, reason: &str) -> Result<(), QueueError> {
+    let mut txn = pool.begin().await?;
+
     // The first thing we do here is forcefully take the lock on this job, ensuring any subsequent worker
     // operations will fail - we do this because the janitor can move jobs out from under workers. We mark
     // the job as "running" and heartbeat so nothing else messes with it.
@@ -61,7 +63,7 @@ where
         lock,
         job
     )
-    .fetch_optional(executor.clone())
+    .fetch_optional(&mut *txn)
     .await?;
 
     let Some(original_queue_name) = original_queue_name else {
@@ -74,7 +76,7 @@ where
         job,
         original_queue_name,
         reason
-    ).execute(executor.clone()).await?;
+    ).execute(&mut *txn).await?;
 
     // And finally, we move the job to the dead letter queue. Jobs in the DLQ are "available", because if they ever
     // get moved back to a queue, they should be re-run.
@@ -83,8 +85,12 @@ where
         DEAD_LETTER_QUEUE,
         job
     )
-    .execute(executor)
+    .execute(&mut *txn)
     .await?;
 
+    // A dead lettered job won't finish unless it's replayed, so its children can't wait on it
+    resolve_dependencies(&mut txn, vec![(job, ParentOutcome::Failed)]).await?;
+    txn.commit().await?;
+
     Ok(())
 }
@@ -1,5 +1,10 @@
+pub mod bulk;
 pub mod compress;
+pub mod dead_letter;
+pub mod dependencies;
 pub mod janitor;
 pub mod manager;
 pub mod meta;
+pub mod recurring;
+pub mod retry;
 pub mod worker;
@@ -0,0 +1,249 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    error::QueueError,
+    ops::manager::{create_job, notify_queues, queues_to_notify},
+    schedule::Schedule,
+    types::{Bytes, CatchUpPolicy, JobInit, RecurringJobInit, RecurringSchedule},
+};
+
+// If a recurring job with an "all" catch up policy has fallen further behind than this, we only
+// enqueue the most recent occurrences
+const MAX_CATCH_UP_OCCURRENCES: usize = 100;
+
+#[derive(Debug, sqlx::FromRow)]
+struct DueRecurringJob {
+    id: Uuid,
+    team_id: i32,
+    function_id: Option<Uuid>,
+    queue_name: String,
+    priority: i16,
+    vm_state: Option<Bytes>,
+    metadata: Option<Bytes>,
+    parameters: Option<Bytes>,
+    blob: Option<Bytes>,
+    cron_expression: Option<String>,
+    interval_seconds: Option<i64>,
+    timezone: String,
+    catch_up_policy: String,
+    next_run: DateTime<Utc>,
+}
+
+pub async fn create_recurring_job<'c, E>(
+    executor: E,
+    init: RecurringJobInit,
+) -> Result<Uuid, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let schedule = Schedule::new(&init.schedule, init.timezone.as_deref())?;
+    let Some(next_run) = schedule.first_at_or_after(init.template.scheduled) else {
+        return Err(QueueError::InvalidSchedule(
+            "schedule has no upcoming occurrences".to_string(),
+        ));
+    };
+
+    let (cron_expression, interval_seconds) = match &init.schedule {
+        RecurringSchedule::Cron(expression) => (Some(expression.clone()), None),
+        RecurringSchedule::IntervalSeconds(seconds) => (None, Some(*seconds as i64)),
+    };
+
+    let id = Uuid::now_v7();
+    let template = init.template;
+    sqlx::query(
+        r#"
+INSERT INTO cyclotron_recurring_jobs
+    (
+        id,
+        created,
+        team_id,
+        function_id,
+        queue_name,
+        priority,
+        vm_state,
+        metadata,
+        parameters,
+        blob,
+        cron_expression,
+        interval_seconds,
+        timezone,
+        catch_up_policy,
+        paused,
+        next_run,
+        last_run
+    )
+VALUES
+    ($1, NOW(), $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, FALSE, $14, NULL)
+    "#,
+    )
+    .bind(id)
+    .bind(template.team_id)
+    .bind(template.function_id)
+    .bind(template.queue_name)
+    .bind(template.priority)
+    .bind(template.vm_state)
+    .bind(template.metadata)
+    .bind(template.parameters)
+    .bind(template.blob)
+    .bind(cron_expression)
+    .bind(interval_seconds)
+    .bind(init.timezone.unwrap_or_else(|| "UTC".to_string()))
+    .bind(init.catch_up.as_str())
+    .bind(next_run)
+    .execute(executor)
+    .await?;
+
+    Ok(id)
+}
+
+// Returns false if there's no recurring job with this id
+pub async fn set_recurring_job_paused<'c, E>(
+    executor: E,
+    id: Uuid,
+    paused: bool,
+) -> Result<bool, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let res = sqlx::query("UPDATE cyclotron_recurring_jobs SET paused = $2 WHERE id = $1")
+        .bind(id)
+        .bind(paused)
+        .execute(executor)
+        .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+// Returns false if there's no recurring job with this id. Instances already enqueued are left alone.
+pub async fn delete_recurring_job<'c, E>(executor: E, id: Uuid) -> Result<bool, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let res = sqlx::query("DELETE FROM cyclotron_recurring_jobs WHERE id = $1")
+        .bind(id)
+        .execute(executor)
+        .await?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+// Enqueue instances for up to `max` due recurring jobs, advancing each one's next run. Recurring
+// jobs are locked for the duration, so concurrent schedulers skip over each other's work rather
+// than enqueueing an occurrence twice. Returns the number of instances enqueued.
+pub async fn schedule_recurring_jobs(
+    pool: &PgPool,
+    max: usize,
+    should_compress_vm_state: bool,
+) -> Result<u64, QueueError> {
+    let now = Utc::now();
+    let mut txn = pool.begin().await?;
+
+    let due: Vec<DueRecurringJob> = sqlx::query_as(
+        r#"
+SELECT
+    id,
+    team_id,
+    function_id,
+    queue_name,
+    priority,
+    vm_state,
+    metadata,
+    parameters,
+    blob,
+    cron_expression,
+    interval_seconds,
+    timezone,
+    catch_up_policy,
+    next_run
+FROM cyclotron_recurring_jobs
+WHERE
+    NOT paused
+    AND next_run <= $1
+ORDER BY next_run ASC
+LIMIT $2
+FOR UPDATE SKIP LOCKED
+    "#,
+    )
+    .bind(now)
+    .bind(max as i64)
+    .fetch_all(&mut *txn)
+    .await?;
+
+    let mut enqueued = Vec::new();
+    for recurring in due {
+        let schedule = match recurring.schedule() {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                // This was validated on creation, so someone's been editing the table by hand. We pause
+                // the recurring job rather than failing every scheduler run from now on.
+                error!(
+                    "Pausing recurring job {} with invalid schedule: {}",
+                    recurring.id, e
+                );
+                set_recurring_job_paused(&mut *txn, recurring.id, true).await?;
+                continue;
+            }
+        };
+        let policy: CatchUpPolicy = recurring.catch_up_policy.parse().unwrap_or_default();
+
+        let (occurrences, next_run) =
+            schedule.due_occurrences(recurring.next_run, now, policy, MAX_CATCH_UP_OCCURRENCES);
+
+        for scheduled in occurrences.iter() {
+            let instance = recurring.instance(*scheduled);
+            create_job(&mut *txn, instance.clone(), should_compress_vm_state).await?;
+            enqueued.push(instance);
+        }
+
+        sqlx::query(
+            "UPDATE cyclotron_recurring_jobs SET next_run = $2, last_run = COALESCE($3, last_run) WHERE id = $1",
+        )
+        .bind(recurring.id)
+        .bind(next_run)
+        .bind(occurrences.last())
+        .execute(&mut *txn)
+        .await?;
+    }
+
+    notify_queues(&mut *txn, &queues_to_notify(&enqueued)).await?;
+    txn.commit().await?;
+
+    Ok(enqueued.len() as u64)
+}
+
+impl DueRecurringJob {
+    fn schedule(&self) -> Result<Schedule, QueueError> {
+        let schedule = match (&self.cron_expression, self.interval_seconds) {
+            (Some(expression), _) => RecurringSchedule::Cron(expression.clone()),
+            (None, Some(seconds)) => RecurringSchedule::IntervalSeconds(seconds.max(0) as u64),
+            (None, None) => {
+                return Err(QueueError::InvalidSchedule(
+                    "no cron expression or interval".to_string(),
+                ))
+            }
+        };
+        Schedule::new(&schedule, Some(&self.timezone))
+    }
+
+    fn instance(&self, scheduled: DateTime<Utc>) -> JobInit {
+        JobInit {
+            team_id: self.team_id,
+            queue_name: self.queue_name.clone(),
+            priority: self.priority,
+            scheduled,
+            function_id: self.function_id,
+            vm_state: self.vm_state.clone(),
+            parameters: self.parameters.clone(),
+            blob: self.blob.clone(),
+            metadata: self.metadata.clone(),
+            idempotency_key: None,
+            on_key_conflict: Default::default(),
+            parents: vec![],
+            on_parent_failure: Default::default(),
+            retry_policy: None,
+        }
+    }
+}
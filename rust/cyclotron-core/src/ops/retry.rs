@@ -0,0 +1,157 @@
+use chrono::Utc;
+use sqlx::{types::Json, PgConnection};
+use uuid::Uuid;
+
+use crate::{
+    error::{JobError, QueueError},
+    types::{Attempt, AttemptError, JobInit, RetryPolicy},
+};
+
+#[derive(Debug, sqlx::FromRow)]
+struct RecordedAttempt {
+    policy: Option<Json<RetryPolicy>>,
+    attempt: i32,
+}
+
+// Record the retry policies of newly created jobs that have their own. Ids that don't belong to a
+// job (e.g. because an idempotency key conflict meant the job was ignored) are skipped, and a job
+// that already has a policy keeps it.
+pub async fn insert_retry_policies(
+    conn: &mut PgConnection,
+    ids: &[Uuid],
+    inits: &[JobInit],
+) -> Result<(), QueueError> {
+    let mut job_ids = Vec::new();
+    let mut policies = Vec::new();
+    for (id, init) in ids.iter().zip(inits) {
+        if let Some(policy) = &init.retry_policy {
+            job_ids.push(*id);
+            policies.push(Json(policy.clone()));
+        }
+    }
+
+    if job_ids.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r#"
+INSERT INTO cyclotron_job_retry_policies (job_id, policy)
+SELECT new.job_id, new.policy
+FROM UNNEST($1::UUID[], $2::JSONB[]) AS new(job_id, policy)
+JOIN cyclotron_jobs ON cyclotron_jobs.id = new.job_id
+ON CONFLICT (job_id) DO NOTHING
+    "#,
+    )
+    .bind(job_ids)
+    .bind(policies)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+// Passing None removes the queue's policy
+pub async fn set_queue_retry_policy<'c, E>(
+    executor: E,
+    queue_name: &str,
+    policy: Option<&RetryPolicy>,
+) -> Result<(), QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    match policy {
+        Some(policy) => {
+            sqlx::query(
+                r#"
+INSERT INTO cyclotron_queue_retry_policies (queue_name, policy, updated)
+VALUES ($1, $2, NOW())
+ON CONFLICT (queue_name) DO UPDATE SET policy = EXCLUDED.policy, updated = EXCLUDED.updated
+            "#,
+            )
+            .bind(queue_name)
+            .bind(Json(policy))
+            .execute(executor)
+            .await?;
+        }
+        None => {
+            sqlx::query("DELETE FROM cyclotron_queue_retry_policies WHERE queue_name = $1")
+                .bind(queue_name)
+                .execute(executor)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+// Record a failed attempt at a job, returning the policy that applies to it and the number of
+// attempts made so far, including this one. Returns an InvalidLock error if the job isn't locked
+// by `lock_id`.
+pub async fn record_attempt<'c, E>(
+    executor: E,
+    job_id: Uuid,
+    lock_id: Uuid,
+    error: &AttemptError,
+) -> Result<(RetryPolicy, u32), QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let recorded: Option<RecordedAttempt> = sqlx::query_as(
+        r#"
+WITH job AS (
+    SELECT
+        jobs.id,
+        COALESCE(job_policy.policy, queue_policy.policy) AS policy
+    FROM cyclotron_jobs AS jobs
+    LEFT JOIN cyclotron_job_retry_policies AS job_policy ON job_policy.job_id = jobs.id
+    LEFT JOIN cyclotron_queue_retry_policies AS queue_policy ON queue_policy.queue_name = jobs.queue_name
+    WHERE jobs.id = $1 AND jobs.lock_id = $2
+),
+recorded AS (
+    INSERT INTO cyclotron_job_attempts (job_id, attempt, error_class, message, failed_at)
+    SELECT
+        job.id,
+        COALESCE((SELECT MAX(attempt) FROM cyclotron_job_attempts WHERE job_id = $1), 0) + 1,
+        $3,
+        $4,
+        $5
+    FROM job
+    RETURNING attempt
+)
+SELECT job.policy, recorded.attempt
+FROM job, recorded
+    "#,
+    )
+    .bind(job_id)
+    .bind(lock_id)
+    .bind(&error.class)
+    .bind(&error.message)
+    .bind(Utc::now())
+    .fetch_optional(executor)
+    .await?;
+
+    let Some(recorded) = recorded else {
+        return Err(JobError::InvalidLock(lock_id, job_id).into());
+    };
+
+    let policy = recorded.policy.map(|p| p.0).unwrap_or_default();
+    Ok((policy, recorded.attempt.max(0) as u32))
+}
+
+pub async fn get_attempts<'c, E>(executor: E, job_id: Uuid) -> Result<Vec<Attempt>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    Ok(sqlx::query_as(
+        r#"
+SELECT attempt, error_class, message, failed_at
+FROM cyclotron_job_attempts
+WHERE job_id = $1
+ORDER BY attempt
+    "#,
+    )
+    .bind(job_id)
+    .fetch_all(executor)
+    .await?)
+}
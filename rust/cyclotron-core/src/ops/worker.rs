@@ -1,9 +1,12 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use sqlx::{postgres::PgArguments, query::Query, Encode, QueryBuilder, Type};
 use uuid::Uuid;
 
 use crate::{
-    error::QueueError,
+    config::FairShareConfig,
+    error::{JobError, QueueError},
     ops::compress::decompress_vm_state,
     types::{Bytes, Job, JobState, JobUpdate},
 };
@@ -149,6 +152,272 @@ RETURNING
     Ok(out)
 }
 
+// Dequeue a batch of jobs, shared out across the teams with available jobs according to their
+// weights, and limited by their concurrency limits. Teams that have been waiting longest get
+// first pick when the batch is too small to give every team a job. If a team has fewer jobs
+// available than its share, the slots it can't use go to the other teams. Concurrency limits are checked against running jobs before dequeueing, so concurrent
+// workers can briefly exceed a limit by up to one batch each.
+pub async fn dequeue_jobs_fair<'c, E>(
+    executor: E,
+    queue: &str,
+    max: usize,
+    config: &FairShareConfig,
+    with_vm_state: bool,
+) -> Result<Vec<Job>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres> + Clone,
+{
+    let teams = teams_with_available_jobs(executor.clone(), queue, max).await?;
+    if teams.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let running = if config.has_concurrency_limits() {
+        let team_ids: Vec<i32> = teams.iter().map(|(team_id, _)| *team_id).collect();
+        running_jobs_per_team(executor.clone(), queue, &team_ids).await?
+    } else {
+        HashMap::new()
+    };
+
+    let candidates: Vec<TeamShare> = teams
+        .into_iter()
+        .map(|(team_id, available)| TeamShare {
+            team_id,
+            weight: config.weight(team_id),
+            available,
+            headroom: config
+                .concurrency_limit(team_id)
+                .map(|limit| limit.saturating_sub(running.get(&team_id).copied().unwrap_or(0))),
+        })
+        .collect();
+
+    let (team_ids, quotas): (Vec<i32>, Vec<i64>) = fair_shares(&candidates, max)
+        .into_iter()
+        .filter(|(_, quota)| *quota > 0)
+        .map(|(team_id, quota)| (team_id, quota as i64))
+        .unzip();
+
+    if team_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let vm_state_column = if with_vm_state {
+        "cyclotron_jobs.vm_state"
+    } else {
+        "NULL::bytea as vm_state"
+    };
+
+    // Transient lock id, see dequeue_jobs
+    let lock_id = Uuid::now_v7();
+    let query = format!(
+        r#"
+WITH quotas AS (
+    SELECT team_id, quota FROM UNNEST($2::INT[], $3::BIGINT[]) AS q(team_id, quota)
+),
+available AS (
+    SELECT
+        candidates.id,
+        candidates.state
+    FROM quotas
+    CROSS JOIN LATERAL (
+        SELECT
+            id,
+            state
+        FROM cyclotron_jobs
+        WHERE
+            state = 'available'::JobState
+            AND queue_name = $1
+            AND team_id = quotas.team_id
+            AND scheduled <= NOW()
+        ORDER BY
+            priority ASC,
+            scheduled ASC
+        LIMIT quotas.quota
+        FOR UPDATE SKIP LOCKED
+    ) candidates
+)
+UPDATE cyclotron_jobs
+SET
+    state = 'running'::JobState,
+    lock_id = $4,
+    last_heartbeat = NOW(),
+    last_transition = NOW(),
+    transition_count = transition_count + 1
+FROM available
+WHERE
+    cyclotron_jobs.id = available.id
+RETURNING
+    cyclotron_jobs.id,
+    team_id,
+    available.state,
+    queue_name,
+    priority,
+    function_id,
+    created,
+    last_transition,
+    scheduled,
+    transition_count,
+    {vm_state_column},
+    metadata,
+    parameters,
+    blob,
+    lock_id,
+    last_heartbeat,
+    janitor_touch_count
+    "#
+    );
+
+    let mut jobs: Vec<Job> = sqlx::query_as(&query)
+        .bind(queue)
+        .bind(team_ids)
+        .bind(quotas)
+        .bind(lock_id)
+        .fetch_all(executor)
+        .await?;
+
+    // Hand jobs back in the same order a priority dequeue would have
+    jobs.sort_by(|a, b| (a.priority, a.scheduled).cmp(&(b.priority, b.scheduled)));
+
+    if with_vm_state {
+        for job in jobs.iter_mut() {
+            job.vm_state = decompress_vm_state(job.vm_state.take());
+        }
+    }
+
+    Ok(jobs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TeamShare {
+    pub team_id: i32,
+    pub weight: u64,
+    // How many jobs this team has available to dequeue, up to the batch size
+    pub available: u64,
+    // How many more jobs this team can have running, if it's limited
+    pub headroom: Option<u64>,
+}
+
+// Hand out up to `max` slots, one at a time, to whichever team has the fewest slots relative
+// to its weight and still has both jobs available and headroom. Ties go to the team listed first.
+pub fn fair_shares(teams: &[TeamShare], max: usize) -> Vec<(i32, u64)> {
+    let mut allocated = vec![0u64; teams.len()];
+    for _ in 0..max {
+        let mut next: Option<usize> = None;
+        for (i, team) in teams.iter().enumerate() {
+            if allocated[i] >= team.available || team.headroom.is_some_and(|h| allocated[i] >= h) {
+                continue;
+            }
+            // Compare allocated[i] / weight[i] < allocated[j] / weight[j], without dividing
+            let is_better = next.map_or(true, |j| {
+                allocated[i] * teams[j].weight < allocated[j] * team.weight
+            });
+            if is_better {
+                next = Some(i);
+            }
+        }
+        let Some(next) = next else {
+            break; // Every team is out of jobs or at its limit
+        };
+        allocated[next] += 1;
+    }
+
+    teams
+        .iter()
+        .zip(allocated)
+        .map(|(team, allocated)| (team.team_id, allocated))
+        .collect()
+}
+
+// The distinct teams with jobs available on a queue, and how many they have available (counting no
+// further than `max`), ordered by how long their oldest available job has been waiting. This is a
+// loose index scan, so it's cheap even with huge per-team backlogs.
+async fn teams_with_available_jobs<'c, E>(
+    executor: E,
+    queue: &str,
+    max: usize,
+) -> Result<Vec<(i32, u64)>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let teams: Vec<(i32, i64)> = sqlx::query_as(
+        r#"
+WITH RECURSIVE teams AS (
+    (
+        SELECT team_id FROM cyclotron_jobs
+        WHERE state = 'available'::JobState AND queue_name = $1 AND scheduled <= NOW()
+        ORDER BY team_id
+        LIMIT 1
+    )
+    UNION ALL
+    SELECT (
+        SELECT team_id FROM cyclotron_jobs
+        WHERE
+            state = 'available'::JobState
+            AND queue_name = $1
+            AND scheduled <= NOW()
+            AND team_id > teams.team_id
+        ORDER BY team_id
+        LIMIT 1
+    )
+    FROM teams
+    WHERE teams.team_id IS NOT NULL
+)
+SELECT teams.team_id, available.count
+FROM teams
+CROSS JOIN LATERAL (
+    SELECT scheduled FROM cyclotron_jobs
+    WHERE state = 'available'::JobState AND queue_name = $1 AND team_id = teams.team_id
+    ORDER BY scheduled ASC
+    LIMIT 1
+) oldest
+CROSS JOIN LATERAL (
+    SELECT COUNT(*) AS count FROM (
+        SELECT 1 FROM cyclotron_jobs
+        WHERE
+            state = 'available'::JobState
+            AND queue_name = $1
+            AND team_id = teams.team_id
+            AND scheduled <= NOW()
+        LIMIT $2
+    ) capped
+) available
+WHERE teams.team_id IS NOT NULL
+ORDER BY oldest.scheduled ASC
+        "#,
+    )
+    .bind(queue)
+    .bind(max as i64)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(teams
+        .into_iter()
+        .map(|(team_id, count)| (team_id, count as u64))
+        .collect())
+}
+
+async fn running_jobs_per_team<'c, E>(
+    executor: E,
+    queue: &str,
+    teams: &[i32],
+) -> Result<HashMap<i32, u64>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    let rows: Vec<(i32, i64)> = sqlx::query_as(
+        "SELECT team_id, COUNT(*) FROM cyclotron_jobs WHERE state = 'running'::JobState AND queue_name = $1 AND team_id = ANY($2) GROUP BY team_id",
+    )
+    .bind(queue)
+    .bind(teams)
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(team_id, count)| (team_id, count as u64))
+        .collect())
+}
+
 pub async fn get_vm_state<'c, E>(
     executor: E,
     job_id: Uuid,
@@ -275,6 +544,8 @@ fn set_helper<'args, T, DB>(
     query.push_bind(value);
 }
 
+// Returns a Cancelled error if the job has been cancelled since it was dequeued, in which case the
+// worker should stop working on it. The job is deleted when the worker next flushes an update for it.
 pub async fn set_heartbeat<'c, E>(
     executor: E,
     job_id: Uuid,
@@ -283,12 +554,46 @@ pub async fn set_heartbeat<'c, E>(
 where
     E: sqlx::Executor<'c, Database = sqlx::Postgres>,
 {
-    let q = sqlx::query!(
-        "UPDATE cyclotron_jobs SET last_heartbeat = NOW() WHERE id = $1 AND lock_id = $2",
-        job_id,
-        lock_id
-    );
-    assert_does_update(executor, job_id, lock_id, q).await
+    let cancel_requested: Option<bool> = sqlx::query_scalar(
+        "UPDATE cyclotron_jobs SET last_heartbeat = NOW() WHERE id = $1 AND lock_id = $2 RETURNING cancel_requested",
+    )
+    .bind(job_id)
+    .bind(lock_id)
+    .fetch_optional(executor)
+    .await?;
+
+    match cancel_requested {
+        None => Err(JobError::InvalidLock(lock_id, job_id).into()),
+        Some(true) => Err(JobError::Cancelled(job_id).into()),
+        Some(false) => Ok(()),
+    }
+}
+
+// Delete any of the given jobs that were cancelled while running, returning their ids. Flushes
+// call this first, so a cancelled job's update is never applied.
+pub async fn take_cancelled_jobs<'c, E>(
+    executor: E,
+    job_ids: &[Uuid],
+    lock_ids: &[Uuid],
+) -> Result<Vec<Uuid>, QueueError>
+where
+    E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+{
+    Ok(sqlx::query_scalar(
+        r#"
+DELETE FROM cyclotron_jobs
+USING UNNEST($1::UUID[], $2::UUID[]) AS locked(id, lock_id)
+WHERE
+    cyclotron_jobs.id = locked.id
+    AND cyclotron_jobs.lock_id = locked.lock_id
+    AND cyclotron_jobs.cancel_requested
+RETURNING cyclotron_jobs.id
+    "#,
+    )
+    .bind(job_ids)
+    .bind(lock_ids)
+    .fetch_all(executor)
+    .await?)
 }
 
 // Simple wrapper, that just executes a query and returns an InvalidLock error if no rows were affected.
@@ -306,3 +611,64 @@ where
     // JobError -> QueueError
     Ok(throw_if_no_rows(res, job_id, lock_id)?)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{fair_shares, TeamShare};
+
+    fn team(team_id: i32, weight: u64, headroom: Option<u64>) -> TeamShare {
+        TeamShare {
+            team_id,
+            weight,
+            available: u64::MAX,
+            headroom,
+        }
+    }
+
+    #[test]
+    fn test_fair_shares_round_robin() {
+        let teams = [team(1, 1, None), team(2, 1, None), team(3, 1, None)];
+        assert_eq!(fair_shares(&teams, 7), vec![(1, 3), (2, 2), (3, 2)]);
+        // Smaller batches than there are teams go to the first listed (longest waiting) teams
+        assert_eq!(fair_shares(&teams, 2), vec![(1, 1), (2, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn test_fair_shares_weighted() {
+        let teams = [team(1, 3, None), team(2, 1, None)];
+        assert_eq!(fair_shares(&teams, 100), vec![(1, 75), (2, 25)]);
+    }
+
+    #[test]
+    fn test_fair_shares_respects_headroom() {
+        let teams = [team(1, 1, Some(2)), team(2, 1, None), team(3, 1, Some(0))];
+        assert_eq!(fair_shares(&teams, 10), vec![(1, 2), (2, 8), (3, 0)]);
+
+        // If every team is at its limit, the batch is underfilled
+        let teams = [team(1, 1, Some(1)), team(2, 1, Some(1))];
+        assert_eq!(fair_shares(&teams, 10), vec![(1, 1), (2, 1)]);
+    }
+
+    #[test]
+    fn test_fair_shares_gives_unused_slots_to_teams_with_backlogs() {
+        let teams = [
+            TeamShare {
+                available: 1,
+                ..team(1, 1, None)
+            },
+            team(2, 1, None),
+            TeamShare {
+                available: 2,
+                ..team(3, 1, None)
+            },
+        ];
+        assert_eq!(fair_shares(&teams, 9), vec![(1, 1), (2, 6), (3, 2)]);
+
+        // If no team has enough jobs, the batch is underfilled
+        let teams = [TeamShare {
+            available: 3,
+            ..team(1, 1, None)
+        }];
+        assert_eq!(fair_shares(&teams, 9), vec![(1, 3)]);
+    }
+}
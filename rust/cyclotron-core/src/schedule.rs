@@ -0,0 +1,435 @@
+use std::{collections::VecDeque, str::FromStr};
+
+use chrono::{
+    DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc,
+};
+use chrono_tz::Tz;
+
+use crate::{
+    error::QueueError,
+    types::{CatchUpPolicy, RecurringSchedule},
+};
+
+// If we can't find a matching time within this many years, there isn't one (e.g. "0 0 30 2 *")
+const MAX_SEARCH_YEARS: i32 = 5;
+
+// A standard 5 field cron expression - minute, hour, day of month, month, day of week - supporting
+// "*", single values, ranges ("1-5"), steps ("*/15", "10-50/10") and lists of any of those. As with
+// most crons, if both day of month and day of week are restricted, a day matching either is a match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    day_of_month_restricted: bool,
+    day_of_week_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            return Err(format!("expected 5 fields, got {}", fields.len()));
+        };
+
+        let mut days_of_week_bits = parse_field(days_of_week, 0, 7)?;
+        // Both 0 and 7 are Sunday
+        if days_of_week_bits & (1 << 7) != 0 {
+            days_of_week_bits |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days_of_month: parse_field(days_of_month, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            days_of_week: days_of_week_bits,
+            day_of_month_restricted: days_of_month != "*",
+            day_of_week_restricted: days_of_week != "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    // The first matching minute strictly after `after`, in the given timezone. Local times that
+    // don't exist (skipped by a DST change) never match, and ambiguous ones match their first instance.
+    pub fn next_after<T: TimeZone>(&self, after: &DateTime<T>) -> Option<DateTime<T>> {
+        let tz = after.timezone();
+        let local = after.naive_local();
+        let mut candidate = local.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let give_up_after = local.year() + MAX_SEARCH_YEARS;
+
+        while candidate.year() <= give_up_after {
+            if !bit_set(self.months, candidate.month()) {
+                candidate = start_of_next_month(candidate)?;
+                continue;
+            }
+            if !self.day_matches(candidate.date()) {
+                candidate = start_of_day(candidate.date().succ_opt()?);
+                continue;
+            }
+            if !bit_set(self.hours, candidate.hour()) {
+                candidate = candidate.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !bit_set(self.minutes, candidate.minute()) {
+                candidate += Duration::minutes(1);
+                continue;
+            }
+
+            match tz.from_local_datetime(&candidate) {
+                LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) if dt > *after => {
+                    return Some(dt)
+                }
+                _ => candidate += Duration::minutes(1),
+            }
+        }
+
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        let dom = bit_set(self.days_of_month, date.day());
+        let dow = bit_set(self.days_of_week, date.weekday().num_days_from_sunday());
+        match (self.day_of_month_restricted, self.day_of_week_restricted) {
+            (true, true) => dom || dow,
+            (true, false) => dom,
+            (false, true) => dow,
+            (false, false) => true,
+        }
+    }
+}
+
+// A recurring job's schedule, parsed and ready to be evaluated
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    Cron(CronSchedule, Tz),
+    Interval(Duration),
+}
+
+impl Schedule {
+    pub fn new(schedule: &RecurringSchedule, timezone: Option<&str>) -> Result<Self, QueueError> {
+        match schedule {
+            RecurringSchedule::Cron(expression) => {
+                let cron = expression
+                    .parse()
+                    .map_err(|e| QueueError::InvalidSchedule(format!("{}: {}", expression, e)))?;
+                let tz = timezone
+                    .unwrap_or("UTC")
+                    .parse()
+                    .map_err(|e| QueueError::InvalidSchedule(format!("invalid timezone: {}", e)))?;
+                Ok(Schedule::Cron(cron, tz))
+            }
+            RecurringSchedule::IntervalSeconds(0) => Err(QueueError::InvalidSchedule(
+                "interval must be at least one second".to_string(),
+            )),
+            RecurringSchedule::IntervalSeconds(seconds) => {
+                Ok(Schedule::Interval(Duration::seconds(*seconds as i64)))
+            }
+        }
+    }
+
+    // The first occurrence strictly after `after`, if there is one
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron(cron, tz) => cron
+                .next_after(&after.with_timezone(tz))
+                .map(|dt| dt.with_timezone(&Utc)),
+            Schedule::Interval(interval) => Some(after + *interval),
+        }
+    }
+
+    // The first occurrence at or after `start`
+    pub fn first_at_or_after(&self, start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Cron(..) => self.next_after(start - Duration::seconds(1)),
+            Schedule::Interval(_) => Some(start),
+        }
+    }
+
+    // Find the occurrences from `next_run` (the earliest occurrence not yet enqueued) up to `now`
+    // to enqueue according to the catch up policy, and the next run time after now, if the schedule
+    // has one. A schedule can be arbitrarily far behind, so we never walk every missed occurrence -
+    // at most `max_occurrences` of them are returned, the most recent first to go.
+    pub fn due_occurrences(
+        &self,
+        next_run: DateTime<Utc>,
+        now: DateTime<Utc>,
+        policy: CatchUpPolicy,
+        max_occurrences: usize,
+    ) -> (Vec<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        if next_run > now {
+            return (Vec::new(), Some(next_run));
+        }
+
+        // Skipped occurrences more than SKIP_GRACE late are never enqueued, so we don't look for them
+        let (earliest, limit) = match policy {
+            CatchUpPolicy::All => (next_run, max_occurrences),
+            CatchUpPolicy::Latest => (next_run, 1),
+            CatchUpPolicy::Skip => (
+                next_run.max(now - CatchUpPolicy::SKIP_GRACE),
+                max_occurrences,
+            ),
+        };
+
+        match self {
+            Schedule::Interval(interval) => {
+                Self::last_intervals(*interval, next_run, earliest, now, limit)
+            }
+            Schedule::Cron(..) => self.last_occurrences(earliest, now, limit),
+        }
+    }
+
+    // Interval occurrences are evenly spaced from next_run, so we can jump straight to the last one
+    fn last_intervals(
+        interval: Duration,
+        next_run: DateTime<Utc>,
+        earliest: DateTime<Utc>,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> (Vec<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let step = interval.num_seconds();
+        let last = next_run + Duration::seconds((now - next_run).num_seconds() / step * step);
+        let next = last + interval;
+
+        let due = (0..limit as i64)
+            .map(|i| last - Duration::seconds(i * step))
+            .take_while(|o| *o >= earliest)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .collect();
+
+        (due, Some(next))
+    }
+
+    // Cron occurrences can only be walked forwards, so we walk an exponentially growing window
+    // ending at `now`, until it either holds `limit` occurrences or reaches back to `earliest`.
+    fn last_occurrences(
+        &self,
+        earliest: DateTime<Utc>,
+        now: DateTime<Utc>,
+        limit: usize,
+    ) -> (Vec<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let mut window = Duration::hours(1);
+        loop {
+            let start = earliest.max(now - window);
+            let mut due = VecDeque::with_capacity(limit);
+            let mut next = self.first_at_or_after(start);
+            while let Some(occurrence) = next.filter(|n| *n <= now) {
+                if due.len() == limit {
+                    due.pop_front();
+                }
+                due.push_back(occurrence);
+                next = self.next_after(occurrence);
+            }
+
+            if due.len() >= limit || start == earliest {
+                return (due.into(), next);
+            }
+            window = window * 2;
+        }
+    }
+}
+
+fn bit_set(bits: u64, index: u32) -> bool {
+    bits & (1 << index) != 0
+}
+
+fn start_of_day(date: NaiveDate) -> NaiveDateTime {
+    date.and_hms_opt(0, 0, 0).expect("midnight is a valid time")
+}
+
+fn start_of_next_month(dt: NaiveDateTime) -> Option<NaiveDateTime> {
+    let (year, month) = match dt.month() {
+        12 => (dt.year() + 1, 1),
+        m => (dt.year(), m + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1).map(start_of_day)
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .map_err(|_| format!("invalid step in '{}'", part))?;
+                if step == 0 {
+                    return Err(format!("step must be positive in '{}'", part));
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, part)?, parse_value(end, part)?)
+        } else {
+            let value = parse_value(range, part)?;
+            // "5/15" means "every 15, starting at 5"
+            (value, if step > 1 { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("'{}' is outside of {}-{}", part, min, max));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, part: &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' in '{}'", value, part))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    use crate::types::{CatchUpPolicy, RecurringSchedule};
+
+    use super::{CronSchedule, Schedule};
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cron_parsing() {
+        assert!("* * * * *".parse::<CronSchedule>().is_ok());
+        assert!("*/15 9-17 * * 1-5".parse::<CronSchedule>().is_ok());
+        assert!("0 0 1,15 * 0,7".parse::<CronSchedule>().is_ok());
+        assert!("* * * *".parse::<CronSchedule>().is_err());
+        assert!("60 * * * *".parse::<CronSchedule>().is_err());
+        assert!("*/0 * * * *".parse::<CronSchedule>().is_err());
+        assert!("5-1 * * * *".parse::<CronSchedule>().is_err());
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let cron: CronSchedule = "*/15 9-17 * * 1-5".parse().unwrap();
+        // Friday evening rolls over to Monday morning
+        let friday = utc("2025-06-20T17:50:00Z");
+        assert_eq!(cron.next_after(&friday), Some(utc("2025-06-23T09:00:00Z")));
+        // Occurrences are strictly after the given time
+        let on_the_dot = utc("2025-06-23T09:15:00Z");
+        assert_eq!(
+            cron.next_after(&on_the_dot),
+            Some(utc("2025-06-23T09:30:00Z"))
+        );
+
+        // Either day of month or day of week can match
+        let cron: CronSchedule = "0 12 13 * 5".parse().unwrap();
+        let next = cron.next_after(&utc("2025-06-01T00:00:00Z"));
+        assert_eq!(next, Some(utc("2025-06-06T12:00:00Z")));
+
+        // Impossible dates never match
+        let cron: CronSchedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(cron.next_after(&utc("2025-01-01T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn test_cron_timezones() {
+        let cron: CronSchedule = "0 9 * * *".parse().unwrap();
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let after = tz.with_ymd_and_hms(2025, 6, 20, 12, 0, 0).unwrap();
+        let next = cron.next_after(&after).unwrap();
+        assert_eq!(next.with_timezone(&Utc), utc("2025-06-21T13:00:00Z"));
+
+        // 02:30 doesn't exist on the day clocks go forward, so we skip to the next day
+        let cron: CronSchedule = "30 2 * * *".parse().unwrap();
+        let after = tz.with_ymd_and_hms(2025, 3, 8, 12, 0, 0).unwrap();
+        let next = cron.next_after(&after).unwrap();
+        assert_eq!(next.with_timezone(&Utc), utc("2025-03-10T06:30:00Z"));
+    }
+
+    #[test]
+    fn test_catch_up_policies() {
+        let schedule = Schedule::Interval(Duration::minutes(10));
+        let next_run = utc("2025-06-20T12:00:00Z");
+        let now = utc("2025-06-20T12:35:00Z");
+
+        let (due, next) = schedule.due_occurrences(next_run, now, CatchUpPolicy::All, 100);
+        assert_eq!(due.len(), 4);
+        assert_eq!(next, Some(utc("2025-06-20T12:40:00Z")));
+
+        let (due, _) = schedule.due_occurrences(next_run, now, CatchUpPolicy::All, 2);
+        assert_eq!(
+            due,
+            vec![utc("2025-06-20T12:20:00Z"), utc("2025-06-20T12:30:00Z")]
+        );
+
+        let (due, _) = schedule.due_occurrences(next_run, now, CatchUpPolicy::Latest, 100);
+        assert_eq!(due, vec![utc("2025-06-20T12:30:00Z")]);
+
+        let (due, _) = schedule.due_occurrences(next_run, now, CatchUpPolicy::Skip, 100);
+        assert_eq!(due, vec![utc("2025-06-20T12:30:00Z")]);
+
+        // Nothing due yet
+        let (due, next) = schedule.due_occurrences(
+            next_run,
+            next_run - Duration::seconds(1),
+            CatchUpPolicy::All,
+            100,
+        );
+        assert!(due.is_empty());
+        assert_eq!(next, Some(next_run));
+    }
+
+    #[test]
+    fn test_catching_up_a_long_way() {
+        // A year behind on a one second interval is ~31 million occurrences, which we never walk
+        let schedule = Schedule::Interval(Duration::seconds(1));
+        let next_run = utc("2024-06-20T12:00:00Z");
+        let now = utc("2025-06-20T12:00:00.500Z");
+
+        let (due, next) = schedule.due_occurrences(next_run, now, CatchUpPolicy::All, 3);
+        assert_eq!(
+            due,
+            vec![
+                utc("2025-06-20T11:59:58Z"),
+                utc("2025-06-20T11:59:59Z"),
+                utc("2025-06-20T12:00:00Z")
+            ]
+        );
+        assert_eq!(next, Some(utc("2025-06-20T12:00:01Z")));
+
+        let (due, _) = schedule.due_occurrences(next_run, now, CatchUpPolicy::Skip, 1000);
+        assert_eq!(due.len(), 300);
+        assert_eq!(due[0], utc("2025-06-20T11:55:01Z"));
+
+        // Every minute, a year behind
+        let schedule =
+            Schedule::new(&RecurringSchedule::Cron("* * * * *".to_string()), None).unwrap();
+        let (due, next) = schedule.due_occurrences(next_run, now, CatchUpPolicy::All, 2);
+        assert_eq!(
+            due,
+            vec![utc("2025-06-20T11:59:00Z"), utc("2025-06-20T12:00:00Z")]
+        );
+        assert_eq!(next, Some(utc("2025-06-20T12:01:00Z")));
+
+        let (due, _) = schedule.due_occurrences(next_run, now, CatchUpPolicy::Latest, 100);
+        assert_eq!(due, vec![utc("2025-06-20T12:00:00Z")]);
+
+        // Once a year, so the window has to grow back to the start to find it
+        let schedule =
+            Schedule::new(&RecurringSchedule::Cron("0 0 1 1 *".to_string()), None).unwrap();
+        let (due, next) = schedule.due_occurrences(next_run, now, CatchUpPolicy::All, 100);
+        assert_eq!(due, vec![utc("2025-01-01T00:00:00Z")]);
+        assert_eq!(next, Some(utc("2026-01-01T00:00:00Z")));
+    }
+}
@@ -1,11 +1,11 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use uuid::Uuid;
 
 pub type Bytes = Vec<u8>;
 
-#[derive(Debug, Deserialize, Serialize, sqlx::Type)]
+#[derive(Debug, Deserialize, Serialize, sqlx::Type, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 #[sqlx(type_name = "JobState", rename_all = "lowercase")]
 pub enum JobState {
@@ -14,6 +14,20 @@ pub enum JobState {
     Completed,
     Failed,
     Paused,
+    Waiting, // Waiting for the job's parents to finish. See `JobInit::parents`
+}
+
+impl JobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Available => "available",
+            JobState::Running => "running",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Paused => "paused",
+            JobState::Waiting => "waiting",
+        }
+    }
 }
 
 impl FromStr for JobState {
@@ -25,6 +39,8 @@ impl FromStr for JobState {
             "running" => Ok(JobState::Running),
             "completed" => Ok(JobState::Completed),
             "failed" => Ok(JobState::Failed),
+            "paused" => Ok(JobState::Paused),
+            "waiting" => Ok(JobState::Waiting),
             _ => Err(()),
         }
     }
@@ -42,9 +58,177 @@ pub struct JobInit {
     pub parameters: Option<Bytes>,
     pub blob: Option<Bytes>,
     pub metadata: Option<Bytes>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>, // If set, unique per queue among jobs that haven't completed or failed
+    #[serde(default)]
+    pub on_key_conflict: OnKeyConflict,
+    #[serde(default)]
+    pub parents: Vec<Uuid>, // If set, the job waits for all of these jobs to complete or fail before becoming available
+    #[serde(default)]
+    pub on_parent_failure: OnParentFailure,
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>, // If unset, the job's queue's policy (or the default policy) applies
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+// How a job is retried when its worker reports a failed attempt with `Worker::retry`. Set on a job
+// when it's created, or on a whole queue with `QueueManager::set_retry_policy` - the job's own
+// policy takes precedence.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32, // Including the first. Once they're used up, the job is dead lettered
+    pub initial_interval_ms: u64,
+    #[serde(default = "default_backoff_multiplier")]
+    pub backoff_multiplier: u32, // Each retry waits this many times longer than the last
+    pub max_interval_ms: u64,
+    #[serde(default)]
+    pub jitter_percent: u32, // Each wait is randomly adjusted by up to this percentage, either way
+    #[serde(default)]
+    pub retryable_errors: Option<Vec<String>>, // Error classes worth retrying. If unset, all of them are
+}
+
+fn default_backoff_multiplier() -> u32 {
+    2
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_interval_ms: 1000,
+            backoff_multiplier: default_backoff_multiplier(),
+            max_interval_ms: 60 * 60 * 1000,
+            jitter_percent: 10,
+            retryable_errors: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn is_retryable(&self, error_class: &str) -> bool {
+        self.retryable_errors
+            .as_ref()
+            .map_or(true, |classes| classes.iter().any(|c| c == error_class))
+    }
+
+    // How long to wait before the next attempt, after `attempts` have failed, before jitter
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let multiplier = (self.backoff_multiplier.max(1) as f64).powi(attempts.max(1) as i32 - 1);
+        let interval =
+            (self.initial_interval_ms as f64 * multiplier).min(self.max_interval_ms as f64);
+        Duration::milliseconds(interval as i64)
+    }
+
+    // As above, with jitter. `random` is a uniform sample from [0, 1)
+    pub fn backoff_with_jitter(&self, attempts: u32, random: f64) -> Duration {
+        let backoff = self.backoff(attempts);
+        let jitter = self.jitter_percent.min(100) as f64 / 100.0;
+        let factor = 1.0 + jitter * (2.0 * random - 1.0);
+        Duration::milliseconds((backoff.num_milliseconds() as f64 * factor) as i64)
+    }
+}
+
+// A failed attempt at running a job, as reported by its worker. The class is what retry policies
+// match against to decide whether the job is worth retrying, and the message is for humans.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct AttemptError {
+    pub class: String,
+    pub message: String,
+}
+
+impl AttemptError {
+    pub fn new(class: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            class: class.into(),
+            message: message.into(),
+        }
+    }
+}
+
+// A job's history of failed attempts, oldest first
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Attempt {
+    pub attempt: i32,
+    pub error_class: String,
+    pub message: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+// What `Worker::retry` did with a job
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RetryOutcome {
+    Retrying {
+        attempt: u32,
+        scheduled: DateTime<Utc>,
+    },
+    DeadLettered {
+        attempt: u32,
+    },
+}
+
+// What to do when a job is enqueued with an idempotency key that's already held by another job in
+// the same queue, which hasn't completed or failed yet
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnKeyConflict {
+    // Leave the existing job alone, and return its id
+    #[default]
+    ReturnExisting,
+    // Drop the new job, along with its parents and retry policy, and return the existing job's id.
+    // Unlike ReturnExisting, the new job's parents are never added to the existing one
+    Ignore,
+    // Overwrite the existing job's parameters and blob with the new job's, if it isn't running yet,
+    // and return its id
+    Replace,
+}
+
+impl OnKeyConflict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OnKeyConflict::ReturnExisting => "return_existing",
+            OnKeyConflict::Ignore => "ignore",
+            OnKeyConflict::Replace => "replace",
+        }
+    }
+}
+
+// Selects the jobs a bulk operation (cancel, pause, resume etc) applies to. Unset fields match any
+// job, but at least one of team_id, function_id or queue_name must be set, so an empty filter
+// can't be used to cancel everything on a shard by accident. Each operation only touches jobs in
+// the states it makes sense for, further narrowed by `states` if it's set.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct JobFilter {
+    #[serde(default)]
+    pub team_id: Option<i32>,
+    #[serde(default)]
+    pub function_id: Option<Uuid>,
+    #[serde(default)]
+    pub queue_name: Option<String>,
+    #[serde(default)]
+    pub states: Option<Vec<JobState>>,
+    #[serde(default)]
+    pub created_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub created_after: Option<DateTime<Utc>>,
+}
+
+// The result of a bulk cancel. Jobs that weren't running are deleted outright, but running jobs
+// are only flagged, and are deleted once their worker next heartbeats or flushes an update for them
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CancelledJobs {
+    pub cancelled: u64,
+    pub cancel_requested: u64,
+}
+
+// The result of a dead letter replay. Jobs are skipped, and left in the dead letter queue, if their
+// idempotency key is already in use in the queue they'd be replayed to
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayedDeadLetters {
+    pub replayed: u64,
+    pub skipped: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, sqlx::FromRow)]
 pub struct Job {
     // Job metadata
     pub id: Uuid,
@@ -110,6 +294,135 @@ impl JobUpdate {
     }
 }
 
+// When a recurring job's instances should be enqueued
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecurringSchedule {
+    // A standard 5 field cron expression, evaluated in the recurring job's timezone
+    Cron(String),
+    // A fixed interval, starting from the template's scheduled time
+    IntervalSeconds(u64),
+}
+
+// What to do about occurrences that were missed, because the scheduler was down, or the
+// recurring job was paused
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CatchUpPolicy {
+    // Enqueue a single instance for all missed occurrences
+    #[default]
+    Latest,
+    // Enqueue an instance for every missed occurrence
+    All,
+    // Only enqueue occurrences that are at most SKIP_GRACE late
+    Skip,
+}
+
+impl CatchUpPolicy {
+    pub const SKIP_GRACE: chrono::Duration = chrono::Duration::minutes(5);
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CatchUpPolicy::Latest => "latest",
+            CatchUpPolicy::All => "all",
+            CatchUpPolicy::Skip => "skip",
+        }
+    }
+}
+
+impl FromStr for CatchUpPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "latest" => Ok(CatchUpPolicy::Latest),
+            "all" => Ok(CatchUpPolicy::All),
+            "skip" => Ok(CatchUpPolicy::Skip),
+            _ => Err(()),
+        }
+    }
+}
+
+// What to do with a waiting job once all its parents have finished, if any of them failed (or were
+// cancelled, or no longer exist)
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnParentFailure {
+    // Delete the job without running it. Its own children see it as cancelled
+    #[default]
+    Cancel,
+    // Run the job anyway - it can check its parents' outcomes itself
+    Run,
+    // Mark the job as failed without running it
+    Fail,
+}
+
+impl OnParentFailure {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OnParentFailure::Cancel => "cancel",
+            OnParentFailure::Run => "run",
+            OnParentFailure::Fail => "fail",
+        }
+    }
+}
+
+// How a parent job finished, as seen by its children
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParentOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+    Missing, // The parent was deleted, or never existed, without finishing
+}
+
+impl ParentOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParentOutcome::Completed => "completed",
+            ParentOutcome::Failed => "failed",
+            ParentOutcome::Cancelled => "cancelled",
+            ParentOutcome::Missing => "missing",
+        }
+    }
+}
+
+impl FromStr for ParentOutcome {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "completed" => Ok(ParentOutcome::Completed),
+            "failed" => Ok(ParentOutcome::Failed),
+            "cancelled" => Ok(ParentOutcome::Cancelled),
+            "missing" => Ok(ParentOutcome::Missing),
+            _ => Err(()),
+        }
+    }
+}
+
+// A finished parent of a job, and its result - the parameters it was left with when it finished,
+// which, by convention, is where a job leaves data for "the next guy"
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ParentResult {
+    pub parent_id: Uuid,
+    pub outcome: ParentOutcome,
+    pub result: Option<Bytes>,
+}
+
+// The chunk of data needed to create a recurring job. Every instance is created from the template,
+// with its scheduled time set to the occurrence it's for. The template's scheduled time is when
+// the schedule starts - the first instance is the first occurrence at or after it.
+#[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq)]
+pub struct RecurringJobInit {
+    pub template: JobInit,
+    pub schedule: RecurringSchedule,
+    pub timezone: Option<String>, // An IANA timezone name, defaults to UTC. Only used by cron schedules
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+}
+
 // Result of janitor's `delete_completed_and_failed_jobs`
 #[derive(sqlx::FromRow, Debug)]
 pub struct AggregatedDelete {
@@ -120,3 +433,52 @@ pub struct AggregatedDelete {
     pub state: String,
     pub count: i64,
 }
+
+// A job in the dead letter queue, along with why it was put there
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct DeadLetterEntry {
+    pub job_id: Uuid,
+    pub team_id: i32,
+    pub function_id: Option<Uuid>,
+    pub original_queue_name: String,
+    pub reason: String,
+    pub dlq_time: DateTime<Utc>,
+    pub created: DateTime<Utc>,
+}
+
+// Dead letter queue entries sharing a reason, team and function
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct DeadLetterGroup {
+    pub reason: String,
+    pub team_id: i32,
+    pub function_id: Option<Uuid>,
+    pub count: i64,
+    pub oldest: DateTime<Utc>, // The earliest and latest dlq_time in the group
+    pub newest: DateTime<Utc>,
+}
+
+// Selects dead letter queue entries. Unset fields match any entry. Replaying or purging requires at
+// least one field to be set, but listing and grouping don't.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct DeadLetterFilter {
+    #[serde(default)]
+    pub job_ids: Option<Vec<Uuid>>,
+    #[serde(default)]
+    pub team_id: Option<i32>,
+    #[serde(default)]
+    pub function_id: Option<Uuid>,
+    #[serde(default)]
+    pub original_queue_name: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>, // Matched exactly, so a group's reason selects just that group
+    #[serde(default)]
+    pub dlq_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub dlq_after: Option<DateTime<Utc>>,
+}
+
+impl DeadLetterFilter {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     future::Future,
     sync::{Arc, Weak},
     task::Poll,
@@ -14,13 +14,19 @@ use tracing::error;
 use uuid::Uuid;
 
 use crate::{
-    config::WorkerConfig,
+    config::{FairShareConfig, WorkerConfig},
     error::JobError,
+    notify::QueueNotifier,
     ops::{
+        dependencies::{finished_outcome, get_parent_results, resolve_dependencies},
         meta::{dead_letter, run_migrations},
-        worker::{dequeue_jobs, dequeue_with_vm_state, flush_job, get_vm_state, set_heartbeat},
+        retry::{get_attempts, record_attempt},
+        worker::{
+            dequeue_jobs, dequeue_jobs_fair, dequeue_with_vm_state, flush_job, get_vm_state,
+            set_heartbeat, take_cancelled_jobs,
+        },
     },
-    types::Bytes,
+    types::{Attempt, AttemptError, Bytes, ParentOutcome, ParentResult, RetryOutcome},
     Job, JobState, JobUpdate, PoolConfig, QueueError,
 };
 
@@ -46,11 +52,15 @@ pub struct Worker {
     // some conditions.
     flush_batch: Arc<Mutex<FlushBatch>>,
 
+    // Wakes the worker when jobs are inserted into a queue it's waiting on
+    notifier: Arc<QueueNotifier>,
+
     pub heartbeat_window: Duration, // The worker will only pass one heartbeat to the DB per job every heartbeat_window
     pub linger: Duration,           // Updates will be held at most this long
     pub max_buffered: usize,        // Updates will be flushed after this many are buffered
     pub max_bytes: usize, // Updates will be flushed after the vm_state and blob sizes combined exceed this
     pub should_compress_vm_state: bool, // Compress vm_state when persisting to the DB?
+    pub fair_share: Option<FairShareConfig>, // If set, batches are shared out across teams, rather than dequeued strictly by priority
 }
 
 impl Worker {
@@ -60,6 +70,7 @@ impl Worker {
     }
 
     pub fn from_pool(pool: PgPool, worker_config: WorkerConfig) -> Self {
+        let notifier = Arc::new(QueueNotifier::new(pool.clone()));
         let worker = Self {
             pool,
             running: Default::default(),
@@ -67,10 +78,12 @@ impl Worker {
             flush_batch: Arc::new(Mutex::new(FlushBatch::new(
                 worker_config.should_compress_vm_state(),
             ))),
+            notifier,
             linger: worker_config.linger_time(),
             max_buffered: worker_config.max_updates_buffered(),
             max_bytes: worker_config.max_bytes_buffered(),
             should_compress_vm_state: worker_config.should_compress_vm_state(),
+            fair_share: worker_config.fair_share(),
         };
 
         tokio::spawn(flush_loop(
@@ -91,9 +104,15 @@ impl Worker {
 
     /// Dequeues jobs from the queue, and returns them. Job sorting happens at the queue level,
     /// workers can't provide any filtering or sorting criteria - queue managers decide which jobs are run,
-    /// workers just run them.
+    /// workers just run them. If the worker was configured for fair share dequeueing, the batch is shared
+    /// out across the teams with available jobs, rather than taken strictly in priority order.
     pub async fn dequeue_jobs(&self, queue: &str, limit: usize) -> Result<Vec<Job>, QueueError> {
-        let jobs = dequeue_jobs(&self.pool, queue, limit).await?;
+        let jobs = match &self.fair_share {
+            Some(fair_share) => {
+                dequeue_jobs_fair(&self.pool, queue, limit, fair_share, false).await?
+            }
+            None => dequeue_jobs(&self.pool, queue, limit).await?,
+        };
 
         let mut running = self.running.lock().unwrap();
         for job in &jobs {
@@ -108,13 +127,27 @@ impl Worker {
         Ok(jobs)
     }
 
+    /// Waits until jobs might be available in the queue, either because a manager notified us that
+    /// jobs were inserted into it, or because the timeout passed, and returns true in the former case.
+    /// Callers should dequeue either way - the timeout is the polling fallback, since notifications
+    /// can be lost (e.g. across a reconnect), and jobs scheduled in the future become available
+    /// without one. The first call opens a dedicated LISTEN connection.
+    pub async fn wait_for_jobs(&self, queue: &str, timeout: Duration) -> bool {
+        self.notifier.wait(queue, timeout).await
+    }
+
     /// This is the same as dequeue_jobs, but it also returns the vm_state of the job
     pub async fn dequeue_with_vm_state(
         &self,
         queue: &str,
         limit: usize,
     ) -> Result<Vec<Job>, QueueError> {
-        let jobs = dequeue_with_vm_state(&self.pool, queue, limit).await?;
+        let jobs = match &self.fair_share {
+            Some(fair_share) => {
+                dequeue_jobs_fair(&self.pool, queue, limit, fair_share, true).await?
+            }
+            None => dequeue_with_vm_state(&self.pool, queue, limit).await?,
+        };
 
         let mut running = self.running.lock().unwrap();
         for job in &jobs {
@@ -143,6 +176,54 @@ impl Worker {
         get_vm_state(&self.pool, job_id, lock_id).await
     }
 
+    /// Retrieve the outcomes and results of a job's parents. A job that had no parents has no
+    /// results, and this doesn't require the job to be held by this worker.
+    pub async fn parent_results(&self, job_id: Uuid) -> Result<Vec<ParentResult>, QueueError> {
+        get_parent_results(&self.pool, job_id).await
+    }
+
+    /// Report a failed attempt at a job, and retry it according to its retry policy - the job's
+    /// own, its queue's, or the default one, in that order. If the error is retryable and the policy
+    /// has attempts left, the job is released back to its queue, scheduled after the policy's
+    /// backoff, along with any other updates made to it. Otherwise, it's dead lettered. Either way
+    /// the attempt is recorded in the job's history, and the worker is done with the job.
+    pub async fn retry(
+        &self,
+        job_id: Uuid,
+        error: AttemptError,
+    ) -> Result<RetryOutcome, QueueError> {
+        let lock_id = {
+            let running = self.running.lock().unwrap();
+            running
+                .get(&job_id)
+                .ok_or(JobError::UnknownJobId(job_id))?
+                .lock_id
+        };
+
+        let (policy, attempt) = record_attempt(&self.pool, job_id, lock_id, &error).await?;
+
+        if attempt >= policy.max_attempts || !policy.is_retryable(&error.class) {
+            let reason = format!(
+                "{} after {} attempt(s): {}",
+                error.class, attempt, error.message
+            );
+            dead_letter(&self.pool, job_id, &reason).await?;
+            self.running.lock().unwrap().remove(&job_id);
+            return Ok(RetryOutcome::DeadLettered { attempt });
+        }
+
+        let scheduled = Utc::now() + policy.backoff_with_jitter(attempt, rand::random());
+        self.set_state(job_id, JobState::Available)?;
+        self.set_scheduled_at(job_id, scheduled)?;
+        self.release_job(job_id, None).await?;
+        Ok(RetryOutcome::Retrying { attempt, scheduled })
+    }
+
+    /// The failed attempts recorded for a job by `retry`, oldest first
+    pub async fn attempts(&self, job_id: Uuid) -> Result<Vec<Attempt>, QueueError> {
+        get_attempts(&self.pool, job_id).await
+    }
+
     /// Release a job back to the queue. Callers are returned a flush handle, which they
     /// may use to await the flushing of the updated job state, which happens asynchronously
     /// to allow for batching of updates. Callers may drop the flush handle without impacting
@@ -208,7 +289,9 @@ impl Worker {
     /// jobs are longer or shorter running). A job is considered "dead" if it's in a running state,
     /// and it's last heartbeat was more than the reaping time ago. This, like flush, returns an
     /// error if you try to set the heartbeat on a job whose lock you don't have (which can happen
-    /// if e.g. the job was reaped out from under you).
+    /// if e.g. the job was reaped out from under you). If the job has been cancelled, this returns
+    /// a `JobError::Cancelled`, and the worker should stop working on it - any update released for
+    /// it afterwards deletes the job instead of being applied.
     pub async fn heartbeat(&self, job_id: Uuid) -> Result<(), QueueError> {
         let lock_id = {
             let mut pending = self.running.lock().unwrap();
@@ -422,9 +505,28 @@ impl FlushBatch {
         }
 
         let mut txn = pool.begin().await?;
+
+        // Jobs cancelled while running are deleted rather than updated, and their worker is told
+        // via the flush result
+        let (job_ids, lock_ids): (Vec<Uuid>, Vec<Uuid>) = self
+            .pending
+            .iter()
+            .map(|p| (p.job_id, p.update.lock_id))
+            .unzip();
+        let cancelled: HashSet<Uuid> = take_cancelled_jobs(&mut *txn, &job_ids, &lock_ids)
+            .await?
+            .into_iter()
+            .collect();
+
         let mut results = Vec::new();
+        let mut finished = Vec::new();
         for to_flush in self.pending.iter_mut() {
             to_flush.tries += 1;
+            if cancelled.contains(&to_flush.job_id) {
+                finished.push((to_flush.job_id, ParentOutcome::Cancelled));
+                results.push(Err(JobError::Cancelled(to_flush.job_id)));
+                continue;
+            }
             let result = flush_job(
                 &mut *txn,
                 to_flush.job_id,
@@ -434,6 +536,10 @@ impl FlushBatch {
             .await;
             match result {
                 Ok(()) => {
+                    if let Some(outcome) = to_flush.update.state.as_ref().and_then(finished_outcome)
+                    {
+                        finished.push((to_flush.job_id, outcome));
+                    }
                     results.push(Ok(()));
                 }
                 Err(QueueError::JobError(e)) => {
@@ -444,6 +550,8 @@ impl FlushBatch {
                 }
             }
         }
+        // Any children waiting on the jobs we just finished are released in the same transaction
+        resolve_dependencies(&mut txn, finished).await?;
         txn.commit().await?;
 
         // We only dispatch results and clear the pending set if we actually commit the transaction, otherwise
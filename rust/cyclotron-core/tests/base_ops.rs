@@ -2,7 +2,12 @@ use std::sync::Arc;
 
 use chrono::{Duration, Utc};
 use common::{assert_job_matches_init, create_new_job, dates_match};
-use cyclotron_core::{Job, JobState, QueueManager, Worker, WorkerConfig};
+use cyclotron_core::{
+    AttemptError, CancelledJobs, CatchUpPolicy, DeadLetterFilter, DequeueMode, Janitor, Job,
+    JobError, JobFilter, JobState, OnKeyConflict, OnParentFailure, ParentOutcome, QueueError,
+    QueueManager, RecurringJobInit, RecurringSchedule, ReplayedDeadLetters, RetryOutcome,
+    RetryPolicy, Worker, WorkerConfig,
+};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -379,3 +384,637 @@ pub async fn test_bulk_insert_copy_from_with_compressed_vm_state(db: PgPool) {
         &VM_STATE_PAYLOAD.clone().to_vec()
     );
 }
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_fair_share_dequeue(db: PgPool) {
+    let worker_cfg = WorkerConfig {
+        dequeue_mode: Some(DequeueMode::FairShare),
+        team_concurrency_limits: Some([(3, 1)].into_iter().collect()),
+        ..Default::default()
+    };
+    let worker = Worker::from_pool(db.clone(), worker_cfg);
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+
+    // Team 1 has a huge backlog, all of it higher priority and older than everyone else's
+    let mut jobs = Vec::new();
+    for _ in 0..50 {
+        let mut job = create_new_job();
+        job.scheduled = Utc::now() - Duration::minutes(10);
+        jobs.push(job);
+    }
+    for team_id in [2, 3] {
+        for _ in 0..5 {
+            let mut job = create_new_job();
+            job.team_id = team_id;
+            job.priority = 5;
+            jobs.push(job);
+        }
+    }
+    let queue_name = jobs[0].queue_name.clone();
+    manager
+        .bulk_create_jobs(jobs)
+        .await
+        .expect("failed to bulk insert jobs");
+
+    let dequeued = worker
+        .dequeue_jobs(&queue_name, 9)
+        .await
+        .expect("failed to dequeue jobs");
+
+    let count = |team_id| dequeued.iter().filter(|j| j.team_id == team_id).count();
+    // Team 3 is limited to one running job, so its share is split between teams 1 and 2
+    assert_eq!(count(1), 4);
+    assert_eq!(count(2), 4);
+    assert_eq!(count(3), 1);
+
+    // Team 3 is now at its limit, so it gets nothing more until its running job is released
+    let dequeued = worker
+        .dequeue_jobs(&queue_name, 9)
+        .await
+        .expect("failed to dequeue jobs");
+    assert!(dequeued.iter().all(|j| j.team_id != 3));
+    assert_eq!(dequeued.iter().filter(|j| j.team_id == 2).count(), 1);
+    // Team 2 only had one job left, so the rest of the batch goes to team 1's backlog
+    assert_eq!(dequeued.iter().filter(|j| j.team_id == 1).count(), 8);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_recurring_jobs(db: PgPool) {
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+    let worker = Worker::from_pool(db.clone(), Default::default());
+    let janitor = Janitor::from_pool(db.clone());
+
+    // Started 10 minutes ago, running every minute, and catching up on everything it missed
+    let mut template = create_new_job();
+    template.scheduled = Utc::now() - Duration::minutes(10) + Duration::seconds(30);
+    let queue_name = template.queue_name.clone();
+    let init = RecurringJobInit {
+        template,
+        schedule: RecurringSchedule::IntervalSeconds(60),
+        timezone: None,
+        catch_up: CatchUpPolicy::All,
+    };
+    let id = manager.create_recurring_job(init.clone()).await.unwrap();
+
+    let scheduled = janitor.schedule_recurring_jobs(100, false).await.unwrap();
+    assert_eq!(scheduled, 10);
+    let jobs = worker.dequeue_jobs(&queue_name, 20).await.unwrap();
+    assert_eq!(jobs.len(), 10);
+    assert!(jobs
+        .iter()
+        .all(|j| j.function_id == init.template.function_id));
+
+    // Nothing is due again until the next minute
+    assert_eq!(
+        janitor.schedule_recurring_jobs(100, false).await.unwrap(),
+        0
+    );
+
+    // A second recurring job, that only enqueues the latest of its missed occurrences
+    let mut latest = init.clone();
+    latest.catch_up = CatchUpPolicy::Latest;
+    let latest_id = manager.create_recurring_job(latest).await.unwrap();
+    manager.pause_recurring_job(latest_id).await.unwrap();
+    assert_eq!(
+        janitor.schedule_recurring_jobs(100, false).await.unwrap(),
+        0
+    );
+    manager.resume_recurring_job(latest_id).await.unwrap();
+    assert_eq!(
+        janitor.schedule_recurring_jobs(100, false).await.unwrap(),
+        1
+    );
+
+    manager.delete_recurring_job(id).await.unwrap();
+    assert!(manager.delete_recurring_job(id).await.is_err());
+    assert!(manager.pause_recurring_job(id).await.is_err());
+
+    // Invalid schedules are rejected up front
+    let mut invalid = init.clone();
+    invalid.schedule = RecurringSchedule::Cron("61 * * * *".to_string());
+    assert!(manager.create_recurring_job(invalid).await.is_err());
+    let mut invalid = init;
+    invalid.schedule = RecurringSchedule::Cron("0 * * * *".to_string());
+    invalid.timezone = Some("Not/AZone".to_string());
+    assert!(manager.create_recurring_job(invalid).await.is_err());
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_idempotency_keys(db: PgPool) {
+    for use_copy in [false, true] {
+        let manager = QueueManager::from_pool(db.clone(), false, use_copy);
+        let mut worker = Worker::from_pool(db.clone(), Default::default());
+        worker.max_buffered = 0;
+
+        let queue_name = format!("idempotency_{}", use_copy);
+        let keyed = |key: &str, on_key_conflict: OnKeyConflict, parameters: &str| {
+            let mut job = create_new_job();
+            job.queue_name = queue_name.clone();
+            job.idempotency_key = Some(key.to_string());
+            job.on_key_conflict = on_key_conflict;
+            job.parameters = Some(parameters.as_bytes().to_vec());
+            job
+        };
+
+        let first = manager
+            .create_job(keyed("a", OnKeyConflict::ReturnExisting, "1"))
+            .await
+            .unwrap();
+
+        // Retries of the same logical job resolve to the job already queued
+        let retried = manager
+            .create_job(keyed("a", OnKeyConflict::ReturnExisting, "2"))
+            .await
+            .unwrap();
+        assert_eq!(retried, first);
+        let ignored = manager
+            .create_job(keyed("a", OnKeyConflict::Ignore, "2"))
+            .await
+            .unwrap();
+        assert_eq!(ignored, first);
+
+        // Bulk inserts resolve keys against the queue, and against earlier jobs in the same batch
+        let ids = manager
+            .bulk_create_jobs(vec![
+                keyed("a", OnKeyConflict::Replace, "3"),
+                keyed("b", OnKeyConflict::ReturnExisting, "1"),
+                keyed("b", OnKeyConflict::Ignore, "2"),
+                create_new_job(),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(ids[0], first);
+        assert!(!ids[1].is_nil() && ids[1] != first);
+        assert_eq!(ids[2], ids[1]);
+        assert!(!ids[3].is_nil());
+
+        let jobs = worker.dequeue_jobs(&queue_name, 10).await.unwrap();
+        assert_eq!(jobs.len(), 2);
+        let job_a = jobs.iter().find(|j| j.id == first).unwrap();
+        assert_eq!(job_a.parameters.as_deref(), Some("3".as_bytes()));
+
+        // Once the job is finished, its key can be used again
+        worker.set_state(first, JobState::Completed).unwrap();
+        worker.release_job(first, None).await.unwrap();
+        let reused = manager
+            .create_job(keyed("a", OnKeyConflict::ReturnExisting, "4"))
+            .await
+            .unwrap();
+        assert_ne!(reused, first);
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_idempotency_keys_with_parents(db: PgPool) {
+    for use_copy in [false, true] {
+        let manager = QueueManager::from_pool(db.clone(), false, use_copy);
+
+        let parent = manager.create_job(create_new_job()).await.unwrap();
+        let keyed_child = || {
+            let mut job = create_new_job();
+            job.queue_name = format!("idempotency_parents_{}", use_copy);
+            job.idempotency_key = Some("child".to_string());
+            job.parents = vec![parent];
+            job
+        };
+
+        // A job waiting on its parents still holds its key
+        let first = manager.create_job(keyed_child()).await.unwrap();
+        let second = manager.create_job(keyed_child()).await.unwrap();
+        assert_eq!(second, first);
+
+        let ids = manager.bulk_create_jobs(vec![keyed_child()]).await.unwrap();
+        assert_eq!(ids, vec![first]);
+
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM cyclotron_jobs WHERE queue_name = $1 AND idempotency_key = 'child'",
+        )
+        .bind(format!("idempotency_parents_{}", use_copy))
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(count, 1);
+    }
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_job_dependencies(db: PgPool) {
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+    let mut worker = Worker::from_pool(db.clone(), Default::default());
+    worker.max_buffered = 0;
+
+    let parent_a = manager.create_job(create_new_job()).await.unwrap();
+    let parent_b = manager.create_job(create_new_job()).await.unwrap();
+
+    // A fan-in over both parents, and a job that runs even if its parent fails
+    let mut fan_in = create_new_job();
+    fan_in.parents = vec![parent_a, parent_b];
+    let fan_in = manager.create_job(fan_in).await.unwrap();
+    let mut run_anyway = create_new_job();
+    run_anyway.parents = vec![parent_b];
+    run_anyway.on_parent_failure = OnParentFailure::Run;
+    let run_anyway = manager.create_job(run_anyway).await.unwrap();
+    // A grandchild of a job that gets cancelled is resolved too
+    let mut grandchild = create_new_job();
+    grandchild.parents = vec![fan_in];
+    grandchild.on_parent_failure = OnParentFailure::Fail;
+    let grandchild = manager.create_job(grandchild).await.unwrap();
+
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    let mut ids: Vec<Uuid> = jobs.iter().map(|j| j.id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![parent_a, parent_b]);
+
+    worker.set_state(parent_a, JobState::Completed).unwrap();
+    worker
+        .set_parameters(parent_a, Some(b"a result".to_vec()))
+        .unwrap();
+    worker.release_job(parent_a, None).await.unwrap();
+
+    // The fan in is still waiting on its other parent
+    assert!(worker.dequeue_jobs("test", 10).await.unwrap().is_empty());
+
+    worker.set_state(parent_b, JobState::Failed).unwrap();
+    worker.release_job(parent_b, None).await.unwrap();
+
+    // The fan in is cancelled, which fails the grandchild, and the other child runs anyway
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, run_anyway);
+
+    let results = worker.parent_results(run_anyway).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].parent_id, parent_b);
+    assert_eq!(results[0].outcome, ParentOutcome::Failed);
+
+    let results = worker.parent_results(fan_in).await.unwrap();
+    let result_a = results.iter().find(|r| r.parent_id == parent_a).unwrap();
+    assert_eq!(result_a.outcome, ParentOutcome::Completed);
+    assert_eq!(result_a.result.as_deref(), Some(b"a result".as_slice()));
+
+    let state: Option<String> =
+        sqlx::query_scalar("SELECT state::TEXT FROM cyclotron_jobs WHERE id = $1")
+            .bind(fan_in)
+            .fetch_optional(&db)
+            .await
+            .unwrap();
+    assert_eq!(state, None);
+    let state: String = sqlx::query_scalar("SELECT state::TEXT FROM cyclotron_jobs WHERE id = $1")
+        .bind(grandchild)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(state, "failed");
+
+    // Jobs waiting on parents that don't exist are resolved by the janitor
+    let mut orphan = create_new_job();
+    orphan.parents = vec![Uuid::now_v7()];
+    orphan.on_parent_failure = OnParentFailure::Run;
+    let orphan = manager.create_job(orphan).await.unwrap();
+    assert!(worker.dequeue_jobs("test", 10).await.unwrap().is_empty());
+
+    let janitor = Janitor::from_pool(db.clone());
+    assert_eq!(janitor.resolve_orphaned_dependencies().await.unwrap(), 1);
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, orphan);
+
+    // A dead lettered parent counts as failed
+    let parent = manager.create_job(create_new_job()).await.unwrap();
+    let mut child = create_new_job();
+    child.parents = vec![parent];
+    child.on_parent_failure = OnParentFailure::Run;
+    let child = manager.create_job(child).await.unwrap();
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, parent);
+
+    worker.dead_letter(parent, "bad input").await.unwrap();
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, child);
+    let results = worker.parent_results(child).await.unwrap();
+    assert_eq!(results[0].outcome, ParentOutcome::Failed);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_bulk_operations(db: PgPool) {
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+    let mut worker = Worker::from_pool(db.clone(), Default::default());
+    worker.max_buffered = 0;
+    // Dequeueing counts as a heartbeat, so don't throttle the one that finds out about the cancel
+    worker.heartbeat_window = Duration::zero();
+
+    for _ in 0..3 {
+        manager.create_job(create_new_job()).await.unwrap();
+    }
+    let mut other_team = create_new_job();
+    other_team.team_id = 2;
+    let other_team = manager.create_job(other_team).await.unwrap();
+
+    let team_1 = JobFilter {
+        team_id: Some(1),
+        ..Default::default()
+    };
+    let team_2 = JobFilter {
+        team_id: Some(2),
+        ..Default::default()
+    };
+
+    // An empty filter is rejected, rather than matching every job
+    let res = manager.cancel_jobs(&JobFilter::default()).await;
+    assert!(matches!(res, Err(QueueError::InvalidFilter(_))));
+
+    assert_eq!(manager.pause_jobs(&team_1).await.unwrap(), 3);
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, other_team);
+
+    assert_eq!(manager.resume_jobs(&team_1).await.unwrap(), 3);
+    assert_eq!(manager.reprioritize_jobs(&team_1, 0).await.unwrap(), 3);
+    let later = Utc::now() + Duration::hours(1);
+    assert_eq!(manager.reschedule_jobs(&team_1, later).await.unwrap(), 3);
+    assert!(worker.dequeue_jobs("test", 10).await.unwrap().is_empty());
+
+    // Running jobs are only flagged, and their worker finds out on heartbeat or flush
+    let res = manager.cancel_jobs(&team_2).await.unwrap();
+    assert_eq!(
+        res,
+        CancelledJobs {
+            cancelled: 0,
+            cancel_requested: 1
+        }
+    );
+    let res = worker.heartbeat(other_team).await;
+    assert!(matches!(
+        res,
+        Err(QueueError::JobError(JobError::Cancelled(id))) if id == other_team
+    ));
+    worker.set_state(other_team, JobState::Completed).unwrap();
+    let res = worker.release_job(other_team, None).await;
+    assert!(matches!(res, Err(JobError::Cancelled(id)) if id == other_team));
+
+    // Filters on state only narrow the states an operation applies to
+    let running_only = JobFilter {
+        states: Some(vec![JobState::Running]),
+        ..team_1.clone()
+    };
+    assert_eq!(
+        manager.cancel_jobs(&running_only).await.unwrap(),
+        CancelledJobs::default()
+    );
+
+    let res = manager.cancel_jobs(&team_1).await.unwrap();
+    assert_eq!(res.cancelled, 3);
+
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM cyclotron_jobs")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_dead_letter_tooling(db: PgPool) {
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+    let mut worker = Worker::from_pool(db.clone(), Default::default());
+    worker.max_buffered = 0;
+
+    // Groups are per function, so the jobs all need to share one
+    let function_id = Some(Uuid::now_v7());
+    for _ in 0..3 {
+        let mut init = create_new_job();
+        init.function_id = function_id;
+        manager.create_job(init).await.unwrap();
+    }
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    worker.dead_letter(jobs[0].id, "timed out").await.unwrap();
+    worker.dead_letter(jobs[1].id, "timed out").await.unwrap();
+    worker.dead_letter(jobs[2].id, "bad input").await.unwrap();
+
+    let entries = manager
+        .list_dead_letters(&DeadLetterFilter::default(), 10)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 3);
+    assert!(entries.iter().all(|e| e.original_queue_name == "test"));
+
+    let groups = manager
+        .group_dead_letters(&DeadLetterFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].reason, "timed out");
+    assert_eq!(groups[0].count, 2);
+    assert_eq!(groups[1].reason, "bad input");
+    assert_eq!(groups[1].count, 1);
+
+    // Replaying or purging needs a filter
+    let res = manager
+        .purge_dead_letters(&DeadLetterFilter::default())
+        .await;
+    assert!(matches!(res, Err(QueueError::InvalidFilter(_))));
+
+    let timed_out = DeadLetterFilter {
+        reason: Some("timed out".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        manager.replay_dead_letters(&timed_out).await.unwrap(),
+        ReplayedDeadLetters {
+            replayed: 2,
+            skipped: 0
+        }
+    );
+    let replayed = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(replayed.len(), 2);
+    assert!(replayed.iter().all(|j| j.janitor_touch_count == 0));
+
+    let bad_input = DeadLetterFilter {
+        job_ids: Some(vec![jobs[2].id]),
+        ..Default::default()
+    };
+    assert_eq!(manager.purge_dead_letters(&bad_input).await.unwrap(), 1);
+    let entries = manager
+        .list_dead_letters(&DeadLetterFilter::default(), 10)
+        .await
+        .unwrap();
+    assert!(entries.is_empty());
+
+    // A replayed job can be dead lettered again
+    worker
+        .dead_letter(replayed[0].id, "timed out")
+        .await
+        .unwrap();
+    assert_eq!(
+        manager
+            .list_dead_letters(&DeadLetterFilter::default(), 10)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Replaying a job whose idempotency key is back in use skips it, rather than failing the replay
+    let mut keyed = create_new_job();
+    keyed.idempotency_key = Some("key".to_string());
+    let dead = manager.create_job(keyed.clone()).await.unwrap();
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs[0].id, dead);
+    worker.dead_letter(dead, "bad input").await.unwrap();
+    let live = manager.create_job(keyed).await.unwrap();
+    assert_ne!(live, dead);
+
+    let all = DeadLetterFilter {
+        original_queue_name: Some("test".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        manager.replay_dead_letters(&all).await.unwrap(),
+        ReplayedDeadLetters {
+            replayed: 1,
+            skipped: 1
+        }
+    );
+    let entries = manager
+        .list_dead_letters(&DeadLetterFilter::default(), 10)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].job_id, dead);
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_retry_policies(db: PgPool) {
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+    let mut worker = Worker::from_pool(db.clone(), Default::default());
+    worker.max_buffered = 0;
+
+    let queue_policy = RetryPolicy {
+        max_attempts: 2,
+        initial_interval_ms: 0,
+        jitter_percent: 0,
+        ..Default::default()
+    };
+    manager
+        .set_retry_policy("test", Some(queue_policy))
+        .await
+        .unwrap();
+
+    let uses_queue_policy = manager.create_job(create_new_job()).await.unwrap();
+    let mut own_policy = create_new_job();
+    own_policy.retry_policy = Some(RetryPolicy {
+        max_attempts: 5,
+        retryable_errors: Some(vec!["timeout".to_string()]),
+        ..Default::default()
+    });
+    let own_policy = manager.create_job(own_policy).await.unwrap();
+
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 2);
+
+    let outcome = worker
+        .retry(
+            uses_queue_policy,
+            AttemptError::new("timeout", "took too long"),
+        )
+        .await
+        .unwrap();
+    assert!(matches!(outcome, RetryOutcome::Retrying { attempt: 1, .. }));
+
+    // Errors that aren't retryable under the job's policy dead letter it straight away
+    let outcome = worker
+        .retry(own_policy, AttemptError::new("bad_request", "invalid url"))
+        .await
+        .unwrap();
+    assert_eq!(outcome, RetryOutcome::DeadLettered { attempt: 1 });
+
+    let jobs = worker.dequeue_jobs("test", 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].id, uses_queue_policy);
+
+    let outcome = worker
+        .retry(
+            uses_queue_policy,
+            AttemptError::new("timeout", "took too long again"),
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcome, RetryOutcome::DeadLettered { attempt: 2 });
+
+    let attempts = worker.attempts(uses_queue_policy).await.unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[1].attempt, 2);
+    assert_eq!(attempts[1].message, "took too long again");
+
+    // Backoff grows exponentially, up to the policy's max interval
+    let policy = RetryPolicy {
+        initial_interval_ms: 1000,
+        max_interval_ms: 5000,
+        jitter_percent: 50,
+        ..Default::default()
+    };
+    assert_eq!(policy.backoff(1), Duration::seconds(1));
+    assert_eq!(policy.backoff(3), Duration::seconds(4));
+    assert_eq!(policy.backoff(10), Duration::seconds(5));
+    assert_eq!(
+        policy.backoff_with_jitter(1, 0.0),
+        Duration::milliseconds(500)
+    );
+}
+
+#[sqlx::test(migrations = "./migrations")]
+pub async fn test_notify_wakeups(db: PgPool) {
+    let manager = QueueManager::from_pool(db.clone(), false, false);
+    let worker = Worker::from_pool(db.clone(), Default::default());
+    let queue_name = create_new_job().queue_name;
+    let timeout = Duration::seconds(10);
+    let idle = Duration::milliseconds(200);
+
+    // The first wait opens the listener, and wakes once it's connected, in case anything was
+    // inserted before it was
+    assert!(worker.wait_for_jobs(&queue_name, timeout).await);
+
+    // With nothing inserted, waiting only ends on the fallback timeout
+    assert!(!worker.wait_for_jobs(&queue_name, idle).await);
+
+    // Jobs scheduled in the future don't wake anyone
+    let mut job = create_new_job();
+    job.scheduled = Utc::now() + Duration::hours(1);
+    manager.create_job(job).await.unwrap();
+    assert!(!worker.wait_for_jobs(&queue_name, idle).await);
+
+    // Jobs for other queues don't wake us either
+    let mut job = create_new_job();
+    job.queue_name = "other".to_string();
+    manager.create_job(job).await.unwrap();
+    assert!(!worker.wait_for_jobs(&queue_name, idle).await);
+
+    // A newly inserted job does. The notification is held until someone waits, so a job inserted
+    // between a dequeue and a wait isn't missed, and it's only used once
+    manager.create_job(create_new_job()).await.unwrap();
+    assert!(worker.wait_for_jobs(&queue_name, timeout).await);
+    let jobs = worker.dequeue_jobs(&queue_name, 10).await.unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert!(!worker.wait_for_jobs(&queue_name, idle).await);
+
+    // Jobs that become available other than by insert wake workers too, like a child released by
+    // its parent finishing
+    let mut child = create_new_job();
+    child.parents = vec![jobs[0].id];
+    manager.create_job(child).await.unwrap();
+    assert!(!worker.wait_for_jobs(&queue_name, idle).await);
+    worker.set_state(jobs[0].id, JobState::Completed).unwrap();
+    worker.release_job(jobs[0].id, None).await.unwrap();
+    assert!(worker.wait_for_jobs(&queue_name, timeout).await);
+
+    // Or paused jobs being resumed, here the released child and the one scheduled in the future
+    let queue = JobFilter {
+        queue_name: Some(queue_name.clone()),
+        ..Default::default()
+    };
+    assert_eq!(manager.pause_jobs(&queue).await.unwrap(), 2);
+    assert!(!worker.wait_for_jobs(&queue_name, idle).await);
+    assert_eq!(manager.resume_jobs(&queue).await.unwrap(), 2);
+    assert!(worker.wait_for_jobs(&queue_name, timeout).await);
+}
@@ -15,6 +15,11 @@ pub fn create_new_job() -> JobInit {
         parameters: None,
         blob: None,
         metadata: None,
+        idempotency_key: None,
+        on_key_conflict: Default::default(),
+        parents: vec![],
+        on_parent_failure: Default::default(),
+        retry_policy: None,
     }
 }
 
@@ -14,6 +14,7 @@ pub fn get_shard(db: PgPool) -> Shard {
         depth_limit: 10,
         should_compress_vm_state: true, // enabled by default in test suite
         should_use_bulk_job_copy: true, // enabled by default in test suite
+        should_notify: true,
     }
 }
 
@@ -0,0 +1,554 @@
+use std::{collections::HashMap, path::PathBuf, sync::Mutex, time::SystemTime};
+
+use aws_credential_types::Credentials;
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SigningParams, SigningSettings},
+    sign::v4,
+};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use http::{HeaderMap, HeaderName, HeaderValue, Method};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+pub const DEFAULT_SIGNATURE_HEADER: &str = "x-signature";
+pub const DEFAULT_TIMESTAMP_HEADER: &str = "x-timestamp";
+
+// We refresh tokens this long before they expire, so a token never expires mid-request
+const TOKEN_EXPIRY_MARGIN_SECONDS: i64 = 60;
+// If a token endpoint doesn't tell us how long a token lives, we assume this
+const DEFAULT_TOKEN_LIFETIME_SECONDS: i64 = 300;
+
+// How a fetch should authenticate itself to the destination. Secrets are never included
+// directly - instead, we take the name of a secret, which the worker resolves at request time,
+// from the secrets belonging to the job's team.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthDescriptor {
+    // An HMAC-SHA256 of "<timestamp>.<body>", hex encoded, with the unix timestamp in its own header
+    Hmac {
+        secret_ref: String,
+        signature_header: Option<String>, // Defaults to x-signature
+        timestamp_header: Option<String>, // Defaults to x-timestamp
+    },
+    AwsSigv4 {
+        access_key_id_ref: String,
+        secret_access_key_ref: String,
+        session_token_ref: Option<String>,
+        region: String,
+        service: String,
+    },
+    // An OAuth2 client-credentials grant, with the token sent as a bearer token
+    OauthClientCredentials {
+        token_url: String,
+        client_id: String,
+        client_secret_ref: String,
+        scope: Option<String>,
+        audience: Option<String>,
+    },
+}
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("no secrets directory is configured")]
+    SecretsNotConfigured,
+    #[error("invalid secret reference: {0}")]
+    InvalidSecretRef(String),
+    #[error("secret not found: {0}")]
+    SecretNotFound(String),
+    #[error("invalid auth header: {0}")]
+    InvalidHeader(String),
+    #[error("failed to sign request: {0}")]
+    SigningFailed(String),
+    #[error("failed to fetch oauth token: {0}")]
+    TokenRequestFailed(String),
+    #[error("token url is not on an allowed host: {0}")]
+    TokenUrlNotAllowed(String),
+    #[error("sigv4 signed requests can't be sent to host: {0}")]
+    Sigv4HostNotAllowed(String),
+}
+
+// Resolves secret references to their values. Secrets are files in a per-team subdirectory of
+// a directory (e.g. a mounted kubernetes secret), at `<dir>/<team_id>/<reference>`, so a job
+// can only ever reference its own team's secrets. They're re-read on every use so rotations are
+// picked up without a restart.
+pub struct SecretStore {
+    dir: Option<PathBuf>,
+}
+
+impl SecretStore {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    pub async fn resolve(&self, team_id: i32, secret_ref: &str) -> Result<String, AuthError> {
+        // References are bare file names - anything else could be used to read arbitrary files
+        let valid = !secret_ref.is_empty()
+            && !secret_ref.starts_with('.')
+            && secret_ref
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(AuthError::InvalidSecretRef(secret_ref.to_string()));
+        }
+
+        let dir = self.dir.as_ref().ok_or(AuthError::SecretsNotConfigured)?;
+        let path = dir.join(team_id.to_string()).join(secret_ref);
+        let secret = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|_| AuthError::SecretNotFound(secret_ref.to_string()))?;
+
+        Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+// Where descriptors are allowed to send credentials. Hmac secrets never leave the worker, but
+// oauth client secrets are sent to the token endpoint, and sigv4 sends the access key id to the
+// destination, so both are limited to hosts we trust with them.
+#[derive(Debug, Clone, Default)]
+pub struct AuthPolicy {
+    pub token_hosts: Vec<String>, // Hosts token urls may point at. If empty, oauth is disabled
+    pub sigv4_host_suffixes: Vec<String>, // Domains sigv4 signed requests may be sent to
+}
+
+impl AuthPolicy {
+    fn check_token_url(&self, token_url: &str) -> Result<(), AuthError> {
+        let url: reqwest::Url = token_url
+            .parse()
+            .map_err(|_| AuthError::TokenUrlNotAllowed(token_url.to_string()))?;
+        let host = url.host_str().unwrap_or_default();
+        if !self.token_hosts.iter().any(|allowed| allowed == host) {
+            return Err(AuthError::TokenUrlNotAllowed(token_url.to_string()));
+        }
+        Ok(())
+    }
+
+    fn check_sigv4_host(&self, url: &reqwest::Url) -> Result<(), AuthError> {
+        let host = url.host_str().unwrap_or_default();
+        let allowed = self.sigv4_host_suffixes.iter().any(|suffix| {
+            host == suffix || host.ends_with(&format!(".{}", suffix.trim_start_matches('.')))
+        });
+        if !allowed {
+            return Err(AuthError::Sigv4HostNotAllowed(host.to_string()));
+        }
+        Ok(())
+    }
+}
+
+// Tokens are cached per team and secret, as well as per grant, so a job can never be handed a
+// token obtained with another team's credentials
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct TokenKey {
+    team_id: i32,
+    client_secret_ref: String,
+    token_url: String,
+    client_id: String,
+    scope: Option<String>,
+    audience: Option<String>,
+}
+
+impl TokenKey {
+    fn new(team_id: i32, auth: &AuthDescriptor) -> Option<Self> {
+        let AuthDescriptor::OauthClientCredentials {
+            token_url,
+            client_id,
+            client_secret_ref,
+            scope,
+            audience,
+        } = auth
+        else {
+            return None;
+        };
+        Some(Self {
+            team_id,
+            client_secret_ref: client_secret_ref.clone(),
+            token_url: token_url.clone(),
+            client_id: client_id.clone(),
+            scope: scope.clone(),
+            audience: audience.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<i64>,
+}
+
+// Applies auth descriptors to outgoing requests, caching oauth tokens until shortly before they
+// expire. Concurrent requests that miss the cache may each fetch a token - the last one wins.
+pub struct Authenticator {
+    secrets: SecretStore,
+    policy: AuthPolicy,
+    tokens: Mutex<HashMap<TokenKey, CachedToken>>,
+}
+
+impl Authenticator {
+    pub fn new(secrets: SecretStore, policy: AuthPolicy) -> Self {
+        Self {
+            secrets,
+            policy,
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Adds whatever headers the descriptor requires to the request, using the secrets of the
+    // given team. The client passed must be the one used for the request itself, so that token
+    // endpoints are subject to the same restrictions (e.g. on internal IPs) as everything else.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn authorize(
+        &self,
+        client: &reqwest::Client,
+        team_id: i32,
+        auth: &AuthDescriptor,
+        method: &Method,
+        url: &reqwest::Url,
+        headers: &mut HeaderMap,
+        body: &[u8],
+    ) -> Result<(), AuthError> {
+        match auth {
+            AuthDescriptor::Hmac {
+                secret_ref,
+                signature_header,
+                timestamp_header,
+            } => {
+                let secret = self.secrets.resolve(team_id, secret_ref).await?;
+                let timestamp = Utc::now().timestamp().to_string();
+                let signature = hmac_signature(secret.as_bytes(), &timestamp, body);
+
+                insert_header(
+                    headers,
+                    signature_header
+                        .as_deref()
+                        .unwrap_or(DEFAULT_SIGNATURE_HEADER),
+                    &signature,
+                )?;
+                insert_header(
+                    headers,
+                    timestamp_header
+                        .as_deref()
+                        .unwrap_or(DEFAULT_TIMESTAMP_HEADER),
+                    &timestamp,
+                )?;
+            }
+            AuthDescriptor::AwsSigv4 {
+                access_key_id_ref,
+                secret_access_key_ref,
+                session_token_ref,
+                region,
+                service,
+            } => {
+                self.policy.check_sigv4_host(url)?;
+                let access_key_id = self.secrets.resolve(team_id, access_key_id_ref).await?;
+                let secret_access_key =
+                    self.secrets.resolve(team_id, secret_access_key_ref).await?;
+                let session_token = match session_token_ref {
+                    Some(r) => Some(self.secrets.resolve(team_id, r).await?),
+                    None => None,
+                };
+                let credentials = Credentials::new(
+                    access_key_id,
+                    secret_access_key,
+                    session_token,
+                    None,
+                    "cyclotron-fetch",
+                );
+
+                let signed = sigv4_headers(
+                    credentials,
+                    region,
+                    service,
+                    method,
+                    url,
+                    headers,
+                    body,
+                    SystemTime::now(),
+                )?;
+                for (name, value) in signed {
+                    insert_header(headers, &name, &value)?;
+                }
+            }
+            AuthDescriptor::OauthClientCredentials {
+                token_url,
+                client_secret_ref,
+                ..
+            } => {
+                self.policy.check_token_url(token_url)?;
+                let key = TokenKey::new(team_id, auth).expect("descriptor is an oauth grant");
+                let token = match self.cached_token(&key) {
+                    Some(token) => token,
+                    None => {
+                        let secret = self.secrets.resolve(team_id, client_secret_ref).await?;
+                        let token = fetch_token(client, &key, &secret).await?;
+                        self.tokens.lock().unwrap().insert(key, token.clone());
+                        token
+                    }
+                };
+
+                insert_header(
+                    headers,
+                    http::header::AUTHORIZATION.as_str(),
+                    &format!("Bearer {}", token.access_token),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Drop any cached token for the descriptor, e.g. because the destination rejected it
+    pub fn invalidate(&self, team_id: i32, auth: &AuthDescriptor) {
+        if let Some(key) = TokenKey::new(team_id, auth) {
+            self.tokens.lock().unwrap().remove(&key);
+        }
+    }
+
+    fn cached_token(&self, key: &TokenKey) -> Option<CachedToken> {
+        let tokens = self.tokens.lock().unwrap();
+        tokens
+            .get(key)
+            .filter(|t| t.expires_at > Utc::now() + Duration::seconds(TOKEN_EXPIRY_MARGIN_SECONDS))
+            .cloned()
+    }
+}
+
+pub fn hmac_signature(secret: &[u8], timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sigv4_headers(
+    credentials: Credentials,
+    region: &str,
+    service: &str,
+    method: &Method,
+    url: &reqwest::Url,
+    headers: &HeaderMap,
+    body: &[u8],
+    time: SystemTime,
+) -> Result<Vec<(String, String)>, AuthError> {
+    let identity = credentials.into();
+    let params: SigningParams = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region)
+        .name(service)
+        .time(time)
+        .settings(SigningSettings::default())
+        .build()
+        .map_err(|e| AuthError::SigningFailed(e.to_string()))?
+        .into();
+
+    let request_headers = headers
+        .iter()
+        .filter_map(|(k, v)| Some((k.as_str(), v.to_str().ok()?)));
+    let signable = SignableRequest::new(
+        method.as_str(),
+        url.as_str(),
+        request_headers,
+        SignableBody::Bytes(body),
+    )
+    .map_err(|e| AuthError::SigningFailed(e.to_string()))?;
+
+    let (instructions, _signature) = sign(signable, &params)
+        .map_err(|e| AuthError::SigningFailed(e.to_string()))?
+        .into_parts();
+
+    Ok(instructions
+        .headers()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect())
+}
+
+async fn fetch_token(
+    client: &reqwest::Client,
+    key: &TokenKey,
+    client_secret: &str,
+) -> Result<CachedToken, AuthError> {
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", key.client_id.as_str()),
+        ("client_secret", client_secret),
+    ];
+    if let Some(scope) = &key.scope {
+        form.push(("scope", scope));
+    }
+    if let Some(audience) = &key.audience {
+        form.push(("audience", audience));
+    }
+
+    let res = client
+        .post(&key.token_url)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| AuthError::TokenRequestFailed(e.to_string()))?;
+
+    let status = res.status();
+    if !status.is_success() {
+        return Err(AuthError::TokenRequestFailed(format!(
+            "token endpoint returned {}",
+            status
+        )));
+    }
+
+    let body = res
+        .bytes()
+        .await
+        .map_err(|e| AuthError::TokenRequestFailed(e.to_string()))?;
+    let token: TokenResponse = serde_json::from_slice(&body)
+        .map_err(|e| AuthError::TokenRequestFailed(format!("invalid token response: {}", e)))?;
+
+    let lifetime = token.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECONDS);
+    Ok(CachedToken {
+        access_token: token.access_token,
+        expires_at: Utc::now() + Duration::seconds(lifetime),
+    })
+}
+
+fn insert_header(headers: &mut HeaderMap, name: &str, value: &str) -> Result<(), AuthError> {
+    let name = HeaderName::try_from(name).map_err(|e| AuthError::InvalidHeader(e.to_string()))?;
+    let value =
+        HeaderValue::try_from(value).map_err(|e| AuthError::InvalidHeader(e.to_string()))?;
+    headers.insert(name, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    #[test]
+    fn test_hmac_signature() {
+        let signature = hmac_signature(b"secret", "1700000000", br#"{"hello":"world"}"#);
+        assert_eq!(signature.len(), 64);
+        assert_eq!(
+            signature,
+            hmac_signature(b"secret", "1700000000", br#"{"hello":"world"}"#)
+        );
+        assert_ne!(
+            signature,
+            hmac_signature(b"secret", "1700000001", br#"{"hello":"world"}"#)
+        );
+    }
+
+    #[test]
+    fn test_sigv4_headers() {
+        // From the AWS SigV4 test suite, "get-vanilla"
+        let credentials = Credentials::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            None,
+            None,
+            "test",
+        );
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1440938160); // 2015-08-30T12:36:00Z
+        let url: reqwest::Url = "https://example.amazonaws.com/".parse().unwrap();
+
+        let headers = sigv4_headers(
+            credentials,
+            "us-east-1",
+            "service",
+            &Method::GET,
+            &url,
+            &HeaderMap::new(),
+            b"",
+            time,
+        )
+        .unwrap();
+        let headers: HashMap<_, _> = headers.into_iter().collect();
+
+        assert_eq!(headers["x-amz-date"], "20150830T123600Z");
+        let authorization = &headers["authorization"];
+        assert!(authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_secret_refs_are_file_names() {
+        let store = SecretStore::new(Some(std::env::temp_dir()));
+        for secret_ref in ["../etc/passwd", "/etc/passwd", ".hidden", "a/b", "..", ""] {
+            assert!(matches!(
+                store.resolve(1, secret_ref).await,
+                Err(AuthError::InvalidSecretRef(_))
+            ));
+        }
+
+        let store = SecretStore::new(None);
+        assert!(matches!(
+            store.resolve(1, "valid-name").await,
+            Err(AuthError::SecretsNotConfigured)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_secrets_are_namespaced_by_team() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("1")).unwrap();
+        std::fs::write(dir.path().join("1").join("key"), "team 1 secret\n").unwrap();
+        std::fs::write(dir.path().join("key"), "shared secret").unwrap();
+
+        let store = SecretStore::new(Some(dir.path().into()));
+        assert_eq!(store.resolve(1, "key").await.unwrap(), "team 1 secret");
+        assert!(matches!(
+            store.resolve(2, "key").await,
+            Err(AuthError::SecretNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_auth_policy() {
+        let policy = AuthPolicy {
+            token_hosts: vec!["auth.example.com".to_string()],
+            sigv4_host_suffixes: vec!["amazonaws.com".to_string()],
+        };
+
+        assert!(policy
+            .check_token_url("https://auth.example.com/oauth/token")
+            .is_ok());
+        assert!(policy
+            .check_token_url("https://attacker.example.com/token")
+            .is_err());
+        assert!(policy.check_token_url("not a url").is_err());
+        assert!(AuthPolicy::default()
+            .check_token_url("https://auth.example.com/oauth/token")
+            .is_err());
+
+        let url = |s: &str| s.parse::<reqwest::Url>().unwrap();
+        assert!(policy
+            .check_sigv4_host(&url("https://lambda.us-east-1.amazonaws.com/"))
+            .is_ok());
+        assert!(policy
+            .check_sigv4_host(&url("https://amazonaws.com.attacker.com/"))
+            .is_err());
+        assert!(policy
+            .check_sigv4_host(&url("https://notamazonaws.com/"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_tokens_are_cached_per_team() {
+        let auth = AuthDescriptor::OauthClientCredentials {
+            token_url: "https://auth.example.com/token".to_string(),
+            client_id: "client".to_string(),
+            client_secret_ref: "secret".to_string(),
+            scope: None,
+            audience: None,
+        };
+        assert_ne!(TokenKey::new(1, &auth), TokenKey::new(2, &auth));
+    }
+}
@@ -5,6 +5,11 @@ use uuid::Uuid;
 
 use common_kafka::config::KafkaConfig;
 
+use crate::{
+    auth::AuthPolicy,
+    limits::{DestinationLimit, DestinationLimitsConfig, LimitOverrides},
+};
+
 #[derive(Envconfig)]
 pub struct Config {
     #[envconfig(from = "BIND_HOST", default = "::")]
@@ -64,6 +69,43 @@ pub struct Config {
     #[envconfig(default = "4000")]
     pub retry_backoff_base_ms: i64,
 
+    // Per-destination limits, in the form "<rate_per_second>[/<burst>[/<max_concurrent>]]",
+    // where 0 means unlimited. The default applies to every host without an override.
+    #[envconfig(default = "0")]
+    pub default_host_limit: DestinationLimit,
+
+    // Comma separated "<host>=<limit>" pairs
+    #[envconfig(default = "")]
+    pub host_limit_overrides: LimitOverrides<String>,
+
+    // Comma separated "<function_id>=<limit>" pairs, applied on top of the host limit
+    #[envconfig(default = "")]
+    pub function_limit_overrides: LimitOverrides<Uuid>,
+
+    #[envconfig(default = "1000")]
+    pub throttle_delay_ms: i64,
+
+    #[envconfig(default = "3600")]
+    pub max_retry_after_seconds: i64,
+
+    // How many times a job can be rescheduled for a Retry-After before further ones use up tries
+    #[envconfig(default = "10")]
+    pub max_retry_afters: u32,
+
+    // Directory holding the secrets auth descriptors can reference, one subdirectory per team,
+    // holding one file per secret
+    pub secrets_dir: Option<String>,
+
+    // Comma separated hosts oauth token urls may point at. Client secrets are sent to the token
+    // endpoint, so it can't be left up to the job - if this is empty, oauth is disabled
+    #[envconfig(default = "")]
+    pub oauth_token_hosts: String,
+
+    // Comma separated domains sigv4 signed requests may be sent to, as the signature includes
+    // the access key id
+    #[envconfig(default = "amazonaws.com")]
+    pub sigv4_host_suffixes: String,
+
     #[envconfig(nested = true)]
     pub kafka: KafkaConfig,
 
@@ -112,6 +154,9 @@ pub struct AppConfig {
     pub allow_internal_ips: bool,
     pub should_compress_vm_state: bool, // Default "false" (for now!)
     pub should_use_bulk_job_copy: bool, // Default "false" (for now!)
+    pub destination_limits: DestinationLimitsConfig,
+    pub secrets_dir: Option<String>,
+    pub auth_policy: AuthPolicy,
 }
 
 impl Config {
@@ -132,6 +177,19 @@ impl Config {
             allow_internal_ips: self.allow_internal_ips,
             should_compress_vm_state: self.should_compress_vm_state,
             should_use_bulk_job_copy: self.should_use_bulk_job_copy,
+            destination_limits: DestinationLimitsConfig {
+                default_host_limit: self.default_host_limit,
+                host_overrides: self.host_limit_overrides.0,
+                function_overrides: self.function_limit_overrides.0,
+                throttle_delay: Duration::milliseconds(self.throttle_delay_ms),
+                max_retry_after: Duration::seconds(self.max_retry_after_seconds),
+                max_retry_afters: self.max_retry_afters,
+            },
+            secrets_dir: self.secrets_dir,
+            auth_policy: AuthPolicy {
+                token_hosts: split_list(&self.oauth_token_hosts),
+                sigv4_host_suffixes: split_list(&self.sigv4_host_suffixes),
+            },
         };
 
         let pool_config = PoolConfig {
@@ -150,8 +208,17 @@ impl Config {
             max_bytes_buffered: Some(self.max_bytes_buffered),
             flush_loop_interval_ms: Some(self.flush_loop_interval_ms),
             should_compress_vm_state: Some(self.should_compress_vm_state),
+            ..Default::default()
         };
 
         (app_config, pool_config, self.kafka, worker_config)
     }
 }
+
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
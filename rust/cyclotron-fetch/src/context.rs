@@ -9,13 +9,20 @@ use health::HealthHandle;
 use rdkafka::producer::FutureProducer;
 use tokio::sync::Semaphore;
 
-use crate::{config::AppConfig, fetch::FetchError};
+use crate::{
+    auth::{Authenticator, SecretStore},
+    config::AppConfig,
+    fetch::FetchError,
+    limits::DestinationLimiter,
+};
 
 pub struct AppContext {
     pub worker: Worker,
     pub client: reqwest::Client,
     pub kafka_producer: FutureProducer<KafkaContext>,
     pub concurrency_limit: Arc<Semaphore>,
+    pub destination_limiter: DestinationLimiter,
+    pub authenticator: Authenticator,
     pub liveness: HealthHandle,
     pub config: AppConfig,
     pub metric_labels: RwLock<Vec<(String, String)>>,
@@ -31,6 +38,11 @@ impl AppContext {
         kafka_liveness: HealthHandle,
     ) -> Result<Self, FetchError> {
         let concurrency_limit = Arc::new(Semaphore::new(config.concurrent_requests_limit as usize));
+        let destination_limiter = DestinationLimiter::new(config.destination_limits.clone());
+        let authenticator = Authenticator::new(
+            SecretStore::new(config.secrets_dir.as_ref().map(Into::into)),
+            config.auth_policy.clone(),
+        );
 
         let resolver = Arc::new(common_dns::PublicIPv4Resolver {});
 
@@ -69,6 +81,8 @@ impl AppContext {
             client,
             kafka_producer,
             concurrency_limit,
+            destination_limiter,
+            authenticator,
             liveness,
             config,
             metric_labels: RwLock::new(labels),
@@ -11,7 +11,12 @@ use tokio::sync::OwnedSemaphorePermit;
 use tracing::{error, instrument, warn};
 use uuid::Uuid;
 
-use crate::{context::AppContext, metrics_constants::*};
+use crate::{
+    auth::{AuthDescriptor, AuthError},
+    context::AppContext,
+    limits::{parse_retry_after, Admission},
+    metrics_constants::*,
+};
 
 // TODO - a lot of these should maybe be configurable
 pub const DEFAULT_RETRIES: u32 = 3;
@@ -70,8 +75,9 @@ pub struct FetchParameters {
     pub method: HttpMethod,
     pub return_queue: String,
     pub headers: Option<HashMap<String, String>>,
-    pub max_tries: Option<u32>,      // Defaults to 3
-    pub on_finish: Option<OnFinish>, // Defaults to Return
+    pub max_tries: Option<u32>,       // Defaults to 3
+    pub on_finish: Option<OnFinish>,  // Defaults to Return
+    pub auth: Option<AuthDescriptor>, // Defaults to no auth beyond the static headers
 }
 
 // What should we do when we get a result, or run out of tries for a given job?
@@ -90,6 +96,9 @@ pub struct FetchMetadata {
     tries: u32,
     // The history of failures seen with this job
     trace: Vec<FetchFailure>,
+    // How many times we've rescheduled the job because the destination sent a Retry-After
+    #[serde(default)]
+    retry_afters: u32,
 }
 
 // This is what we put in the parameters of the job queue for the next
@@ -201,6 +210,12 @@ impl From<reqwest::Error> for FetchFailure {
     }
 }
 
+impl From<AuthError> for FetchFailure {
+    fn from(e: AuthError) -> Self {
+        FetchFailure::new(FetchFailureKind::AuthFailed, e.to_string())
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum FetchFailureKind {
@@ -212,6 +227,7 @@ pub enum FetchFailureKind {
     FailureStatus,
     InvalidBody, // We force bodies to be a utf8 string, for the sake of callers. TODO - we should consider letting callers enforce a body schema
     ResponseTooLarge,
+    AuthFailed,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -286,6 +302,7 @@ impl From<&Job> for FetchMetadata {
             return FetchMetadata {
                 tries: 0,
                 trace: vec![],
+                retry_afters: 0,
             };
         };
 
@@ -293,6 +310,7 @@ impl From<&Job> for FetchMetadata {
             return FetchMetadata {
                 tries: 0,
                 trace: vec![],
+                retry_afters: 0,
             };
         };
 
@@ -374,7 +392,7 @@ pub async fn run_job(
         }
     };
 
-    let headers = match (&params.headers.unwrap_or_default()).try_into() {
+    let mut headers: http::HeaderMap = match (&params.headers.unwrap_or_default()).try_into() {
         Ok(h) => h,
         Err(e) => {
             warn!("Failed to parse headers: {}", e);
@@ -399,7 +417,62 @@ pub async fn run_job(
         }
     };
 
-    let body = reqwest::Body::from(job.blob.unwrap_or_default());
+    // Throttling doesn't count against the job's tries - we just put it back in the queue for later
+    let host = url.host_str().unwrap_or_default().to_string();
+    let _destination_permit = match context
+        .destination_limiter
+        .try_acquire(&host, job.function_id)
+    {
+        Admission::Admitted(permit) => permit,
+        Admission::Throttled { until, reason } => {
+            let mut labels = labels.clone();
+            labels.push((
+                THROTTLE_REASON_LABEL.to_string(),
+                reason.as_str().to_string(),
+            ));
+            common_metrics::inc(FETCH_THROTTLED, &labels, 1);
+            let res = reschedule_job(&context, job.id, until).await;
+            job_total.label(OUTCOME_LABEL, "throttled").fin();
+            return res;
+        }
+    };
+
+    let body = job.blob.unwrap_or_default();
+
+    if let Some(auth) = &params.auth {
+        let authorized = context
+            .authenticator
+            .authorize(
+                &context.client,
+                job.team_id,
+                auth,
+                &method,
+                &url,
+                &mut headers,
+                &body,
+            )
+            .await;
+        if let Err(e) = authorized {
+            warn!("Failed to authorize request: {}", e);
+            // Auth failures are usually transient (a token endpoint being down, a secret not yet
+            // mounted), so we retry them like any other failure
+            let res = handle_fetch_failure(
+                &context,
+                job.id,
+                job.priority,
+                &metadata,
+                params.max_tries.unwrap_or(DEFAULT_RETRIES),
+                params.return_queue,
+                params.on_finish.unwrap_or(DEFAULT_ON_FINISH),
+                e,
+            )
+            .await;
+            job_total.label(OUTCOME_LABEL, "auth_failed").fin();
+            return res;
+        }
+    }
+
+    let body = reqwest::Body::from(body);
 
     let mut send_fut = context
         .client
@@ -461,6 +534,46 @@ pub async fn run_job(
         .collect();
 
     request_time.label(OUTCOME_LABEL, &status.to_string()).fin();
+
+    // If the destination told us when to come back, we pause it until then, and reschedule the job
+    // for that time without burning a try. A destination that keeps asking us to wait only gets
+    // so many of those, after which the response is treated like any other failure status
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        let retry_after = headers
+            .get(http::header::RETRY_AFTER.as_str())
+            .and_then(|v| parse_retry_after(v, Utc::now()));
+        if let Some(until) = retry_after {
+            let until = context.destination_limiter.clamp_retry_after(until);
+            context.destination_limiter.pause_host(&host, until);
+        }
+        let max_retry_afters = context.config.destination_limits.max_retry_afters;
+        if let Some(until) = retry_after.filter(|_| metadata.retry_afters < max_retry_afters) {
+            let mut labels = labels.clone();
+            labels.push((RESPONSE_STATUS_LABEL.to_string(), status.to_string()));
+            common_metrics::inc(RESPONSE_RECEIVED, &labels, 1);
+            common_metrics::inc(RETRY_AFTER_HONOURED, &labels, 1);
+
+            let mut metadata = metadata.clone();
+            metadata.retry_afters += 1;
+            context
+                .worker
+                .set_metadata(job.id, Some(serde_json::to_vec(&metadata)?))?;
+            let res = reschedule_job(&context, job.id, until).await;
+            job_total
+                .label(RESPONSE_STATUS_LABEL, &status.to_string())
+                .label(OUTCOME_LABEL, "retry_after")
+                .fin();
+            return res;
+        }
+    }
+
+    // If the destination rejected our token, make sure the retry gets a fresh one
+    if status == StatusCode::UNAUTHORIZED {
+        if let Some(auth) = &params.auth {
+            context.authenticator.invalidate(job.team_id, auth);
+        }
+    }
+
     // Label the job with the request status, re-binding to avoid dropping the guard
     let job_total = job_total.label(RESPONSE_STATUS_LABEL, &status.to_string());
 
@@ -618,6 +731,30 @@ where
     Ok(())
 }
 
+// Put the job back in the queue to be run at (roughly) the given time, without touching its
+// priority or its count of tries - used when we chose not to run it, or the destination asked us
+// to wait, neither of which should count as a failed try.
+pub async fn reschedule_job(
+    context: &AppContext,
+    job_id: Uuid,
+    until: DateTime<Utc>,
+) -> Result<(), FetchError> {
+    // Spread rescheduled jobs out a bit, so they don't all come back at once and get throttled again
+    let jitter_ms = context
+        .config
+        .destination_limits
+        .throttle_delay
+        .num_milliseconds()
+        .max(1) as u64;
+    let until = until + Duration::milliseconds((rand::random::<u64>() % jitter_ms) as i64);
+
+    context.worker.set_state(job_id, JobState::Available)?;
+    context.worker.set_scheduled_at(job_id, until)?;
+    context.worker.release_job(job_id, None).await?;
+
+    Ok(())
+}
+
 // Complete the job with some result.
 pub async fn complete_job(
     worker: &Worker,
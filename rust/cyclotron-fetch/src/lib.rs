@@ -1,4 +1,6 @@
+pub mod auth;
 pub mod config;
 pub mod context;
 pub mod fetch;
+pub mod limits;
 pub mod metrics_constants;
@@ -0,0 +1,521 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    hash::Hash,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use uuid::Uuid;
+
+// Past this many tracked destinations, we drop the state of any that are idle, so that a long
+// tail of one-off hosts doesn't grow the limiter without bound.
+const MAX_TRACKED_DESTINATIONS: usize = 10_000;
+
+// Limits applied to a single destination. A rate of 0 means no rate limit, and a max_concurrent
+// of 0 means no concurrency cap. A burst of 0 means "one second's worth of requests".
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DestinationLimit {
+    pub rate_per_second: f64,
+    pub burst: u32,
+    pub max_concurrent: u32,
+}
+
+impl DestinationLimit {
+    fn is_rate_limited(&self) -> bool {
+        self.rate_per_second > 0.0
+    }
+
+    fn capacity(&self) -> f64 {
+        if self.burst > 0 {
+            self.burst as f64
+        } else {
+            self.rate_per_second.ceil().max(1.0)
+        }
+    }
+}
+
+// Parsed from "<rate_per_second>[/<burst>[/<max_concurrent>]]", e.g. "10/20/5"
+impl FromStr for DestinationLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/').map(str::trim);
+
+        let rate_per_second = parts
+            .next()
+            .unwrap_or_default()
+            .parse::<f64>()
+            .map_err(|e| format!("invalid rate in limit {:?}: {}", s, e))?;
+        let burst = match parts.next() {
+            Some(b) => b
+                .parse::<u32>()
+                .map_err(|e| format!("invalid burst in limit {:?}: {}", s, e))?,
+            None => 0,
+        };
+        let max_concurrent = match parts.next() {
+            Some(c) => c
+                .parse::<u32>()
+                .map_err(|e| format!("invalid concurrency in limit {:?}: {}", s, e))?,
+            None => 0,
+        };
+
+        if parts.next().is_some() || rate_per_second < 0.0 || !rate_per_second.is_finite() {
+            return Err(format!("invalid limit {:?}", s));
+        }
+
+        Ok(Self {
+            rate_per_second,
+            burst,
+            max_concurrent,
+        })
+    }
+}
+
+// A comma separated list of "<key>=<limit>" pairs, e.g. "api.example.com=10/20/5,hooks.example.com=1"
+#[derive(Debug, Clone)]
+pub struct LimitOverrides<K>(pub HashMap<K, DestinationLimit>);
+
+impl<K> Default for LimitOverrides<K> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+impl<K> FromStr for LimitOverrides<K>
+where
+    K: FromStr + Hash + Eq,
+    K::Err: Display,
+{
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut overrides = HashMap::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let Some((key, limit)) = entry.split_once('=') else {
+                return Err(format!("expected <key>=<limit>, got {:?}", entry));
+            };
+            let key = key
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid key {:?}: {}", key, e))?;
+            overrides.insert(key, limit.parse()?);
+        }
+        Ok(Self(overrides))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DestinationLimitsConfig {
+    pub default_host_limit: DestinationLimit,
+    pub host_overrides: HashMap<String, DestinationLimit>,
+    pub function_overrides: HashMap<Uuid, DestinationLimit>,
+    pub throttle_delay: Duration, // How long we push a job back by when a destination is at its concurrency cap
+    pub max_retry_after: Duration, // The longest Retry-After we'll honour - longer ones are clamped to this
+    pub max_retry_afters: u32, // How many Retry-Afters we honour per job before they count as failed tries
+}
+
+impl Default for DestinationLimitsConfig {
+    fn default() -> Self {
+        Self {
+            default_host_limit: Default::default(),
+            host_overrides: Default::default(),
+            function_overrides: Default::default(),
+            throttle_delay: Duration::seconds(1),
+            max_retry_after: Duration::hours(1),
+            max_retry_afters: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleReason {
+    RateLimited,
+    ConcurrencyLimited,
+    RetryAfter,
+}
+
+impl ThrottleReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThrottleReason::RateLimited => "rate_limited",
+            ThrottleReason::ConcurrencyLimited => "concurrency_limited",
+            ThrottleReason::RetryAfter => "retry_after",
+        }
+    }
+}
+
+// Held for the duration of a request, releasing the destination's concurrency slots on drop
+pub struct DestinationPermit {
+    _permits: Vec<OwnedSemaphorePermit>,
+}
+
+pub enum Admission {
+    Admitted(DestinationPermit),
+    // The job should not be run now, and should be rescheduled for (roughly) `until`
+    Throttled {
+        until: DateTime<Utc>,
+        reason: ThrottleReason,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LimitKey {
+    Host(String),
+    Function(Uuid),
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: DateTime<Utc>,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+// A token bucket, plus a concurrency cap, for a single destination
+struct Bucket {
+    limit: DestinationLimit,
+    concurrency: Option<Arc<Semaphore>>,
+    state: Mutex<BucketState>,
+}
+
+impl Bucket {
+    fn new(limit: DestinationLimit, now: DateTime<Utc>) -> Self {
+        let concurrency = (limit.max_concurrent > 0)
+            .then(|| Arc::new(Semaphore::new(limit.max_concurrent as usize)));
+        Self {
+            limit,
+            concurrency,
+            state: Mutex::new(BucketState {
+                tokens: limit.capacity(),
+                refilled_at: now,
+                paused_until: None,
+            }),
+        }
+    }
+
+    fn refill(&self, state: &mut BucketState, now: DateTime<Utc>) {
+        let elapsed = (now - state.refilled_at).num_milliseconds().max(0) as f64 / 1000.0;
+        state.tokens =
+            (state.tokens + elapsed * self.limit.rate_per_second).min(self.limit.capacity());
+        state.refilled_at = now;
+    }
+
+    fn take_token(&self, now: DateTime<Utc>) -> Result<(), (DateTime<Utc>, ThrottleReason)> {
+        let mut state = self.state.lock().unwrap();
+
+        match state.paused_until {
+            Some(until) if until > now => return Err((until, ThrottleReason::RetryAfter)),
+            Some(_) => state.paused_until = None,
+            None => {}
+        }
+
+        if !self.limit.is_rate_limited() {
+            return Ok(());
+        }
+
+        self.refill(&mut state, now);
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let wait_ms = ((1.0 - state.tokens) / self.limit.rate_per_second * 1000.0).ceil() as i64;
+        Err((
+            now + Duration::milliseconds(wait_ms),
+            ThrottleReason::RateLimited,
+        ))
+    }
+
+    fn refund_token(&self) {
+        if !self.limit.is_rate_limited() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        state.tokens = (state.tokens + 1.0).min(self.limit.capacity());
+    }
+
+    fn pause_until(&self, until: DateTime<Utc>) {
+        let mut state = self.state.lock().unwrap();
+        state.paused_until = Some(state.paused_until.map_or(until, |u| u.max(until)));
+    }
+
+    // A bucket is idle if dropping it and recreating it later would be indistinguishable
+    fn is_idle(&self, now: DateTime<Utc>) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.paused_until.is_some_and(|u| u > now) {
+            return false;
+        }
+        self.refill(&mut state, now);
+        let tokens_full = !self.limit.is_rate_limited() || state.tokens >= self.limit.capacity();
+        let no_requests = self.concurrency.as_ref().map_or(true, |s| {
+            s.available_permits() == self.limit.max_concurrent as usize
+        });
+        tokens_full && no_requests
+    }
+}
+
+// Per-destination rate limits and concurrency caps. Every request is subject to the limit of
+// its host (the configured default, or a per-host override), and additionally to the limit of
+// its function, if that function has one configured.
+pub struct DestinationLimiter {
+    config: DestinationLimitsConfig,
+    buckets: Mutex<HashMap<LimitKey, Arc<Bucket>>>,
+}
+
+impl DestinationLimiter {
+    pub fn new(mut config: DestinationLimitsConfig) -> Self {
+        // Url hosts are always lowercase, so we normalise configured ones to match
+        config.host_overrides = config
+            .host_overrides
+            .into_iter()
+            .map(|(host, limit)| (host.to_lowercase(), limit))
+            .collect();
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn try_acquire(&self, host: &str, function_id: Option<Uuid>) -> Admission {
+        let now = Utc::now();
+        let buckets = self.buckets_for(host, function_id, now);
+
+        // Concurrency first, since unused concurrency permits are returned on drop, whereas
+        // tokens have to be explicitly refunded
+        let mut permits = Vec::with_capacity(buckets.len());
+        for bucket in &buckets {
+            let Some(semaphore) = &bucket.concurrency else {
+                continue;
+            };
+            match semaphore.clone().try_acquire_owned() {
+                Ok(permit) => permits.push(permit),
+                Err(_) => {
+                    return Admission::Throttled {
+                        until: now + self.config.throttle_delay,
+                        reason: ThrottleReason::ConcurrencyLimited,
+                    }
+                }
+            }
+        }
+
+        for (i, bucket) in buckets.iter().enumerate() {
+            if let Err((until, reason)) = bucket.take_token(now) {
+                buckets[..i].iter().for_each(|b| b.refund_token());
+                return Admission::Throttled { until, reason };
+            }
+        }
+
+        Admission::Admitted(DestinationPermit { _permits: permits })
+    }
+
+    // Hold off all requests to the host until the given time, e.g. because it sent us a Retry-After
+    pub fn pause_host(&self, host: &str, until: DateTime<Utc>) {
+        let now = Utc::now();
+        let key = LimitKey::Host(host.to_lowercase());
+        let limit = self.limit_for(&key).unwrap_or_default();
+        self.bucket(key, limit, now).pause_until(until);
+    }
+
+    // Clamp a requested Retry-After to the longest we're willing to honour
+    pub fn clamp_retry_after(&self, until: DateTime<Utc>) -> DateTime<Utc> {
+        until.min(Utc::now() + self.config.max_retry_after)
+    }
+
+    fn limit_for(&self, key: &LimitKey) -> Option<DestinationLimit> {
+        match key {
+            LimitKey::Host(host) => Some(
+                self.config
+                    .host_overrides
+                    .get(host)
+                    .copied()
+                    .unwrap_or(self.config.default_host_limit),
+            ),
+            LimitKey::Function(id) => self.config.function_overrides.get(id).copied(),
+        }
+    }
+
+    fn buckets_for(
+        &self,
+        host: &str,
+        function_id: Option<Uuid>,
+        now: DateTime<Utc>,
+    ) -> Vec<Arc<Bucket>> {
+        // We always track the host, even if it's unlimited, so we can pause it on Retry-After
+        let host_key = LimitKey::Host(host.to_lowercase());
+        let host_limit = self.limit_for(&host_key).unwrap_or_default();
+        let mut buckets = vec![self.bucket(host_key, host_limit, now)];
+
+        let function_key = function_id.map(LimitKey::Function);
+        if let Some(key) = function_key {
+            if let Some(limit) = self.limit_for(&key) {
+                buckets.push(self.bucket(key, limit, now));
+            }
+        }
+
+        buckets
+    }
+
+    fn bucket(&self, key: LimitKey, limit: DestinationLimit, now: DateTime<Utc>) -> Arc<Bucket> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.get(&key) {
+            return bucket.clone();
+        }
+
+        if buckets.len() >= MAX_TRACKED_DESTINATIONS {
+            buckets.retain(|_, b| !b.is_idle(now));
+        }
+
+        let bucket = Arc::new(Bucket::new(limit, now));
+        buckets.insert(key, bucket.clone());
+        bucket
+    }
+}
+
+// Retry-After is either a number of seconds, or an HTTP date
+pub fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u32>() {
+        return Some(now + Duration::seconds(seconds as i64));
+    }
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|d| d.with_timezone(&Utc).max(now))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn limiter(default_host_limit: &str, function_overrides: &str) -> DestinationLimiter {
+        let function_overrides: LimitOverrides<Uuid> = function_overrides.parse().unwrap();
+        DestinationLimiter::new(DestinationLimitsConfig {
+            default_host_limit: default_host_limit.parse().unwrap(),
+            function_overrides: function_overrides.0,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limit: DestinationLimit = "10/20/5".parse().unwrap();
+        assert_eq!(limit.rate_per_second, 10.0);
+        assert_eq!(limit.burst, 20);
+        assert_eq!(limit.max_concurrent, 5);
+
+        let limit: DestinationLimit = "0.5".parse().unwrap();
+        assert_eq!(limit.capacity(), 1.0);
+        assert_eq!(limit.max_concurrent, 0);
+
+        assert!("fast".parse::<DestinationLimit>().is_err());
+        assert!("1/2/3/4".parse::<DestinationLimit>().is_err());
+        assert!("-1".parse::<DestinationLimit>().is_err());
+
+        let overrides: LimitOverrides<String> = "api.example.com=10/20/5, hooks.example.com=1"
+            .parse()
+            .unwrap();
+        assert_eq!(overrides.0.len(), 2);
+        assert_eq!(overrides.0["hooks.example.com"].rate_per_second, 1.0);
+
+        assert!("".parse::<LimitOverrides<String>>().unwrap().0.is_empty());
+        assert!("api.example.com".parse::<LimitOverrides<String>>().is_err());
+        assert!("not-a-uuid=1".parse::<LimitOverrides<Uuid>>().is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = limiter("1/2", "");
+
+        assert!(matches!(
+            limiter.try_acquire("example.com", None),
+            Admission::Admitted(_)
+        ));
+        assert!(matches!(
+            limiter.try_acquire("example.com", None),
+            Admission::Admitted(_)
+        ));
+        let Admission::Throttled { until, reason } = limiter.try_acquire("example.com", None)
+        else {
+            panic!("Expected the burst to be exhausted");
+        };
+        assert_eq!(reason, ThrottleReason::RateLimited);
+        assert!(until > Utc::now());
+
+        // Other hosts have their own bucket
+        assert!(matches!(
+            limiter.try_acquire("other.example.com", None),
+            Admission::Admitted(_)
+        ));
+    }
+
+    #[test]
+    fn test_concurrency_limit() {
+        let function_id = Uuid::now_v7();
+        let limiter = limiter("0", &format!("{}=0/0/1", function_id));
+
+        let permit = limiter.try_acquire("example.com", Some(function_id));
+        assert!(matches!(permit, Admission::Admitted(_)));
+
+        let Admission::Throttled { reason, .. } =
+            limiter.try_acquire("other.example.com", Some(function_id))
+        else {
+            panic!("Expected the function to be at its concurrency cap");
+        };
+        assert_eq!(reason, ThrottleReason::ConcurrencyLimited);
+
+        // Other functions, and requests without a function, are unaffected
+        assert!(matches!(
+            limiter.try_acquire("example.com", Some(Uuid::now_v7())),
+            Admission::Admitted(_)
+        ));
+        assert!(matches!(
+            limiter.try_acquire("example.com", None),
+            Admission::Admitted(_)
+        ));
+
+        drop(permit);
+        assert!(matches!(
+            limiter.try_acquire("other.example.com", Some(function_id)),
+            Admission::Admitted(_)
+        ));
+    }
+
+    #[test]
+    fn test_pause_host() {
+        let limiter = limiter("0", "");
+        let until = Utc::now() + Duration::seconds(30);
+
+        limiter.pause_host("Example.com", until);
+
+        let Admission::Throttled {
+            until: throttled_until,
+            reason,
+        } = limiter.try_acquire("example.com", None)
+        else {
+            panic!("Expected the host to be paused");
+        };
+        assert_eq!(reason, ThrottleReason::RetryAfter);
+        assert_eq!(throttled_until, until);
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = Utc::now();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(now + Duration::seconds(120))
+        );
+
+        let date = parse_retry_after("Wed, 21 Oct 2065 07:28:00 GMT", now).unwrap();
+        assert_eq!(date.to_rfc3339(), "2065-10-21T07:28:00+00:00");
+
+        // Dates in the past mean "now"
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now),
+            Some(now)
+        );
+
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -42,9 +42,21 @@ async fn worker_loop(context: AppContext) -> Result<(), FetchError> {
         context.liveness.report_healthy().await;
         let started = tick(context.clone()).await?;
         info!("started {} jobs", started);
-        // This will happen if 1) there are no jobs or 2) we have no capacity to start new jobs. Either way, we should sleep for a bit
+        // This will happen if 1) there are no jobs or 2) we have no capacity to start new jobs. In the
+        // first case, we wait to be notified of new jobs (polling as a fallback), and in the second we
+        // just sleep for a bit
         if started == 0 {
-            tokio::time::sleep(context.config.job_poll_interval.to_std().unwrap()).await;
+            if context.concurrency_limit.available_permits() > 0 {
+                context
+                    .worker
+                    .wait_for_jobs(
+                        &context.config.queue_served,
+                        context.config.job_poll_interval,
+                    )
+                    .await;
+            } else {
+                tokio::time::sleep(context.config.job_poll_interval.to_std().unwrap()).await;
+            }
         }
     }
 }
@@ -12,7 +12,10 @@ pub const FETCH_DEAD_LETTER: &str = "cyclotron_fetch_dead_letter";
 pub const RESPONSE_RECEIVED: &str = "cyclotron_fetch_got_response";
 pub const BODY_FETCH_FAILED: &str = "cyclotron_fetch_body_fetch_failed";
 pub const BODY_FETCH_SUCCEEDED: &str = "cyclotron_fetch_body_fetch_succeeded";
+pub const FETCH_THROTTLED: &str = "cyclotron_fetch_throttled";
+pub const RETRY_AFTER_HONOURED: &str = "cyclotron_fetch_retry_after_honoured";
 
 // Label keys
 pub const OUTCOME_LABEL: &str = "outcome";
 pub const RESPONSE_STATUS_LABEL: &str = "response_status";
+pub const THROTTLE_REASON_LABEL: &str = "reason";
@@ -1,8 +1,11 @@
 use std::{collections::HashMap, sync::Arc};
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use cyclotron_core::{QueueManager, Worker};
-use cyclotron_fetch::fetch::{tick, FetchResult, HttpMethod};
+use cyclotron_fetch::{
+    auth::{AuthDescriptor, AuthPolicy, Authenticator, SecretStore},
+    fetch::{tick, FetchResult, HttpMethod},
+};
 use httpmock::{Method, MockServer};
 use serde_json::json;
 use sqlx::PgPool;
@@ -310,3 +313,198 @@ pub async fn test_completes_fetch_with_vm_state(db: PgPool) {
 
     mock.assert_hits(1);
 }
+
+#[sqlx::test(migrations = "../cyclotron-core/migrations")]
+pub async fn test_honours_retry_after(db: PgPool) {
+    let context = Arc::new(get_app_test_context(db.clone()).await);
+    let producer = QueueManager::from_pool(db.clone(), true, true);
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(Method::GET).path("/test");
+        then.status(429).header("Retry-After", "30");
+    });
+
+    let params = construct_params(server.url("/test"), HttpMethod::Get);
+    let job = construct_job(params, None);
+    producer.create_job(job).await.unwrap();
+
+    let started = tick(context.clone()).await.unwrap();
+    assert_eq!(started, 1);
+    wait_on_no_running(&db, Duration::milliseconds(500)).await;
+
+    // The job goes back to the fetch queue, scheduled for when the server asked us to come back,
+    // without having used up a try
+    let (queue_name, scheduled, metadata): (String, DateTime<Utc>, Option<Vec<u8>>) =
+        sqlx::query_as("SELECT queue_name, scheduled, metadata FROM cyclotron_jobs")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(queue_name, "fetch");
+    assert!(scheduled > Utc::now() + Duration::seconds(25));
+    let metadata: serde_json::Value = serde_json::from_slice(&metadata.unwrap()).unwrap();
+    assert_eq!(metadata["tries"], 0);
+    assert_eq!(metadata["retry_afters"], 1);
+
+    // And the host is paused, so even if the job becomes available again, we don't hit it
+    make_immediately_available(&db).await;
+    let started = tick(context.clone()).await.unwrap();
+    assert_eq!(started, 1);
+    wait_on_no_running(&db, Duration::milliseconds(500)).await;
+
+    mock.assert_hits(1);
+}
+
+#[sqlx::test(migrations = "../cyclotron-core/migrations")]
+pub async fn test_caps_retry_afters(db: PgPool) {
+    let mut context = get_app_test_context(db.clone()).await;
+    context.config.destination_limits.max_retry_afters = 1;
+    let context = Arc::new(context);
+    let producer = QueueManager::from_pool(db.clone(), true, true);
+    let return_worker = Worker::from_pool(db.clone(), Default::default());
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(Method::GET).path("/test");
+        then.status(503).header("Retry-After", "0");
+    });
+
+    let mut params = construct_params(server.url("/test"), HttpMethod::Get);
+    params.max_tries = Some(1);
+    let job = construct_job(params, None);
+    producer.create_job(job).await.unwrap();
+
+    // The first Retry-After is honoured without using up the job's only try
+    let started = tick(context.clone()).await.unwrap();
+    assert_eq!(started, 1);
+    wait_on_no_running(&db, Duration::milliseconds(500)).await;
+    make_immediately_available(&db).await;
+
+    // But once the job has used up its Retry-Afters, the next one counts as a failed try
+    let started = tick(context.clone()).await.unwrap();
+    assert_eq!(started, 1);
+
+    let returned = wait_on_return(&return_worker, 1, false).await.unwrap();
+    let response: FetchResult =
+        serde_json::from_slice(returned[0].parameters.as_ref().unwrap()).unwrap();
+    let FetchResult::Failure { trace } = response else {
+        panic!("Expected failure response");
+    };
+    assert_eq!(trace.len(), 1);
+
+    mock.assert_hits(2);
+}
+
+#[sqlx::test(migrations = "../cyclotron-core/migrations")]
+pub async fn test_signs_requests_with_hmac(db: PgPool) {
+    let secrets = tempfile::tempdir().unwrap();
+    let team_secrets = secrets.path().join("1");
+    std::fs::create_dir(&team_secrets).unwrap();
+    std::fs::write(team_secrets.join("signing-key"), "secret\n").unwrap();
+
+    let mut context = get_app_test_context(db.clone()).await;
+    context.authenticator = Authenticator::new(
+        SecretStore::new(Some(secrets.path().into())),
+        AuthPolicy::default(),
+    );
+    let context = Arc::new(context);
+    let producer = QueueManager::from_pool(db.clone(), true, true);
+    let return_worker = Worker::from_pool(db.clone(), Default::default());
+    let server = MockServer::start();
+
+    let mock = server.mock(|when, then| {
+        when.method(Method::POST)
+            .path("/test")
+            .header_exists("x-signature")
+            .header_exists("x-timestamp");
+        then.status(200).body("Hello, world!");
+    });
+
+    let mut params = construct_params(server.url("/test"), HttpMethod::Post);
+    params.auth = Some(AuthDescriptor::Hmac {
+        secret_ref: "signing-key".to_string(),
+        signature_header: None,
+        timestamp_header: None,
+    });
+    let job = construct_job(params, Some(b"body".to_vec()));
+    producer.create_job(job).await.unwrap();
+
+    let started = tick(context).await.unwrap();
+    assert_eq!(started, 1);
+
+    let returned = wait_on_return(&return_worker, 1, false).await.unwrap();
+    let response: FetchResult =
+        serde_json::from_slice(returned[0].parameters.as_ref().unwrap()).unwrap();
+    assert!(response.is_success());
+
+    mock.assert_hits(1);
+}
+
+#[sqlx::test(migrations = "../cyclotron-core/migrations")]
+pub async fn test_caches_oauth_tokens(db: PgPool) {
+    let secrets = tempfile::tempdir().unwrap();
+    let team_secrets = secrets.path().join("1");
+    std::fs::create_dir(&team_secrets).unwrap();
+    std::fs::write(team_secrets.join("client-secret"), "shh").unwrap();
+
+    let mut context = get_app_test_context(db.clone()).await;
+    context.authenticator = Authenticator::new(
+        SecretStore::new(Some(secrets.path().into())),
+        AuthPolicy {
+            token_hosts: vec!["127.0.0.1".to_string()],
+            ..Default::default()
+        },
+    );
+    let context = Arc::new(context);
+    let producer = QueueManager::from_pool(db.clone(), true, true);
+    let return_worker = Worker::from_pool(db.clone(), Default::default());
+    let server = MockServer::start();
+
+    let token_mock = server.mock(|when, then| {
+        when.method(Method::POST)
+            .path("/token")
+            .body_contains("grant_type=client_credentials")
+            .body_contains("client_secret=shh");
+        then.status(200)
+            .json_body(json!({"access_token": "abc123", "expires_in": 3600}));
+    });
+
+    let mock = server.mock(|when, then| {
+        when.method(Method::GET)
+            .path("/test")
+            .header("authorization", "Bearer abc123");
+        then.status(200).body("Hello, world!");
+    });
+
+    for _ in 0..2 {
+        let mut params = construct_params(server.url("/test"), HttpMethod::Get);
+        params.auth = Some(AuthDescriptor::OauthClientCredentials {
+            token_url: server.url("/token"),
+            client_id: "client".to_string(),
+            client_secret_ref: "client-secret".to_string(),
+            scope: None,
+            audience: None,
+        });
+        producer
+            .create_job(construct_job(params, None))
+            .await
+            .unwrap();
+    }
+
+    // The test context only runs one job at a time
+    for _ in 0..2 {
+        let started = tick(context.clone()).await.unwrap();
+        assert_eq!(started, 1);
+        wait_on_no_running(&db, Duration::milliseconds(500)).await;
+    }
+
+    let returned = wait_on_return(&return_worker, 2, false).await.unwrap();
+    for job in returned {
+        let response: FetchResult =
+            serde_json::from_slice(job.parameters.as_ref().unwrap()).unwrap();
+        assert!(response.is_success());
+    }
+
+    token_mock.assert_hits(1);
+    mock.assert_hits(2);
+}
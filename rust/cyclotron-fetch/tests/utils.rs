@@ -5,9 +5,11 @@ use chrono::{Duration, Utc};
 use common_kafka::test::create_mock_kafka;
 use cyclotron_core::{Bytes, Job, JobInit, QueueError, Worker};
 use cyclotron_fetch::{
+    auth::{AuthPolicy, Authenticator, SecretStore},
     config::AppConfig,
     context::AppContext,
     fetch::{FetchParameters, HttpMethod},
+    limits::DestinationLimiter,
 };
 use sqlx::PgPool;
 use tokio::sync::Semaphore;
@@ -40,6 +42,9 @@ pub async fn get_app_test_context(db: PgPool) -> AppContext {
         allow_internal_ips: true,
         should_compress_vm_state: true,
         should_use_bulk_job_copy: true,
+        destination_limits: Default::default(),
+        secrets_dir: None,
+        auth_policy: AuthPolicy::default(),
     };
 
     let (_, mock_producer) = create_mock_kafka().await;
@@ -49,6 +54,8 @@ pub async fn get_app_test_context(db: PgPool) -> AppContext {
         client,
         kafka_producer: mock_producer,
         concurrency_limit,
+        destination_limiter: DestinationLimiter::new(config.destination_limits.clone()),
+        authenticator: Authenticator::new(SecretStore::new(None), AuthPolicy::default()),
         liveness,
         config,
         metric_labels: Default::default(),
@@ -63,6 +70,7 @@ pub fn construct_params(url: String, method: HttpMethod) -> FetchParameters {
         headers: None,
         max_tries: None,
         on_finish: None,
+        auth: None,
     }
 }
 
@@ -77,6 +85,11 @@ pub fn construct_job(parameters: FetchParameters, body: Option<Bytes>) -> JobIni
         parameters: Some(serde_json::to_vec(&parameters).unwrap()),
         blob: body,
         metadata: None,
+        idempotency_key: None,
+        on_key_conflict: Default::default(),
+        parents: vec![],
+        on_parent_failure: Default::default(),
+        retry_policy: None,
     }
 }
 
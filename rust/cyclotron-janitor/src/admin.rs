@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use chrono::{DateTime, Utc};
+use cyclotron_core::{CancelledJobs, JobFilter, QueueError, QueueManager};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+// Bulk job operations, exposed over HTTP for operators. Each janitor only operates on its own shard,
+// so acting on every shard means calling every janitor.
+pub fn admin_router(manager: Arc<QueueManager>) -> Router {
+    Router::new()
+        .route("/admin/jobs/cancel", post(cancel_jobs))
+        .route("/admin/jobs/pause", post(pause_jobs))
+        .route("/admin/jobs/resume", post(resume_jobs))
+        .route("/admin/jobs/reprioritize", post(reprioritize_jobs))
+        .route("/admin/jobs/reschedule", post(reschedule_jobs))
+        .with_state(manager)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FilterRequest {
+    pub filter: JobFilter,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReprioritizeRequest {
+    pub filter: JobFilter,
+    pub priority: i16,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RescheduleRequest {
+    pub filter: JobFilter,
+    pub scheduled: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AffectedResponse {
+    pub affected: u64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+type AdminResult<T> = Result<Json<T>, (StatusCode, Json<ErrorResponse>)>;
+
+async fn cancel_jobs(
+    State(manager): State<Arc<QueueManager>>,
+    Json(request): Json<FilterRequest>,
+) -> AdminResult<CancelledJobs> {
+    let res = manager
+        .cancel_jobs(&request.filter)
+        .await
+        .map_err(to_response)?;
+    info!(
+        "Cancelled {} jobs, and requested cancellation of {} running jobs, matching {:?}",
+        res.cancelled, res.cancel_requested, request.filter
+    );
+    Ok(Json(res))
+}
+
+async fn pause_jobs(
+    State(manager): State<Arc<QueueManager>>,
+    Json(request): Json<FilterRequest>,
+) -> AdminResult<AffectedResponse> {
+    let affected = manager
+        .pause_jobs(&request.filter)
+        .await
+        .map_err(to_response)?;
+    info!("Paused {} jobs matching {:?}", affected, request.filter);
+    Ok(Json(AffectedResponse { affected }))
+}
+
+async fn resume_jobs(
+    State(manager): State<Arc<QueueManager>>,
+    Json(request): Json<FilterRequest>,
+) -> AdminResult<AffectedResponse> {
+    let affected = manager
+        .resume_jobs(&request.filter)
+        .await
+        .map_err(to_response)?;
+    info!("Resumed {} jobs matching {:?}", affected, request.filter);
+    Ok(Json(AffectedResponse { affected }))
+}
+
+async fn reprioritize_jobs(
+    State(manager): State<Arc<QueueManager>>,
+    Json(request): Json<ReprioritizeRequest>,
+) -> AdminResult<AffectedResponse> {
+    let affected = manager
+        .reprioritize_jobs(&request.filter, request.priority)
+        .await
+        .map_err(to_response)?;
+    info!(
+        "Set priority of {} jobs matching {:?} to {}",
+        affected, request.filter, request.priority
+    );
+    Ok(Json(AffectedResponse { affected }))
+}
+
+async fn reschedule_jobs(
+    State(manager): State<Arc<QueueManager>>,
+    Json(request): Json<RescheduleRequest>,
+) -> AdminResult<AffectedResponse> {
+    let affected = manager
+        .reschedule_jobs(&request.filter, request.scheduled)
+        .await
+        .map_err(to_response)?;
+    info!(
+        "Rescheduled {} jobs matching {:?} to {}",
+        affected, request.filter, request.scheduled
+    );
+    Ok(Json(AffectedResponse { affected }))
+}
+
+fn to_response(e: QueueError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match e {
+        QueueError::InvalidFilter(_) => StatusCode::BAD_REQUEST,
+        _ => {
+            error!("admin operation failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    };
+    (
+        status,
+        Json(ErrorResponse {
+            error: e.to_string(),
+        }),
+    )
+}
@@ -54,6 +54,15 @@ pub struct Config {
 
     #[envconfig(default = "false")]
     pub should_compress_vm_state: bool, // Defaults to "false" (for now!)
+
+    #[envconfig(default = "true")]
+    pub recurring_scheduler_enabled: bool,
+
+    #[envconfig(default = "1000")]
+    pub recurring_schedule_batch_size: usize, // The max number of recurring jobs to enqueue instances of per run
+
+    #[envconfig(default = "false")]
+    pub admin_api_enabled: bool, // Serve the bulk job operations (cancel, pause etc) under /admin. These are unauthenticated.
 }
 
 #[allow(dead_code)]
@@ -77,6 +86,9 @@ impl Config {
             max_touches: self.janitor_max_touches,
             id: self.janitor_id.clone(),
             shard_id: self.shard_id.clone(),
+            schedule_recurring_jobs: self.recurring_scheduler_enabled,
+            recurring_schedule_batch_size: self.recurring_schedule_batch_size,
+            should_compress_vm_state: self.should_compress_vm_state,
         };
 
         JanitorConfig {
@@ -98,4 +110,7 @@ pub struct JanitorSettings {
     pub max_touches: i16,
     pub id: String,
     pub shard_id: String,
+    pub schedule_recurring_jobs: bool,
+    pub recurring_schedule_batch_size: usize,
+    pub should_compress_vm_state: bool,
 }
@@ -22,6 +22,9 @@ pub struct CleanupResult {
     pub failed: u64,
     pub poisoned: u64,
     pub stalled: u64,
+    pub cancelled: u64,
+    pub recurring_scheduled: u64,
+    pub orphaned_parents: u64,
 }
 
 pub struct Janitor {
@@ -133,6 +136,50 @@ impl Janitor {
             warn!("Reset {} stalled jobs", stalled);
         }
 
+        // Jobs cancelled while running, that were reset or dead lettered above before their worker
+        // noticed the cancellation
+        let cancelled = {
+            let _time = common_metrics::timing_guard(CANCELLED_TIME, &self.metrics_labels);
+            self.inner.delete_cancelled_jobs().await?
+        };
+        common_metrics::inc(CANCELLED_COUNT, &self.metrics_labels, cancelled);
+
+        if cancelled > 0 {
+            info!("Deleted {} cancelled jobs", cancelled);
+        }
+
+        let recurring_scheduled = if self.settings.schedule_recurring_jobs {
+            let _time =
+                common_metrics::timing_guard(RECURRING_SCHEDULED_TIME, &self.metrics_labels);
+            self.inner
+                .schedule_recurring_jobs(
+                    self.settings.recurring_schedule_batch_size,
+                    self.settings.should_compress_vm_state,
+                )
+                .await?
+        } else {
+            0
+        };
+        common_metrics::inc(
+            RECURRING_SCHEDULED_COUNT,
+            &self.metrics_labels,
+            recurring_scheduled,
+        );
+
+        let orphaned_parents = {
+            let _time = common_metrics::timing_guard(ORPHANED_PARENTS_TIME, &self.metrics_labels);
+            self.inner.resolve_orphaned_dependencies().await?
+        };
+        common_metrics::inc(
+            ORPHANED_PARENTS_COUNT,
+            &self.metrics_labels,
+            orphaned_parents,
+        );
+
+        if orphaned_parents > 0 {
+            warn!("Resolved {} orphaned parent jobs", orphaned_parents);
+        }
+
         let available = {
             let _time = common_metrics::timing_guard(AVAILABLE_DEPTH_TIME, &self.metrics_labels);
             self.inner.waiting_jobs().await?
@@ -158,6 +205,9 @@ impl Janitor {
             failed: failed_count,
             poisoned,
             stalled,
+            cancelled,
+            recurring_scheduled,
+            orphaned_parents,
         })
     }
 }
@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod config;
 pub mod janitor;
 pub mod metrics_constants;
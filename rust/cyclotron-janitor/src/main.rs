@@ -1,10 +1,11 @@
 use axum::{extract::State, routing::get, Router};
 use common_metrics::setup_metrics_routes;
-use cyclotron_janitor::{config::Config, janitor::Janitor};
+use cyclotron_core::QueueManager;
+use cyclotron_janitor::{admin::admin_router, config::Config, janitor::Janitor};
 use envconfig::Envconfig;
 use eyre::Result;
 use health::{HealthHandle, HealthRegistry};
-use std::{future::ready, time::Duration};
+use std::{future::ready, sync::Arc, time::Duration};
 use tracing::{error, info};
 
 common_alloc::used!();
@@ -71,6 +72,13 @@ async fn main() {
 
     janitor.run_migrations().await;
 
+    // The admin API shares the janitor's pool, and so only operates on the janitor's shard
+    let admin_manager = Arc::new(QueueManager::from_pool(
+        janitor.inner.pool.clone(),
+        janitor.settings.should_compress_vm_state,
+        false,
+    ));
+
     let janitor_liveness = liveness
         .register(
             "janitor".to_string(),
@@ -84,7 +92,11 @@ async fn main() {
         config.cleanup_interval_secs,
     ));
 
-    let app = setup_metrics_routes(app(liveness, janitor_id));
+    let mut router = app(liveness, janitor_id);
+    if config.admin_api_enabled {
+        router = router.merge(admin_router(admin_manager));
+    }
+    let app = setup_metrics_routes(router);
     let http_server = tokio::spawn(listen(app, bind));
 
     tokio::select! {
@@ -12,6 +12,15 @@ pub const POISONED_TIME: &str = "cyclotron_janitor_poison_pills_cleanup_ms";
 pub const STALLED_COUNT: &str = "cyclotron_janitor_stalled_jobs_reset";
 pub const STALLED_TIME: &str = "cyclotron_janitor_stalled_jobs_reset_ms";
 
+pub const CANCELLED_COUNT: &str = "cyclotron_janitor_cancelled_jobs_deleted";
+pub const CANCELLED_TIME: &str = "cyclotron_janitor_cancelled_jobs_delete_ms";
+
+pub const RECURRING_SCHEDULED_COUNT: &str = "cyclotron_janitor_recurring_instances_scheduled";
+pub const RECURRING_SCHEDULED_TIME: &str = "cyclotron_janitor_recurring_jobs_schedule_ms";
+
+pub const ORPHANED_PARENTS_COUNT: &str = "cyclotron_janitor_orphaned_parents_resolved";
+pub const ORPHANED_PARENTS_TIME: &str = "cyclotron_janitor_orphaned_parents_resolve_ms";
+
 // The janitor should report some basic shard-level metrics
 pub const AVAILABLE_DEPTH: &str = "cyclotron_available_jobs";
 pub const AVAILABLE_DEPTH_TIME: &str = "cyclotron_available_jobs_ms";
@@ -51,6 +51,9 @@ async fn janitor_test(db: PgPool) {
         max_touches,
         id: "test_janitor".to_string(),
         shard_id: "test_shard".to_string(),
+        schedule_recurring_jobs: true,
+        recurring_schedule_batch_size: 1000,
+        should_compress_vm_state,
     };
     let janitor = Janitor {
         inner: cyclotron_core::Janitor::from_pool(db.clone()),
@@ -73,6 +76,11 @@ async fn janitor_test(db: PgPool) {
         parameters: None,
         blob: None,
         metadata: None,
+        idempotency_key: None,
+        on_key_conflict: Default::default(),
+        parents: vec![],
+        on_parent_failure: Default::default(),
+        retry_policy: None,
     };
 
     // First test - if we mark a job as completed, the janitor will clean it up
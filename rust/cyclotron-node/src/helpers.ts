@@ -1,4 +1,4 @@
-import { CyclotronInternalPoolConfig, CyclotronPoolConfig } from './types'
+import { CyclotronInternalPoolConfig, CyclotronPoolConfig, CyclotronRetryPolicy } from './types'
 
 export function convertToInternalPoolConfig(poolConfig: CyclotronPoolConfig): CyclotronInternalPoolConfig {
     return {
@@ -28,3 +28,14 @@ export function deserializeObject(name: string, str: any): Record<string, any> |
     }
     throw new Error(`${name} must be either a string or null`)
 }
+
+export function convertToInternalRetryPolicy(policy: CyclotronRetryPolicy): Record<string, any> {
+    return {
+        max_attempts: policy.maxAttempts,
+        initial_interval_ms: policy.initialIntervalMs,
+        backoff_multiplier: policy.backoffMultiplier ?? 2,
+        max_interval_ms: policy.maxIntervalMs,
+        jitter_percent: policy.jitterPercent ?? 0,
+        retryable_errors: policy.retryableErrors ?? null,
+    }
+}
@@ -1,7 +1,10 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 
 use cyclotron_core::{
-    Job, JobInit, JobState, ManagerConfig, PoolConfig, QueueManager, Worker, WorkerConfig,
+    AttemptError, CatchUpPolicy, DeadLetterEntry, DeadLetterFilter, DeadLetterGroup, Job, JobInit,
+    JobState, ManagerConfig, OnKeyConflict, OnParentFailure, ParentOutcome, PoolConfig,
+    QueueManager, RecurringJobInit, RecurringSchedule, ReplayedDeadLetters, RetryPolicy, Worker,
+    WorkerConfig,
 };
 use neon::{
     handle::Handle,
@@ -15,7 +18,7 @@ use neon::{
 };
 use once_cell::sync::OnceCell;
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tokio::runtime::Runtime;
 use uuid::Uuid;
@@ -164,6 +167,16 @@ pub struct JsJob {
     pub vm_state: Option<String>,
     pub parameters: Option<String>,
     pub metadata: Option<String>,
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    #[serde(default)]
+    pub on_key_conflict: OnKeyConflict,
+    #[serde(default)]
+    pub parents: Vec<Uuid>,
+    #[serde(default)]
+    pub on_parent_failure: OnParentFailure,
+    #[serde(default)]
+    pub retry_policy: Option<RetryPolicy>,
 }
 
 fn create_job(mut cx: FunctionContext) -> JsResult<JsPromise> {
@@ -288,6 +301,212 @@ fn bulk_create_jobs(mut cx: FunctionContext) -> JsResult<JsPromise> {
     Ok(promise)
 }
 
+#[derive(Debug, Deserialize)]
+pub struct JsRecurringJob {
+    pub template: JsJob,
+    pub schedule: RecurringSchedule,
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub catch_up: CatchUpPolicy,
+}
+
+fn create_recurring_job(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let arg1: Handle<JsString> = cx.argument::<JsString>(0)?;
+
+    let blob = cx.argument::<JsValue>(1)?;
+    let blob = if blob.is_a::<JsNull, _>(&mut cx) || blob.is_a::<JsUndefined, _>(&mut cx) {
+        None
+    } else {
+        Some(
+            blob.downcast_or_throw::<JsUint8Array, _>(&mut cx)?
+                .as_slice(&cx)
+                .to_vec(),
+        )
+    };
+
+    let js_job: JsRecurringJob = from_json_string(&mut cx, arg1)?;
+
+    let init = RecurringJobInit {
+        template: js_job.template.to_job_init(blob),
+        schedule: js_job.schedule,
+        timezone: js_job.timezone,
+        catch_up: js_job.catch_up,
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let manager = match MANAGER.get() {
+            Some(manager) => manager,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "manager not initialized")
+                });
+                return;
+            }
+        };
+        let res = manager.create_recurring_job(init).await;
+        deferred.settle_with(&channel, move |mut cx| {
+            let id = res.or_else(|e| cx.throw_error(format!("{}", e)))?;
+            Ok(cx.string(id.to_string()))
+        });
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum RecurringJobOp {
+    Pause,
+    Resume,
+    Delete,
+}
+
+enum DeadLetterOp {
+    List,
+    Group,
+    Replay,
+    Purge,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum DeadLetterResult {
+    Entries(Vec<DeadLetterEntry>),
+    Groups(Vec<DeadLetterGroup>),
+    Replayed(ReplayedDeadLetters),
+    Count(u64),
+}
+
+fn update_recurring_job(mut cx: FunctionContext, op: RecurringJobOp) -> JsResult<JsPromise> {
+    let arg1 = cx.argument::<JsString>(0)?.value(&mut cx);
+    let id: Uuid = arg1
+        .parse()
+        .or_else(|_| cx.throw_error(format!("invalid recurring job id: {}", arg1)))?;
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let manager = match MANAGER.get() {
+            Some(manager) => manager,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "manager not initialized")
+                });
+                return;
+            }
+        };
+        let res = match op {
+            RecurringJobOp::Pause => manager.pause_recurring_job(id).await,
+            RecurringJobOp::Resume => manager.resume_recurring_job(id).await,
+            RecurringJobOp::Delete => manager.delete_recurring_job(id).await,
+        };
+        deferred.settle_with(&channel, move |mut cx| {
+            res.or_else(|e| cx.throw_error(format!("{}", e)))?;
+            Ok(cx.null())
+        });
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
+fn pause_recurring_job(cx: FunctionContext) -> JsResult<JsPromise> {
+    update_recurring_job(cx, RecurringJobOp::Pause)
+}
+
+fn resume_recurring_job(cx: FunctionContext) -> JsResult<JsPromise> {
+    update_recurring_job(cx, RecurringJobOp::Resume)
+}
+
+fn delete_recurring_job(cx: FunctionContext) -> JsResult<JsPromise> {
+    update_recurring_job(cx, RecurringJobOp::Delete)
+}
+
+// Every dead letter operation takes a json filter, and resolves to a json string - the matching
+// entries or groups, or the number of entries replayed or purged
+fn dead_letter_op(mut cx: FunctionContext, op: DeadLetterOp) -> JsResult<JsPromise> {
+    let arg1: Handle<JsString> = cx.argument::<JsString>(0)?;
+    let filter: DeadLetterFilter = from_json_string(&mut cx, arg1)?;
+
+    let limit = match op {
+        DeadLetterOp::List => {
+            let limit = cx.argument::<JsNumber>(1)?.value(&mut cx);
+            if limit < 0.0 {
+                return cx.throw_error(format!("invalid limit: {}", limit));
+            }
+            limit as usize
+        }
+        _ => 0,
+    };
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let manager = match MANAGER.get() {
+            Some(manager) => manager,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "manager not initialized")
+                });
+                return;
+            }
+        };
+        let res = match op {
+            DeadLetterOp::List => manager
+                .list_dead_letters(&filter, limit)
+                .await
+                .map(DeadLetterResult::Entries),
+            DeadLetterOp::Group => manager
+                .group_dead_letters(&filter)
+                .await
+                .map(DeadLetterResult::Groups),
+            DeadLetterOp::Replay => manager
+                .replay_dead_letters(&filter)
+                .await
+                .map(DeadLetterResult::Replayed),
+            DeadLetterOp::Purge => manager
+                .purge_dead_letters(&filter)
+                .await
+                .map(DeadLetterResult::Count),
+        };
+        deferred.settle_with(&channel, move |mut cx| {
+            let value = res.or_else(|e| cx.throw_error(format!("{}", e)))?;
+            let json = to_json_string(&mut cx, value)?;
+            Ok(cx.string(json))
+        });
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
+fn list_dead_letters(cx: FunctionContext) -> JsResult<JsPromise> {
+    dead_letter_op(cx, DeadLetterOp::List)
+}
+
+fn group_dead_letters(cx: FunctionContext) -> JsResult<JsPromise> {
+    dead_letter_op(cx, DeadLetterOp::Group)
+}
+
+fn replay_dead_letters(cx: FunctionContext) -> JsResult<JsPromise> {
+    dead_letter_op(cx, DeadLetterOp::Replay)
+}
+
+fn purge_dead_letters(cx: FunctionContext) -> JsResult<JsPromise> {
+    dead_letter_op(cx, DeadLetterOp::Purge)
+}
+
 fn dequeue_jobs(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let queue_name = cx.argument::<JsString>(0)?.value(&mut cx);
 
@@ -320,6 +539,36 @@ fn dequeue_jobs(mut cx: FunctionContext) -> JsResult<JsPromise> {
     Ok(promise)
 }
 
+fn wait_for_jobs(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let queue_name = cx.argument::<JsString>(0)?.value(&mut cx);
+
+    let timeout_ms = cx.argument::<JsNumber>(1)?.value(&mut cx) as i64;
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let worker = match WORKER.get() {
+            Some(worker) => worker,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "worker not initialized")
+                });
+                return;
+            }
+        };
+        let notified = worker
+            .wait_for_jobs(&queue_name, Duration::milliseconds(timeout_ms))
+            .await;
+        deferred.settle_with(&channel, move |mut cx| Ok(cx.boolean(notified)));
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
 fn dequeue_with_vm_state(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let queue_name = cx.argument::<JsString>(0)?.value(&mut cx);
 
@@ -386,6 +635,121 @@ fn release_job(mut cx: FunctionContext) -> JsResult<JsPromise> {
     Ok(promise)
 }
 
+#[derive(Debug, Serialize)]
+struct JsParentResult {
+    parent_id: Uuid,
+    outcome: ParentOutcome,
+    result: Option<String>,
+}
+
+fn get_parent_results(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let arg1 = cx.argument::<JsString>(0)?.value(&mut cx);
+    let job_id: Uuid = arg1
+        .parse()
+        .or_else(|_| cx.throw_error(format!("invalid job id: {}", arg1)))?;
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let worker = match WORKER.get() {
+            Some(worker) => worker,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "worker not initialized")
+                });
+                return;
+            }
+        };
+        let res = worker.parent_results(job_id).await;
+        deferred.settle_with(&channel, move |mut cx| {
+            let results = res.or_else(|e| cx.throw_error(format!("{}", e)))?;
+            // Results are the parents' parameters, which node always writes as json strings
+            let results: Vec<JsParentResult> = results
+                .into_iter()
+                .map(|r| JsParentResult {
+                    parent_id: r.parent_id,
+                    outcome: r.outcome,
+                    result: r.result.map(|b| String::from_utf8_lossy(&b).into_owned()),
+                })
+                .collect();
+            let json = to_json_string(&mut cx, results)?;
+            Ok(cx.string(json))
+        });
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
+fn retry_job(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let arg1 = cx.argument::<JsString>(0)?.value(&mut cx);
+    let job_id: Uuid = arg1
+        .parse()
+        .or_else(|_| cx.throw_error(format!("invalid job id: {}", arg1)))?;
+    let arg2: Handle<JsString> = cx.argument::<JsString>(1)?;
+    let error: AttemptError = from_json_string(&mut cx, arg2)?;
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let worker = match WORKER.get() {
+            Some(worker) => worker,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "worker not initialized")
+                });
+                return;
+            }
+        };
+        let res = worker.retry(job_id, error).await;
+        deferred.settle_with(&channel, move |mut cx| {
+            let outcome = res.or_else(|e| cx.throw_error(format!("{}", e)))?;
+            let json = to_json_string(&mut cx, outcome)?;
+            Ok(cx.string(json))
+        });
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
+fn set_retry_policy(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let queue_name = cx.argument::<JsString>(0)?.value(&mut cx);
+    let arg2: Handle<JsString> = cx.argument::<JsString>(1)?;
+    let policy: Option<RetryPolicy> = from_json_string(&mut cx, arg2)?;
+
+    let (deferred, promise) = cx.promise();
+    let channel = cx.channel();
+    let runtime = runtime(&mut cx)?;
+
+    let fut = async move {
+        let manager = match MANAGER.get() {
+            Some(manager) => manager,
+            None => {
+                deferred.settle_with(&channel, |mut cx| {
+                    throw_null_err(&mut cx, "manager not initialized")
+                });
+                return;
+            }
+        };
+        let res = manager.set_retry_policy(&queue_name, policy).await;
+        deferred.settle_with(&channel, move |mut cx| {
+            res.or_else(|e| cx.throw_error(format!("{}", e)))?;
+            Ok(cx.null())
+        });
+    };
+
+    runtime.spawn(fut);
+
+    Ok(promise)
+}
+
 fn force_flush(mut cx: FunctionContext) -> JsResult<JsPromise> {
     let (deferred, promise) = cx.promise();
     let channel = cx.channel();
@@ -725,6 +1089,11 @@ impl JsJob {
             parameters: self.parameters.as_ref().map(|s| s.as_bytes().to_vec()),
             metadata: self.metadata.as_ref().map(|s| s.as_bytes().to_vec()),
             blob,
+            idempotency_key: self.idempotency_key.clone(),
+            on_key_conflict: self.on_key_conflict,
+            parents: self.parents.clone(),
+            on_parent_failure: self.on_parent_failure,
+            retry_policy: self.retry_policy.clone(),
         }
     }
 }
@@ -738,10 +1107,22 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("maybeInitManager", maybe_init_manager)?;
     cx.export_function("createJob", create_job)?;
     cx.export_function("bulkCreateJobs", bulk_create_jobs)?;
+    cx.export_function("createRecurringJob", create_recurring_job)?;
+    cx.export_function("pauseRecurringJob", pause_recurring_job)?;
+    cx.export_function("resumeRecurringJob", resume_recurring_job)?;
+    cx.export_function("deleteRecurringJob", delete_recurring_job)?;
+    cx.export_function("listDeadLetters", list_dead_letters)?;
+    cx.export_function("groupDeadLetters", group_dead_letters)?;
+    cx.export_function("replayDeadLetters", replay_dead_letters)?;
+    cx.export_function("purgeDeadLetters", purge_dead_letters)?;
     cx.export_function("dequeueJobs", dequeue_jobs)?;
     cx.export_function("dequeueJobsWithVmState", dequeue_with_vm_state)?;
+    cx.export_function("waitForJobs", wait_for_jobs)?;
     cx.export_function("releaseJob", release_job)?;
     cx.export_function("forceFlush", force_flush)?;
+    cx.export_function("retryJob", retry_job)?;
+    cx.export_function("setRetryPolicy", set_retry_policy)?;
+    cx.export_function("getParentResults", get_parent_results)?;
     cx.export_function("setState", set_state)?;
     cx.export_function("setQueue", set_queue)?;
     cx.export_function("setPriority", set_priority)?;
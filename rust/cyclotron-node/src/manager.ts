@@ -1,8 +1,18 @@
 // eslint-disable-next-line @typescript-eslint/no-var-requires
 const cyclotron = require('../index.node')
 
-import { convertToInternalPoolConfig, serializeObject } from './helpers'
-import { CyclotronJobInit, CyclotronPoolConfig, CyclotronInternalPoolConfig } from './types'
+import { convertToInternalPoolConfig, convertToInternalRetryPolicy, serializeObject } from './helpers'
+import {
+    CyclotronDeadLetterEntry,
+    CyclotronDeadLetterFilter,
+    CyclotronDeadLetterGroup,
+    CyclotronInternalPoolConfig,
+    CyclotronJobInit,
+    CyclotronPoolConfig,
+    CyclotronRecurringJobInit,
+    CyclotronReplayedDeadLetters,
+    CyclotronRetryPolicy,
+} from './types'
 
 type CyclotronManagerInternalConfig = {
     shards: CyclotronInternalPoolConfig[]
@@ -10,6 +20,8 @@ type CyclotronManagerInternalConfig = {
     shardDepthCheckIntervalSeconds?: number
     shouldCompressVmState?: boolean
     shouldUseBulkJobCopy?: boolean
+    /** Notify workers waiting on a queue when jobs are inserted into it. Default: true */
+    shouldNotifyOnInsert?: boolean
 }
 
 export type CyclotronManagerConfig = Omit<CyclotronManagerInternalConfig, 'shards'> & {
@@ -28,6 +40,7 @@ export class CyclotronManager {
             shardDepthCheckIntervalSeconds: this.config.shardDepthCheckIntervalSeconds,
             shouldCompressVmState: this.config.shouldCompressVmState,
             shouldUseBulkJobCopy: this.config.shouldUseBulkJobCopy,
+            shouldNotifyOnInsert: this.config.shouldNotifyOnInsert,
         }
         return await cyclotron.maybeInitManager(JSON.stringify(config))
     }
@@ -46,6 +59,11 @@ export class CyclotronManager {
             vm_state: job.vmState ? serializeObject('vmState', job.vmState) : null,
             parameters: job.parameters ? serializeObject('parameters', job.parameters) : null,
             metadata: job.metadata ? serializeObject('metadata', job.metadata) : null,
+            idempotency_key: job.idempotencyKey ?? null,
+            on_key_conflict: job.onKeyConflict ?? 'return_existing',
+            parents: job.parents ?? [],
+            on_parent_failure: job.onParentFailure ?? 'cancel',
+            retry_policy: job.retryPolicy ? convertToInternalRetryPolicy(job.retryPolicy) : null,
         }
 
         const json = JSON.stringify(jobInitInternal)
@@ -66,6 +84,11 @@ export class CyclotronManager {
                 vm_state: job.vmState ? serializeObject('vmState', job.vmState) : null,
                 parameters: job.parameters ? serializeObject('parameters', job.parameters) : null,
                 metadata: job.metadata ? serializeObject('metadata', job.metadata) : null,
+                idempotency_key: job.idempotencyKey ?? null,
+                on_key_conflict: job.onKeyConflict ?? 'return_existing',
+                parents: job.parents ?? [],
+                on_parent_failure: job.onParentFailure ?? 'cancel',
+                retry_policy: job.retryPolicy ? convertToInternalRetryPolicy(job.retryPolicy) : null,
             }
         })
         const json = JSON.stringify(jobInitsInternal)
@@ -91,4 +114,112 @@ export class CyclotronManager {
 
         return await cyclotron.bulkCreateJobs(json, blobs, blobLengths)
     }
+
+    async createRecurringJob(recurring: CyclotronRecurringJobInit): Promise<string> {
+        const job = recurring.template
+        job.priority ??= 1
+        job.scheduled ??= new Date().toISOString()
+
+        const recurringInitInternal = {
+            template: {
+                team_id: job.teamId,
+                function_id: job.functionId,
+                queue_name: job.queueName,
+                priority: job.priority,
+                scheduled: job.scheduled,
+                vm_state: job.vmState ? serializeObject('vmState', job.vmState) : null,
+                parameters: job.parameters ? serializeObject('parameters', job.parameters) : null,
+                metadata: job.metadata ? serializeObject('metadata', job.metadata) : null,
+            },
+            schedule:
+                'cron' in recurring.schedule
+                    ? { cron: recurring.schedule.cron }
+                    : { interval_seconds: recurring.schedule.intervalSeconds },
+            timezone: recurring.timezone ?? null,
+            catch_up: recurring.catchUp ?? 'latest',
+        }
+
+        const json = JSON.stringify(recurringInitInternal)
+        return await cyclotron.createRecurringJob(json, job.blob ? job.blob : undefined)
+    }
+
+    async pauseRecurringJob(id: string): Promise<void> {
+        return await cyclotron.pauseRecurringJob(id)
+    }
+
+    async resumeRecurringJob(id: string): Promise<void> {
+        return await cyclotron.resumeRecurringJob(id)
+    }
+
+    async deleteRecurringJob(id: string): Promise<void> {
+        return await cyclotron.deleteRecurringJob(id)
+    }
+
+    // Applies to every job in the queue without a policy of its own. Passing null reverts to the default policy
+    async setRetryPolicy(queueName: string, policy: CyclotronRetryPolicy | null): Promise<void> {
+        const json = JSON.stringify(policy ? convertToInternalRetryPolicy(policy) : null)
+        return await cyclotron.setRetryPolicy(queueName, json)
+    }
+
+    async listDeadLetters(filter: CyclotronDeadLetterFilter = {}, limit = 100): Promise<CyclotronDeadLetterEntry[]> {
+        const entries: {
+            job_id: string
+            team_id: number
+            function_id: string | null
+            original_queue_name: string
+            reason: string
+            dlq_time: string
+            created: string
+        }[] = JSON.parse(await cyclotron.listDeadLetters(serializeDeadLetterFilter(filter), limit))
+        return entries.map((e) => ({
+            jobId: e.job_id,
+            teamId: e.team_id,
+            functionId: e.function_id,
+            originalQueueName: e.original_queue_name,
+            reason: e.reason,
+            dlqTime: new Date(e.dlq_time),
+            created: new Date(e.created),
+        }))
+    }
+
+    async groupDeadLetters(filter: CyclotronDeadLetterFilter = {}): Promise<CyclotronDeadLetterGroup[]> {
+        const groups: {
+            reason: string
+            team_id: number
+            function_id: string | null
+            count: number
+            oldest: string
+            newest: string
+        }[] = JSON.parse(await cyclotron.groupDeadLetters(serializeDeadLetterFilter(filter)))
+        return groups.map((g) => ({
+            reason: g.reason,
+            teamId: g.team_id,
+            functionId: g.function_id,
+            count: g.count,
+            oldest: new Date(g.oldest),
+            newest: new Date(g.newest),
+        }))
+    }
+
+    // Moves matching entries back to the queue they were dead lettered from, returning how many were replayed, and
+    // how many were skipped because their idempotency key was already in use
+    async replayDeadLetters(filter: CyclotronDeadLetterFilter): Promise<CyclotronReplayedDeadLetters> {
+        return JSON.parse(await cyclotron.replayDeadLetters(serializeDeadLetterFilter(filter)))
+    }
+
+    async purgeDeadLetters(filter: CyclotronDeadLetterFilter): Promise<number> {
+        return JSON.parse(await cyclotron.purgeDeadLetters(serializeDeadLetterFilter(filter)))
+    }
+}
+
+function serializeDeadLetterFilter(filter: CyclotronDeadLetterFilter): string {
+    return JSON.stringify({
+        job_ids: filter.jobIds ?? null,
+        team_id: filter.teamId ?? null,
+        function_id: filter.functionId ?? null,
+        original_queue_name: filter.originalQueueName ?? null,
+        reason: filter.reason ?? null,
+        dlq_before: filter.dlqBefore ?? null,
+        dlq_after: filter.dlqAfter ?? null,
+    })
 }
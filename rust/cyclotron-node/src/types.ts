@@ -18,7 +18,9 @@ export type CyclotronInternalPoolConfig = {
 }
 
 
-export type CyclotronJobState = 'available' | 'running' | 'completed' | 'failed' | 'paused'
+export type CyclotronDequeueMode = 'priority' | 'fair_share'
+
+export type CyclotronJobState = 'available' | 'running' | 'completed' | 'failed' | 'paused' | 'waiting'
 
 export type CyclotronJob = {
     id: string
@@ -40,8 +42,98 @@ export type CyclotronJob = {
     blob: Uint8Array | null
 }
 
+// What to do if a job with the same idempotency key is already queued (and hasn't completed or failed)
+export type CyclotronOnKeyConflict = 'return_existing' | 'ignore' | 'replace'
+
 export type CyclotronJobInit = Pick<CyclotronJob, 'teamId' | 'functionId' | 'queueName' | 'priority'> &
-    Pick<Partial<CyclotronJob>, 'scheduled' | 'vmState' | 'parameters' | 'metadata' | 'blob'>
+    Pick<Partial<CyclotronJob>, 'scheduled' | 'vmState' | 'parameters' | 'metadata' | 'blob'> & {
+        idempotencyKey?: string
+        onKeyConflict?: CyclotronOnKeyConflict
+        /** The job waits until all of these jobs have completed or failed */
+        parents?: string[]
+        onParentFailure?: CyclotronOnParentFailure
+        /** Overrides the queue's retry policy for this job */
+        retryPolicy?: CyclotronRetryPolicy
+    }
+
+export type CyclotronRetryPolicy = {
+    /** Including the first attempt. Once they're used up, the job is dead lettered */
+    maxAttempts: number
+    initialIntervalMs: number
+    /** Default: 2 */
+    backoffMultiplier?: number
+    maxIntervalMs: number
+    /** Each wait is randomly adjusted by up to this percentage, either way. Default: 0 */
+    jitterPercent?: number
+    /** Error classes worth retrying. If unset, all of them are */
+    retryableErrors?: string[]
+}
+
+export type CyclotronRetryOutcome =
+    | { outcome: 'retrying'; attempt: number; scheduled: Date }
+    | { outcome: 'dead_lettered'; attempt: number }
+
+// What happens to a job once its parents have finished, if any of them didn't complete
+export type CyclotronOnParentFailure = 'cancel' | 'run' | 'fail'
+
+export type CyclotronParentOutcome = 'completed' | 'failed' | 'cancelled' | 'missing'
+
+export type CyclotronParentResult = {
+    parentId: string
+    outcome: CyclotronParentOutcome
+    // The parent's parameters when it finished
+    result: object | null
+}
+
+// Exactly one of cron (a 5 field cron expression) or intervalSeconds must be set
+export type CyclotronRecurringSchedule = { cron: string } | { intervalSeconds: number }
+
+export type CyclotronCatchUpPolicy = 'latest' | 'all' | 'skip'
+
+export type CyclotronRecurringJobInit = {
+    // Every instance is created from the template. Its scheduled time is when the schedule starts
+    template: CyclotronJobInit
+    schedule: CyclotronRecurringSchedule
+    timezone?: string
+    catchUp?: CyclotronCatchUpPolicy
+}
+
+// Unset fields match any entry. Replaying or purging requires at least one field to be set
+export type CyclotronDeadLetterFilter = {
+    jobIds?: string[]
+    teamId?: number
+    functionId?: string
+    originalQueueName?: string
+    // Matched exactly
+    reason?: string
+    dlqBefore?: string
+    dlqAfter?: string
+}
+
+export type CyclotronDeadLetterEntry = {
+    jobId: string
+    teamId: number
+    functionId: string | null
+    originalQueueName: string
+    reason: string
+    dlqTime: Date
+    created: Date
+}
+
+export type CyclotronDeadLetterGroup = {
+    reason: string
+    teamId: number
+    functionId: string | null
+    count: number
+    oldest: Date
+    newest: Date
+}
+
+// Jobs are skipped, and left dead lettered, if their idempotency key is in use in the queue they'd go back to
+export type CyclotronReplayedDeadLetters = {
+    replayed: number
+    skipped: number
+}
 
 export type CyclotronJobUpdate = Pick<
     Partial<CyclotronJob>,
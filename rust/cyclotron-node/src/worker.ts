@@ -2,10 +2,13 @@
 const cyclotron = require('../index.node')
 import { convertToInternalPoolConfig, deserializeObject, serializeObject } from './helpers'
 import {
+    CyclotronDequeueMode,
     CyclotronJob,
     CyclotronJobState,
     CyclotronJobUpdate,
+    CyclotronParentResult,
     CyclotronPoolConfig,
+    CyclotronRetryOutcome,
 } from './types'
 
 const parseJob = (job: CyclotronJob): CyclotronJob => {
@@ -28,6 +31,8 @@ type CyclotronWorkerNodeConfig = {
     includeVmState?: boolean
     /** Amount of delay between dequeue polls. Default: 50ms */
     pollDelayMs?: number
+    /** If set, empty polls wait up to this long to be notified of newly enqueued jobs, rather than sleeping for pollDelayMs */
+    notifyWaitMs?: number
     /** Heartbeat timeout. After this time without response from the worker loop the worker will be considered unhealthy. Default 30000 */
     heartbeatTimeoutMs?: number
     /** Include empty batches - useful if you want to track them. Default: false */
@@ -50,6 +55,14 @@ type CyclotronWorkerInternalConfig = {
     flushLoopIntervalMs?: number
     /** Whether to compress vmState. Default false */
     shouldCompressVmState?: boolean
+    /** How batches are drawn from the queue. 'fair_share' splits each batch across teams with available jobs. Default 'priority' */
+    dequeueMode?: CyclotronDequeueMode
+    /** Fair share only. Relative share of each batch per team. Teams not listed have a weight of 1 */
+    teamWeights?: Record<number, number>
+    /** Fair share only. Max running jobs per team on the queue */
+    teamConcurrencyLimits?: Record<number, number>
+    /** Fair share only. Max running jobs on the queue for teams without a specific limit. Default: no limit */
+    defaultTeamConcurrencyLimit?: number
 }
 
 
@@ -82,6 +95,10 @@ export class CyclotronWorker {
             maxBytesBuffered: this.config.maxBytesBuffered ?? 10000000,
             flushLoopIntervalMs: this.config.flushLoopIntervalMs ?? 10,
             shouldCompressVmState: this.config.shouldCompressVmState ?? false,
+            dequeueMode: this.config.dequeueMode ?? 'priority',
+            teamWeights: this.config.teamWeights,
+            teamConcurrencyLimits: this.config.teamConcurrencyLimits,
+            defaultTeamConcurrencyLimit: this.config.defaultTeamConcurrencyLimit,
         }
 
         await cyclotron.maybeInitWorker(
@@ -113,8 +130,12 @@ export class CyclotronWorker {
                 ).map(parseJob)
 
                 if (!jobs.length) {
-                    // Wait a bit before polling again
-                    await new Promise((resolve) => setTimeout(resolve, pollDelayMs))
+                    // Wait a bit before polling again - or until we're told there are new jobs
+                    if (this.config.notifyWaitMs) {
+                        await cyclotron.waitForJobs(this.config.queueName, this.config.notifyWaitMs)
+                    } else {
+                        await new Promise((resolve) => setTimeout(resolve, pollDelayMs))
+                    }
                     if (this.config.includeEmptyBatches) {
                         await processBatch(jobs)
                     }
@@ -139,6 +160,26 @@ export class CyclotronWorker {
         return cyclotron.releaseJob(jobId)
     }
 
+    // Records a failed attempt, and either reschedules the job or dead letters it, according to its retry policy.
+    // Any other updates made to the job are released along with it.
+    async retryJob(jobId: string, errorClass: string, message: string): Promise<CyclotronRetryOutcome> {
+        const outcome = JSON.parse(await cyclotron.retryJob(jobId, JSON.stringify({ class: errorClass, message })))
+        if (outcome.outcome === 'retrying') {
+            return { outcome: 'retrying', attempt: outcome.attempt, scheduled: new Date(outcome.scheduled) }
+        }
+        return { outcome: 'dead_lettered', attempt: outcome.attempt }
+    }
+
+    async getParentResults(jobId: string): Promise<CyclotronParentResult[]> {
+        const results: { parent_id: string; outcome: CyclotronParentResult['outcome']; result: string | null }[] =
+            JSON.parse(await cyclotron.getParentResults(jobId))
+        return results.map((r) => ({
+            parentId: r.parent_id,
+            outcome: r.outcome,
+            result: deserializeObject('result', r.result),
+        }))
+    }
+
     updateJob(id: CyclotronJob['id'], state: CyclotronJobState, updates?: CyclotronJobUpdate): void {
         cyclotron.setState(id, state)
         if (updates?.queueName !== undefined) {
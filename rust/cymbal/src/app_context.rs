@@ -5,7 +5,7 @@ use common_kafka::{
     kafka_producer::{create_kafka_producer, KafkaContext},
     transaction::TransactionalProducer,
 };
-use common_redis::RedisClient;
+use common_redis::{Client, RedisClient};
 use health::{HealthHandle, HealthRegistry};
 use limiters::redis::{QuotaResource, RedisLimiter, ServiceName, QUOTA_LIMITER_CACHE_KEY};
 use rdkafka::producer::FutureProducer;
@@ -49,9 +49,12 @@ pub struct AppContext {
 
     pub team_manager: TeamManager,
     pub billing_limiter: RedisLimiter,
+    pub redis: Arc<dyn Client + Send + Sync>,
 
     pub filtered_teams: Vec<i32>,
     pub filter_mode: FilterMode,
+
+    pub message_normalization_disabled_teams: Vec<i32>,
 }
 
 impl AppContext {
@@ -141,7 +144,7 @@ impl AppContext {
         let geoip_client = GeoIpClient::new(config.maxmind_db_path.clone())?;
 
         let redis_client = RedisClient::new(config.redis_url.clone())?;
-        let redis_client = Arc::new(redis_client);
+        let redis_client: Arc<dyn Client + Send + Sync> = Arc::new(redis_client);
 
         // TODO - we expect here rather returning an UnhandledError because the limiter returns an Anyhow::Result,
         // which we don't want to put into the UnhandledError enum since it basically means "any error"
@@ -161,6 +164,7 @@ impl AppContext {
             .filter(|s| !s.is_empty())
             .map(|tid| tid.parse().expect("Filtered team id's must be i32s"))
             .collect();
+        let message_normalization_disabled_teams = config.message_normalization_disabled_teams();
         let filter_mode = match config.filter_mode.to_lowercase().as_str() {
             "in" => FilterMode::In,
             "out" => FilterMode::Out,
@@ -180,8 +184,15 @@ impl AppContext {
             team_manager,
             geoip_client,
             billing_limiter,
+            redis: redis_client,
             filtered_teams,
             filter_mode,
+            message_normalization_disabled_teams,
         })
     }
+
+    pub fn should_normalize_messages(&self, team_id: i32) -> bool {
+        self.config.message_normalization_enabled
+            && !self.message_normalization_disabled_teams.contains(&team_id)
+    }
 }
@@ -214,6 +214,17 @@ pub async fn try_assignment_rules(
     issue: Issue,
     exception_properties: OutputErrProps,
 ) -> Result<Option<NewAssignment>, UnhandledError> {
+    let matched = match_assignment_rules(con, team_manager, issue, exception_properties).await?;
+    Ok(matched.map(|(_, assignment)| assignment))
+}
+
+// Like try_assignment_rules, but also returns the id of the rule that matched
+pub async fn match_assignment_rules(
+    con: &mut PgConnection,
+    team_manager: &TeamManager,
+    issue: Issue,
+    exception_properties: OutputErrProps,
+) -> Result<Option<(Uuid, NewAssignment)>, UnhandledError> {
     let timing = common_metrics::timing_guard(ASSIGNMENT_RULES_PROCESSING_TIME, &[]);
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct IssueJson {
@@ -244,7 +255,7 @@ pub async fn try_assignment_rules(
             Ok(Some(new_assignment)) => {
                 timing.label("outcome", "match").fin();
                 metrics::counter!(AUTO_ASSIGNMENTS).increment(1);
-                return Ok(Some(new_assignment));
+                return Ok(Some((rule.id, new_assignment)));
             }
             Err(err) => {
                 rule.disable(
@@ -0,0 +1,262 @@
+use std::path::PathBuf;
+
+use common_types::ClickHouseEvent;
+use cymbal::{
+    assignment_rules::{match_assignment_rules, NewAssignment},
+    config::Config,
+    error::UnhandledError,
+    fingerprinting::{generate_fingerprint, resolve_fingerprint, FingerprintRecordPart},
+    frames::{Frame, RawFrame},
+    issue_resolution::{Issue, IssueStatus},
+    pipeline::exception::get_props,
+    symbol_store::{local::LocalSymbolStore, Catalog},
+    teams::TeamManager,
+    types::{RawErrProps, Stacktrace},
+};
+use envconfig::Envconfig;
+use serde_json::{json, Value};
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use uuid::Uuid;
+
+// Runs exception events through cymbal's processing stages offline, printing what cymbal would
+// do with them. Events are read as ClickHouse event json lines on stdin, and one json line is
+// written to stdout per event. Symbol sets are read from a local directory (see LocalSymbolStore),
+// and never from the frame cache, so events can be re-processed after a sourcemap is added. If
+// REPROCESS_USE_DATABASE is set, grouping and assignment rules are loaded from, and issues looked
+// up in, the database at DATABASE_URL. Nothing is ever written to it.
+#[derive(Envconfig)]
+struct ReprocessConfig {
+    #[envconfig(from = "REPROCESS_SYMBOL_SET_DIR", default = "./symbol_sets")]
+    symbol_set_dir: PathBuf,
+
+    #[envconfig(from = "REPROCESS_USE_DATABASE", default = "false")]
+    use_database: bool,
+
+    // Process every event as if it were sent by this team, e.g. to test against local rules
+    #[envconfig(from = "REPROCESS_TEAM_ID")]
+    team_id: Option<i32>,
+}
+
+// Properties we add to events on output. Historical events already have these set, and we
+// don't want to treat an earlier run's fingerprint as one sent by the client.
+const OUTPUT_PROPERTIES: &[&str] = &[
+    "$exception_proposed_fingerprint",
+    "$exception_fingerprint_record",
+    "$exception_issue_id",
+    "$exception_handled",
+    "$exception_releases",
+    "$exception_primary_release",
+    "$exception_types",
+    "$exception_values",
+    "$exception_sources",
+    "$exception_functions",
+];
+
+#[tokio::main]
+async fn main() {
+    let config = Config::init_with_defaults().unwrap();
+    let reprocess_config = ReprocessConfig::init_from_env().unwrap();
+
+    let catalog = Catalog::new(LocalSymbolStore::new(&reprocess_config.symbol_set_dir));
+    let team_manager = TeamManager::new(&config);
+    let pool = if reprocess_config.use_database {
+        let options = PgPoolOptions::new().max_connections(1);
+        Some(options.connect(&config.database_url).await.unwrap())
+    } else {
+        None
+    };
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await.unwrap() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let output = match serde_json::from_str::<ClickHouseEvent>(&line) {
+            Ok(event) => reprocess(
+                &config,
+                &reprocess_config,
+                &catalog,
+                &team_manager,
+                pool.as_ref(),
+                event,
+            )
+            .await
+            .unwrap_or_else(|e| json!({ "error": e })),
+            Err(e) => json!({ "error": format!("Invalid event: {}", e) }),
+        };
+
+        println!("{}", output);
+    }
+}
+
+async fn reprocess(
+    config: &Config,
+    reprocess_config: &ReprocessConfig,
+    catalog: &Catalog,
+    team_manager: &TeamManager,
+    pool: Option<&PgPool>,
+    mut event: ClickHouseEvent,
+) -> Result<Value, String> {
+    strip_output_properties(&mut event).map_err(|e| e.to_string())?;
+    let mut props = get_props(&event).map_err(|e| e.to_string())?;
+    let team_id = reprocess_config.team_id.unwrap_or(event.team_id);
+
+    resolve_frames(&mut props, team_id, catalog)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let normalize_messages = config.message_normalization_enabled
+        && !config
+            .message_normalization_disabled_teams()
+            .contains(&team_id);
+    let Some(pool) = pool else {
+        let fingerprint = generate_fingerprint(&props.exception_list, normalize_messages);
+        let output = props.to_fingerprinted(fingerprint).to_output(Uuid::nil());
+        return Ok(json!({
+            "uuid": event.uuid,
+            "team_id": team_id,
+            "fingerprint": &output.fingerprint,
+            "proposed_fingerprint": &output.proposed_fingerprint,
+            "fingerprint_record": &output.fingerprint_record,
+            "properties": output,
+        }));
+    };
+
+    // Rules that fail to run get disabled, so we do everything in a transaction we never commit
+    let mut txn = pool.begin().await.map_err(|e| e.to_string())?;
+    let fingerprint =
+        resolve_fingerprint(&mut *txn, team_manager, team_id, &props, normalize_messages)
+            .await
+            .map_err(|e| e.to_string())?;
+
+    let fingerprinted = props.to_fingerprinted(fingerprint);
+    let grouping_rule = fingerprinted
+        .fingerprint
+        .record
+        .iter()
+        .find_map(|part| match part {
+            FingerprintRecordPart::Custom { rule_id } => Some(*rule_id),
+            _ => None,
+        });
+
+    let existing_issue =
+        Issue::load_by_fingerprint(&mut *txn, team_id, &fingerprinted.fingerprint.value)
+            .await
+            .map_err(|e| e.to_string())?;
+    let issue = existing_issue.clone().unwrap_or_else(|| Issue {
+        id: Uuid::nil(),
+        team_id,
+        status: IssueStatus::Active,
+        name: Some(fingerprinted.exception_list[0].exception_type.clone()),
+        description: Some(fingerprinted.exception_list[0].exception_message.clone()),
+    });
+
+    // Grouping rules can carry an assignment with them, which take precedence over assignment rules
+    let (assignment, assignment_rule) = match fingerprinted.fingerprint.assignment.clone() {
+        Some(assignment) => (Some(assignment), None),
+        None => match match_assignment_rules(
+            &mut *txn,
+            team_manager,
+            issue.clone(),
+            fingerprinted.clone().to_output(issue.id),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        {
+            Some((rule_id, assignment)) => (Some(assignment), Some(rule_id)),
+            None => (None, None),
+        },
+    };
+
+    txn.rollback().await.map_err(|e| e.to_string())?;
+
+    let output = fingerprinted.to_output(issue.id);
+    Ok(json!({
+        "uuid": event.uuid,
+        "team_id": team_id,
+        "fingerprint": &output.fingerprint,
+        "proposed_fingerprint": &output.proposed_fingerprint,
+        "fingerprint_record": &output.fingerprint_record,
+        "grouping_rule_id": grouping_rule,
+        "assignment": assignment.as_ref().map(assignment_json),
+        "assignment_rule_id": assignment_rule,
+        "existing_issue_id": existing_issue.map(|i| i.id),
+        "properties": output,
+    }))
+}
+
+fn strip_output_properties(event: &mut ClickHouseEvent) -> Result<(), UnhandledError> {
+    let mut props = event.take_raw_properties()?;
+
+    // A fingerprint with a record of only a manual part was sent by the client, so we keep it
+    let manual = props
+        .get("$exception_fingerprint_record")
+        .and_then(|r| serde_json::from_value::<Vec<FingerprintRecordPart>>(r.clone()).ok())
+        .is_some_and(|r| matches!(r.as_slice(), [FingerprintRecordPart::Manual]));
+    if props.contains_key("$exception_fingerprint_record") && !manual {
+        props.remove("$exception_fingerprint");
+    }
+
+    for key in OUTPUT_PROPERTIES {
+        props.remove(*key);
+    }
+
+    event.set_raw_properties(props)?;
+    Ok(())
+}
+
+async fn resolve_frames(
+    props: &mut RawErrProps,
+    team_id: i32,
+    catalog: &Catalog,
+) -> Result<(), UnhandledError> {
+    for exception in props.exception_list.iter_mut() {
+        let frames = match exception.stack.take() {
+            Some(Stacktrace::Raw { frames }) => {
+                let mut resolved = Vec::with_capacity(frames.len());
+                for frame in frames {
+                    resolved.push(frame.resolve(team_id, catalog).await?);
+                }
+                resolved
+            }
+            // Historical events have already been resolved, but we keep the raw frame around
+            // for frames that needed symbolication, so we can resolve those again
+            Some(Stacktrace::Resolved { frames }) => {
+                let mut resolved = Vec::with_capacity(frames.len());
+                for frame in frames {
+                    match recover_raw_frame(&frame) {
+                        Some(raw) => resolved.push(raw.resolve(team_id, catalog).await?),
+                        None => resolved.push(frame),
+                    }
+                }
+                resolved
+            }
+            None => continue,
+        };
+        exception.stack = Some(Stacktrace::Resolved { frames });
+    }
+    Ok(())
+}
+
+fn recover_raw_frame(frame: &Frame) -> Option<RawFrame> {
+    let mut raw = frame.junk_drawer.as_ref()?.get("raw_frame")?.clone();
+    let raw_obj = raw.as_object_mut()?;
+    // The raw frame is stored without its platform tag, but node frames always carry context
+    let platform = if raw_obj.contains_key("pre_context") {
+        "node:javascript"
+    } else {
+        "web:javascript"
+    };
+    raw_obj.insert("platform".to_string(), Value::String(platform.to_string()));
+    serde_json::from_value(raw).ok()
+}
+
+fn assignment_json(assignment: &NewAssignment) -> Value {
+    json!({
+        "user_id": assignment.user_id,
+        "user_group_id": assignment.user_group_id,
+        "role_id": assignment.role_id,
+    })
+}
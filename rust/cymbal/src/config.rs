@@ -136,6 +136,52 @@ pub struct Config {
 
     #[envconfig(default = "false")]
     pub auto_assignment_enabled: bool, // Comma seperated list of users to either filter in (process) or filter out (ignore)
+
+    // Replace ids, numbers, urls etc in exception messages before they contribute to a fingerprint. This
+    // changes the fingerprint of existing issues whose messages get rewritten, splitting them from any
+    // new occurrences, so teams that can't take that can be opted out
+    #[envconfig(default = "true")]
+    pub message_normalization_enabled: bool,
+
+    #[envconfig(default = "")]
+    pub message_normalization_disabled_teams: String, // Comma seperated list of teams to skip message normalization for
+
+    // Track which releases each issue has been seen in, and alert on issues new in or regressed in a release
+    #[envconfig(default = "false")]
+    pub release_tracking_enabled: bool,
+
+    // Compare each new issue's signature to those of existing issues, and suggest merging it into any that look alike
+    #[envconfig(default = "false")]
+    pub merge_suggestions_enabled: bool,
+
+    // The minimum estimated similarity between two issues' signatures before we suggest merging them
+    #[envconfig(default = "0.8")]
+    pub merge_suggestion_threshold: f64,
+
+    // The most existing issues we'll compare a new issue's signature against
+    #[envconfig(default = "100")]
+    pub max_merge_suggestion_candidates: i64,
+
+    // Count each issue's occurrences per hour in redis, and alert on issues whose current hourly rate spikes
+    #[envconfig(default = "false")]
+    pub spike_detection_enabled: bool,
+
+    // An issue is spiking when its occurrences in the current hour exceed this multiple of its trailing hourly rate
+    #[envconfig(default = "10")]
+    pub spike_multiplier: f64,
+
+    // How many hours before the current one we average over to get an issues baseline rate
+    #[envconfig(default = "24")]
+    pub spike_baseline_hours: u64,
+
+    // Issues with fewer occurrences than this in the current hour are never considered spiking,
+    // so that e.g. going from 0 to 10 events an hour doesn't page anyone
+    #[envconfig(default = "100")]
+    pub spike_min_occurrences: u64,
+
+    // Once we've alerted on a spiking issue, we won't alert on it again for this long
+    #[envconfig(default = "60")]
+    pub spike_alert_cooldown_minutes: u64,
 }
 
 impl Config {
@@ -158,6 +204,17 @@ impl Config {
         init_global_state(&res);
         Ok(res)
     }
+
+    pub fn message_normalization_disabled_teams(&self) -> Vec<i32> {
+        self.message_normalization_disabled_teams
+            .split(",")
+            .filter(|s| !s.is_empty())
+            .map(|tid| {
+                tid.parse()
+                    .expect("Message normalization team id's must be i32s")
+            })
+            .collect()
+    }
 }
 
 pub fn init_global_state(config: &Config) {
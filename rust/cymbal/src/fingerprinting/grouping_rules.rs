@@ -245,7 +245,7 @@ mod test {
         team_manager.grouping_rules.insert(test_team_id, vec![rule]);
 
         let mut conn = db.acquire().await.unwrap();
-        let res = resolve_fingerprint(&mut conn, &team_manager, test_team_id, &test_props)
+        let res = resolve_fingerprint(&mut conn, &team_manager, test_team_id, &test_props, true)
             .await
             .unwrap();
 
@@ -258,7 +258,7 @@ mod test {
         rule.user_id = Some(1);
         team_manager.grouping_rules.insert(test_team_id, vec![rule]);
 
-        let res = resolve_fingerprint(&mut conn, &team_manager, test_team_id, &test_props)
+        let res = resolve_fingerprint(&mut conn, &team_manager, test_team_id, &test_props, true)
             .await
             .unwrap();
 
@@ -271,7 +271,7 @@ mod test {
             .other
             .insert("test_value".to_string(), JsonValue::from("no_match"));
 
-        let res = resolve_fingerprint(&mut conn, &team_manager, test_team_id, &test_props)
+        let res = resolve_fingerprint(&mut conn, &team_manager, test_team_id, &test_props, true)
             .await
             .unwrap();
 
@@ -12,22 +12,30 @@ use sqlx::PgConnection;
 use uuid::Uuid;
 
 pub mod grouping_rules;
+pub mod normalize;
 
 pub async fn resolve_fingerprint(
     conn: &mut PgConnection,
     team_manager: &TeamManager,
     team_id: TeamId,
     props: &RawErrProps,
+    normalize_messages: bool,
 ) -> Result<Fingerprint, UnhandledError> {
     if let Some(rule) = try_grouping_rules(conn, team_id, team_manager, props).await? {
         Ok(Fingerprint::from_rule(rule))
     } else {
-        Ok(generate_fingerprint(&props.exception_list))
+        Ok(generate_fingerprint(
+            &props.exception_list,
+            normalize_messages,
+        ))
     }
 }
 
-pub fn generate_fingerprint(exceptions: &[Exception]) -> Fingerprint {
-    let mut fingerprint = FingerprintBuilder::default();
+pub fn generate_fingerprint(exceptions: &[Exception], normalize_messages: bool) -> Fingerprint {
+    let mut fingerprint = FingerprintBuilder {
+        normalize_messages,
+        ..Default::default()
+    };
 
     for exc in exceptions.iter() {
         exc.include_in_fingerprint(&mut fingerprint);
@@ -71,6 +79,8 @@ pub struct Fingerprint {
 pub struct FingerprintBuilder {
     pub record: Vec<FingerprintRecordPart>,
     pub hasher: Sha512,
+    // Whether exception messages should have variable tokens (ids, numbers, etc) replaced before hashing
+    pub normalize_messages: bool,
 }
 
 impl FingerprintBuilder {
@@ -178,14 +188,15 @@ mod test {
             frames: resolved_frames.clone(),
         });
 
-        let fingerprint_with_all_resolved = super::generate_fingerprint(&[exception.clone()]).value;
+        let fingerprint_with_all_resolved =
+            super::generate_fingerprint(&[exception.clone()], false).value;
 
         resolved_frames.push(unresolved_frame);
         exception.stack = Some(Stacktrace::Resolved {
             frames: resolved_frames,
         });
 
-        let mixed_fingerprint = super::generate_fingerprint(&[exception]).value;
+        let mixed_fingerprint = super::generate_fingerprint(&[exception], false).value;
 
         // In cases where there are SOME resolved frames, the fingerprint should be identical
         // to the case where all frames are resolved (unresolved frames should be ignored)
@@ -252,13 +263,13 @@ mod test {
             },
         ];
 
-        let no_stack_fingerprint = super::generate_fingerprint(&[exception.clone()]).value;
+        let no_stack_fingerprint = super::generate_fingerprint(&[exception.clone()], false).value;
 
         exception.stack = Some(Stacktrace::Resolved {
             frames: resolved_frames,
         });
 
-        let with_stack_fingerprint = super::generate_fingerprint(&[exception]).value;
+        let with_stack_fingerprint = super::generate_fingerprint(&[exception], false).value;
 
         // If there are NO resolved frames, fingerprinting should account for the unresolved frames
         assert_ne!(no_stack_fingerprint, with_stack_fingerprint);
@@ -312,16 +323,50 @@ mod test {
             frames: resolved_frames.clone(),
         });
 
-        let fingerprint_1 = super::generate_fingerprint(&[exception.clone()]).value;
+        let fingerprint_1 = super::generate_fingerprint(&[exception.clone()], false).value;
 
         resolved_frames.push(non_app_frame);
         exception.stack = Some(Stacktrace::Resolved {
             frames: resolved_frames,
         });
 
-        let fingerprint_2 = super::generate_fingerprint(&[exception]).value;
+        let fingerprint_2 = super::generate_fingerprint(&[exception], false).value;
 
         // Fingerprinting should ignore non-in-app frames
         assert_eq!(fingerprint_1, fingerprint_2);
     }
+
+    #[test]
+    fn test_message_normalization() {
+        let mut exception = Exception {
+            exception_id: None,
+            exception_type: "NotFoundError".to_string(),
+            exception_message: "User 12345 not found".to_string(),
+            mechanism: Default::default(),
+            module: Default::default(),
+            thread_id: None,
+            stack: Default::default(),
+        };
+
+        let first = super::generate_fingerprint(&[exception.clone()], true);
+        exception.exception_message = "User 67890 not found".to_string();
+        let second = super::generate_fingerprint(&[exception.clone()], true);
+
+        // With no stack, messages differing only in ids should group together
+        assert_eq!(first.value, second.value);
+        let FingerprintRecordPart::Exception { pieces, .. } = &second.record[0] else {
+            panic!("Expected an exception record part");
+        };
+        assert_eq!(
+            pieces,
+            &vec![
+                "Exception Type".to_string(),
+                "Normalized Exception Message: User <num> not found".to_string()
+            ]
+        );
+
+        // But not if normalization is disabled
+        let unnormalized = super::generate_fingerprint(&[exception], false);
+        assert_ne!(second.value, unnormalized.value);
+    }
 }
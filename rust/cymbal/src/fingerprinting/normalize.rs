@@ -0,0 +1,102 @@
+use std::{borrow::Cow, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+// Exception messages often embed values that vary per occurrence (ids, addresses, counts),
+// which would otherwise split a single bug into one issue per distinct value when the message
+// contributes to the fingerprint. We replace such tokens with placeholders before hashing.
+// The order matters - earlier patterns consume tokens that later, more general ones would
+// partially match (e.g. the digits in a UUID or URL).
+static NORMALIZATION_PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        (r"[a-zA-Z][a-zA-Z0-9+.-]*://[^\s'\x22<>]+", "<url>"),
+        (r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+", "<email>"),
+        (
+            r"\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\b",
+            "<uuid>",
+        ),
+        (r"\b0[xX][0-9a-fA-F]+\b", "<hex>"),
+        // Long hex runs, like hashes or object ids. Only replaced if they contain a digit, see below
+        (r"\b[0-9a-fA-F]{8,}\b", "<hex>"),
+        // Numbers at the start of a word, so "3000ms" becomes "<num>ms" but "utf8" is left alone
+        (r"\b[0-9]+(?:\.[0-9]+)?", "<num>"),
+    ]
+    .into_iter()
+    .map(|(pattern, placeholder)| {
+        (
+            Regex::new(pattern).expect("normalization patterns are valid"),
+            placeholder,
+        )
+    })
+    .collect()
+});
+
+pub fn normalize_message(message: &str) -> Cow<'_, str> {
+    let mut normalized = Cow::Borrowed(message);
+    for (pattern, placeholder) in NORMALIZATION_PATTERNS.iter() {
+        if !pattern.is_match(&normalized) {
+            continue;
+        }
+
+        let replaced = pattern.replace_all(&normalized, |caps: &Captures| {
+            let token = &caps[0];
+            // Words like "deadbeef" or "facade" are all hex digits, but aren't ids
+            if *placeholder == "<hex>" && !token.bytes().any(|b| b.is_ascii_digit()) {
+                token.to_string()
+            } else {
+                placeholder.to_string()
+            }
+        });
+        normalized = Cow::Owned(replaced.into_owned());
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod test {
+    use super::normalize_message;
+
+    #[test]
+    fn it_leaves_plain_messages_alone() {
+        let message = "Cannot read properties of undefined (reading 'foo')";
+        assert_eq!(normalize_message(message), message);
+    }
+
+    #[test]
+    fn it_replaces_variable_tokens() {
+        let cases = [
+            ("User 12345 not found", "User <num> not found"),
+            (
+                "No row with id 0190f1b2-3c4d-7e8f-9a0b-1c2d3e4f5a6b",
+                "No row with id <uuid>",
+            ),
+            ("Segfault at 0x7ffee4b1c9a0", "Segfault at <hex>"),
+            ("Object 5f2b8c1e9d3a7b4c missing", "Object <hex> missing"),
+            (
+                "Could not deliver to jane.doe+test@example.co.uk",
+                "Could not deliver to <email>",
+            ),
+            (
+                "Failed to fetch https://api.example.com/v1/users/42?page=3",
+                "Failed to fetch <url>",
+            ),
+            ("Invalid utf8 sequence", "Invalid utf8 sequence"),
+            (
+                "Unexpected token in 'deadbeef'",
+                "Unexpected token in 'deadbeef'",
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalize_message(input), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn it_groups_messages_differing_only_in_ids() {
+        assert_eq!(
+            normalize_message("Timeout after 3000ms waiting for job 981"),
+            normalize_message("Timeout after 2500ms waiting for job 17"),
+        );
+    }
+}
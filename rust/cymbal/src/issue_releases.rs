@@ -0,0 +1,268 @@
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use sqlx::{Acquire, PgConnection};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    app_context::AppContext,
+    error::UnhandledError,
+    frames::releases::ReleaseRecord,
+    issue_resolution::{emit_internal_event, issue_internal_event, Issue},
+    metric_consts::{ISSUE_NEW_IN_RELEASE, ISSUE_REGRESSED_IN_RELEASE, RELEASE_TRACKING_ERRORS},
+    types::{FingerprintedErrProps, Stacktrace},
+};
+
+// How an issue relates to a release it was first seen in, if notably. Issues seen in a release
+// for the first time, having also been seen in the release of the same project before it, are
+// ongoing, and have no particular relation to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueReleaseKind {
+    // The first release this issue was ever seen in
+    New,
+    // The issue was seen in earlier releases, but not in the one immediately before this one
+    Regressed,
+}
+
+// The occurrences of one issue in one release, within a batch of events
+#[derive(Debug, Clone)]
+pub struct IssueReleaseOccurrences {
+    pub issue: Issue,
+    pub release: ReleaseRecord,
+    pub count: i64,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct PriorReleases {
+    release_count: i64,
+    this_release_seen: bool,
+    // None if there's no earlier release of the same project
+    previous_release_seen: Option<bool>,
+}
+
+impl IssueReleaseKind {
+    pub fn classify(prior_release_count: i64, previous_release_seen: Option<bool>) -> Option<Self> {
+        if prior_release_count == 0 {
+            return Some(IssueReleaseKind::New);
+        }
+        match previous_release_seen {
+            Some(false) => Some(IssueReleaseKind::Regressed),
+            _ => None,
+        }
+    }
+
+    fn event_name(&self) -> &'static str {
+        match self {
+            IssueReleaseKind::New => "$error_tracking_issue_new_in_release",
+            IssueReleaseKind::Regressed => "$error_tracking_issue_regressed_in_release",
+        }
+    }
+}
+
+impl Display for IssueReleaseKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IssueReleaseKind::New => write!(f, "new"),
+            IssueReleaseKind::Regressed => write!(f, "regressed"),
+        }
+    }
+}
+
+// The release an exception event "belongs" to - that of the first in-app frame that has one,
+// falling back to the first frame with one at all.
+pub fn primary_release(props: &FingerprintedErrProps) -> Option<&ReleaseRecord> {
+    let frames = props
+        .exception_list
+        .iter()
+        .filter_map(|e| e.stack.as_ref())
+        .flat_map(Stacktrace::get_frames);
+
+    frames
+        .clone()
+        .filter(|f| f.in_app)
+        .find_map(|f| f.release.as_ref())
+        .or_else(|| frames.clone().find_map(|f| f.release.as_ref()))
+}
+
+impl IssueReleaseOccurrences {
+    // Record these occurrences against the issue's per-release counters, returning how the issue
+    // relates to the release if this was the first time the issue was seen in it.
+    pub async fn record(
+        &self,
+        conn: &mut PgConnection,
+    ) -> Result<Option<IssueReleaseKind>, UnhandledError> {
+        let mut txn = conn.begin().await?;
+
+        let prior: PriorReleases = sqlx::query_as(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE release_id != $2) AS release_count,
+                COUNT(*) FILTER (WHERE release_id = $2) > 0 AS this_release_seen,
+                (
+                    SELECT EXISTS (
+                        SELECT 1 FROM posthog_errortrackingissuerelease
+                        WHERE issue_id = $1 AND release_id = previous.id
+                    )
+                    FROM posthog_errortrackingrelease AS previous
+                    WHERE previous.team_id = $3 AND previous.project = $4 AND previous.created_at < $5
+                    ORDER BY previous.created_at DESC
+                    LIMIT 1
+                ) AS previous_release_seen
+            FROM posthog_errortrackingissuerelease
+            WHERE issue_id = $1
+            "#,
+        )
+        .bind(self.issue.id)
+        .bind(self.release.id)
+        .bind(self.release.team_id)
+        .bind(&self.release.project)
+        .bind(self.release.created_at)
+        .fetch_one(&mut *txn)
+        .await?;
+
+        let kind = if prior.this_release_seen {
+            None
+        } else {
+            IssueReleaseKind::classify(prior.release_count, prior.previous_release_seen)
+        };
+
+        // If another worker beat us to inserting this row, it's responsible for alerting
+        let inserted: bool = sqlx::query_scalar(
+            r#"
+            INSERT INTO posthog_errortrackingissuerelease
+                (id, team_id, issue_id, release_id, first_seen, last_seen, occurrences, kind)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (issue_id, release_id) DO UPDATE SET
+                first_seen = LEAST(posthog_errortrackingissuerelease.first_seen, EXCLUDED.first_seen),
+                last_seen = GREATEST(posthog_errortrackingissuerelease.last_seen, EXCLUDED.last_seen),
+                occurrences = posthog_errortrackingissuerelease.occurrences + EXCLUDED.occurrences
+            RETURNING (xmax = 0) AS inserted
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(self.issue.team_id)
+        .bind(self.issue.id)
+        .bind(self.release.id)
+        .bind(self.first_seen)
+        .bind(self.last_seen)
+        .bind(self.count)
+        .bind(kind.map(|k| k.to_string()))
+        .fetch_one(&mut *txn)
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(kind.filter(|_| inserted))
+    }
+}
+
+// Group the events of a batch by issue and release, and update each issue's release history,
+// alerting when an issue is new in, or has regressed in, a release. Failures here are logged and
+// swallowed - like spike detection, release tracking should never block ingestion.
+pub async fn record_release_occurrences(
+    context: Arc<AppContext>,
+    occurrences: Vec<IssueReleaseOccurrences>,
+) {
+    let mut handles = Vec::with_capacity(occurrences.len());
+    for occurrence in occurrences {
+        let m_context = context.clone();
+        handles.push(tokio::spawn(async move {
+            let res = record_and_alert(&m_context, &occurrence).await;
+            (occurrence, res)
+        }));
+    }
+
+    for handle in handles {
+        let (occurrence, res) = handle.await.expect("release tracking task did not panic");
+        if let Err(e) = res {
+            metrics::counter!(RELEASE_TRACKING_ERRORS).increment(1);
+            warn!(
+                team = occurrence.issue.team_id,
+                issue = occurrence.issue.id.to_string(),
+                release = occurrence.release.id.to_string(),
+                "Failed to record issue release occurrences: {:?}",
+                e
+            );
+        }
+    }
+}
+
+async fn record_and_alert(
+    context: &AppContext,
+    occurrence: &IssueReleaseOccurrences,
+) -> Result<(), UnhandledError> {
+    let mut conn = context.pool.acquire().await?;
+    let Some(kind) = occurrence.record(&mut conn).await? else {
+        return Ok(());
+    };
+    match kind {
+        IssueReleaseKind::New => metrics::counter!(ISSUE_NEW_IN_RELEASE).increment(1),
+        IssueReleaseKind::Regressed => metrics::counter!(ISSUE_REGRESSED_IN_RELEASE).increment(1),
+    }
+    send_issue_release_alert(context, occurrence, kind).await
+}
+
+pub fn group_release_occurrences<'a, I>(items: I) -> Vec<IssueReleaseOccurrences>
+where
+    I: IntoIterator<Item = (&'a Issue, &'a ReleaseRecord, DateTime<Utc>)>,
+{
+    let mut grouped: HashMap<(Uuid, Uuid), IssueReleaseOccurrences> = HashMap::new();
+    for (issue, release, timestamp) in items {
+        grouped
+            .entry((issue.id, release.id))
+            .and_modify(|o| {
+                o.count += 1;
+                o.first_seen = o.first_seen.min(timestamp);
+                o.last_seen = o.last_seen.max(timestamp);
+            })
+            .or_insert_with(|| IssueReleaseOccurrences {
+                issue: issue.clone(),
+                release: release.clone(),
+                count: 1,
+                first_seen: timestamp,
+                last_seen: timestamp,
+            });
+    }
+    grouped.into_values().collect()
+}
+
+async fn send_issue_release_alert(
+    context: &AppContext,
+    occurrence: &IssueReleaseOccurrences,
+    kind: IssueReleaseKind,
+) -> Result<(), UnhandledError> {
+    let mut event = issue_internal_event(kind.event_name(), &occurrence.issue, None)?;
+    event.insert_prop("release_id", occurrence.release.id)?;
+    event.insert_prop("release_version", &occurrence.release.version)?;
+    event.insert_prop("release_project", &occurrence.release.project)?;
+    emit_internal_event(context, occurrence.issue.team_id, event).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::IssueReleaseKind;
+
+    #[test]
+    fn it_classifies_issue_releases() {
+        // Never seen in any release before
+        assert_eq!(
+            IssueReleaseKind::classify(0, None),
+            Some(IssueReleaseKind::New)
+        );
+
+        // Seen in earlier releases, but not the one immediately before this one
+        assert_eq!(
+            IssueReleaseKind::classify(2, Some(false)),
+            Some(IssueReleaseKind::Regressed)
+        );
+
+        // Seen in the release immediately before this one - an ongoing issue, nothing notable
+        assert_eq!(IssueReleaseKind::classify(2, Some(true)), None);
+
+        // Only seen in releases of other projects, so there's nothing to have regressed from
+        assert_eq!(IssueReleaseKind::classify(2, None), None);
+    }
+}
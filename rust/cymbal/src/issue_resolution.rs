@@ -7,15 +7,17 @@ use common_kafka::kafka_producer::send_iter_to_kafka;
 
 use serde_json::json;
 use sqlx::{Acquire, PgConnection};
+use tracing::warn;
 use uuid::Uuid;
 
 use crate::assignment_rules::{try_assignment_rules, Assignment};
+use crate::issue_similarity::suggest_merges;
 use crate::teams::TeamManager;
 use crate::types::FingerprintedErrProps;
 use crate::{
     app_context::AppContext,
     error::UnhandledError,
-    metric_consts::{ISSUE_CREATED, ISSUE_REOPENED},
+    metric_consts::{ISSUE_CREATED, ISSUE_REOPENED, MERGE_SUGGESTION_ERRORS},
     posthog_utils::{capture_issue_created, capture_issue_reopened},
 };
 
@@ -47,7 +49,7 @@ pub enum IssueStatus {
 }
 
 impl IssueStatus {
-    fn as_str(&self) -> &'static str {
+    pub fn as_str(&self) -> &'static str {
         match self {
             IssueStatus::Archived => "Archived",
             IssueStatus::Active => "Active",
@@ -335,6 +337,27 @@ pub async fn resolve_issue(
         send_issue_created_alert(&context, &issue, assignment).await?;
         txn.commit().await?;
         capture_issue_created(team_id, issue_override.issue_id);
+
+        // Suggestions are advisory, so failing to make them should never fail the issue insert
+        if context.config.merge_suggestions_enabled {
+            if let Err(e) = suggest_merges(
+                &mut conn,
+                &issue,
+                &event_properties.exception_list,
+                context.config.merge_suggestion_threshold,
+                context.config.max_merge_suggestion_candidates,
+            )
+            .await
+            {
+                metrics::counter!(MERGE_SUGGESTION_ERRORS).increment(1);
+                warn!(
+                    team = team_id,
+                    issue = issue.id.to_string(),
+                    "Failed to suggest merges for new issue: {:?}",
+                    e
+                );
+            }
+        }
     };
 
     Ok(issue)
@@ -383,6 +406,15 @@ async fn send_internal_event(
     issue: &Issue,
     new_assignment: Option<Assignment>,
 ) -> Result<(), UnhandledError> {
+    let event = issue_internal_event(event, issue, new_assignment)?;
+    emit_internal_event(context, issue.team_id, event).await
+}
+
+pub fn issue_internal_event(
+    event: &str,
+    issue: &Issue,
+    new_assignment: Option<Assignment>,
+) -> Result<InternalEventEvent, UnhandledError> {
     let mut event = InternalEventEvent::new(event, issue.id, Utc::now(), None);
     event
         .insert_prop("name", issue.name.clone())
@@ -419,11 +451,19 @@ async fn send_internal_event(
         }
     }
 
+    Ok(event)
+}
+
+pub async fn emit_internal_event(
+    context: &AppContext,
+    team_id: i32,
+    event: InternalEventEvent,
+) -> Result<(), UnhandledError> {
     send_iter_to_kafka(
         &context.immediate_producer,
         &context.config.internal_events_topic,
         &[InternalEvent {
-            team_id: issue.team_id,
+            team_id,
             event,
             person: None,
         }],
@@ -0,0 +1,309 @@
+use chrono::Utc;
+use sha2::{Digest, Sha512};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    error::UnhandledError,
+    fingerprinting::normalize::normalize_message,
+    issue_resolution::Issue,
+    metric_consts::{ISSUE_MERGE_SUGGESTIONS, ISSUE_SIGNATURES_SAVED},
+    types::{Exception, Stacktrace},
+};
+
+// A MinHash signature of 64 values, split into 16 bands of 4 for locality sensitive hashing.
+// Two issues with a jaccard similarity of 0.8 share at least one band ~99.9% of the time, and
+// two with a similarity of 0.3 only ~12% of the time, so band overlap is a cheap, indexable
+// pre-filter before we compare full signatures.
+const SIGNATURE_LEN: usize = 64;
+const ROWS_PER_BAND: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimilaritySignature {
+    pub values: Vec<i64>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SignatureRow {
+    issue_id: Uuid,
+    signature: Vec<i64>,
+}
+
+impl SimilaritySignature {
+    // The signature is computed over the set of exception types and (normalized) function names
+    // of the in-app frames of every exception. Returns None if there's nothing to compute it over.
+    pub fn from_exceptions(exceptions: &[Exception]) -> Option<Self> {
+        let tokens: Vec<String> = exceptions.iter().flat_map(signature_tokens).collect();
+        if tokens.is_empty() {
+            return None;
+        }
+
+        let base_hashes: Vec<u64> = tokens.iter().map(|t| stable_hash(t.as_bytes())).collect();
+
+        let values = (0..SIGNATURE_LEN as u64)
+            .map(|i| {
+                let (a, b) = hash_coefficients(i);
+                base_hashes
+                    .iter()
+                    .map(|h| a.wrapping_mul(*h).wrapping_add(b))
+                    .min()
+                    .expect("tokens is non-empty") as i64
+            })
+            .collect();
+
+        Some(Self { values })
+    }
+
+    pub fn bands(&self) -> Vec<i64> {
+        self.values
+            .chunks(ROWS_PER_BAND)
+            .enumerate()
+            .map(|(band, rows)| {
+                let mut hasher = Sha512::new();
+                hasher.update(band.to_le_bytes());
+                for row in rows {
+                    hasher.update(row.to_le_bytes());
+                }
+                truncate_hash(&hasher.finalize()) as i64
+            })
+            .collect()
+    }
+
+    // The fraction of matching positions is an unbiased estimate of the jaccard similarity
+    // of the underlying token sets
+    pub fn similarity(&self, other: &Self) -> f64 {
+        if self.values.len() != other.values.len() || self.values.is_empty() {
+            return 0.0;
+        }
+        let matching = self
+            .values
+            .iter()
+            .zip(other.values.iter())
+            .filter(|(a, b)| a == b)
+            .count();
+        matching as f64 / self.values.len() as f64
+    }
+
+    pub async fn save(&self, conn: &mut PgConnection, issue: &Issue) -> Result<(), UnhandledError> {
+        sqlx::query(
+            r#"
+            INSERT INTO posthog_errortrackingissuesignature (issue_id, team_id, signature, bands, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (issue_id) DO NOTHING
+            "#,
+        )
+        .bind(issue.id)
+        .bind(issue.team_id)
+        .bind(&self.values)
+        .bind(self.bands())
+        .bind(Utc::now())
+        .execute(conn)
+        .await?;
+
+        metrics::counter!(ISSUE_SIGNATURES_SAVED).increment(1);
+        Ok(())
+    }
+
+    // Find other issues for the same team whose signatures share at least one band with this one,
+    // and whose estimated similarity is above the threshold, most similar first. If there are more
+    // candidates than we're willing to compare against, we prefer those sharing the most bands.
+    pub async fn find_similar(
+        &self,
+        conn: &mut PgConnection,
+        issue: &Issue,
+        threshold: f64,
+        max_candidates: i64,
+    ) -> Result<Vec<(Uuid, f64)>, UnhandledError> {
+        let candidates: Vec<SignatureRow> = sqlx::query_as(
+            r#"
+            SELECT issue_id, signature FROM posthog_errortrackingissuesignature
+            WHERE team_id = $1 AND bands && $2 AND issue_id != $3
+            ORDER BY (SELECT COUNT(*) FROM unnest(bands) AS band WHERE band = ANY($2)) DESC, created_at DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(issue.team_id)
+        .bind(self.bands())
+        .bind(issue.id)
+        .bind(max_candidates)
+        .fetch_all(conn)
+        .await?;
+
+        let mut similar: Vec<(Uuid, f64)> = candidates
+            .into_iter()
+            .map(|row| {
+                let other = SimilaritySignature {
+                    values: row.signature,
+                };
+                (row.issue_id, self.similarity(&other))
+            })
+            .filter(|(_, similarity)| *similarity >= threshold)
+            .collect();
+        similar.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        Ok(similar)
+    }
+}
+
+// Save the signature for a newly created issue, and record a merge suggestion for each existing
+// issue it looks like. Suggestions are only ever recorded from the newer issue to the older one.
+pub async fn suggest_merges(
+    conn: &mut PgConnection,
+    issue: &Issue,
+    exceptions: &[Exception],
+    threshold: f64,
+    max_candidates: i64,
+) -> Result<(), UnhandledError> {
+    let Some(signature) = SimilaritySignature::from_exceptions(exceptions) else {
+        return Ok(());
+    };
+
+    let similar = signature
+        .find_similar(&mut *conn, issue, threshold, max_candidates)
+        .await?;
+    signature.save(&mut *conn, issue).await?;
+
+    for (similar_issue_id, similarity) in similar {
+        sqlx::query(
+            r#"
+            INSERT INTO posthog_errortrackingissuemergesuggestion (id, team_id, issue_id, similar_issue_id, similarity, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (issue_id, similar_issue_id) DO NOTHING
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(issue.team_id)
+        .bind(issue.id)
+        .bind(similar_issue_id)
+        .bind(similarity)
+        .bind(Utc::now())
+        .execute(&mut *conn)
+        .await?;
+        metrics::counter!(ISSUE_MERGE_SUGGESTIONS).increment(1);
+    }
+
+    Ok(())
+}
+
+fn signature_tokens(exception: &Exception) -> Vec<String> {
+    let mut tokens = vec![format!("type:{}", exception.exception_type)];
+
+    let Some(Stacktrace::Resolved { frames }) = &exception.stack else {
+        return tokens;
+    };
+
+    // Like fingerprinting, we prefer in-app frames, but fall back to all frames if there are none
+    let has_in_app = frames.iter().any(|f| f.in_app);
+    for frame in frames.iter().filter(|f| f.in_app || !has_in_app) {
+        let name = frame.resolved_name.as_ref().unwrap_or(&frame.mangled_name);
+        if name.is_empty() {
+            continue;
+        }
+        tokens.push(format!("fn:{}", normalize_message(name)));
+    }
+
+    tokens
+}
+
+// Signatures are persisted, so we need a hash that's stable across builds, unlike std's DefaultHasher
+fn stable_hash(data: &[u8]) -> u64 {
+    truncate_hash(&Sha512::digest(data))
+}
+
+fn truncate_hash(digest: &[u8]) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_le_bytes(bytes)
+}
+
+// Deterministically derive the (odd) multiplier and offset of the i'th hash function, via splitmix64
+fn hash_coefficients(i: u64) -> (u64, u64) {
+    let mix = |mut z: u64| {
+        z = z.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    (mix(2 * i) | 1, mix(2 * i + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        frames::Frame,
+        types::{Exception, Stacktrace},
+    };
+
+    use super::SimilaritySignature;
+
+    fn frame(name: &str) -> Frame {
+        Frame {
+            raw_id: String::new(),
+            mangled_name: name.to_string(),
+            line: Some(1),
+            column: Some(1),
+            source: None,
+            in_app: true,
+            resolved_name: Some(name.to_string()),
+            resolved: true,
+            resolve_failure: None,
+            lang: "javascript".to_string(),
+            junk_drawer: None,
+            context: None,
+            release: None,
+        }
+    }
+
+    fn exception(exception_type: &str, names: &[&str]) -> Exception {
+        Exception {
+            exception_id: None,
+            exception_type: exception_type.to_string(),
+            exception_message: "message".to_string(),
+            mechanism: None,
+            module: None,
+            thread_id: None,
+            stack: Some(Stacktrace::Resolved {
+                frames: names.iter().map(|n| frame(n)).collect(),
+            }),
+        }
+    }
+
+    #[test]
+    fn it_computes_stable_signatures() {
+        let exc = exception("TypeError", &["render", "update", "commit"]);
+        let a = SimilaritySignature::from_exceptions(&[exc.clone()]).unwrap();
+        let b = SimilaritySignature::from_exceptions(&[exc]).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(a.values.len(), 64);
+        assert_eq!(a.bands().len(), 16);
+        assert_eq!(a.similarity(&b), 1.0);
+    }
+
+    #[test]
+    fn it_scores_similar_issues_higher() {
+        let names: Vec<String> = (0..20).map(|i| format!("function_{}", i)).collect();
+        let names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+        let base = SimilaritySignature::from_exceptions(&[exception("TypeError", &names)]).unwrap();
+
+        // Same stack, one extra frame
+        let mut nearly = names.clone();
+        nearly.push("extraFrame");
+        let nearly =
+            SimilaritySignature::from_exceptions(&[exception("TypeError", &nearly)]).unwrap();
+
+        let different = SimilaritySignature::from_exceptions(&[exception(
+            "RangeError",
+            &["parse", "tokenize", "lex"],
+        )])
+        .unwrap();
+
+        assert!(base.similarity(&nearly) > 0.7);
+        assert!(base.similarity(&different) < 0.2);
+    }
+
+    #[test]
+    fn it_needs_something_to_sign() {
+        assert_eq!(SimilaritySignature::from_exceptions(&[]), None);
+    }
+}
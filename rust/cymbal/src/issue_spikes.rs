@@ -0,0 +1,285 @@
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use common_redis::{Client, RedisValueFormat};
+use tracing::warn;
+
+use crate::{
+    app_context::AppContext,
+    config::Config,
+    error::UnhandledError,
+    issue_resolution::{emit_internal_event, issue_internal_event, Issue, IssueStatus},
+    metric_consts::{ISSUE_SPIKING, SPIKE_DETECTION_ERRORS},
+};
+
+const OCCURRENCES_KEY_PREFIX: &str = "cymbal:issue_occurrences";
+const SPIKE_ALERTED_KEY_PREFIX: &str = "cymbal:issue_spike_alerted";
+const SECONDS_PER_BUCKET: i64 = 3600;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpikeThresholds {
+    pub multiplier: f64,
+    pub baseline_hours: u64,
+    pub min_occurrences: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spike {
+    pub occurrences: u64,
+    pub baseline: f64,
+}
+
+impl From<&Config> for SpikeThresholds {
+    fn from(config: &Config) -> Self {
+        Self {
+            multiplier: config.spike_multiplier,
+            baseline_hours: config.spike_baseline_hours,
+            min_occurrences: config.spike_min_occurrences,
+        }
+    }
+}
+
+impl SpikeThresholds {
+    // Given the per-hour occurrence counts we've recorded for an issue, decide whether
+    // the current hour is a spike relative to the trailing baseline. Missing buckets
+    // are hours with no occurrences.
+    pub fn check(&self, buckets: &HashMap<i64, u64>, current_bucket: i64) -> Option<Spike> {
+        let occurrences = buckets.get(&current_bucket).copied().unwrap_or_default();
+        if occurrences < self.min_occurrences {
+            return None;
+        }
+
+        let baseline_hours = self.baseline_hours.max(1);
+        let trailing: u64 = (1..=baseline_hours as i64)
+            .filter_map(|offset| buckets.get(&(current_bucket - offset)))
+            .sum();
+        let baseline = trailing as f64 / baseline_hours as f64;
+
+        if (occurrences as f64) < baseline * self.multiplier {
+            return None;
+        }
+
+        Some(Spike {
+            occurrences,
+            baseline,
+        })
+    }
+}
+
+pub fn hour_bucket(ts: DateTime<Utc>) -> i64 {
+    ts.timestamp().div_euclid(SECONDS_PER_BUCKET)
+}
+
+// Record the occurrences seen in this batch against each issue's rolling counters, and
+// emit a spike alert for any issue whose current hourly rate is well above its baseline.
+// Failures here are logged and swallowed - spike detection should never block ingestion.
+pub async fn record_occurrences(context: Arc<AppContext>, occurrences: Vec<(Issue, u64)>) {
+    let now = Utc::now();
+    let mut handles = Vec::with_capacity(occurrences.len());
+    for (issue, count) in occurrences {
+        if matches!(issue.status, IssueStatus::Suppressed) {
+            continue;
+        }
+        let m_context = context.clone();
+        handles.push(tokio::spawn(async move {
+            let res = record_and_check(&m_context, &issue, count, now).await;
+            (issue, res)
+        }));
+    }
+
+    for handle in handles {
+        let (issue, res) = handle.await.expect("spike detection task did not panic");
+        if let Err(e) = res {
+            metrics::counter!(SPIKE_DETECTION_ERRORS).increment(1);
+            warn!(
+                team = issue.team_id,
+                issue = issue.id.to_string(),
+                "Failed to check issue for spike: {:?}",
+                e
+            );
+        }
+    }
+}
+
+async fn record_and_check(
+    context: &AppContext,
+    issue: &Issue,
+    count: u64,
+    now: DateTime<Utc>,
+) -> Result<(), UnhandledError> {
+    let thresholds = SpikeThresholds::from(&context.config);
+    let current_bucket = hour_bucket(now);
+    let key = format!("{}:{}:{}", OCCURRENCES_KEY_PREFIX, issue.team_id, issue.id);
+
+    let buckets = record_buckets(
+        context.redis.as_ref(),
+        key,
+        &thresholds,
+        current_bucket,
+        count,
+    )
+    .await?;
+
+    let Some(spike) = thresholds.check(&buckets, current_bucket) else {
+        return Ok(());
+    };
+
+    // Only alert once per cooldown window, no matter how many batches see the spike
+    let alerted_key = format!("{}:{}", SPIKE_ALERTED_KEY_PREFIX, issue.id);
+    let first_alert = context
+        .redis
+        .set_nx_ex_with_format(
+            alerted_key,
+            "1".to_string(),
+            context.config.spike_alert_cooldown_minutes * 60,
+            RedisValueFormat::Utf8,
+        )
+        .await?;
+    if !first_alert {
+        return Ok(());
+    }
+
+    metrics::counter!(ISSUE_SPIKING).increment(1);
+    send_issue_spiking_alert(context, issue, spike).await
+}
+
+// Bump the current hour's counter, and return every hour we still have a count for. The
+// counters for an issue live in a single hash, with a field per hour. Redis TTLs apply to the
+// whole key, so the TTL only cleans up issues that stop occurring - for active issues, we have
+// to drop the buckets that have fallen out of the baseline window ourselves. This is all done
+// in a single pipeline, so it's one round trip per issue.
+async fn record_buckets(
+    redis: &(dyn Client + Send + Sync),
+    key: String,
+    thresholds: &SpikeThresholds,
+    current_bucket: i64,
+    count: u64,
+) -> Result<HashMap<i64, u64>, UnhandledError> {
+    let baseline_hours = thresholds.baseline_hours.max(1) as i64;
+    let oldest_bucket = current_bucket - baseline_hours;
+    let ttl = (thresholds.baseline_hours + 1) * SECONDS_PER_BUCKET as u64;
+
+    // The key expires if an issue goes a whole window without occurring, so the only buckets that
+    // can have fallen out of the window since we last wrote to it are the window before this one
+    let stale_buckets = (oldest_bucket - baseline_hours - 1..oldest_bucket)
+        .map(|bucket| bucket.to_string())
+        .collect();
+
+    let stored = redis
+        .hincrby_and_getall(
+            key,
+            current_bucket.to_string(),
+            count.min(i32::MAX as u64) as i32,
+            ttl,
+            stale_buckets,
+        )
+        .await?;
+
+    let buckets = stored
+        .into_iter()
+        .filter_map(|(field, value)| Some((field.parse::<i64>().ok()?, value.parse::<u64>().ok()?)))
+        .filter(|(bucket, _)| *bucket >= oldest_bucket)
+        .collect();
+
+    Ok(buckets)
+}
+
+async fn send_issue_spiking_alert(
+    context: &AppContext,
+    issue: &Issue,
+    spike: Spike,
+) -> Result<(), UnhandledError> {
+    let mut event = issue_internal_event("$error_tracking_issue_spiking", issue, None)?;
+    event.insert_prop("occurrences", spike.occurrences)?;
+    event.insert_prop("baseline_hourly_occurrences", spike.baseline)?;
+    emit_internal_event(context, issue.team_id, event).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use common_redis::{MockRedisClient, MockRedisValue};
+
+    use super::{record_buckets, Spike, SpikeThresholds};
+
+    const THRESHOLDS: SpikeThresholds = SpikeThresholds {
+        multiplier: 10.0,
+        baseline_hours: 4,
+        min_occurrences: 100,
+    };
+
+    #[test]
+    fn it_ignores_low_volume_issues() {
+        let buckets = HashMap::from([(100, 99)]);
+        assert_eq!(THRESHOLDS.check(&buckets, 100), None);
+    }
+
+    #[test]
+    fn it_detects_spikes_against_trailing_baseline() {
+        // 10 an hour for the last 4 hours, then 400 in the current one
+        let mut buckets: HashMap<i64, u64> = (96..100).map(|b| (b, 10)).collect();
+        buckets.insert(100, 400);
+        assert_eq!(
+            THRESHOLDS.check(&buckets, 100),
+            Some(Spike {
+                occurrences: 400,
+                baseline: 10.0
+            })
+        );
+
+        // 50 an hour is a baseline of 50, so 400 is only 8x
+        let mut buckets: HashMap<i64, u64> = (96..100).map(|b| (b, 50)).collect();
+        buckets.insert(100, 400);
+        assert_eq!(THRESHOLDS.check(&buckets, 100), None);
+    }
+
+    #[test]
+    fn it_ignores_buckets_outside_the_baseline_window() {
+        // A huge count 5 hours ago is outside the 4 hour window, and shouldn't raise the baseline
+        let buckets = HashMap::from([(95, 100_000), (100, 200)]);
+        assert_eq!(
+            THRESHOLDS.check(&buckets, 100),
+            Some(Spike {
+                occurrences: 200,
+                baseline: 0.0
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_drops_buckets_outside_the_baseline_window() {
+        let key = "cymbal:issue_occurrences:1:issue";
+        let stored = HashMap::from([
+            ("95".to_string(), "100000".to_string()),
+            ("96".to_string(), "10".to_string()),
+            ("100".to_string(), "200".to_string()),
+        ]);
+        let redis = MockRedisClient::new().hincrby_and_getall_ret(key, Ok(stored));
+
+        let buckets = record_buckets(&redis, key.to_string(), &THRESHOLDS, 100, 200)
+            .await
+            .unwrap();
+
+        assert_eq!(buckets, HashMap::from([(96, 10), (100, 200)]));
+
+        let ops: Vec<_> = redis
+            .get_calls()
+            .into_iter()
+            .map(|c| (c.op, c.value))
+            .collect();
+        // Everything happens in one pipeline, dropping the window of buckets before the baseline
+        assert_eq!(
+            ops,
+            vec![(
+                "hincrby_and_getall".to_string(),
+                MockRedisValue::HashIncrement {
+                    field: "100".to_string(),
+                    count: 200,
+                    seconds: 5 * 3600,
+                    stale_fields: (91..96).map(|b| b.to_string()).collect(),
+                }
+            )]
+        );
+    }
+}
@@ -11,7 +11,10 @@ pub mod config;
 pub mod error;
 pub mod fingerprinting;
 pub mod frames;
+pub mod issue_releases;
 pub mod issue_resolution;
+pub mod issue_similarity;
+pub mod issue_spikes;
 pub mod langs;
 pub mod metric_consts;
 pub mod pipeline;
@@ -64,3 +64,11 @@ pub const GROUPING_RULES_PROCESSING_TIME: &str = "cymbal_grouping_rules_processi
 pub const GROUPING_RULES_FOUND: &str = "cymbal_grouping_rules_found";
 pub const GROUPING_RULES_TRIED: &str = "cymbal_grouping_rules_tried";
 pub const CUSTOM_GROUPED_EVENTS: &str = "cymbal_custom_grouped_events";
+pub const ISSUE_SPIKING: &str = "cymbal_issue_spiking";
+pub const SPIKE_DETECTION_ERRORS: &str = "cymbal_spike_detection_errors";
+pub const ISSUE_SIGNATURES_SAVED: &str = "cymbal_issue_signatures_saved";
+pub const ISSUE_MERGE_SUGGESTIONS: &str = "cymbal_issue_merge_suggestions";
+pub const MERGE_SUGGESTION_ERRORS: &str = "cymbal_merge_suggestion_errors";
+pub const ISSUE_NEW_IN_RELEASE: &str = "cymbal_issue_new_in_release";
+pub const ISSUE_REGRESSED_IN_RELEASE: &str = "cymbal_issue_regressed_in_release";
+pub const RELEASE_TRACKING_ERRORS: &str = "cymbal_release_tracking_errors";
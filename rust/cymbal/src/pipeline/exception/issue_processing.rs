@@ -5,11 +5,14 @@ use std::{
 
 use chrono::Utc;
 use tracing::warn;
+use uuid::Uuid;
 
 use crate::{
     app_context::AppContext,
     error::{PipelineResult, UnhandledError},
+    issue_releases::{group_release_occurrences, primary_release, record_release_occurrences},
     issue_resolution::{resolve_issue, Issue},
+    issue_spikes::record_occurrences,
     pipeline::parse_ts_assuming_utc,
     types::FingerprintedErrProps,
 };
@@ -69,6 +72,29 @@ pub async fn do_issue_processing(
         resolved_issues.insert(fingerprint, issue);
     }
 
+    if context.config.release_tracking_enabled {
+        let occurrences =
+            group_release_occurrences(indexed_fingerprinted.iter().filter_map(|(index, props)| {
+                let issue = resolved_issues.get(&props.fingerprint.value)?;
+                let release = primary_release(props)?;
+                let event = events[*index]
+                    .as_ref()
+                    .expect("no events have been dropped since indexed-property gathering");
+                let timestamp =
+                    parse_ts_assuming_utc(&event.timestamp).unwrap_or_else(|_| Utc::now());
+                Some((issue, release, timestamp))
+            }));
+        record_release_occurrences(context.clone(), occurrences).await;
+    }
+
+    if context.config.spike_detection_enabled {
+        record_occurrences(
+            context,
+            count_occurrences(&resolved_issues, indexed_fingerprinted),
+        )
+        .await;
+    }
+
     Ok(resolved_issues)
 }
 
@@ -83,3 +109,21 @@ fn find_index_with_matching_fingerprint(
     }
     0
 }
+
+// Multiple fingerprints can resolve to the same issue, so we count per issue, not per fingerprint
+fn count_occurrences(
+    resolved_issues: &HashMap<String, Issue>,
+    list: &[(usize, FingerprintedErrProps)],
+) -> Vec<(Issue, u64)> {
+    let mut counts: HashMap<Uuid, (Issue, u64)> = HashMap::new();
+    for (_, props) in list.iter() {
+        let Some(issue) = resolved_issues.get(&props.fingerprint.value) else {
+            continue;
+        };
+        counts
+            .entry(issue.id)
+            .or_insert_with(|| (issue.clone(), 0))
+            .1 += 1;
+    }
+    counts.into_values().collect()
+}
@@ -109,9 +109,15 @@ pub async fn do_stack_processing(
             .await
             .map_err(|e| (index, e.into()))?;
 
-        let proposed = resolve_fingerprint(&mut conn, &context.team_manager, team_id, &props)
-            .await
-            .map_err(|e| (index, e))?;
+        let proposed = resolve_fingerprint(
+            &mut conn,
+            &context.team_manager,
+            team_id,
+            &props,
+            context.should_normalize_messages(team_id),
+        )
+        .await
+        .map_err(|e| (index, e))?;
 
         let fingerprinted = props.to_fingerprinted(proposed);
         indexed_fingerprinted.push((index, fingerprinted));
@@ -0,0 +1,143 @@
+use std::path::PathBuf;
+
+use axum::async_trait;
+use posthog_symbol_data::{read_symbol_data, write_symbol_data, SourceAndMap};
+use reqwest::Url;
+
+use crate::error::{Error, FrameError, JsResolveErr, UnhandledError};
+
+use super::{chunk_id::OrChunkId, sourcemap::OwnedSourceMapCache, Fetcher, Parser};
+
+// A symbol set store backed by a local directory, for offline debugging and reprocessing. Chunk
+// ids are looked up as `<root>/<chunk_id>`, containing symbol data as uploaded by the CLI. Source
+// urls are looked up as `<root>/<host>[:<port>]/<path>`, with the sourcemap alongside it at
+// `<path>.map`. The port is only included if the url has a non-default one.
+pub struct LocalSymbolStore {
+    root: PathBuf,
+}
+
+impl LocalSymbolStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    async fn fetch_chunk(&self, id: &str) -> Result<Option<Vec<u8>>, Error> {
+        // Chunk ids are user provided, so we don't let them escape the root
+        if id.contains('/') || id.contains('\\') || id.starts_with('.') {
+            return Err(FrameError::MissingChunkIdData(id.to_string()).into());
+        }
+        read_if_exists(self.root.join(id)).await
+    }
+
+    async fn fetch_url(&self, url: Url) -> Result<Vec<u8>, Error> {
+        let Some(host) = url.host_str() else {
+            return Err(JsResolveErr::InvalidSourceUrl(url.to_string()).into());
+        };
+        // Keep the port, so e.g. a local dev server's sources are kept apart from production's
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        };
+
+        let mut source_path = self.root.join(host);
+        for segment in url.path_segments().into_iter().flatten() {
+            if segment.is_empty() || segment == "." || segment == ".." {
+                continue;
+            }
+            source_path.push(segment);
+        }
+        let mut map_path = source_path.clone().into_os_string();
+        map_path.push(".map");
+
+        let Some(minified_source) = read_if_exists(source_path).await? else {
+            return Err(JsResolveErr::NoSourcemap(url.to_string()).into());
+        };
+        let Some(sourcemap) = read_if_exists(map_path.into()).await? else {
+            return Err(JsResolveErr::NoSourcemap(url.to_string()).into());
+        };
+
+        let sam = SourceAndMap {
+            minified_source: String::from_utf8_lossy(&minified_source).into_owned(),
+            sourcemap: String::from_utf8_lossy(&sourcemap).into_owned(),
+        };
+        Ok(write_symbol_data(sam).map_err(JsResolveErr::JSDataError)?)
+    }
+}
+
+#[async_trait]
+impl Fetcher for LocalSymbolStore {
+    type Ref = OrChunkId<Url>;
+    type Fetched = Vec<u8>;
+    type Err = Error;
+
+    async fn fetch(&self, _: i32, r: Self::Ref) -> Result<Self::Fetched, Self::Err> {
+        match r {
+            OrChunkId::Inner(url) => self.fetch_url(url).await,
+            OrChunkId::ChunkId(id) => self
+                .fetch_chunk(&id)
+                .await?
+                .ok_or_else(|| FrameError::MissingChunkIdData(id).into()),
+            OrChunkId::Both { inner, id } => match self.fetch_chunk(&id).await? {
+                Some(data) => Ok(data),
+                None => self.fetch_url(inner).await,
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Parser for LocalSymbolStore {
+    type Source = Vec<u8>;
+    type Set = OwnedSourceMapCache;
+    type Err = Error;
+
+    async fn parse(&self, data: Vec<u8>) -> Result<Self::Set, Self::Err> {
+        let sam: SourceAndMap = read_symbol_data(data).map_err(JsResolveErr::JSDataError)?;
+        Ok(OwnedSourceMapCache::from_source_and_map(sam)
+            .map_err(|_| JsResolveErr::InvalidSourceAndMap)?)
+    }
+}
+
+async fn read_if_exists(path: PathBuf) -> Result<Option<Vec<u8>>, Error> {
+    match tokio::fs::read(&path).await {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            Err(UnhandledError::Other(format!("Failed to read {}: {}", path.display(), e)).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::Url;
+
+    use crate::symbol_store::{chunk_id::OrChunkId, Catalog, SymbolCatalog};
+
+    use super::LocalSymbolStore;
+
+    const MINIFIED: &str = include_str!("../../tests/static/inline_sourcemap_example.js");
+    const MAP: &str = include_str!("../../tests/static/inline_sourcemap_example.js.map");
+
+    #[tokio::test]
+    async fn it_reads_sources_and_maps_from_disk() {
+        let root =
+            std::env::temp_dir().join(format!("cymbal-local-store-{}", uuid::Uuid::now_v7()));
+        let dir = root.join("localhost:8000").join("static");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("example.js"), MINIFIED).unwrap();
+        std::fs::write(dir.join("example.js.map"), MAP).unwrap();
+
+        let catalog = Catalog::new(LocalSymbolStore::new(&root));
+
+        let url: Url = "http://localhost:8000/static/example.js".parse().unwrap();
+        let res = catalog.lookup(0, OrChunkId::inner(url)).await;
+        assert!(res.is_ok());
+
+        let missing: Url = "http://localhost:8000/static/missing.js".parse().unwrap();
+        let res = catalog.lookup(0, OrChunkId::inner(missing)).await;
+        assert!(res.is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -11,6 +11,7 @@ use crate::error::Error;
 pub mod caching;
 pub mod chunk_id;
 pub mod concurrency;
+pub mod local;
 pub mod saving;
 pub mod sourcemap;
 
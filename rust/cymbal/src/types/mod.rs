@@ -6,10 +6,12 @@ use std::hash::Hash;
 use uuid::Uuid;
 
 use crate::fingerprinting::{
-    Fingerprint, FingerprintBuilder, FingerprintComponent, FingerprintRecordPart,
+    normalize::normalize_message, Fingerprint, FingerprintBuilder, FingerprintComponent,
+    FingerprintRecordPart,
 };
 use crate::frames::releases::{ReleaseInfo, ReleaseRecord};
 use crate::frames::{Frame, RawFrame};
+use crate::issue_releases::primary_release;
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Mechanism {
@@ -98,6 +100,12 @@ pub struct OutputErrProps {
         skip_serializing_if = "HashMap::is_empty"
     )]
     pub releases: HashMap<String, ReleaseInfo>,
+    // The key in `releases` of the release this exception is attributed to, if any
+    #[serde(
+        rename = "$exception_primary_release",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub primary_release: Option<String>,
     // Search metadata (materialized)
     #[serde(rename = "$exception_types")]
     pub types: Vec<String>,
@@ -115,8 +123,18 @@ impl FingerprintComponent for Exception {
         fp.update(self.exception_type.as_bytes());
         pieces.push("Exception Type".to_string());
         if !matches!(self.stack, Some(Stacktrace::Resolved { frames: _ })) {
-            fp.update(self.exception_message.as_bytes());
-            pieces.push("Exception Message".to_string());
+            if fp.normalize_messages {
+                let normalized = normalize_message(&self.exception_message);
+                fp.update(normalized.as_bytes());
+                if normalized != self.exception_message {
+                    pieces.push(format!("Normalized Exception Message: {}", normalized));
+                } else {
+                    pieces.push("Exception Message".to_string());
+                }
+            } else {
+                fp.update(self.exception_message.as_bytes());
+                pieces.push("Exception Message".to_string());
+            }
         };
         fp.add_part(FingerprintRecordPart::Exception {
             id: self.exception_id.clone(),
@@ -196,6 +214,8 @@ impl RawErrProps {
 
 impl FingerprintedErrProps {
     pub fn to_output(self, issue_id: Uuid) -> OutputErrProps {
+        let primary_release = primary_release(&self).map(|r| r.hash_id.clone());
+
         let frames = self
             .exception_list
             .iter()
@@ -234,6 +254,7 @@ impl FingerprintedErrProps {
             functions,
             handled,
             releases,
+            primary_release,
         }
     }
 }
@@ -0,0 +1,24 @@
+CREATE TABLE IF NOT EXISTS posthog_errortrackingissuesignature
+(
+    issue_id uuid NOT NULL,
+    team_id integer NOT NULL,
+    signature bigint[] NOT NULL,
+    bands bigint[] NOT NULL,
+    created_at timestamp with time zone NOT NULL,
+    CONSTRAINT posthog_errortrackingissuesignature_pkey PRIMARY KEY (issue_id)
+);
+
+CREATE INDEX IF NOT EXISTS posthog_errortrackingissuesignature_bands_idx
+    ON posthog_errortrackingissuesignature USING GIN (bands);
+
+CREATE TABLE IF NOT EXISTS posthog_errortrackingissuemergesuggestion
+(
+    id uuid NOT NULL,
+    team_id integer NOT NULL,
+    issue_id uuid NOT NULL,
+    similar_issue_id uuid NOT NULL,
+    similarity double precision NOT NULL,
+    created_at timestamp with time zone NOT NULL,
+    CONSTRAINT posthog_errortrackingissuemergesuggestion_pkey PRIMARY KEY (id),
+    CONSTRAINT posthog_errortrackingissuemergesuggestion_pair_uniq UNIQUE (issue_id, similar_issue_id)
+);
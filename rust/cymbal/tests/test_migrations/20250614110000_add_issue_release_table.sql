@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS posthog_errortrackingissuerelease
+(
+    id uuid NOT NULL,
+    team_id integer NOT NULL,
+    issue_id uuid NOT NULL,
+    release_id uuid NOT NULL,
+    first_seen timestamp with time zone NOT NULL,
+    last_seen timestamp with time zone NOT NULL,
+    occurrences bigint NOT NULL,
+    kind varchar(32),
+    CONSTRAINT posthog_errortrackingissuerelease_pkey PRIMARY KEY (id),
+    CONSTRAINT posthog_errortrackingissuerelease_issue_release_uniq UNIQUE (issue_id, release_id)
+);
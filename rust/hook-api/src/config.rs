@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use envconfig::Envconfig;
 
 #[derive(Envconfig)]
@@ -25,10 +27,36 @@ pub struct Config {
 
     #[envconfig(default = "false")]
     pub hog_mode: bool,
+
+    /// A comma separated list of the topics webhooks may be produced to with the Kafka transport.
+    #[envconfig(default = "")]
+    pub kafka_transport_topics: String,
+
+    /// A comma separated list of the domains webhooks may be emailed to with the SMTP transport.
+    #[envconfig(default = "")]
+    pub smtp_recipient_domains: String,
 }
 
 impl Config {
     pub fn bind(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
+
+    pub fn kafka_transport_topics(&self) -> HashSet<String> {
+        self.kafka_transport_topics
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    pub fn smtp_recipient_domains(&self) -> HashSet<String> {
+        self.smtp_recipient_domains
+            .split(',')
+            .map(str::trim)
+            .filter(|domain| !domain.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect()
+    }
 }
@@ -1,11 +1,13 @@
+use std::collections::HashSet;
 use std::convert::Infallible;
+use std::sync::Arc;
 
 use axum::{extract::DefaultBodyLimit, routing, Router};
 use tower::limit::ConcurrencyLimitLayer;
 
 use hook_common::pgqueue::PgQueue;
 
-use super::webhook;
+use super::{delivery, webhook};
 
 pub fn add_routes(
     router: Router,
@@ -13,6 +15,8 @@ pub fn add_routes(
     hog_mode: bool,
     max_body_size: usize,
     concurrency_limit: usize,
+    kafka_transport_topics: HashSet<String>,
+    smtp_recipient_domains: HashSet<String>,
 ) -> Router {
     let router = router
         .route("/", routing::get(index))
@@ -28,13 +32,26 @@ pub fn add_routes(
                 .layer(DefaultBodyLimit::max(max_body_size)),
         )
     } else {
-        router.route(
-            "/webhook",
-            routing::post(webhook::post_webhook)
-                .with_state(pg_pool)
-                .layer::<_, Infallible>(ConcurrencyLimitLayer::new(concurrency_limit))
-                .layer(DefaultBodyLimit::max(max_body_size)),
-        )
+        router
+            .route(
+                "/webhook",
+                routing::post(webhook::post_webhook)
+                    .with_state(webhook::WebhookState {
+                        pg_queue: pg_pool.clone(),
+                        kafka_transport_topics: Arc::new(kafka_transport_topics),
+                        smtp_recipient_domains: Arc::new(smtp_recipient_domains),
+                    })
+                    .layer::<_, Infallible>(ConcurrencyLimitLayer::new(concurrency_limit))
+                    .layer(DefaultBodyLimit::max(max_body_size)),
+            )
+            .route(
+                "/webhook/deliveries",
+                routing::get(delivery::list_deliveries).with_state(pg_pool.clone()),
+            )
+            .route(
+                "/webhook/jobs/:job_id/redeliver",
+                routing::post(delivery::redeliver).with_state(pg_pool),
+            )
     }
 }
 
@@ -59,7 +76,15 @@ mod tests {
         let pg_queue = PgQueue::new_from_pool("test_index", db).await;
         let hog_mode = false;
 
-        let app = add_routes(Router::new(), pg_queue, hog_mode, 1_000_000, 10);
+        let app = add_routes(
+            Router::new(),
+            pg_queue,
+            hog_mode,
+            1_000_000,
+            10,
+            HashSet::new(),
+            HashSet::new(),
+        );
 
         let response = app
             .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
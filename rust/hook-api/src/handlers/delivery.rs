@@ -0,0 +1,224 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde_derive::Deserialize;
+
+use hook_common::delivery::{Delivery, DeliveryLog};
+use hook_common::pgqueue::{NewJob, PgQueue};
+
+use super::webhook::{get_hostname, internal_error, WebhookPostResponse};
+
+const MAX_DELIVERIES_LIMIT: i64 = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct ListDeliveriesQuery {
+    plugin_config_id: i32,
+    /// Only list deliveries older than this one, for paginating.
+    before_id: Option<i64>,
+    #[serde(default = "default_limit")]
+    limit: i64,
+}
+
+fn default_limit() -> i64 {
+    20
+}
+
+/// List the most recent delivery attempts for a plugin config, newest first.
+pub async fn list_deliveries(
+    State(pg_queue): State<PgQueue>,
+    Query(query): Query<ListDeliveriesQuery>,
+) -> Result<Json<Vec<Delivery>>, (StatusCode, Json<WebhookPostResponse>)> {
+    let delivery_log = DeliveryLog::new(pg_queue.pool().clone());
+
+    let deliveries = delivery_log
+        .list(
+            query.plugin_config_id,
+            query.before_id,
+            query.limit.clamp(1, MAX_DELIVERIES_LIMIT),
+        )
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(deliveries))
+}
+
+/// Enqueue a job that was previously delivered again, as a new job with a fresh set of attempts.
+pub async fn redeliver(
+    State(pg_queue): State<PgQueue>,
+    Path(job_id): Path<i64>,
+) -> Result<Json<WebhookPostResponse>, (StatusCode, Json<WebhookPostResponse>)> {
+    let delivery_log = DeliveryLog::new(pg_queue.pool().clone());
+
+    let Some(delivered_job) = delivery_log.get_job(job_id).await.map_err(internal_error)? else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(WebhookPostResponse {
+                error: Some(format!("no deliveries found for job {}", job_id)),
+            }),
+        ));
+    };
+
+    let url_hostname = get_hostname(&delivered_job.parameters.url)?;
+    let job = NewJob::new(
+        delivered_job.max_attempts,
+        delivered_job.metadata.0,
+        delivered_job.parameters.0,
+        url_hostname.as_str(),
+    );
+
+    pg_queue.enqueue(job).await.map_err(internal_error)?;
+
+    metrics::counter!("webhook_api_redeliveries").increment(1);
+
+    Ok(Json(WebhookPostResponse { error: None }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::time::Duration;
+
+    use axum::{
+        body::Body,
+        http::{self, Request, StatusCode},
+        Router,
+    };
+    use hook_common::delivery::{DeliveryLog, NewDelivery};
+    use hook_common::pgqueue::PgQueue;
+    use hook_common::webhook::{
+        HttpMethod, WebhookJobMetadata, WebhookJobParameters, WebhookTransport,
+    };
+    use http_body_util::BodyExt; // for `collect`
+    use serde_json::Value;
+    use sqlx::PgPool;
+    use tower::ServiceExt; // for `call`, `oneshot`, and `ready`
+
+    use crate::handlers::app::add_routes;
+
+    const MAX_BODY_SIZE: usize = 1_000_000;
+    const CONCURRENCY_LIMIT: usize = 10;
+
+    async fn record_delivery(db: PgPool, job_id: i64) {
+        let metadata = WebhookJobMetadata {
+            team_id: 1,
+            plugin_id: 2,
+            plugin_config_id: 3,
+            signing_secret_ref: None,
+            batch: false,
+        };
+        let parameters = WebhookJobParameters {
+            body: r#"{"a": "b"}"#.to_owned(),
+            headers: HashMap::new(),
+            method: HttpMethod::POST,
+            url: "http://example.com/".to_owned(),
+            transport: WebhookTransport::Http,
+        };
+
+        DeliveryLog::new(db)
+            .record(NewDelivery {
+                job_id,
+                attempt: 1,
+                max_attempts: 3,
+                metadata: &metadata,
+                parameters: &parameters,
+                request_headers: &parameters.headers,
+                status: Some(500),
+                response_headers: None,
+                response_body: Some("oops"),
+                error: Some("500 Internal Server Error".to_owned()),
+                duration: Duration::from_millis(25),
+            })
+            .await
+            .expect("failed to record delivery");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn list_deliveries(db: PgPool) {
+        let pg_queue = PgQueue::new_from_pool("test_list_deliveries", db.clone()).await;
+        record_delivery(db, 42).await;
+
+        let app = add_routes(
+            Router::new(),
+            pg_queue,
+            false,
+            MAX_BODY_SIZE,
+            CONCURRENCY_LIMIT,
+            HashSet::new(),
+            HashSet::new(),
+        );
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/webhook/deliveries?plugin_config_id=3")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let deliveries: Vec<Value> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0]["job_id"], 42);
+        assert_eq!(deliveries[0]["status"], 500);
+        assert_eq!(deliveries[0]["response_body"], "oops");
+        assert_eq!(deliveries[0]["duration_ms"], 25);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn redeliver(db: PgPool) {
+        let pg_queue = PgQueue::new_from_pool("test_redeliver", db.clone()).await;
+        record_delivery(db.clone(), 42).await;
+
+        let app = add_routes(
+            Router::new(),
+            pg_queue,
+            false,
+            MAX_BODY_SIZE,
+            CONCURRENCY_LIMIT,
+            HashSet::new(),
+            HashSet::new(),
+        );
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/webhook/jobs/42/redeliver")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let (target, max_attempts): (String, i32) = sqlx::query_as(
+            "SELECT target, max_attempts FROM job_queue WHERE queue = 'test_redeliver'",
+        )
+        .fetch_one(&db)
+        .await
+        .expect("redelivered job not found");
+        assert_eq!(target, "example.com");
+        assert_eq!(max_attempts, 3);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method(http::Method::POST)
+                    .uri("/webhook/jobs/43/redeliver")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::time::Instant;
 
 use axum::{body::Bytes, extract::State, http::StatusCode, Json};
-use hook_common::webhook::{WebhookJobMetadata, WebhookJobParameters};
+use hook_common::webhook::{
+    kafka_topic, mailto_domains, WebhookJobMetadata, WebhookJobParameters, WebhookTransport,
+};
 use serde_derive::Deserialize;
 use serde_json::Value;
 use url::Url;
@@ -15,7 +18,7 @@ use tracing::{debug, error};
 #[derive(Serialize, Deserialize)]
 pub struct WebhookPostResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+    pub(super) error: Option<String>,
 }
 
 /// The body of a request made to create a webhook Job.
@@ -26,14 +29,31 @@ pub struct WebhookPostRequestBody {
 
     #[serde(default = "default_max_attempts")]
     max_attempts: u32,
+
+    /// Webhooks sharing an ordering key are delivered strictly in the order they were posted.
+    #[serde(default)]
+    ordering_key: Option<String>,
 }
 
 fn default_max_attempts() -> u32 {
     3
 }
 
+#[derive(Clone)]
+pub struct WebhookState {
+    pub pg_queue: PgQueue,
+    /// The topics webhooks may be produced to with the Kafka transport.
+    pub kafka_transport_topics: Arc<HashSet<String>>,
+    /// The domains webhooks may be emailed to with the SMTP transport.
+    pub smtp_recipient_domains: Arc<HashSet<String>>,
+}
+
 pub async fn post_webhook(
-    State(pg_queue): State<PgQueue>,
+    State(WebhookState {
+        pg_queue,
+        kafka_transport_topics,
+        smtp_recipient_domains,
+    }): State<WebhookState>,
     body: Bytes,
 ) -> Result<Json<WebhookPostResponse>, (StatusCode, Json<WebhookPostResponse>)> {
     let payload: WebhookPostRequestBody = {
@@ -51,6 +71,28 @@ pub async fn post_webhook(
 
     debug!("received payload: {:?}", payload);
 
+    if payload.parameters.transport == WebhookTransport::Kafka {
+        let topic = kafka_topic(&payload.parameters.url)
+            .map_err(|e| bad_request(format!("invalid kafka url: {}", e)))?;
+        if !kafka_transport_topics.contains(&topic) {
+            return Err(bad_request(format!("kafka topic {} is not allowed", topic)));
+        }
+    }
+
+    if payload.parameters.transport == WebhookTransport::Smtp {
+        let domains = mailto_domains(&payload.parameters.url)
+            .map_err(|e| bad_request(format!("invalid mailto url: {}", e)))?;
+        if let Some(domain) = domains
+            .iter()
+            .find(|domain| !smtp_recipient_domains.contains(*domain))
+        {
+            return Err(bad_request(format!(
+                "email domain {} is not allowed",
+                domain
+            )));
+        }
+    }
+
     let url_hostname = get_hostname(&payload.parameters.url)?;
     // We could cast to i32, but this ensures we are not wrapping.
     let max_attempts = i32::try_from(payload.max_attempts).map_err(|_| {
@@ -61,12 +103,15 @@ pub async fn post_webhook(
             }),
         )
     })?;
-    let job = NewJob::new(
+    let mut job = NewJob::new(
         max_attempts,
         payload.metadata,
         payload.parameters,
         url_hostname.as_str(),
     );
+    if let Some(ordering_key) = &payload.ordering_key {
+        job = job.ordering_key(ordering_key);
+    }
 
     let start_time = Instant::now();
 
@@ -163,6 +208,7 @@ pub async fn post_hoghook(
             headers: fetch_options.headers.unwrap_or_default(),
             method: fetch_options.method.unwrap_or(HttpMethod::POST),
             url,
+            transport: WebhookTransport::Http,
         }
     } else {
         WebhookJobParameters {
@@ -170,6 +216,7 @@ pub async fn post_hoghook(
             headers: HashMap::new(),
             method: HttpMethod::POST,
             url,
+            transport: WebhookTransport::Http,
         }
     };
 
@@ -188,7 +235,7 @@ pub async fn post_hoghook(
     Ok(Json(WebhookPostResponse { error: None }))
 }
 
-fn bad_request(msg: String) -> (StatusCode, Json<WebhookPostResponse>) {
+pub(super) fn bad_request(msg: String) -> (StatusCode, Json<WebhookPostResponse>) {
     error!(msg);
     (
         StatusCode::BAD_REQUEST,
@@ -196,7 +243,7 @@ fn bad_request(msg: String) -> (StatusCode, Json<WebhookPostResponse>) {
     )
 }
 
-fn internal_error<E>(err: E) -> (StatusCode, Json<WebhookPostResponse>)
+pub(super) fn internal_error<E>(err: E) -> (StatusCode, Json<WebhookPostResponse>)
 where
     E: std::error::Error,
 {
@@ -209,13 +256,26 @@ where
     )
 }
 
-fn get_hostname(url_str: &str) -> Result<String, (StatusCode, Json<WebhookPostResponse>)> {
+pub(super) fn get_hostname(
+    url_str: &str,
+) -> Result<String, (StatusCode, Json<WebhookPostResponse>)> {
     let url =
         Url::parse(url_str).map_err(|e| bad_request(format!("could not parse url: {}", e)))?;
 
-    match url.host_str() {
-        Some(hostname) => Ok(hostname.to_owned()),
-        None => Err(bad_request("couldn't extract hostname from url".to_owned())),
+    // Emails delivered over SMTP are addressed with a `mailto:` url, which has no host.
+    let hostname = match url.scheme() {
+        "mailto" => url
+            .path()
+            .split(',')
+            .next()
+            .and_then(|recipient| recipient.rsplit_once('@'))
+            .map(|(_, domain)| domain),
+        _ => url.host_str(),
+    };
+
+    match hostname {
+        Some(hostname) if !hostname.is_empty() => Ok(hostname.to_owned()),
+        _ => Err(bad_request("couldn't extract hostname from url".to_owned())),
     }
 }
 
@@ -234,7 +294,7 @@ mod tests {
         Router,
     };
     use hook_common::pgqueue::PgQueue;
-    use hook_common::webhook::{HttpMethod, WebhookJobParameters};
+    use hook_common::webhook::{HttpMethod, WebhookJobParameters, WebhookTransport};
     use http_body_util::BodyExt;
     use sqlx::PgPool; // for `collect`
     use std::collections;
@@ -247,7 +307,7 @@ mod tests {
 
     #[sqlx::test(migrations = "../migrations")]
     async fn webhook_success(db: PgPool) {
-        let pg_queue = PgQueue::new_from_pool("test_index", db).await;
+        let pg_queue = PgQueue::new_from_pool("test_index", db.clone()).await;
         let hog_mode = false;
 
         let app = add_routes(
@@ -256,6 +316,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let mut headers = collections::HashMap::new();
@@ -273,13 +335,17 @@ mod tests {
                                 method: HttpMethod::POST,
                                 url: "http://example.com/".to_owned(),
                                 body: r#"{"a": "b"}"#.to_owned(),
+                                transport: WebhookTransport::Http,
                             },
                             metadata: WebhookJobMetadata {
                                 team_id: 1,
                                 plugin_id: 2,
                                 plugin_config_id: 3,
+                                signing_secret_ref: None,
+                                batch: false,
                             },
                             max_attempts: 1,
+                            ordering_key: Some("person-1".to_owned()),
                         })
                         .unwrap(),
                     ))
@@ -292,6 +358,13 @@ mod tests {
 
         let body = response.into_body().collect().await.unwrap().to_bytes();
         assert_eq!(&body[..], b"{}");
+
+        let ordering_key: Option<String> =
+            sqlx::query_scalar("SELECT ordering_key FROM job_queue WHERE queue = 'test_index'")
+                .fetch_one(&db)
+                .await
+                .expect("enqueued job not found");
+        assert_eq!(ordering_key.as_deref(), Some("person-1"));
     }
 
     #[sqlx::test(migrations = "../migrations")]
@@ -305,6 +378,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let response = app
@@ -320,13 +395,17 @@ mod tests {
                                 method: HttpMethod::POST,
                                 url: "invalid".to_owned(),
                                 body: r#"{"a": "b"}"#.to_owned(),
+                                transport: WebhookTransport::Http,
                             },
                             metadata: WebhookJobMetadata {
                                 team_id: 1,
                                 plugin_id: 2,
                                 plugin_config_id: 3,
+                                signing_secret_ref: None,
+                                batch: false,
                             },
                             max_attempts: 1,
+                            ordering_key: None,
                         })
                         .unwrap(),
                     ))
@@ -338,6 +417,118 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[sqlx::test(migrations = "../migrations")]
+    async fn webhook_kafka_topic_allowlist(db: PgPool) {
+        let pg_queue = PgQueue::new_from_pool("test_index", db).await;
+        let hog_mode = false;
+
+        for (topic, expected_status) in [
+            ("webhooks", StatusCode::OK),
+            ("events_json", StatusCode::BAD_REQUEST),
+        ] {
+            let app = add_routes(
+                Router::new(),
+                pg_queue.clone(),
+                hog_mode,
+                MAX_BODY_SIZE,
+                CONCURRENCY_LIMIT,
+                collections::HashSet::from(["webhooks".to_owned()]),
+                collections::HashSet::new(),
+            );
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri("/webhook")
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(
+                            serde_json::to_string(&WebhookPostRequestBody {
+                                parameters: WebhookJobParameters {
+                                    headers: collections::HashMap::new(),
+                                    method: HttpMethod::POST,
+                                    url: format!("kafka://{}", topic),
+                                    body: r#"{"a": "b"}"#.to_owned(),
+                                    transport: WebhookTransport::Kafka,
+                                },
+                                metadata: WebhookJobMetadata {
+                                    team_id: 1,
+                                    plugin_id: 2,
+                                    plugin_config_id: 3,
+                                    signing_secret_ref: None,
+                                    batch: false,
+                                },
+                                max_attempts: 1,
+                                ordering_key: None,
+                            })
+                            .unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), expected_status);
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn webhook_smtp_recipient_domain_allowlist(db: PgPool) {
+        let pg_queue = PgQueue::new_from_pool("test_index", db).await;
+        let hog_mode = false;
+
+        for (recipients, expected_status) in [
+            ("alice@example.com", StatusCode::OK),
+            ("alice@EXAMPLE.com,bob@example.com", StatusCode::OK),
+            ("alice@example.com,eve@evil.com", StatusCode::BAD_REQUEST),
+            ("not-an-address", StatusCode::BAD_REQUEST),
+        ] {
+            let app = add_routes(
+                Router::new(),
+                pg_queue.clone(),
+                hog_mode,
+                MAX_BODY_SIZE,
+                CONCURRENCY_LIMIT,
+                collections::HashSet::new(),
+                collections::HashSet::from(["example.com".to_owned()]),
+            );
+
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method(http::Method::POST)
+                        .uri("/webhook")
+                        .header(http::header::CONTENT_TYPE, "application/json")
+                        .body(Body::from(
+                            serde_json::to_string(&WebhookPostRequestBody {
+                                parameters: WebhookJobParameters {
+                                    headers: collections::HashMap::new(),
+                                    method: HttpMethod::POST,
+                                    url: format!("mailto:{}", recipients),
+                                    body: "hello".to_owned(),
+                                    transport: WebhookTransport::Smtp,
+                                },
+                                metadata: WebhookJobMetadata {
+                                    team_id: 1,
+                                    plugin_id: 2,
+                                    plugin_config_id: 3,
+                                    signing_secret_ref: None,
+                                    batch: false,
+                                },
+                                max_attempts: 1,
+                                ordering_key: None,
+                            })
+                            .unwrap(),
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), expected_status, "{}", recipients);
+        }
+    }
+
     #[sqlx::test(migrations = "../migrations")]
     async fn webhook_payload_missing_fields(db: PgPool) {
         let pg_queue = PgQueue::new_from_pool("test_index", db).await;
@@ -349,6 +540,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let response = app
@@ -377,6 +570,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let response = app
@@ -405,6 +600,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let bytes: Vec<u8> = vec![b'a'; MAX_BODY_SIZE + 1];
@@ -423,13 +620,17 @@ mod tests {
                                 method: HttpMethod::POST,
                                 url: "http://example.com".to_owned(),
                                 body: long_string.to_string(),
+                                transport: WebhookTransport::Http,
                             },
                             metadata: WebhookJobMetadata {
                                 team_id: 1,
                                 plugin_id: 2,
                                 plugin_config_id: 3,
+                                signing_secret_ref: None,
+                                batch: false,
                             },
                             max_attempts: 1,
+                            ordering_key: None,
                         })
                         .unwrap(),
                     ))
@@ -459,6 +660,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let valid_payloads = vec![
@@ -550,6 +753,8 @@ mod tests {
             hog_mode,
             MAX_BODY_SIZE,
             CONCURRENCY_LIMIT,
+            collections::HashSet::new(),
+            collections::HashSet::new(),
         );
 
         let invalid_payloads = vec![
@@ -589,4 +794,24 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn hostname_from_url() {
+        assert_eq!(
+            get_hostname("http://example.com/hook").ok().as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            get_hostname("kafka://events_json").ok().as_deref(),
+            Some("events_json")
+        );
+        assert_eq!(
+            get_hostname("mailto:alice@example.com,bob@posthog.com")
+                .ok()
+                .as_deref(),
+            Some("example.com")
+        );
+        assert!(get_hostname("mailto:alice").is_err());
+        assert!(get_hostname("not a url").is_err());
+    }
 }
@@ -42,6 +42,8 @@ async fn main() {
         config.hog_mode,
         config.max_body_size,
         config.concurrency_limit,
+        config.kafka_transport_topics(),
+        config.smtp_recipient_domains(),
     );
     let app = setup_metrics_routes(app);
 
@@ -0,0 +1,457 @@
+//! # Delivery
+//!
+//! A log of webhook delivery attempts, recording what was sent and how the target responded.
+use std::collections::HashMap;
+use std::time;
+
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use sqlx::types::Json;
+
+use crate::pgqueue::DatabaseError;
+use crate::webhook::{WebhookJobMetadata, WebhookJobParameters};
+
+/// How much of request and response bodies is kept by default.
+pub const DEFAULT_MAX_BODY_BYTES: usize = 4 * 1024;
+
+/// What the value of a sensitive header is replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Headers that carry credentials or signatures, and so are never kept or listed.
+const SENSITIVE_HEADERS: [&str; 7] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "webhook-signature",
+    "x-posthog-signature",
+    "x-hub-signature-256",
+];
+
+/// Parts of header names that suggest a header carries a credential, e.g. `x-api-key`.
+const SENSITIVE_HEADER_PARTS: [&str; 5] = ["api-key", "apikey", "token", "secret", "password"];
+
+/// A single attempt to deliver a webhook, to be recorded in the `DeliveryLog`.
+pub struct NewDelivery<'a> {
+    /// The job this attempt was made for.
+    pub job_id: i64,
+    /// The number of this attempt.
+    pub attempt: i32,
+    /// The job's maximum number of attempts, re-used when redelivering.
+    pub max_attempts: i32,
+    pub metadata: &'a WebhookJobMetadata,
+    pub parameters: &'a WebhookJobParameters,
+    /// The headers that were sent, which may include some not in the job parameters.
+    pub request_headers: &'a HashMap<String, String>,
+    /// The response status, if the target responded.
+    pub status: Option<u16>,
+    pub response_headers: Option<HashMap<String, String>>,
+    pub response_body: Option<&'a str>,
+    /// A description of what went wrong, if the attempt failed.
+    pub error: Option<String>,
+    /// How long the attempt took, including reading the response.
+    pub duration: time::Duration,
+}
+
+/// A recorded delivery attempt, as returned when listing the `DeliveryLog`.
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct Delivery {
+    pub id: i64,
+    pub job_id: i64,
+    pub attempt: i32,
+    pub team_id: i32,
+    pub plugin_config_id: i32,
+    pub created_at: chrono::DateTime<chrono::offset::Utc>,
+    pub method: String,
+    pub url: String,
+    pub request_headers: Json<HashMap<String, String>>,
+    pub request_body: String,
+    pub status: Option<i32>,
+    pub response_headers: Option<Json<HashMap<String, String>>>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: i32,
+}
+
+/// What is needed to enqueue a job again.
+#[derive(sqlx::FromRow, Debug)]
+pub struct DeliveredJob {
+    pub max_attempts: i32,
+    pub parameters: Json<WebhookJobParameters>,
+    pub metadata: Json<WebhookJobMetadata>,
+}
+
+/// A log of webhook deliveries backed by a PostgreSQL table.
+#[derive(Clone)]
+pub struct DeliveryLog {
+    pool: PgPool,
+    /// Request and response bodies are truncated to this many bytes.
+    max_body_bytes: usize,
+}
+
+impl DeliveryLog {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+        }
+    }
+
+    /// Set how many bytes of request and response bodies are kept.
+    pub fn max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    /// Record a delivery attempt. Sensitive headers are redacted before they are stored.
+    ///
+    /// The job parameters and metadata needed to redeliver it are stored once per job, with
+    /// sensitive headers stripped, so that no credentials are kept once the job itself is gone.
+    pub async fn record(&self, delivery: NewDelivery<'_>) -> Result<(), DatabaseError> {
+        let base_query = r#"
+WITH job AS (
+    INSERT INTO webhook_delivery_jobs (job_id, max_attempts, parameters, metadata)
+    VALUES ($1, $3, $15, $16)
+    ON CONFLICT (job_id) DO NOTHING
+)
+INSERT INTO webhook_deliveries
+    (job_id, attempt, max_attempts, team_id, plugin_config_id, method, url, request_headers,
+     request_body, status, response_headers, response_body, error, duration_ms)
+VALUES
+    ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        "#;
+
+        sqlx::query(base_query)
+            .bind(delivery.job_id)
+            .bind(delivery.attempt)
+            .bind(delivery.max_attempts)
+            .bind(delivery.metadata.team_id as i32)
+            .bind(delivery.metadata.plugin_config_id)
+            .bind(delivery.parameters.method.to_string())
+            .bind(&delivery.parameters.url)
+            .bind(Json(redact_headers(delivery.request_headers.clone())))
+            .bind(truncate(&delivery.parameters.body, self.max_body_bytes))
+            .bind(delivery.status.map(i32::from))
+            .bind(delivery.response_headers.map(redact_headers).map(Json))
+            .bind(
+                delivery
+                    .response_body
+                    .map(|body| truncate(body, self.max_body_bytes)),
+            )
+            .bind(delivery.error)
+            .bind(i32::try_from(delivery.duration.as_millis()).unwrap_or(i32::MAX))
+            .bind(Json(strip_sensitive_headers(delivery.parameters.clone())))
+            .bind(Json(delivery.metadata))
+            .execute(&self.pool)
+            .await
+            .map_err(|error| DatabaseError::QueryError {
+                command: "INSERT".to_owned(),
+                error,
+            })?;
+
+        Ok(())
+    }
+
+    /// List the most recent deliveries for a plugin config, newest first. Sensitive headers are
+    /// redacted, including in deliveries that were recorded before they were redacted on insert.
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin_config_id`: The plugin config to list deliveries for.
+    /// * `before_id`: Only list deliveries older than this one, for paginating.
+    /// * `limit`: The maximum number of deliveries to return.
+    pub async fn list(
+        &self,
+        plugin_config_id: i32,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Delivery>, DatabaseError> {
+        let base_query = r#"
+SELECT
+    id, job_id, attempt, team_id, plugin_config_id, created_at, method, url, request_headers,
+    request_body, status, response_headers, response_body, error, duration_ms
+FROM
+    webhook_deliveries
+WHERE
+    plugin_config_id = $1
+    AND ($2::BIGINT IS NULL OR id < $2)
+ORDER BY
+    id DESC
+LIMIT $3
+        "#;
+
+        let deliveries: Vec<Delivery> = sqlx::query_as(base_query)
+            .bind(plugin_config_id)
+            .bind(before_id)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|error| DatabaseError::QueryError {
+                command: "SELECT".to_owned(),
+                error,
+            })?;
+
+        Ok(deliveries
+            .into_iter()
+            .map(|mut delivery| {
+                delivery.request_headers = Json(redact_headers(delivery.request_headers.0));
+                delivery.response_headers = delivery
+                    .response_headers
+                    .map(|headers| Json(redact_headers(headers.0)));
+                delivery
+            })
+            .collect())
+    }
+
+    /// Get the job a delivery was made for, so that it can be enqueued again. Returns `None` if
+    /// there is no record of the job, e.g. because it is past retention.
+    ///
+    /// While the job is still in the queue it is taken from there, as sent. Otherwise, it is taken
+    /// from what was stored when it was first delivered, which has no sensitive headers.
+    pub async fn get_job(&self, job_id: i64) -> Result<Option<DeliveredJob>, DatabaseError> {
+        let base_query = r#"
+SELECT
+    COALESCE(job_queue.max_attempts, webhook_delivery_jobs.max_attempts) AS max_attempts,
+    COALESCE(job_queue.parameters, webhook_delivery_jobs.parameters) AS parameters,
+    COALESCE(job_queue.metadata, webhook_delivery_jobs.metadata) AS metadata
+FROM
+    webhook_delivery_jobs
+    LEFT JOIN job_queue ON job_queue.id = webhook_delivery_jobs.job_id
+WHERE
+    webhook_delivery_jobs.job_id = $1
+        "#;
+
+        sqlx::query_as(base_query)
+            .bind(job_id)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|error| DatabaseError::QueryError {
+                command: "SELECT".to_owned(),
+                error,
+            })
+    }
+}
+
+/// Whether a header may carry credentials or signatures.
+fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SENSITIVE_HEADERS.contains(&name.as_str())
+        || SENSITIVE_HEADER_PARTS
+            .iter()
+            .any(|part| name.contains(part))
+}
+
+/// Replace the values of headers that may carry credentials or signatures with `REDACTED`.
+fn redact_headers(mut headers: HashMap<String, String>) -> HashMap<String, String> {
+    for (name, value) in headers.iter_mut() {
+        if is_sensitive_header(name) {
+            *value = REDACTED.to_owned();
+        }
+    }
+    headers
+}
+
+/// Remove the headers that may carry credentials or signatures, so that a redelivery doesn't send
+/// a placeholder in their place.
+fn strip_sensitive_headers(mut parameters: WebhookJobParameters) -> WebhookJobParameters {
+    parameters
+        .headers
+        .retain(|name, _| !is_sensitive_header(name));
+    parameters
+}
+
+/// Truncate `s` to at most `max_bytes`, without splitting a character.
+fn truncate(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pgqueue::{NewJob, PgQueue};
+    use crate::webhook::{HttpMethod, WebhookTransport};
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("hello", 10), "hello");
+        assert_eq!(truncate("hello", 3), "hel");
+        // "é" is two bytes, and can't be split.
+        assert_eq!(truncate("héllo", 2), "h");
+    }
+
+    #[test]
+    fn test_redact_headers() {
+        let headers = HashMap::from([
+            ("Authorization".to_owned(), "Bearer abc".to_owned()),
+            ("X-API-Key".to_owned(), "abc".to_owned()),
+            ("webhook-signature".to_owned(), "v1,abc".to_owned()),
+            ("Content-Type".to_owned(), "application/json".to_owned()),
+        ]);
+
+        let redacted = redact_headers(headers);
+
+        assert_eq!(redacted["Authorization"], REDACTED);
+        assert_eq!(redacted["X-API-Key"], REDACTED);
+        assert_eq!(redacted["webhook-signature"], REDACTED);
+        assert_eq!(redacted["Content-Type"], "application/json");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_record_and_list_deliveries(db: PgPool) {
+        let log = DeliveryLog::new(db).max_body_bytes(4);
+
+        let metadata = WebhookJobMetadata {
+            team_id: 1,
+            plugin_id: 2,
+            plugin_config_id: 3,
+            signing_secret_ref: None,
+            batch: false,
+        };
+        let parameters = WebhookJobParameters {
+            body: "a long body".to_owned(),
+            headers: HashMap::from([
+                ("content-type".to_owned(), "application/json".to_owned()),
+                ("authorization".to_owned(), "Bearer abc".to_owned()),
+            ]),
+            method: HttpMethod::POST,
+            url: "http://example.com".to_owned(),
+            transport: WebhookTransport::Http,
+        };
+        let request_headers = HashMap::from([
+            ("x-test".to_owned(), "1".to_owned()),
+            ("authorization".to_owned(), "Bearer abc".to_owned()),
+        ]);
+
+        for attempt in 1..=2 {
+            log.record(NewDelivery {
+                job_id: 42,
+                attempt,
+                max_attempts: 2,
+                metadata: &metadata,
+                parameters: &parameters,
+                request_headers: &request_headers,
+                status: Some(500),
+                response_headers: Some(HashMap::new()),
+                response_body: Some("internal error"),
+                error: Some("500 Internal Server Error".to_owned()),
+                duration: time::Duration::from_millis(10),
+            })
+            .await
+            .expect("failed to record delivery");
+        }
+
+        let deliveries = log.list(3, None, 10).await.expect("failed to list");
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].attempt, 2);
+        assert_eq!(deliveries[0].request_body, "a lo");
+        assert_eq!(deliveries[0].response_body.as_deref(), Some("inte"));
+        assert_eq!(deliveries[0].status, Some(500));
+        assert_eq!(
+            deliveries[0].request_headers.0,
+            HashMap::from([
+                ("x-test".to_owned(), "1".to_owned()),
+                ("authorization".to_owned(), REDACTED.to_owned()),
+            ])
+        );
+
+        let older = log
+            .list(3, Some(deliveries[0].id), 10)
+            .await
+            .expect("failed to list");
+        assert_eq!(older.len(), 1);
+        assert_eq!(older[0].attempt, 1);
+
+        assert!(log.list(4, None, 10).await.unwrap().is_empty());
+
+        // The job isn't in the queue, so it's redelivered without its credentials
+        let job = log.get_job(42).await.unwrap().expect("job not found");
+        assert_eq!(job.max_attempts, 2);
+        assert_eq!(
+            job.parameters.0,
+            strip_sensitive_headers(parameters.clone())
+        );
+        assert_eq!(
+            job.parameters.0.headers,
+            HashMap::from([("content-type".to_owned(), "application/json".to_owned())])
+        );
+        assert_eq!(job.metadata.0, metadata);
+        assert!(log.get_job(43).await.unwrap().is_none());
+
+        // Removing attempts past retention doesn't stop the job being redelivered
+        sqlx::query("DELETE FROM webhook_deliveries WHERE attempt = 1")
+            .execute(&log.pool)
+            .await
+            .unwrap();
+        assert!(log.get_job(42).await.unwrap().is_some());
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_get_job_from_queue(db: PgPool) {
+        let log = DeliveryLog::new(db.clone());
+        let queue = PgQueue::new_from_pool("test_get_job_from_queue", db).await;
+
+        let metadata = WebhookJobMetadata {
+            team_id: 1,
+            plugin_id: 2,
+            plugin_config_id: 3,
+            signing_secret_ref: None,
+            batch: false,
+        };
+        let parameters = WebhookJobParameters {
+            body: "{}".to_owned(),
+            headers: HashMap::from([("authorization".to_owned(), "Bearer abc".to_owned())]),
+            method: HttpMethod::POST,
+            url: "http://example.com".to_owned(),
+            transport: WebhookTransport::Http,
+        };
+        queue
+            .enqueue(NewJob::new(
+                3,
+                metadata.clone(),
+                parameters.clone(),
+                "example.com",
+            ))
+            .await
+            .unwrap();
+        let job_id: i64 = sqlx::query_scalar("SELECT id FROM job_queue")
+            .fetch_one(&log.pool)
+            .await
+            .unwrap();
+
+        log.record(NewDelivery {
+            job_id,
+            attempt: 1,
+            max_attempts: 3,
+            metadata: &metadata,
+            parameters: &parameters,
+            request_headers: &parameters.headers,
+            status: Some(500),
+            response_headers: None,
+            response_body: None,
+            error: None,
+            duration: time::Duration::from_millis(10),
+        })
+        .await
+        .expect("failed to record delivery");
+
+        // While the job is still in the queue, it's redelivered as it was sent
+        let job = log.get_job(job_id).await.unwrap().expect("job not found");
+        assert_eq!(job.parameters.0, parameters);
+
+        // And once the job is gone, so are its credentials
+        sqlx::query("DELETE FROM job_queue")
+            .execute(&log.pool)
+            .await
+            .unwrap();
+        let job = log.get_job(job_id).await.unwrap().expect("job not found");
+        assert!(job.parameters.0.headers.is_empty());
+    }
+}
@@ -1,3 +1,4 @@
+pub mod delivery;
 pub mod pgqueue;
 pub mod retry;
 pub mod webhook;
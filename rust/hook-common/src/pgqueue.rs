@@ -122,6 +122,8 @@ pub struct Job<J, M> {
     pub status: JobStatus,
     /// The target of the job. E.g. an endpoint or service we are trying to reach.
     pub target: String,
+    /// Jobs sharing an ordering key are dequeued strictly in the order they were enqueued.
+    pub ordering_key: Option<String>,
 }
 
 impl<J, M> Job<J, M> {
@@ -215,6 +217,53 @@ RETURNING
             queue: self.queue,
         })
     }
+
+    /// Consume `Job` to put it back in a queue without counting the current attempt.
+    /// Used when a `Job` was not attempted, e.g. because its target is known to be unhealthy.
+    ///
+    /// # Arguments
+    ///
+    /// * `defer_interval`: The duration until the `Job` is to be attempted. Used to set `scheduled_at`.
+    /// * `queue`: The queue the `Job` is moved to.
+    /// * `executor`: Any sqlx::Executor that can execute the UPDATE query required to mark this `Job` as available.
+    async fn defer<'c, E>(
+        self,
+        defer_interval: time::Duration,
+        queue: &str,
+        executor: E,
+    ) -> Result<RetriedJob, sqlx::Error>
+    where
+        E: sqlx::Executor<'c, Database = sqlx::Postgres>,
+    {
+        let base_query = r#"
+UPDATE
+    job_queue
+SET
+    attempt = attempt - 1,
+    status = 'available'::job_status,
+    scheduled_at = NOW() + $3,
+    queue = $4
+WHERE
+    queue = $1
+    AND id = $2
+RETURNING
+    job_queue.*
+        "#;
+
+        sqlx::query(base_query)
+            .bind(&self.queue)
+            .bind(self.id)
+            .bind(defer_interval)
+            .bind(queue)
+            .execute(executor)
+            .await?;
+
+        Ok(RetriedJob {
+            id: self.id,
+            retry_queue: Some(queue.to_owned()),
+            queue: self.queue,
+        })
+    }
 }
 
 #[async_trait]
@@ -232,6 +281,12 @@ pub trait PgQueueJob {
         retry_interval: time::Duration,
         queue: &str,
     ) -> Result<RetriedJob, RetryError<Box<Self>>>;
+
+    async fn defer(
+        mut self,
+        defer_interval: time::Duration,
+        queue: &str,
+    ) -> Result<RetriedJob, DatabaseError>;
 }
 
 /// A Job within an open PostgreSQL transaction.
@@ -350,6 +405,29 @@ impl<'c, J: std::marker::Send, M: std::marker::Send> PgQueueJob for PgTransactio
 
         Ok(retried_job)
     }
+
+    async fn defer(
+        mut self,
+        defer_interval: time::Duration,
+        queue: &str,
+    ) -> Result<RetriedJob, DatabaseError> {
+        let mut txn_guard = self.shared_txn.lock().await;
+
+        let txn_ref = txn_guard
+            .as_deref_mut()
+            .ok_or(DatabaseError::TransactionAlreadyClosedError)?;
+
+        let deferred_job = self
+            .job
+            .defer(defer_interval, queue, txn_ref)
+            .await
+            .map_err(|error| DatabaseError::QueryError {
+                command: "UPDATE".to_owned(),
+                error,
+            })?;
+
+        Ok(deferred_job)
+    }
 }
 
 /// A Job that has failed but can still be enqueued into a PgQueue to be retried at a later point.
@@ -471,6 +549,8 @@ pub struct NewJob<J, M> {
     pub parameters: JobParameters<J>,
     /// The target of the NewJob. E.g. an endpoint or service we are trying to reach.
     pub target: String,
+    /// An optional key to order this NewJob with others sharing it.
+    pub ordering_key: Option<String>,
 }
 
 impl<J, M> NewJob<J, M> {
@@ -480,8 +560,20 @@ impl<J, M> NewJob<J, M> {
             metadata: sqlx::types::Json(metadata),
             parameters: sqlx::types::Json(parameters),
             target: target.to_owned(),
+            ordering_key: None,
         }
     }
+
+    /// Deliver this NewJob strictly after any earlier jobs sharing `ordering_key`.
+    ///
+    /// A job is held back while an earlier job with the same key is still available, whether it
+    /// is waiting to be retried, being processed, or was moved to a retry queue. Once the earlier
+    /// job completes or fails for good, the next one is released. Keys are compared across the
+    /// whole table, so callers sharing it should namespace them if needed.
+    pub fn ordering_key(mut self, ordering_key: &str) -> Self {
+        self.ordering_key = Some(ordering_key.to_owned());
+        self
+    }
 }
 
 /// A queue implemented on top of a PostgreSQL table.
@@ -531,10 +623,19 @@ impl PgQueue {
         Self { name, pool }
     }
 
+    /// The connection pool backing this queue, for sharing with other users of the same database.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
     /// Dequeue up to `limit` `Job`s from this `PgQueue` and hold the transaction.
     /// Any other `dequeue_tx` calls will skip rows locked, so by holding a transaction we ensure only one
     /// worker can dequeue a job. Holding a transaction open can have performance implications, but
     /// it means no `'running'` state is required.
+    ///
+    /// Jobs with an ordering key are only dequeued once no earlier job with the same key remains
+    /// available. Rows locked by other workers are still visible to that check, so a job is never
+    /// dequeued alongside or while an earlier one is being processed.
     pub async fn dequeue_tx<
         'a,
         J: for<'d> serde::Deserialize<'d> + std::marker::Send + std::marker::Unpin + 'static,
@@ -562,6 +663,17 @@ WITH available_in_queue AS (
         status = 'available'
         AND scheduled_at <= NOW()
         AND queue = $1
+        AND (
+            ordering_key IS NULL
+            OR NOT EXISTS (
+                SELECT 1
+                FROM job_queue AS earlier
+                WHERE
+                    earlier.ordering_key = job_queue.ordering_key
+                    AND earlier.status = 'available'
+                    AND earlier.id < job_queue.id
+            )
+        )
     ORDER BY
         attempt,
         scheduled_at
@@ -631,9 +743,9 @@ RETURNING
     ) -> PgQueueResult<()> {
         let base_query = r#"
 INSERT INTO job_queue
-    (attempt, created_at, scheduled_at, max_attempts, metadata, parameters, queue, status, target)
+    (attempt, created_at, scheduled_at, max_attempts, metadata, parameters, queue, status, target, ordering_key)
 VALUES
-    (0, NOW(), NOW(), $1, $2, $3, $4, 'available'::job_status, $5)
+    (0, NOW(), NOW(), $1, $2, $3, $4, 'available'::job_status, $5, $6)
         "#;
 
         sqlx::query(base_query)
@@ -642,6 +754,7 @@ VALUES
             .bind(&job.parameters)
             .bind(&self.name)
             .bind(&job.target)
+            .bind(&job.ordering_key)
             .execute(&self.pool)
             .await
             .map_err(|error| DatabaseError::QueryError {
@@ -923,6 +1036,115 @@ mod tests {
         assert_eq!(retried_job.job.target, job_target);
     }
 
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_holds_back_jobs_behind_earlier_job_with_same_ordering_key(db: PgPool) {
+        let job_target = job_target();
+        let worker_id = worker_id();
+        let queue_name = "test_holds_back_jobs_behind_earlier_job_with_same_ordering_key";
+
+        let queue = PgQueue::new_from_pool(queue_name, db).await;
+
+        for (team_id, ordering_key) in [(1, Some("a")), (2, Some("a")), (3, Some("b")), (4, None)] {
+            let job_metadata = JobMetadata {
+                team_id,
+                ..JobMetadata::default()
+            };
+            let mut new_job = NewJob::new(2, job_metadata, JobParameters::default(), &job_target);
+            if let Some(ordering_key) = ordering_key {
+                new_job = new_job.ordering_key(ordering_key);
+            }
+            queue.enqueue(new_job).await.expect("failed to enqueue job");
+        }
+
+        // Only the first job for key "a" is dequeued, the other keys are not held back by it.
+        let mut batch: PgTransactionBatch<'_, JobParameters, JobMetadata> = queue
+            .dequeue_tx(&worker_id, 10)
+            .await
+            .expect("failed to dequeue jobs")
+            .expect("didn't find any jobs to dequeue");
+        let mut team_ids: Vec<u32> = batch.jobs.iter().map(|j| j.job.metadata.team_id).collect();
+        team_ids.sort();
+        assert_eq!(team_ids, vec![1, 3, 4]);
+
+        for job in batch.jobs.drain(..) {
+            if job.job.metadata.team_id == 1 {
+                drop(
+                    job.retry("failed", time::Duration::from_secs(0), queue_name)
+                        .await
+                        .expect("failed to retry job"),
+                );
+            } else {
+                job.complete().await.expect("failed to complete job");
+            }
+        }
+        batch.commit().await.expect("failed to commit transaction");
+
+        // The retried job still holds back the later one.
+        let mut batch: PgTransactionBatch<'_, JobParameters, JobMetadata> = queue
+            .dequeue_tx(&worker_id, 10)
+            .await
+            .expect("failed to dequeue jobs")
+            .expect("didn't find retried job to dequeue");
+        assert_eq!(batch.jobs.len(), 1);
+        let job = batch.jobs.pop().unwrap();
+        assert_eq!(job.job.metadata.team_id, 1);
+        assert_eq!(job.job.ordering_key.as_deref(), Some("a"));
+        job.complete().await.expect("failed to complete job");
+        batch.commit().await.expect("failed to commit transaction");
+
+        let mut batch: PgTransactionBatch<'_, JobParameters, JobMetadata> = queue
+            .dequeue_tx(&worker_id, 10)
+            .await
+            .expect("failed to dequeue jobs")
+            .expect("didn't find released job to dequeue");
+        assert_eq!(batch.jobs.len(), 1);
+        let job = batch.jobs.pop().unwrap();
+        assert_eq!(job.job.metadata.team_id, 2);
+        job.complete().await.expect("failed to complete job");
+        batch.commit().await.expect("failed to commit transaction");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_can_defer_job_without_counting_attempt(db: PgPool) {
+        let job_target = job_target();
+        let job_parameters = JobParameters::default();
+        let job_metadata = JobMetadata::default();
+        let worker_id = worker_id();
+        let new_job = NewJob::new(1, job_metadata, job_parameters, &job_target);
+        let queue_name = "test_can_defer_job_without_counting_attempt".to_owned();
+
+        let queue = PgQueue::new_from_pool(&queue_name, db).await;
+
+        queue.enqueue(new_job).await.expect("failed to enqueue job");
+        let mut batch: PgTransactionBatch<'_, JobParameters, JobMetadata> = queue
+            .dequeue_tx(&worker_id, 1)
+            .await
+            .expect("failed to dequeue job")
+            .expect("didn't find a job to dequeue");
+        let job = batch.jobs.pop().unwrap();
+        assert!(job.job.is_gte_max_attempts());
+
+        drop(
+            job.defer(time::Duration::from_secs(0), &queue_name)
+                .await
+                .expect("failed to defer job"),
+        );
+        batch.commit().await.expect("failed to commit transaction");
+
+        let deferred_job: PgTransactionJob<JobParameters, JobMetadata> = queue
+            .dequeue_tx(&worker_id, 1)
+            .await
+            .expect("failed to dequeue job")
+            .expect("didn't find deferred job to dequeue")
+            .jobs
+            .pop()
+            .unwrap();
+
+        assert_eq!(deferred_job.job.attempt, 1);
+        assert_eq!(deferred_job.job.max_attempts, 1);
+        assert_eq!(deferred_job.job.target, job_target);
+    }
+
     #[sqlx::test(migrations = "../migrations")]
     #[should_panic(expected = "failed to retry job")]
     async fn test_cannot_retry_job_without_remaining_attempts(db: PgPool) {
@@ -117,6 +117,74 @@ impl From<&HttpMethod> for http::Method {
     }
 }
 
+/// How a webhook is delivered. Each transport interprets `WebhookJobParameters` as follows:
+///
+/// * `Http`: `body` is sent in a `method` request to `url`, with `headers`.
+/// * `Kafka`: `body` is produced to the topic in a `kafka://<topic>` `url`, with `headers` as
+///   message headers. `method` is ignored.
+/// * `Smtp`: `body` is emailed to the recipients in a `mailto:` `url`, with the `Subject` and
+///   `Reply-To` `headers` used if set. Emails are always sent from the configured sender, with a
+///   `From` header used as the `Reply-To` if there isn't one. `method` is ignored.
+///
+/// Only `Http` webhooks can be signed. A webhook with a signing secret sent over another transport
+/// fails rather than being delivered unsigned.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookTransport {
+    #[default]
+    Http,
+    Kafka,
+    Smtp,
+}
+
+impl WebhookTransport {
+    pub fn is_http(&self) -> bool {
+        *self == WebhookTransport::Http
+    }
+}
+
+/// The topic a `Kafka` transport webhook is produced to, from its `kafka://<topic>` url.
+pub fn kafka_topic(url: &str) -> Result<String, String> {
+    let url = url::Url::parse(url).map_err(|e| e.to_string())?;
+    if url.scheme() != "kafka" {
+        return Err("url must use the kafka:// scheme".to_owned());
+    }
+
+    match url.host_str() {
+        Some(topic) if !topic.is_empty() => Ok(topic.to_owned()),
+        _ => Err("url is missing a topic".to_owned()),
+    }
+}
+
+/// The domains of the recipients an `Smtp` transport webhook is emailed to, from its
+/// `mailto:<recipient>[,<recipient>...]` url. Domains are lowercased.
+pub fn mailto_domains(url: &str) -> Result<Vec<String>, String> {
+    let url = url::Url::parse(url).map_err(|e| e.to_string())?;
+    if url.scheme() != "mailto" {
+        return Err("url must use the mailto: scheme".to_owned());
+    }
+
+    url.path()
+        .split(',')
+        .map(str::trim)
+        .filter(|recipient| !recipient.is_empty())
+        .map(|recipient| match recipient.rsplit_once('@') {
+            Some((_, domain)) if !domain.is_empty() => Ok(domain.to_ascii_lowercase()),
+            _ => Err(format!("{} is not an email address", recipient)),
+        })
+        .collect()
+}
+
+impl fmt::Display for WebhookTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebhookTransport::Http => write!(f, "http"),
+            WebhookTransport::Kafka => write!(f, "kafka"),
+            WebhookTransport::Smtp => write!(f, "smtp"),
+        }
+    }
+}
+
 /// `JobParameters` required for the `WebhookWorker` to execute a webhook.
 /// These parameters should match the exported Webhook interface that PostHog plugins.
 /// implement. See: https://github.com/PostHog/plugin-scaffold/blob/main/src/types.ts#L15.
@@ -126,6 +194,9 @@ pub struct WebhookJobParameters {
     pub headers: collections::HashMap<String, String>,
     pub method: HttpMethod,
     pub url: String,
+    /// How to deliver the webhook, HTTP unless set.
+    #[serde(default, skip_serializing_if = "WebhookTransport::is_http")]
+    pub transport: WebhookTransport,
 }
 
 /// `JobMetadata` required for the `WebhookWorker` to execute a webhook.
@@ -135,6 +206,13 @@ pub struct WebhookJobMetadata {
     pub team_id: u32,
     pub plugin_id: i32,
     pub plugin_config_id: i32,
+    /// The name of one of the team's secrets to sign the webhook with, if it should be signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_secret_ref: Option<String>,
+    /// Whether the destination accepts webhooks batched into a single request, if the worker has
+    /// batching enabled. Only webhooks that opt in are ever batched.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub batch: bool,
 }
 
 /// An error originating during a Webhook Job invocation.
@@ -222,4 +300,18 @@ impl WebhookJobError {
             },
         }
     }
+
+    pub fn new_signing(message: &str) -> Self {
+        let error_details = app_metrics::Error {
+            name: "Signing Error".to_owned(),
+            message: Some(message.to_owned()),
+            stack: None,
+        };
+        Self {
+            r#type: app_metrics::ErrorType::SigningError,
+            details: app_metrics::ErrorDetails {
+                error: error_details,
+            },
+        }
+    }
 }
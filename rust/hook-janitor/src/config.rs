@@ -25,6 +25,14 @@ pub struct Config {
     #[envconfig(default = "false")]
     pub hog_mode: bool,
 
+    // How long webhook deliveries recorded by the worker are kept for.
+    #[envconfig(default = "604800")]
+    pub delivery_log_retention_secs: u64,
+
+    // How long the payloads needed to redeliver a webhook are kept for, once it was first delivered.
+    #[envconfig(default = "86400")]
+    pub delivery_payload_retention_secs: u64,
+
     #[envconfig(default = "clickhouse_app_metrics")]
     pub app_metrics_topic: String,
 
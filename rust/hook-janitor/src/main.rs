@@ -68,7 +68,11 @@ async fn main() {
                     config.app_metrics2_topic.to_owned(),
                     config.hog_mode,
                 )
-                .expect("unable to create webhook cleaner"),
+                .expect("unable to create webhook cleaner")
+                .delivery_log_retention(Duration::from_secs(config.delivery_log_retention_secs))
+                .delivery_payload_retention(Duration::from_secs(
+                    config.delivery_payload_retention_secs,
+                )),
             )
         }
     };
@@ -61,6 +61,9 @@ pub struct WebhookCleaner {
     app_metrics_topic: String,
     app_metrics2_topic: String,
     hog_mode: bool,
+    /// How long webhook deliveries are kept, if they should be deleted.
+    delivery_log_retention: Option<Duration>,
+    delivery_payload_retention: Option<Duration>,
 }
 
 #[derive(sqlx::FromRow, Debug)]
@@ -262,6 +265,8 @@ impl WebhookCleaner {
             app_metrics_topic,
             app_metrics2_topic,
             hog_mode,
+            delivery_log_retention: None,
+            delivery_payload_retention: None,
         })
     }
 
@@ -279,9 +284,24 @@ impl WebhookCleaner {
             app_metrics_topic,
             app_metrics2_topic,
             hog_mode,
+            delivery_log_retention: None,
+            delivery_payload_retention: None,
         })
     }
 
+    /// Delete webhook deliveries once they are older than `delivery_log_retention`.
+    pub fn delivery_log_retention(mut self, delivery_log_retention: Duration) -> Self {
+        self.delivery_log_retention = Some(delivery_log_retention);
+        self
+    }
+
+    /// Delete the payloads kept to redeliver webhooks once they are older than
+    /// `delivery_payload_retention`.
+    pub fn delivery_payload_retention(mut self, delivery_payload_retention: Duration) -> Self {
+        self.delivery_payload_retention = Some(delivery_payload_retention);
+        self
+    }
+
     async fn get_queue_depth(&self) -> Result<QueueDepth> {
         let mut conn = self
             .pg_pool
@@ -455,6 +475,36 @@ impl WebhookCleaner {
         Ok(result.rows_affected())
     }
 
+    async fn delete_expired_deliveries(&self, retention: Duration) -> Result<u64> {
+        let base_query = r#"
+            DELETE FROM webhook_deliveries
+            WHERE created_at < NOW() - $1
+        "#;
+
+        let result = sqlx::query(base_query)
+            .bind(retention)
+            .execute(&self.pg_pool)
+            .await
+            .map_err(|e| WebhookCleanerError::DeleteRowsError { error: e })?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_expired_delivery_payloads(&self, retention: Duration) -> Result<u64> {
+        let base_query = r#"
+            DELETE FROM webhook_delivery_jobs
+            WHERE created_at < NOW() - $1
+        "#;
+
+        let result = sqlx::query(base_query)
+            .bind(retention)
+            .execute(&self.pg_pool)
+            .await
+            .map_err(|e| WebhookCleanerError::DeleteRowsError { error: e })?;
+
+        Ok(result.rows_affected())
+    }
+
     async fn rollback_txn(&self, tx: SerializableTxn<'_>) -> Result<()> {
         tx.0.rollback()
             .await
@@ -624,6 +674,30 @@ impl Cleaner for WebhookCleaner {
                 error!(error = ?error, "WebhookCleaner::cleanup failed");
             }
         }
+
+        if let Some(retention) = self.delivery_log_retention {
+            match self.delete_expired_deliveries(retention).await {
+                Ok(rows_deleted) => {
+                    metrics::counter!("webhook_cleanup_deliveries_deleted",)
+                        .increment(rows_deleted);
+                }
+                Err(error) => {
+                    error!(error = ?error, "WebhookCleaner::cleanup failed to delete expired deliveries");
+                }
+            }
+        }
+
+        if let Some(retention) = self.delivery_payload_retention {
+            match self.delete_expired_delivery_payloads(retention).await {
+                Ok(rows_deleted) => {
+                    metrics::counter!("webhook_cleanup_delivery_payloads_deleted",)
+                        .increment(rows_deleted);
+                }
+                Err(error) => {
+                    error!(error = ?error, "WebhookCleaner::cleanup failed to delete expired delivery payloads");
+                }
+            }
+        }
     }
 }
 
@@ -637,7 +711,9 @@ mod tests {
     use common_kafka::test::create_mock_kafka;
     use hook_common::pgqueue::PgQueueJob;
     use hook_common::pgqueue::{NewJob, PgQueue, PgTransactionBatch};
-    use hook_common::webhook::{HttpMethod, WebhookJobMetadata, WebhookJobParameters};
+    use hook_common::webhook::{
+        HttpMethod, WebhookJobMetadata, WebhookJobParameters, WebhookTransport,
+    };
     use rdkafka::consumer::{Consumer, StreamConsumer};
     use rdkafka::types::{RDKafkaApiKey, RDKafkaRespErr};
     use rdkafka::{ClientConfig, Message};
@@ -1034,6 +1110,84 @@ mod tests {
         assert_eq!(cleanup_stats.failed_agg_row_count, 0);
     }
 
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_delete_expired_deliveries(db: PgPool) {
+        let (_, mock_producer) = create_mock_kafka().await;
+
+        sqlx::query(
+            r#"
+INSERT INTO webhook_deliveries
+    (job_id, attempt, max_attempts, team_id, plugin_config_id, created_at, method, url,
+     request_headers, request_body, duration_ms)
+VALUES
+    (1, 1, 3, 1, 2, NOW() - INTERVAL '8 days', 'POST', 'http://example.com', '{}', '', 10),
+    (2, 1, 3, 1, 2, NOW() - INTERVAL '1 day', 'POST', 'http://example.com', '{}', '', 10)
+            "#,
+        )
+        .execute(&db)
+        .await
+        .expect("failed to insert deliveries");
+
+        let webhook_cleaner = WebhookCleaner::new_from_pool(
+            db.clone(),
+            mock_producer,
+            APP_METRICS_TOPIC.to_owned(),
+            APP_METRICS2_TOPIC.to_owned(),
+            false,
+        )
+        .expect("unable to create webhook cleaner");
+
+        let rows_deleted = webhook_cleaner
+            .delete_expired_deliveries(Duration::from_secs(7 * 24 * 60 * 60))
+            .await
+            .expect("failed to delete expired deliveries");
+        assert_eq!(rows_deleted, 1);
+
+        let job_ids: Vec<i64> = sqlx::query_scalar("SELECT job_id FROM webhook_deliveries")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(job_ids, vec![2]);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_delete_expired_delivery_payloads(db: PgPool) {
+        let (_, mock_producer) = create_mock_kafka().await;
+
+        sqlx::query(
+            r#"
+INSERT INTO webhook_delivery_jobs (job_id, created_at, max_attempts, parameters, metadata)
+VALUES
+    (1, NOW() - INTERVAL '2 days', 3, '{}', '{}'),
+    (2, NOW() - INTERVAL '1 hour', 3, '{}', '{}')
+            "#,
+        )
+        .execute(&db)
+        .await
+        .expect("failed to insert delivery payloads");
+
+        let webhook_cleaner = WebhookCleaner::new_from_pool(
+            db.clone(),
+            mock_producer,
+            APP_METRICS_TOPIC.to_owned(),
+            APP_METRICS2_TOPIC.to_owned(),
+            false,
+        )
+        .expect("unable to create webhook cleaner");
+
+        let rows_deleted = webhook_cleaner
+            .delete_expired_delivery_payloads(Duration::from_secs(24 * 60 * 60))
+            .await
+            .expect("failed to delete expired delivery payloads");
+        assert_eq!(rows_deleted, 1);
+
+        let job_ids: Vec<i64> = sqlx::query_scalar("SELECT job_id FROM webhook_delivery_jobs")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(job_ids, vec![2]);
+    }
+
     #[sqlx::test(migrations = "../migrations", fixtures("webhook_cleanup"))]
     async fn test_serializable_isolation(db: PgPool) {
         let (_, mock_producer) = create_mock_kafka().await;
@@ -1097,11 +1251,14 @@ mod tests {
                 headers: HashMap::new(),
                 method: HttpMethod::POST,
                 url: "http://example.com".to_owned(),
+                transport: WebhookTransport::Http,
             };
             let job_metadata = WebhookJobMetadata {
                 team_id: 1,
                 plugin_id: 2,
                 plugin_config_id: 3,
+                signing_secret_ref: None,
+                batch: false,
             };
             let new_job = NewJob::new(1, job_metadata, job_parameters, "target");
             queue.enqueue(new_job).await.expect("failed to enqueue job");
@@ -1125,11 +1282,14 @@ mod tests {
                 headers: HashMap::new(),
                 method: HttpMethod::POST,
                 url: "http://example.com".to_owned(),
+                transport: WebhookTransport::Http,
             };
             let job_metadata = WebhookJobMetadata {
                 team_id: 1,
                 plugin_id: 2,
                 plugin_config_id: 3,
+                signing_secret_ref: None,
+                batch: false,
             };
             let new_job = NewJob::new(1, job_metadata, job_parameters, "target");
             queue.enqueue(new_job).await.expect("failed to enqueue job");
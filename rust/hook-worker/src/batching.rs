@@ -0,0 +1,202 @@
+//! Coalescing of webhooks to the same destination into a single request.
+//!
+//! Jobs dequeued together that opt in with `WebhookJobMetadata::batch`, and share a target, plugin
+//! config and request (method, URL and headers), can be sent as one request, with their bodies
+//! wrapped in a `BatchEnvelope`. The outcome of that request is then applied to every job in the
+//! batch.
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use hook_common::webhook::{WebhookJobMetadata, WebhookJobParameters};
+
+/// How the bodies of batched jobs are combined into a single request body.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchEnvelope {
+    /// A JSON array with each job's body as an element.
+    JsonArray,
+    /// Newline delimited JSON, with each job's body on its own line.
+    Ndjson,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseBatchEnvelopeError(String);
+
+impl FromStr for BatchEnvelope {
+    type Err = ParseBatchEnvelopeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json_array" => Ok(BatchEnvelope::JsonArray),
+            "ndjson" => Ok(BatchEnvelope::Ndjson),
+            invalid => Err(ParseBatchEnvelopeError(invalid.to_owned())),
+        }
+    }
+}
+
+impl BatchEnvelope {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            BatchEnvelope::JsonArray => "application/json",
+            BatchEnvelope::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    /// Combine `bodies` into a single body. Every body must be valid JSON, see `can_wrap`.
+    pub fn wrap<'a>(&self, bodies: impl Iterator<Item = &'a str>) -> String {
+        match self {
+            BatchEnvelope::JsonArray => {
+                format!("[{}]", bodies.map(str::trim).collect::<Vec<_>>().join(","))
+            }
+            // JSON strings can't contain raw line breaks, so any in a body are whitespace and can
+            // be dropped to fit it on a single line.
+            BatchEnvelope::Ndjson => bodies
+                .map(|body| body.trim().replace(['\n', '\r'], " ") + "\n")
+                .collect(),
+        }
+    }
+
+    /// Whether `body` can be wrapped in an envelope.
+    pub fn can_wrap(body: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(body).is_ok()
+    }
+}
+
+/// How batching is configured for a worker.
+#[derive(Debug, Clone, Copy)]
+pub struct Batching {
+    pub envelope: BatchEnvelope,
+    /// The maximum number of jobs sent in a single request.
+    pub max_jobs: usize,
+}
+
+/// Jobs that share a `BatchKey` can be sent in a single request.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct BatchKey {
+    target: String,
+    plugin_config_id: i32,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl BatchKey {
+    pub fn new(
+        target: &str,
+        metadata: &WebhookJobMetadata,
+        parameters: &WebhookJobParameters,
+    ) -> Self {
+        let mut headers: Vec<(String, String)> = parameters
+            .headers
+            .iter()
+            .map(|(name, value)| (name.to_lowercase(), value.to_owned()))
+            .collect();
+        headers.sort();
+
+        Self {
+            target: target.to_owned(),
+            plugin_config_id: metadata.plugin_config_id,
+            method: parameters.method.to_string(),
+            url: parameters.url.to_owned(),
+            headers,
+        }
+    }
+}
+
+/// The headers for a batched request: those shared by its jobs, with the envelope's content type.
+pub fn batch_headers(
+    parameters: &WebhookJobParameters,
+    envelope: BatchEnvelope,
+) -> HashMap<String, String> {
+    let mut headers: HashMap<String, String> = parameters
+        .headers
+        .iter()
+        .filter(|(name, _)| !name.eq_ignore_ascii_case("content-type"))
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect();
+    headers.insert(
+        "Content-Type".to_owned(),
+        envelope.content_type().to_owned(),
+    );
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hook_common::webhook::{HttpMethod, WebhookTransport};
+
+    fn parameters(headers: &[(&str, &str)]) -> WebhookJobParameters {
+        WebhookJobParameters {
+            body: "{}".to_owned(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            method: HttpMethod::POST,
+            url: "http://example.com/batch".to_owned(),
+            transport: WebhookTransport::Http,
+        }
+    }
+
+    #[test]
+    fn test_wrap_bodies() {
+        let bodies = ["{\"a\": 1}", "{\n  \"b\": \"x\"\n}\n"];
+
+        assert_eq!(
+            BatchEnvelope::JsonArray.wrap(bodies.into_iter()),
+            "[{\"a\": 1},{\n  \"b\": \"x\"\n}]"
+        );
+        assert_eq!(
+            BatchEnvelope::Ndjson.wrap(bodies.into_iter()),
+            "{\"a\": 1}\n{   \"b\": \"x\" }\n"
+        );
+
+        assert!(BatchEnvelope::can_wrap(bodies[1]));
+        assert!(!BatchEnvelope::can_wrap("not json"));
+    }
+
+    #[test]
+    fn test_batch_key_ignores_header_order_and_case() {
+        let metadata = WebhookJobMetadata {
+            team_id: 1,
+            plugin_id: 2,
+            plugin_config_id: 3,
+            signing_secret_ref: None,
+            batch: false,
+        };
+
+        let key = BatchKey::new(
+            "example.com",
+            &metadata,
+            &parameters(&[("X-Token", "1"), ("Accept", "*/*")]),
+        );
+        assert_eq!(
+            key,
+            BatchKey::new(
+                "example.com",
+                &metadata,
+                &parameters(&[("accept", "*/*"), ("x-token", "1")]),
+            )
+        );
+        assert_ne!(
+            key,
+            BatchKey::new(
+                "example.com",
+                &metadata,
+                &parameters(&[("X-Token", "2"), ("Accept", "*/*")]),
+            )
+        );
+    }
+
+    #[test]
+    fn test_batch_headers_use_envelope_content_type() {
+        let headers = batch_headers(
+            &parameters(&[("content-type", "text/plain"), ("X-Token", "1")]),
+            BatchEnvelope::Ndjson,
+        );
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["Content-Type"], "application/x-ndjson");
+        assert_eq!(headers["X-Token"], "1");
+    }
+}
@@ -0,0 +1,576 @@
+//! Per-target circuit breakers, shared across workers through PostgreSQL.
+//!
+//! Each worker keeps an in-memory view of which targets have an open circuit, and buffers the
+//! outcomes of the requests it sends. Both are synced with the `target_circuit_breakers` table
+//! every `sync_interval`, which is also when circuits are opened if a target's failure rate in the
+//! current window is too high. Once an open circuit's `probe_at` passes, a single worker claims a
+//! half-open probe: if that request succeeds the circuit is closed, otherwise it stays open.
+//!
+//! Deferred jobs and probes are also reported to app metrics, against the app each job was for,
+//! so customers can see that deliveries to their target are held back.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time;
+
+use chrono::{DateTime, Utc};
+use common_kafka::kafka_messages::app_metrics2::{self, AppMetric2};
+use common_kafka::kafka_producer::{send_iter_to_kafka, KafkaContext};
+use hook_common::webhook::WebhookJobMetadata;
+use rdkafka::producer::FutureProducer;
+use serde_json::Value;
+use sqlx::PgPool;
+use tracing::error;
+
+use crate::config::CircuitBreakerConfig;
+
+/// Whether a request to a target should be sent.
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    /// The circuit is closed, send the request.
+    Allowed,
+    /// The circuit is half-open and we hold the probe, send the request to check the target.
+    Probe,
+    /// The circuit is open, the job should be deferred for the given duration.
+    Deferred(time::Duration),
+}
+
+#[derive(Default)]
+struct Outcomes {
+    successes: i32,
+    failures: i32,
+}
+
+/// The app a job was for, which its circuit events are reported against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct App {
+    pub team_id: u32,
+    pub source: AppSource,
+    pub source_id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AppSource {
+    HogFunction,
+    PluginConfig,
+}
+
+impl App {
+    /// Get the app from a job's metadata: a hog function in Hog mode, a plugin config otherwise.
+    pub fn from_metadata(metadata: &Value, hog_mode: bool) -> Option<Self> {
+        if hog_mode {
+            Some(Self {
+                team_id: u32::try_from(metadata.get("teamId")?.as_u64()?).ok()?,
+                source: AppSource::HogFunction,
+                source_id: metadata.get("hogFunctionId")?.as_str()?.to_owned(),
+            })
+        } else {
+            serde_json::from_value::<WebhookJobMetadata>(metadata.clone())
+                .ok()
+                .map(|metadata| Self::from_plugin(&metadata))
+        }
+    }
+
+    pub fn from_plugin(metadata: &WebhookJobMetadata) -> Self {
+        Self {
+            team_id: metadata.team_id,
+            source: AppSource::PluginConfig,
+            source_id: metadata.plugin_config_id.to_string(),
+        }
+    }
+}
+
+/// What happened to a job because of its target's circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum CircuitEvent {
+    Deferred,
+    ProbeSucceeded,
+    ProbeFailed,
+}
+
+/// Where circuit events are reported to.
+struct AppMetrics {
+    producer: FutureProducer<KafkaContext>,
+    topic: String,
+    /// Events since the last report, counted by app.
+    pending: Mutex<HashMap<(App, CircuitEvent), u32>>,
+}
+
+pub struct CircuitBreaker {
+    pool: PgPool,
+    config: CircuitBreakerConfig,
+    /// Targets whose circuit is not closed, and when they can next be probed.
+    open: Mutex<HashMap<String, DateTime<Utc>>>,
+    /// Outcomes of requests sent since the last sync.
+    pending: Mutex<HashMap<String, Outcomes>>,
+    app_metrics: Option<AppMetrics>,
+}
+
+impl CircuitBreaker {
+    pub fn new(pool: PgPool, config: CircuitBreakerConfig) -> Self {
+        Self {
+            pool,
+            config,
+            open: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+            app_metrics: None,
+        }
+    }
+
+    /// Report deferred jobs and probes to app metrics, produced to `topic` on every sync.
+    pub fn with_app_metrics(
+        mut self,
+        producer: FutureProducer<KafkaContext>,
+        topic: String,
+    ) -> Self {
+        self.app_metrics = Some(AppMetrics {
+            producer,
+            topic,
+            pending: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// Decide whether a request to `target` should be sent.
+    pub async fn admit(&self, target: &str) -> Admission {
+        let Some(probe_at) = self.open.lock().unwrap().get(target).copied() else {
+            return Admission::Allowed;
+        };
+
+        let now = Utc::now();
+        if probe_at > now {
+            // Jobs are deferred by a Postgres interval, which can't hold nanoseconds.
+            let defer_interval = (probe_at - now).num_milliseconds().max(0) as u64;
+            return Admission::Deferred(time::Duration::from_millis(defer_interval));
+        }
+
+        match self.claim_probe(target).await {
+            Ok(true) => Admission::Probe,
+            // Someone else holds the probe, or the circuit has closed since we last synced. In
+            // the latter case we'll be slower to resume, but won't send a request to a target
+            // that may still be down.
+            Ok(false) => Admission::Deferred(self.config.circuit_breaker_open_duration.0),
+            Err(e) => {
+                // Fail open: a target being unreachable is no reason to stop trying it.
+                error!("error claiming circuit breaker probe: {}", e);
+                Admission::Allowed
+            }
+        }
+    }
+
+    /// Record that a job for `app` was deferred as its target's circuit is open.
+    pub fn record_deferred(&self, app: Option<&App>) {
+        self.report(app, CircuitEvent::Deferred);
+    }
+
+    /// Record whether a request to `target`, for a job for `app`, reached a healthy target.
+    pub async fn record(
+        &self,
+        target: &str,
+        admission: &Admission,
+        success: bool,
+        app: Option<&App>,
+    ) {
+        match admission {
+            Admission::Allowed => {
+                let mut pending = self.pending.lock().unwrap();
+                let outcomes = pending.entry(target.to_owned()).or_default();
+                if success {
+                    outcomes.successes += 1;
+                } else {
+                    outcomes.failures += 1;
+                }
+            }
+            Admission::Probe => {
+                let labels = [("result", if success { "success" } else { "failure" })];
+                metrics::counter!("webhook_circuit_breaker_probes", &labels).increment(1);
+                self.report(
+                    app,
+                    if success {
+                        CircuitEvent::ProbeSucceeded
+                    } else {
+                        CircuitEvent::ProbeFailed
+                    },
+                );
+
+                let result = if success {
+                    self.close(target).await
+                } else {
+                    self.reopen(target).await
+                };
+                if let Err(e) = result {
+                    error!("error recording circuit breaker probe: {}", e);
+                }
+            }
+            Admission::Deferred(_) => {}
+        }
+    }
+
+    /// Sync with the shared circuit state every `sync_interval`, forever.
+    pub async fn run_sync(&self) {
+        let mut interval = tokio::time::interval(self.config.circuit_breaker_sync_interval.0);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.sync().await {
+                error!("error syncing circuit breakers: {}", e);
+            }
+            self.flush_app_metrics().await;
+        }
+    }
+
+    fn report(&self, app: Option<&App>, event: CircuitEvent) {
+        if let (Some(app_metrics), Some(app)) = (&self.app_metrics, app) {
+            *app_metrics
+                .pending
+                .lock()
+                .unwrap()
+                .entry((app.clone(), event))
+                .or_default() += 1;
+        }
+    }
+
+    /// Produce the events reported since the last flush to app metrics. They are dropped if that
+    /// fails, as they are only informative.
+    async fn flush_app_metrics(&self) {
+        let Some(app_metrics) = &self.app_metrics else {
+            return;
+        };
+        let pending = std::mem::take(&mut *app_metrics.pending.lock().unwrap());
+        if pending.is_empty() {
+            return;
+        }
+
+        let timestamp = Utc::now();
+        let rows = pending.into_iter().map(|((app, event), count)| {
+            let (metric_kind, metric_name) = match event {
+                CircuitEvent::Deferred => (app_metrics2::Kind::Other, "circuit_breaker_deferred"),
+                CircuitEvent::ProbeSucceeded => {
+                    (app_metrics2::Kind::Success, "circuit_breaker_probe")
+                }
+                CircuitEvent::ProbeFailed => (app_metrics2::Kind::Failure, "circuit_breaker_probe"),
+            };
+
+            AppMetric2 {
+                team_id: app.team_id,
+                timestamp,
+                app_source: match app.source {
+                    AppSource::HogFunction => app_metrics2::Source::Hoghooks,
+                    AppSource::PluginConfig => app_metrics2::Source::Webhooks,
+                },
+                app_source_id: app.source_id,
+                instance_id: None,
+                metric_kind,
+                metric_name: metric_name.to_owned(),
+                count,
+            }
+        });
+
+        for result in send_iter_to_kafka(&app_metrics.producer, &app_metrics.topic, rows).await {
+            if let Err(e) = result {
+                metrics::counter!("webhook_circuit_breaker_app_metrics_errors").increment(1);
+                error!("error producing circuit breaker app metrics: {}", e);
+            }
+        }
+    }
+
+    /// Flush the outcomes we have buffered, opening circuits as needed, and refresh our view of
+    /// which circuits are open.
+    pub async fn sync(&self) -> Result<(), sqlx::Error> {
+        self.flush().await?;
+        self.refresh().await
+    }
+
+    async fn flush(&self) -> Result<(), sqlx::Error> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut targets = Vec::with_capacity(pending.len());
+        let mut successes = Vec::with_capacity(pending.len());
+        let mut failures = Vec::with_capacity(pending.len());
+        for (target, outcomes) in pending {
+            targets.push(target);
+            successes.push(outcomes.successes);
+            failures.push(outcomes.failures);
+        }
+
+        let base_query = r#"
+INSERT INTO target_circuit_breakers AS cb
+    (target, successes, failures)
+SELECT * FROM UNNEST($1::text[], $2::int[], $3::int[])
+ON CONFLICT (target) DO UPDATE SET
+    window_started_at = CASE
+        WHEN cb.window_started_at < NOW() - $4 THEN NOW()
+        ELSE cb.window_started_at
+    END,
+    successes = CASE
+        WHEN cb.window_started_at < NOW() - $4 THEN EXCLUDED.successes
+        ELSE cb.successes + EXCLUDED.successes
+    END,
+    failures = CASE
+        WHEN cb.window_started_at < NOW() - $4 THEN EXCLUDED.failures
+        ELSE cb.failures + EXCLUDED.failures
+    END
+        "#;
+
+        sqlx::query(base_query)
+            .bind(&targets)
+            .bind(&successes)
+            .bind(&failures)
+            .bind(self.config.circuit_breaker_window.0)
+            .execute(&self.pool)
+            .await?;
+
+        let base_query = r#"
+UPDATE
+    target_circuit_breakers
+SET
+    state = 'open',
+    opened_at = NOW(),
+    probe_at = NOW() + $2
+WHERE
+    target = ANY($1)
+    AND state = 'closed'
+    AND successes + failures >= $3
+    AND failures >= (successes + failures) * $4
+        "#;
+
+        let opened = sqlx::query(base_query)
+            .bind(&targets)
+            .bind(self.config.circuit_breaker_open_duration.0)
+            .bind(self.config.circuit_breaker_min_requests as i32)
+            .bind(self.config.circuit_breaker_failure_rate)
+            .execute(&self.pool)
+            .await?;
+
+        metrics::counter!("webhook_circuit_breaker_opened").increment(opened.rows_affected());
+
+        Ok(())
+    }
+
+    async fn refresh(&self) -> Result<(), sqlx::Error> {
+        let base_query = r#"
+SELECT
+    target,
+    probe_at
+FROM
+    target_circuit_breakers
+WHERE
+    state <> 'closed'
+        "#;
+
+        let open: Vec<(String, DateTime<Utc>)> =
+            sqlx::query_as(base_query).fetch_all(&self.pool).await?;
+
+        metrics::gauge!("webhook_circuit_breakers_open").set(open.len() as f64);
+        *self.open.lock().unwrap() = open.into_iter().collect();
+
+        Ok(())
+    }
+
+    /// Try to become the one worker probing `target`. The claim lasts for `open_duration`, after
+    /// which another worker may probe if we never reported back.
+    async fn claim_probe(&self, target: &str) -> Result<bool, sqlx::Error> {
+        let base_query = r#"
+UPDATE
+    target_circuit_breakers
+SET
+    state = 'half_open',
+    probe_at = NOW() + $2
+WHERE
+    target = $1
+    AND state <> 'closed'
+    AND probe_at <= NOW()
+RETURNING
+    probe_at
+        "#;
+
+        let probe_at: Option<DateTime<Utc>> = sqlx::query_scalar(base_query)
+            .bind(target)
+            .bind(self.config.circuit_breaker_open_duration.0)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        if let Some(probe_at) = probe_at {
+            self.open
+                .lock()
+                .unwrap()
+                .insert(target.to_owned(), probe_at);
+        }
+
+        Ok(probe_at.is_some())
+    }
+
+    async fn close(&self, target: &str) -> Result<(), sqlx::Error> {
+        let base_query = r#"
+UPDATE
+    target_circuit_breakers
+SET
+    state = 'closed',
+    window_started_at = NOW(),
+    successes = 0,
+    failures = 0,
+    opened_at = NULL,
+    probe_at = NULL
+WHERE
+    target = $1
+        "#;
+
+        sqlx::query(base_query)
+            .bind(target)
+            .execute(&self.pool)
+            .await?;
+
+        self.open.lock().unwrap().remove(target);
+        metrics::counter!("webhook_circuit_breaker_closed").increment(1);
+
+        Ok(())
+    }
+
+    async fn reopen(&self, target: &str) -> Result<(), sqlx::Error> {
+        let base_query = r#"
+UPDATE
+    target_circuit_breakers
+SET
+    state = 'open',
+    probe_at = NOW() + $2
+WHERE
+    target = $1
+RETURNING
+    probe_at
+        "#;
+
+        let probe_at: Option<DateTime<Utc>> = sqlx::query_scalar(base_query)
+            .bind(target)
+            .bind(self.config.circuit_breaker_open_duration.0)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        if let Some(probe_at) = probe_at {
+            self.open
+                .lock()
+                .unwrap()
+                .insert(target.to_owned(), probe_at);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EnvMsDuration;
+
+    fn config(open_duration: time::Duration) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            circuit_breaker_enabled: true,
+            circuit_breaker_failure_rate: 0.5,
+            circuit_breaker_min_requests: 4,
+            circuit_breaker_window: EnvMsDuration(time::Duration::from_secs(60)),
+            circuit_breaker_open_duration: EnvMsDuration(open_duration),
+            circuit_breaker_sync_interval: EnvMsDuration(time::Duration::from_secs(1)),
+        }
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_opens_after_sustained_failures(db: PgPool) {
+        let breaker = CircuitBreaker::new(db, config(time::Duration::from_secs(60)));
+
+        // Not enough requests yet, even though they all failed.
+        for _ in 0..3 {
+            breaker
+                .record("example.com", &Admission::Allowed, false, None)
+                .await;
+        }
+        breaker
+            .record("healthy.com", &Admission::Allowed, true, None)
+            .await;
+        breaker.sync().await.expect("failed to sync");
+        assert_eq!(breaker.admit("example.com").await, Admission::Allowed);
+
+        breaker
+            .record("example.com", &Admission::Allowed, true, None)
+            .await;
+        breaker.sync().await.expect("failed to sync");
+
+        assert!(matches!(
+            breaker.admit("example.com").await,
+            Admission::Deferred(_)
+        ));
+        assert_eq!(breaker.admit("healthy.com").await, Admission::Allowed);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_reports_app_metrics(db: PgPool) {
+        use common_kafka::test::create_mock_kafka;
+        use rdkafka::consumer::{Consumer, StreamConsumer};
+        use rdkafka::{ClientConfig, Message};
+
+        let topic = "clickhouse_app_metrics2";
+        let (mock_cluster, mock_producer) = create_mock_kafka().await;
+        mock_cluster
+            .create_topic(topic, 1, 1)
+            .expect("failed to create mock topic");
+
+        let breaker = CircuitBreaker::new(db, config(time::Duration::ZERO))
+            .with_app_metrics(mock_producer, topic.to_owned());
+        let app = App::from_metadata(
+            &serde_json::json!({"teamId": 1, "hogFunctionId": "abc"}),
+            true,
+        )
+        .expect("metadata has an app");
+
+        breaker.record_deferred(Some(&app));
+        breaker.record_deferred(Some(&app));
+        breaker.flush_app_metrics().await;
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", mock_cluster.bootstrap_servers())
+            .set("group.id", "mock")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .expect("failed to create mock consumer");
+        consumer.subscribe(&[topic]).unwrap();
+
+        let message = consumer.recv().await.unwrap();
+        let row: AppMetric2 = serde_json::from_slice(message.payload().unwrap()).unwrap();
+        assert_eq!(row.team_id, 1);
+        assert_eq!(row.app_source, app_metrics2::Source::Hoghooks);
+        assert_eq!(row.app_source_id, "abc");
+        assert_eq!(row.metric_kind, app_metrics2::Kind::Other);
+        assert_eq!(row.metric_name, "circuit_breaker_deferred");
+        assert_eq!(row.count, 2);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_probes_to_close(db: PgPool) {
+        let breaker = CircuitBreaker::new(db.clone(), config(time::Duration::ZERO));
+        let other_worker = CircuitBreaker::new(db, config(time::Duration::ZERO));
+
+        for _ in 0..4 {
+            breaker
+                .record("example.com", &Admission::Allowed, false, None)
+                .await;
+        }
+        breaker.sync().await.expect("failed to sync");
+        other_worker.sync().await.expect("failed to sync");
+
+        // With a zero open duration claims expire immediately, so each probe reports back before
+        // the next is claimed. A failed probe keeps the circuit open for everyone.
+        assert_eq!(other_worker.admit("example.com").await, Admission::Probe);
+        other_worker
+            .record("example.com", &Admission::Probe, false, None)
+            .await;
+        assert_eq!(breaker.admit("example.com").await, Admission::Probe);
+        breaker
+            .record("example.com", &Admission::Probe, true, None)
+            .await;
+
+        assert_eq!(breaker.admit("example.com").await, Admission::Allowed);
+        other_worker.sync().await.expect("failed to sync");
+        assert_eq!(other_worker.admit("example.com").await, Admission::Allowed);
+    }
+}
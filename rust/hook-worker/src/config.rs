@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::str::FromStr;
 use std::time;
 
@@ -5,6 +6,8 @@ use envconfig::Envconfig;
 
 use common_kafka::config::KafkaConfig;
 
+use crate::batching::BatchEnvelope;
+
 #[derive(Envconfig, Clone)]
 pub struct Config {
     #[envconfig(from = "BIND_HOST", default = "::")]
@@ -37,6 +40,18 @@ pub struct Config {
     #[envconfig(nested = true)]
     pub retry_policy: RetryPolicyConfig,
 
+    #[envconfig(nested = true)]
+    pub circuit_breaker: CircuitBreakerConfig,
+
+    #[envconfig(nested = true)]
+    pub batching: BatchingConfig,
+
+    #[envconfig(nested = true)]
+    pub smtp: SmtpConfig,
+
+    #[envconfig(nested = true)]
+    pub kafka_transport: KafkaTransportConfig,
+
     #[envconfig(default = "1")]
     pub dequeue_batch_size: u32,
 
@@ -51,6 +66,18 @@ pub struct Config {
 
     #[envconfig(nested = true)]
     pub kafka: KafkaConfig,
+
+    /// Directory holding the secrets used to sign webhooks, in a directory per team with one file
+    /// per secret reference.
+    pub signing_secrets_dir: Option<String>,
+
+    /// Record every attempt to deliver a plugin webhook, so they can be listed by hook-api.
+    #[envconfig(default = "false")]
+    pub delivery_log_enabled: bool,
+
+    /// How much of request and response bodies the delivery log keeps.
+    #[envconfig(default = "4096")]
+    pub delivery_log_max_body_bytes: usize,
 }
 
 impl Config {
@@ -90,6 +117,119 @@ pub struct RetryPolicyConfig {
     pub retry_queue_name: Option<NonEmptyString>,
 }
 
+#[derive(Envconfig, Clone)]
+pub struct CircuitBreakerConfig {
+    #[envconfig(default = "false")]
+    pub circuit_breaker_enabled: bool,
+
+    /// The fraction of requests to a target that must fail within a window to open its circuit.
+    #[envconfig(default = "0.5")]
+    pub circuit_breaker_failure_rate: f64,
+
+    /// The number of requests to a target needed within a window before its circuit can open.
+    #[envconfig(default = "20")]
+    pub circuit_breaker_min_requests: u32,
+
+    #[envconfig(default = "60000")]
+    pub circuit_breaker_window: EnvMsDuration,
+
+    /// How long a circuit stays open before a probe is sent to check if the target recovered.
+    #[envconfig(default = "30000")]
+    pub circuit_breaker_open_duration: EnvMsDuration,
+
+    #[envconfig(default = "1000")]
+    pub circuit_breaker_sync_interval: EnvMsDuration,
+}
+
+#[derive(Envconfig, Clone)]
+pub struct BatchingConfig {
+    /// Send plugin webhooks dequeued together for the same destination in a single request, for
+    /// the webhooks whose metadata opts in with `batch`. Only useful with a `dequeue_batch_size`
+    /// above 1.
+    #[envconfig(default = "false")]
+    pub batching_enabled: bool,
+
+    /// How the bodies of batched webhooks are combined: `json_array` or `ndjson`.
+    #[envconfig(default = "json_array")]
+    pub batching_envelope: BatchEnvelope,
+
+    #[envconfig(default = "500")]
+    pub batching_max_jobs: usize,
+}
+
+/// Configuration for delivering webhooks by email, see `WebhookTransport::Smtp`.
+#[derive(Envconfig, Clone)]
+pub struct SmtpConfig {
+    /// The relay to send emails through. Without one, emails fail to send.
+    pub smtp_host: Option<String>,
+
+    #[envconfig(default = "25")]
+    pub smtp_port: u16,
+
+    /// Upgrade connections to the relay with STARTTLS.
+    #[envconfig(default = "false")]
+    pub smtp_starttls: bool,
+
+    pub smtp_username: Option<String>,
+
+    pub smtp_password: Option<String>,
+
+    /// The sender for every email. A webhook's own `From` header is only used as its `Reply-To`.
+    pub smtp_from: Option<String>,
+
+    /// A comma separated list of the domains webhooks may be emailed to.
+    #[envconfig(default = "")]
+    pub smtp_recipient_domains: String,
+}
+
+impl SmtpConfig {
+    pub fn recipient_domains(&self) -> HashSet<String> {
+        self.smtp_recipient_domains
+            .split(',')
+            .map(str::trim)
+            .filter(|domain| !domain.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect()
+    }
+}
+
+/// Configuration for delivering webhooks to Kafka, see `WebhookTransport::Kafka`. Webhooks are
+/// produced to their own cluster, not the one the worker reports Hog results to.
+#[derive(Envconfig, Clone)]
+pub struct KafkaTransportConfig {
+    /// The brokers to produce webhooks to. Without them, webhooks for Kafka fail.
+    pub kafka_transport_hosts: Option<String>,
+
+    #[envconfig(default = "false")]
+    pub kafka_transport_tls: bool,
+
+    /// A comma separated list of the topics webhooks may be produced to.
+    #[envconfig(default = "")]
+    pub kafka_transport_topics: String,
+}
+
+impl KafkaTransportConfig {
+    pub fn topics(&self) -> HashSet<String> {
+        self.kafka_transport_topics
+            .split(',')
+            .map(str::trim)
+            .filter(|topic| !topic.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// The producer configuration for the transport's cluster, otherwise the same as `kafka`.
+    pub fn kafka_config(&self, kafka: &KafkaConfig) -> Option<KafkaConfig> {
+        let hosts = self.kafka_transport_hosts.as_ref()?;
+
+        Some(KafkaConfig {
+            kafka_hosts: hosts.to_owned(),
+            kafka_tls: self.kafka_transport_tls,
+            ..kafka.clone()
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NonEmptyString(pub String);
 
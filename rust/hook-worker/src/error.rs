@@ -4,9 +4,11 @@ use std::time;
 
 use common_dns::NoPublicIPv4Error;
 use hook_common::{pgqueue, webhook::WebhookJobError};
-use http::StatusCode;
+use http::{HeaderMap, StatusCode};
 use thiserror::Error;
 
+use crate::signing::SigningError;
+
 /// Enumeration of error classes handled by `WebhookWorker`.
 #[derive(Error, Debug)]
 pub enum WebhookError {
@@ -14,6 +16,8 @@ pub enum WebhookError {
     Parse(#[from] WebhookParseError),
     #[error(transparent)]
     Request(#[from] WebhookRequestError),
+    #[error(transparent)]
+    Signing(#[from] SigningError),
 }
 
 /// Enumeration of parsing errors that can occur as `WebhookWorker` sets up a webhook.
@@ -33,12 +37,14 @@ pub enum WebhookRequestError {
     RetryableRequestError {
         error: reqwest::Error,
         status: Option<StatusCode>,
+        headers: Option<HeaderMap>,
         response: Option<String>,
         retry_after: Option<time::Duration>,
     },
     NonRetryableRetryableRequestError {
         error: reqwest::Error,
         status: Option<StatusCode>,
+        headers: Option<HeaderMap>,
         response: Option<String>,
     },
 }
@@ -71,6 +77,7 @@ impl fmt::Display for WebhookRequestError {
                 error,
                 status: _,
                 response,
+                ..
             } => {
                 let response_message = match response {
                     Some(m) => m.to_string(),
@@ -118,6 +125,24 @@ impl WebhookRequestError {
             }
         }
     }
+
+    pub fn response_headers(&self) -> Option<&HeaderMap> {
+        match self {
+            WebhookRequestError::RetryableRequestError { headers, .. }
+            | WebhookRequestError::NonRetryableRetryableRequestError { headers, .. } => {
+                headers.as_ref()
+            }
+        }
+    }
+
+    pub fn response_body(&self) -> Option<&str> {
+        match self {
+            WebhookRequestError::RetryableRequestError { response, .. }
+            | WebhookRequestError::NonRetryableRetryableRequestError { response, .. } => {
+                response.as_deref()
+            }
+        }
+    }
 }
 
 impl From<&WebhookRequestError> for WebhookJobError {
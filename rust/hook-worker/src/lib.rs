@@ -1,4 +1,8 @@
+pub mod batching;
+pub mod circuit;
 pub mod config;
 pub mod error;
+pub mod signing;
+pub mod transport;
 pub mod util;
 pub mod worker;
@@ -2,15 +2,22 @@
 use axum::routing::get;
 use axum::Router;
 use envconfig::Envconfig;
+use hook_common::delivery::DeliveryLog;
 use hook_common::pgqueue::PgQueue;
 use hook_common::retry::RetryPolicy;
 use std::future::ready;
+use std::path::PathBuf;
 
 use common_kafka::kafka_producer::create_kafka_producer;
+use common_kafka::APP_METRICS2_TOPIC;
 use common_metrics::{serve, setup_metrics_routes};
 use health::HealthRegistry;
+use hook_worker::batching::Batching;
+use hook_worker::circuit::CircuitBreaker;
 use hook_worker::config::Config;
 use hook_worker::error::WorkerError;
+use hook_worker::signing::WebhookSigner;
+use hook_worker::transport::{KafkaTransport, SmtpTransport};
 use hook_worker::worker::WebhookWorker;
 
 common_alloc::used!();
@@ -63,11 +70,68 @@ async fn main() -> Result<(), WorkerError> {
         config.max_concurrent_jobs,
         retry_policy_builder.provide(),
         config.allow_internal_ips,
-        kafka_producer,
+        kafka_producer.clone(),
         config.cdp_function_callbacks_topic.to_owned(),
         config.hog_mode,
         worker_liveness,
-    );
+    )
+    .with_signer(WebhookSigner::new(
+        config.signing_secrets_dir.as_ref().map(PathBuf::from),
+    ));
+
+    let worker = if config.circuit_breaker.circuit_breaker_enabled {
+        worker.with_circuit_breaker(
+            CircuitBreaker::new(queue.pool().clone(), config.circuit_breaker.clone())
+                .with_app_metrics(kafka_producer, APP_METRICS2_TOPIC.to_owned()),
+        )
+    } else {
+        worker
+    };
+
+    let worker = if config.delivery_log_enabled {
+        worker.with_delivery_log(
+            DeliveryLog::new(queue.pool().clone())
+                .max_body_bytes(config.delivery_log_max_body_bytes),
+        )
+    } else {
+        worker
+    };
+
+    let worker = if config.batching.batching_enabled {
+        worker.with_batching(Batching {
+            envelope: config.batching.batching_envelope,
+            max_jobs: config.batching.batching_max_jobs,
+        })
+    } else {
+        worker
+    };
+
+    let worker = if config.smtp.smtp_host.is_some() {
+        worker.with_smtp_transport(
+            SmtpTransport::new(&config.smtp, config.request_timeout.0)
+                .expect("invalid SMTP configuration"),
+        )
+    } else {
+        worker
+    };
+
+    let worker = match config.kafka_transport.kafka_config(&config.kafka) {
+        Some(kafka_config) => {
+            let kafka_transport_liveness = liveness
+                .register("rdkafka-transport".to_string(), time::Duration::seconds(30))
+                .await;
+            let producer = create_kafka_producer(&kafka_config, kafka_transport_liveness)
+                .await
+                .expect("failed to create kafka transport producer");
+
+            worker.with_kafka_transport(KafkaTransport::new(
+                producer,
+                config.request_timeout.0,
+                config.kafka_transport.topics(),
+            ))
+        }
+        None => worker,
+    };
 
     let router = Router::new()
         .route("/", get(index))
@@ -0,0 +1,243 @@
+//! Signing of outgoing webhooks following the Standard Webhooks spec.
+//! See: https://github.com/standard-webhooks/standard-webhooks/blob/main/spec/standard-webhooks.md
+use std::path::PathBuf;
+
+use base64::{engine::general_purpose, Engine};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::Sha256;
+use thiserror::Error;
+
+pub const WEBHOOK_ID_HEADER: &str = "webhook-id";
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "webhook-timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "webhook-signature";
+
+/// The `WebhookJobMetadata` field holding the name of the secret used to sign a webhook.
+const SECRET_REF_FIELD: &str = "signing_secret_ref";
+/// The `WebhookJobMetadata` field holding the team whose secrets are used.
+const TEAM_ID_FIELD: &str = "team_id";
+/// Secrets are base64 encoded, optionally carrying this prefix as recommended by the spec.
+const SECRET_PREFIX: &str = "whsec_";
+/// Version identifier of the symmetric (HMAC-SHA256) signature scheme.
+const SIGNATURE_VERSION: &str = "v1";
+
+/// Enumeration of errors that can occur while signing a webhook.
+#[derive(Error, Debug)]
+pub enum SigningError {
+    #[error("webhook signing requested but no signing secrets directory is configured")]
+    SecretsNotConfigured,
+    #[error("webhook signing requested without a team")]
+    MissingTeam,
+    #[error("invalid signing secret reference: {0}")]
+    InvalidSecretRef(String),
+    #[error("signing secret not found: {0}")]
+    SecretNotFound(String),
+    #[error("signing secret {0} is not valid base64")]
+    InvalidSecret(String),
+}
+
+/// A reference to the secrets a webhook is signed with, taken from its metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecretRef {
+    pub team_id: Option<u64>,
+    pub name: String,
+}
+
+/// Signs outgoing webhooks with per-plugin-config secrets.
+///
+/// Secrets are looked up by reference: each reference names a file in its team's directory of
+/// the secrets directory (e.g. a mounted kubernetes secret), so a webhook can only be signed with
+/// the secrets of the team it belongs to. Every non-empty line of the file is an active secret, and
+/// the request is signed with each of them, so a secret can be rotated by adding the new one,
+/// waiting for consumers to pick it up, and then removing the old one. Files are re-read on every
+/// request so rotations don't require a restart.
+#[derive(Debug, Default)]
+pub struct WebhookSigner {
+    secrets_dir: Option<PathBuf>,
+}
+
+impl WebhookSigner {
+    pub fn new(secrets_dir: Option<PathBuf>) -> Self {
+        Self { secrets_dir }
+    }
+
+    /// Produce the Standard Webhooks headers for a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `secret_ref`: The team and name of the file holding the active secrets.
+    /// * `msg_id`: An identifier for the message, which must be the same across retries.
+    /// * `body`: The exact body that will be sent.
+    pub async fn sign(
+        &self,
+        secret_ref: &SecretRef,
+        msg_id: &str,
+        body: &str,
+    ) -> Result<Vec<(&'static str, String)>, SigningError> {
+        let secrets = self.secrets(secret_ref).await?;
+        let timestamp = Utc::now().timestamp();
+
+        let signatures = secrets
+            .iter()
+            .map(|secret| signature(secret, msg_id, timestamp, body))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(vec![
+            (WEBHOOK_ID_HEADER, msg_id.to_owned()),
+            (WEBHOOK_TIMESTAMP_HEADER, timestamp.to_string()),
+            (WEBHOOK_SIGNATURE_HEADER, signatures),
+        ])
+    }
+
+    async fn secrets(&self, secret_ref: &SecretRef) -> Result<Vec<Vec<u8>>, SigningError> {
+        let name = secret_ref.name.as_str();
+        // References are bare file names, anything else could be used to read arbitrary files.
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(SigningError::InvalidSecretRef(name.to_owned()));
+        }
+        let team_id = secret_ref.team_id.ok_or(SigningError::MissingTeam)?;
+
+        let dir = self
+            .secrets_dir
+            .as_ref()
+            .ok_or(SigningError::SecretsNotConfigured)?;
+        let contents = tokio::fs::read_to_string(dir.join(team_id.to_string()).join(name))
+            .await
+            .map_err(|_| SigningError::SecretNotFound(name.to_owned()))?;
+
+        let secrets = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                general_purpose::STANDARD
+                    .decode(line.strip_prefix(SECRET_PREFIX).unwrap_or(line))
+                    .map_err(|_| SigningError::InvalidSecret(name.to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if secrets.is_empty() {
+            return Err(SigningError::SecretNotFound(name.to_owned()));
+        }
+
+        Ok(secrets)
+    }
+}
+
+/// Get the signing secret reference from a job's metadata, if it has one.
+pub fn secret_ref(metadata: &Value) -> Option<SecretRef> {
+    let name = metadata.get(SECRET_REF_FIELD).and_then(Value::as_str)?;
+
+    Some(SecretRef {
+        team_id: metadata.get(TEAM_ID_FIELD).and_then(Value::as_u64),
+        name: name.to_owned(),
+    })
+}
+
+/// Compute a versioned signature over `{msg_id}.{timestamp}.{body}`.
+fn signature(secret: &[u8], msg_id: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret).expect("HMAC can take a key of any length");
+    mac.update(format!("{msg_id}.{timestamp}.{body}").as_bytes());
+
+    format!(
+        "{},{}",
+        SIGNATURE_VERSION,
+        general_purpose::STANDARD.encode(mac.finalize().into_bytes())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_matches_spec_example() {
+        let secret = general_purpose::STANDARD
+            .decode("MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw")
+            .unwrap();
+
+        assert_eq!(
+            signature(
+                &secret,
+                "msg_p5jXN8AQM9LWM0D4loKWxJek",
+                1614265330,
+                r#"{"test": 2432232314}"#
+            ),
+            "v1,g0hM9SsE+OTPJTGt/tmIKtSyZlE3uFJELVlNIOLJ1OE="
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signs_with_every_active_secret() {
+        let dir = std::env::temp_dir().join(format!("hook-worker-signing-{}", std::process::id()));
+        tokio::fs::create_dir_all(dir.join("1")).await.unwrap();
+        tokio::fs::write(
+            dir.join("1").join("plugin-config-1"),
+            "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw\n\naGVsbG8gd29ybGQ=\n",
+        )
+        .await
+        .unwrap();
+
+        let secret_ref = |team_id: Option<u64>, name: &str| SecretRef {
+            team_id,
+            name: name.to_owned(),
+        };
+
+        let signer = WebhookSigner::new(Some(dir.clone()));
+        let headers = signer
+            .sign(&secret_ref(Some(1), "plugin-config-1"), "msg_1", "{}")
+            .await
+            .expect("failed to sign");
+
+        assert_eq!(headers[0], (WEBHOOK_ID_HEADER, "msg_1".to_owned()));
+        let timestamp: i64 = headers[1].1.parse().unwrap();
+        let signatures: Vec<&str> = headers[2].1.split(' ').collect();
+        assert_eq!(signatures.len(), 2);
+        assert_eq!(
+            signatures[1],
+            signature(b"hello world", "msg_1", timestamp, "{}")
+        );
+
+        assert!(matches!(
+            signer
+                .sign(&secret_ref(Some(1), "../plugin-config-1"), "msg_1", "{}")
+                .await,
+            Err(SigningError::InvalidSecretRef(_))
+        ));
+        assert!(matches!(
+            signer
+                .sign(&secret_ref(Some(1), "missing"), "msg_1", "{}")
+                .await,
+            Err(SigningError::SecretNotFound(_))
+        ));
+        // Another team's secrets can't be used.
+        assert!(matches!(
+            signer
+                .sign(&secret_ref(Some(2), "plugin-config-1"), "msg_1", "{}")
+                .await,
+            Err(SigningError::SecretNotFound(_))
+        ));
+        assert!(matches!(
+            signer
+                .sign(&secret_ref(None, "plugin-config-1"), "msg_1", "{}")
+                .await,
+            Err(SigningError::MissingTeam)
+        ));
+        assert!(matches!(
+            WebhookSigner::default()
+                .sign(&secret_ref(Some(1), "plugin-config-1"), "msg_1", "{}")
+                .await,
+            Err(SigningError::SecretsNotConfigured)
+        ));
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
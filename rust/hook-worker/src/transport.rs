@@ -0,0 +1,454 @@
+//! Delivery of webhooks over transports other than HTTP.
+//!
+//! HTTP webhooks are sent by the worker's own client, as their response is handled in detail. The
+//! transports here only report whether a webhook was delivered, and if not, whether trying again
+//! could help. Which transport is used is selected by the job's `WebhookJobParameters::transport`.
+use std::collections::HashSet;
+use std::time;
+
+use hook_common::webhook::{kafka_topic, WebhookJobError, WebhookJobParameters, WebhookTransport};
+use lettre::address::AddressError;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use thiserror::Error;
+
+use common_kafka::kafka_producer::KafkaContext;
+
+use crate::config::SmtpConfig;
+
+/// Enumeration of errors that can occur while delivering a webhook over a transport.
+#[derive(Error, Debug)]
+pub enum TransportError {
+    #[error("{0} transport is not configured")]
+    NotConfigured(WebhookTransport),
+    #[error("invalid {transport} destination: {reason}")]
+    InvalidDestination {
+        transport: WebhookTransport,
+        reason: String,
+    },
+    #[error("{0}")]
+    Retryable(String),
+    #[error("{0}")]
+    NonRetryable(String),
+    #[error("{0} transport does not support signing")]
+    SigningUnsupported(WebhookTransport),
+}
+
+impl TransportError {
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, TransportError::Retryable(_))
+    }
+}
+
+impl From<&TransportError> for WebhookJobError {
+    fn from(error: &TransportError) -> Self {
+        match error {
+            TransportError::NotConfigured(_) | TransportError::InvalidDestination { .. } => {
+                WebhookJobError::new_parse(&error.to_string())
+            }
+            TransportError::Retryable(_) | TransportError::NonRetryable(_) => {
+                WebhookJobError::new_connection(&error.to_string())
+            }
+            TransportError::SigningUnsupported(_) => {
+                WebhookJobError::new_signing(&error.to_string())
+            }
+        }
+    }
+}
+
+/// The transports available to a worker.
+#[derive(Clone, Default)]
+pub struct Transports {
+    pub kafka: Option<KafkaTransport>,
+    pub smtp: Option<SmtpTransport>,
+}
+
+impl Transports {
+    /// Deliver a webhook over the transport its parameters select.
+    pub async fn send(&self, parameters: &WebhookJobParameters) -> Result<(), TransportError> {
+        match parameters.transport {
+            WebhookTransport::Kafka => match &self.kafka {
+                Some(kafka) => kafka.send(parameters).await,
+                None => Err(TransportError::NotConfigured(WebhookTransport::Kafka)),
+            },
+            WebhookTransport::Smtp => match &self.smtp {
+                Some(smtp) => smtp.send(parameters).await,
+                None => Err(TransportError::NotConfigured(WebhookTransport::Smtp)),
+            },
+            // HTTP webhooks never make it here, see the module docs.
+            WebhookTransport::Http => Err(TransportError::NotConfigured(WebhookTransport::Http)),
+        }
+    }
+}
+
+/// Produces webhooks to the topic in their `kafka://<topic>` url, if it is one of the allowed
+/// topics.
+#[derive(Clone)]
+pub struct KafkaTransport {
+    /// A producer for the cluster webhooks are delivered to, not the one the worker reports to.
+    producer: FutureProducer<KafkaContext>,
+    timeout: time::Duration,
+    topics: HashSet<String>,
+}
+
+impl KafkaTransport {
+    pub fn new(
+        producer: FutureProducer<KafkaContext>,
+        timeout: time::Duration,
+        topics: HashSet<String>,
+    ) -> Self {
+        Self {
+            producer,
+            timeout,
+            topics,
+        }
+    }
+
+    async fn send(&self, parameters: &WebhookJobParameters) -> Result<(), TransportError> {
+        let topic = self.topic(&parameters.url)?;
+
+        let headers =
+            parameters
+                .headers
+                .iter()
+                .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                    headers.insert(Header {
+                        key,
+                        value: Some(value.as_str()),
+                    })
+                });
+        let record: FutureRecord<'_, str, str> = FutureRecord::to(topic.as_str())
+            .payload(parameters.body.as_str())
+            .headers(headers);
+
+        match self.producer.send(record, self.timeout).await {
+            Ok(_) => Ok(()),
+            Err((error, _)) if is_retryable_kafka_error(&error) => {
+                Err(TransportError::Retryable(error.to_string()))
+            }
+            Err((error, _)) => Err(TransportError::NonRetryable(error.to_string())),
+        }
+    }
+
+    fn topic(&self, url: &str) -> Result<String, TransportError> {
+        let invalid = |reason: String| TransportError::InvalidDestination {
+            transport: WebhookTransport::Kafka,
+            reason,
+        };
+
+        let topic = kafka_topic(url).map_err(invalid)?;
+        if !self.topics.contains(&topic) {
+            return Err(invalid(format!("topic {} is not allowed", topic)));
+        }
+
+        Ok(topic)
+    }
+}
+
+/// Errors that will happen again no matter how many times we produce the same message.
+fn is_retryable_kafka_error(error: &KafkaError) -> bool {
+    !matches!(
+        error.rdkafka_error_code(),
+        Some(
+            RDKafkaErrorCode::MessageSizeTooLarge
+                | RDKafkaErrorCode::InvalidMessageSize
+                | RDKafkaErrorCode::InvalidMessage
+                | RDKafkaErrorCode::TopicAuthorizationFailed
+                | RDKafkaErrorCode::InvalidTopic
+        )
+    )
+}
+
+/// Enumeration of errors in the SMTP transport's configuration.
+#[derive(Error, Debug)]
+pub enum SmtpConfigError {
+    #[error("smtp_host is required to send emails")]
+    MissingHost,
+    #[error("smtp_from is required to send emails")]
+    MissingFrom,
+    #[error("invalid smtp_from address: {0}")]
+    InvalidFrom(#[from] AddressError),
+    #[error("invalid smtp relay: {0}")]
+    InvalidRelay(#[from] lettre::transport::smtp::Error),
+}
+
+/// Emails webhooks to the recipients in their `mailto:` url, if they are all in one of the allowed
+/// domains.
+#[derive(Clone)]
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    /// The sender of every email, so webhooks can't send as addresses we don't control.
+    from: Mailbox,
+    recipient_domains: HashSet<String>,
+}
+
+impl SmtpTransport {
+    pub fn new(config: &SmtpConfig, timeout: time::Duration) -> Result<Self, SmtpConfigError> {
+        let host = config
+            .smtp_host
+            .as_deref()
+            .ok_or(SmtpConfigError::MissingHost)?;
+
+        let mut builder = if config.smtp_starttls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        }
+        .port(config.smtp_port)
+        .timeout(Some(timeout));
+
+        if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+
+        let from = config
+            .smtp_from
+            .as_deref()
+            .ok_or(SmtpConfigError::MissingFrom)?
+            .parse::<Mailbox>()?;
+
+        Ok(Self {
+            mailer: builder.build(),
+            from,
+            recipient_domains: config.recipient_domains(),
+        })
+    }
+
+    async fn send(&self, parameters: &WebhookJobParameters) -> Result<(), TransportError> {
+        let message = self.message(parameters)?;
+
+        match self.mailer.send(message).await {
+            Ok(_) => Ok(()),
+            Err(error) if error.is_permanent() => {
+                Err(TransportError::NonRetryable(error.to_string()))
+            }
+            Err(error) => Err(TransportError::Retryable(error.to_string())),
+        }
+    }
+
+    fn message(&self, parameters: &WebhookJobParameters) -> Result<Message, TransportError> {
+        let invalid = |reason: String| TransportError::InvalidDestination {
+            transport: WebhookTransport::Smtp,
+            reason,
+        };
+
+        let url = url::Url::parse(&parameters.url).map_err(|e| invalid(e.to_string()))?;
+        if url.scheme() != "mailto" {
+            return Err(invalid("url must use the mailto: scheme".to_owned()));
+        }
+
+        let header = |name: &str| {
+            parameters
+                .headers
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+        };
+        let mailbox = |address: &str| {
+            address
+                .trim()
+                .parse::<Mailbox>()
+                .map_err(|e| invalid(format!("{}: {}", address, e)))
+        };
+
+        // A subject in the url, as in `mailto:a@example.com?subject=Hi`, is used as a fallback.
+        let subject = header("Subject").map(str::to_owned).or_else(|| {
+            url.query_pairs()
+                .find(|(key, _)| key.eq_ignore_ascii_case("subject"))
+                .map(|(_, value)| value.into_owned())
+        });
+
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject.unwrap_or_default());
+        for recipient in url.path().split(',').filter(|r| !r.trim().is_empty()) {
+            let recipient = mailbox(recipient)?;
+            let domain = recipient.email.domain().to_ascii_lowercase();
+            if !self.recipient_domains.contains(&domain) {
+                return Err(invalid(format!("email domain {} is not allowed", domain)));
+            }
+            builder = builder.to(recipient);
+        }
+        // Replies go to whoever the webhook says it's from, unless it asks for them elsewhere.
+        if let Some(reply_to) = header("Reply-To").or_else(|| header("From")) {
+            builder = builder.reply_to(mailbox(reply_to)?);
+        }
+        let content_type = header("Content-Type")
+            .and_then(|content_type| ContentType::parse(content_type).ok())
+            .unwrap_or(ContentType::TEXT_PLAIN);
+
+        builder
+            .header(content_type)
+            .body(parameters.body.clone())
+            .map_err(|e| invalid(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use common_kafka::test::create_mock_kafka;
+    use hook_common::webhook::HttpMethod;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    use super::*;
+
+    fn parameters(url: &str, headers: &[(&str, &str)]) -> WebhookJobParameters {
+        WebhookJobParameters {
+            body: "hello".to_owned(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            method: HttpMethod::POST,
+            url: url.to_owned(),
+            transport: WebhookTransport::Smtp,
+        }
+    }
+
+    /// Start a stand-in SMTP server that accepts a single message, replying to `RCPT TO` with
+    /// `rcpt_reply`. Returns its port and the message data, if one was accepted.
+    async fn start_smtp_server(rcpt_reply: &'static str) -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            let mut data: Option<String> = None;
+
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if let Some(data) = data.as_mut() {
+                    if line == "." {
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                        tx.send(std::mem::take(data)).ok();
+                        return;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                    continue;
+                }
+
+                let command = line.to_ascii_uppercase();
+                let reply = if command.starts_with("RCPT") {
+                    rcpt_reply
+                } else if command.starts_with("DATA") {
+                    data = Some(String::new());
+                    "354 Go ahead\r\n"
+                } else if command.starts_with("QUIT") {
+                    "221 Bye\r\n"
+                } else {
+                    "250 OK\r\n"
+                };
+                writer.write_all(reply.as_bytes()).await.unwrap();
+            }
+        });
+
+        (port, rx)
+    }
+
+    fn smtp_transport(port: u16) -> SmtpTransport {
+        let config = SmtpConfig {
+            smtp_host: Some("127.0.0.1".to_owned()),
+            smtp_port: port,
+            smtp_starttls: false,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_from: Some("PostHog <hooks@posthog.com>".to_owned()),
+            smtp_recipient_domains: "example.com".to_owned(),
+        };
+        SmtpTransport::new(&config, time::Duration::from_secs(5)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_kafka_topic() {
+        let (_mock_cluster, mock_producer) = create_mock_kafka().await;
+        let transport = KafkaTransport::new(
+            mock_producer,
+            time::Duration::from_secs(5),
+            HashSet::from(["webhooks".to_owned()]),
+        );
+
+        assert_eq!(transport.topic("kafka://webhooks").unwrap(), "webhooks");
+        for url in ["kafka://events_json", "http://example.com", "kafka://"] {
+            assert!(matches!(
+                transport.topic(url),
+                Err(TransportError::InvalidDestination { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_smtp_sends_email() {
+        let (port, data) = start_smtp_server("250 OK\r\n").await;
+        let transport = smtp_transport(port);
+
+        transport
+            .send(&parameters(
+                "mailto:alice@example.com?subject=Ignored",
+                &[("Subject", "New signup"), ("From", "alice@example.com")],
+            ))
+            .await
+            .expect("failed to send email");
+
+        let data = data.await.expect("no email received");
+        assert!(data.contains("From: PostHog <hooks@posthog.com>"));
+        assert!(data.contains("Reply-To: alice@example.com"));
+        assert!(data.contains("To: alice@example.com"));
+        assert!(data.contains("Subject: New signup"));
+        assert!(data.contains("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_smtp_permanent_errors_are_not_retryable() {
+        let (port, _) = start_smtp_server("550 No such user\r\n").await;
+        let transport = smtp_transport(port);
+
+        let error = transport
+            .send(&parameters("mailto:nobody@example.com", &[]))
+            .await
+            .expect_err("email should have been rejected");
+        assert!(!error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_smtp_invalid_destinations() {
+        let transport = smtp_transport(25);
+
+        assert!(matches!(
+            transport.message(&parameters("http://example.com", &[])),
+            Err(TransportError::InvalidDestination { .. })
+        ));
+        assert!(matches!(
+            transport.message(&parameters("mailto:not-an-address", &[])),
+            Err(TransportError::InvalidDestination { .. })
+        ));
+        assert!(matches!(
+            transport.message(&parameters("mailto:alice@example.com,eve@evil.com", &[])),
+            Err(TransportError::InvalidDestination { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unconfigured_transports() {
+        let transports = Transports::default();
+
+        let error = transports
+            .send(&parameters("mailto:alice@example.com", &[]))
+            .await
+            .expect_err("smtp isn't configured");
+        assert!(matches!(
+            error,
+            TransportError::NotConfigured(WebhookTransport::Smtp)
+        ));
+    }
+}
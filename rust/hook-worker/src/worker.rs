@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::sync::Arc;
 use std::time;
 use std::{collections, iter};
@@ -18,14 +19,19 @@ use tracing::error;
 use common_kafka::kafka_producer::KafkaContext;
 use hook_common::pgqueue::PgTransactionBatch;
 use hook_common::{
+    delivery::{DeliveryLog, NewDelivery},
     pgqueue::{Job, PgQueue, PgQueueJob, PgTransactionJob, RetryError, RetryInvalidError},
     retry::RetryPolicy,
-    webhook::{HttpMethod, WebhookJobError, WebhookJobParameters},
+    webhook::{HttpMethod, WebhookJobError, WebhookJobMetadata, WebhookJobParameters},
 };
 
+use crate::batching::{self, BatchEnvelope, BatchKey, Batching};
+use crate::circuit::{Admission, App, CircuitBreaker};
 use crate::error::{
     is_error_source, WebhookError, WebhookParseError, WebhookRequestError, WorkerError,
 };
+use crate::signing::{self, SecretRef, WebhookSigner};
+use crate::transport::{KafkaTransport, SmtpTransport, TransportError, Transports};
 use crate::util::first_n_bytes_of_response;
 use common_dns::{NoPublicIPv4Error, PublicIPv4Resolver};
 
@@ -91,6 +97,16 @@ pub struct WebhookWorker<'p> {
     hog_mode: bool,
     /// The liveness check handle, to call on a schedule to report healthy
     liveness: HealthHandle,
+    /// Signs webhooks whose metadata references a signing secret.
+    signer: Arc<WebhookSigner>,
+    /// Defers jobs for unhealthy targets, if enabled.
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Records every attempt to deliver a plugin webhook, if enabled.
+    delivery_log: Option<DeliveryLog>,
+    /// Coalesces plugin webhooks to the same destination into a single request, if enabled.
+    batching: Option<Batching>,
+    /// Delivers webhooks that aren't sent over HTTP.
+    transports: Transports,
 }
 
 pub fn build_http_client(
@@ -140,12 +156,55 @@ impl<'p> WebhookWorker<'p> {
             max_concurrent_jobs,
             retry_policy,
             kafka_producer,
+            transports: Transports::default(),
             cdp_function_callbacks_topic: cdp_function_callbacks_topic.leak(),
             hog_mode,
             liveness,
+            signer: Arc::new(WebhookSigner::default()),
+            circuit_breaker: None,
+            delivery_log: None,
+            batching: None,
         }
     }
 
+    /// Use the given signer for webhooks that request signing. Without one, any webhook that
+    /// requests signing will fail.
+    pub fn with_signer(mut self, signer: WebhookSigner) -> Self {
+        self.signer = Arc::new(signer);
+        self
+    }
+
+    /// Track the health of targets, deferring jobs for targets with an open circuit.
+    pub fn with_circuit_breaker(mut self, circuit_breaker: CircuitBreaker) -> Self {
+        self.circuit_breaker = Some(Arc::new(circuit_breaker));
+        self
+    }
+
+    /// Record every attempt to deliver a plugin webhook. Not supported in Hog mode.
+    pub fn with_delivery_log(mut self, delivery_log: DeliveryLog) -> Self {
+        self.delivery_log = Some(delivery_log);
+        self
+    }
+
+    /// Send plugin webhooks dequeued together for the same destination in a single request. Not
+    /// supported in Hog mode.
+    pub fn with_batching(mut self, batching: Batching) -> Self {
+        self.batching = Some(batching);
+        self
+    }
+
+    /// Produce webhooks that use the Kafka transport. Without it, those webhooks will fail.
+    pub fn with_kafka_transport(mut self, kafka: KafkaTransport) -> Self {
+        self.transports.kafka = Some(kafka);
+        self
+    }
+
+    /// Email webhooks that use the SMTP transport. Without it, those webhooks will fail.
+    pub fn with_smtp_transport(mut self, smtp: SmtpTransport) -> Self {
+        self.transports.smtp = Some(smtp);
+        self
+    }
+
     /// Wait until at least one job becomes available in our queue in transactional mode.
     async fn wait_for_jobs_tx<'a>(&self) -> PgTransactionBatch<'a, WebhookJobParameters, Value> {
         let mut interval = tokio::time::interval(self.poll_interval);
@@ -179,6 +238,10 @@ impl<'p> WebhookWorker<'p> {
 
         let dequeue_batch_size_histogram = metrics::histogram!("webhook_dequeue_batch_size");
 
+        if let Some(circuit_breaker) = self.circuit_breaker.clone() {
+            tokio::spawn(async move { circuit_breaker.run_sync().await });
+        }
+
         loop {
             report_semaphore_utilization();
             // TODO: We could grab semaphore permits here using something like:
@@ -200,6 +263,11 @@ impl<'p> WebhookWorker<'p> {
             let kafka_producer = self.kafka_producer.clone();
             let cdp_function_callbacks_topic = self.cdp_function_callbacks_topic;
             let hog_mode = self.hog_mode;
+            let signer = self.signer.clone();
+            let circuit_breaker = self.circuit_breaker.clone();
+            let delivery_log = self.delivery_log.clone();
+            let batching = self.batching;
+            let transports = self.transports.clone();
 
             tokio::spawn(async move {
                 // Move `permits` into the closure so they will be dropped when the scope ends.
@@ -212,6 +280,11 @@ impl<'p> WebhookWorker<'p> {
                     kafka_producer,
                     cdp_function_callbacks_topic,
                     hog_mode,
+                    signer,
+                    circuit_breaker,
+                    delivery_log,
+                    batching,
+                    transports,
                 )
                 .await
             });
@@ -238,6 +311,7 @@ async fn log_kafka_error_and_sleep(step: &str, error: Option<KafkaError>) {
     sleep(Duration::from_secs(30)).await;
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_batch<'a>(
     mut batch: PgTransactionBatch<'a, WebhookJobParameters, Value>,
     http_client: Client,
@@ -245,26 +319,93 @@ async fn process_batch<'a>(
     kafka_producer: FutureProducer<KafkaContext>,
     cdp_function_callbacks_topic: &'static str,
     hog_mode: bool,
+    signer: Arc<WebhookSigner>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    delivery_log: Option<DeliveryLog>,
+    batching: Option<Batching>,
+    transports: Transports,
 ) {
-    let mut futures = Vec::with_capacity(batch.jobs.len());
-    let mut metadata_vec = Vec::with_capacity(batch.jobs.len());
-
     // We have to `take` the Vec of jobs from the batch to avoid a borrow checker
     // error below when we commit.
-    for mut job in std::mem::take(&mut batch.jobs) {
+    let jobs = std::mem::take(&mut batch.jobs);
+    let (grouped_jobs, jobs) = match batching.filter(|_| !hog_mode) {
+        Some(batching) => group_jobs(jobs, &batching),
+        None => (Vec::new(), jobs),
+    };
+
+    let mut group_futures = Vec::with_capacity(grouped_jobs.len());
+    for group in grouped_jobs {
         let http_client = http_client.clone();
         let retry_policy = retry_policy.clone();
+        let circuit_breaker = circuit_breaker.clone();
+        let delivery_log = delivery_log.clone();
+        let envelope = batching
+            .expect("jobs are only grouped when batching")
+            .envelope;
+
+        group_futures.push(async move {
+            process_webhook_batch(
+                http_client,
+                group,
+                &retry_policy,
+                envelope,
+                circuit_breaker.as_deref(),
+                delivery_log.as_ref(),
+            )
+            .await
+        });
+    }
+
+    let mut futures = Vec::with_capacity(jobs.len());
+    let mut metadata_vec = Vec::with_capacity(jobs.len());
 
-        metadata_vec.push(job.take_metadata());
+    for mut job in jobs {
+        let http_client = http_client.clone();
+        let retry_policy = retry_policy.clone();
+        let signer = signer.clone();
+        let circuit_breaker = circuit_breaker.clone();
+        let delivery_log = delivery_log.clone();
+        let transports = transports.clone();
+
+        let metadata = job.take_metadata();
+        let signing_secret_ref = signing::secret_ref(&metadata);
+        let app = circuit_breaker
+            .as_ref()
+            .and_then(|_| App::from_metadata(&metadata, hog_mode));
+        // Only plugin webhooks are logged, as the log is listed by plugin config.
+        let delivery_metadata = delivery_log
+            .as_ref()
+            .filter(|_| !hog_mode)
+            .and_then(|_| serde_json::from_value::<WebhookJobMetadata>(metadata.clone()).ok());
+        metadata_vec.push(metadata);
 
         let read_body = hog_mode;
-        let future =
-            async move { process_webhook_job(http_client, job, &retry_policy, read_body).await };
+        let future = async move {
+            process_webhook_job(
+                http_client,
+                job,
+                &retry_policy,
+                read_body,
+                &signer,
+                signing_secret_ref,
+                circuit_breaker.as_deref(),
+                app,
+                delivery_log.as_ref().zip(delivery_metadata),
+                &transports,
+            )
+            .await
+        };
 
         futures.push(future);
     }
 
-    let results = join_all(futures).await;
+    let (results, group_results) = futures::join!(join_all(futures), join_all(group_futures));
+
+    for result in group_results {
+        if let Err(e) = result {
+            error!("error processing batched webhook jobs: {}", e);
+        }
+    }
 
     if hog_mode
         && push_hoghook_results_to_kafka(
@@ -431,35 +572,149 @@ enum WebhookResult {
 /// * `client`: An HTTP client to execute the webhook job request.
 /// * `webhook_job`: The webhook job to process as dequeued from `hook_common::pgqueue::PgQueue`.
 /// * `retry_policy`: The retry policy used to set retry parameters if a job fails and has remaining attempts.
+/// * `read_body`: Whether to read the response body, so it can be returned in the result.
+/// * `signer`: Signs the webhook if it has a `signing_secret_ref`.
+/// * `signing_secret_ref`: The secret to sign the webhook with, if any.
+/// * `circuit_breaker`: Tracks the health of targets, if enabled. Jobs for unhealthy targets are deferred.
+/// * `app`: The app the job is for, which the circuit breaker reports deferrals and probes against.
+/// * `delivery_log`: Where to record this attempt and the plugin it was for, if it should be recorded.
+/// * `transports`: Delivers the webhook if it isn't sent over HTTP.
+#[allow(clippy::too_many_arguments)]
 async fn process_webhook_job<W: WebhookJob>(
     http_client: reqwest::Client,
     webhook_job: W,
     retry_policy: &RetryPolicy,
     read_body: bool,
+    signer: &WebhookSigner,
+    signing_secret_ref: Option<SecretRef>,
+    circuit_breaker: Option<&CircuitBreaker>,
+    app: Option<App>,
+    delivery_log: Option<(&DeliveryLog, WebhookJobMetadata)>,
+    transports: &Transports,
 ) -> Result<WebhookResult, WorkerError> {
+    let labels = [("queue", webhook_job.queue())];
+
+    let admission = match circuit_breaker {
+        Some(circuit_breaker) => circuit_breaker.admit(&webhook_job.job().target).await,
+        None => Admission::Allowed,
+    };
+
+    if let Admission::Deferred(defer_interval) = admission {
+        // The job wasn't attempted, so put it back without counting this attempt.
+        let current_queue = webhook_job.queue();
+        let retry_queue = retry_policy.retry_queue(&current_queue);
+
+        webhook_job
+            .defer(defer_interval, retry_queue)
+            .await
+            .inspect_err(|_| {
+                metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+            })?;
+
+        metrics::counter!("webhook_jobs_deferred", &labels).increment(1);
+        if let Some(circuit_breaker) = circuit_breaker {
+            circuit_breaker.record_deferred(app.as_ref());
+        }
+
+        return Ok(WebhookResult::WillRetry);
+    }
+
+    if !webhook_job.parameters().transport.is_http() {
+        return process_transport_job(
+            webhook_job,
+            retry_policy,
+            signing_secret_ref.is_some(),
+            circuit_breaker,
+            admission,
+            app,
+            delivery_log,
+            transports,
+        )
+        .await;
+    }
+
     let parameters = webhook_job.parameters();
 
-    let labels = [("queue", webhook_job.queue())];
     metrics::counter!("webhook_jobs_total", &labels).increment(1);
 
     let now = tokio::time::Instant::now();
 
-    let send_result = send_webhook(
-        http_client,
-        &parameters.method,
-        &parameters.url,
-        &parameters.headers,
-        parameters.body.clone(),
-    )
-    .await;
+    // The job id is stable across retries, so consumers can use the message id to deduplicate.
+    let headers = match &signing_secret_ref {
+        Some(secret_ref) => signer
+            .sign(
+                secret_ref,
+                &format!("msg_{}", webhook_job.job().id),
+                &parameters.body,
+            )
+            .await
+            .map(|signature_headers| {
+                let mut headers = parameters.headers.clone();
+                headers.extend(
+                    signature_headers
+                        .into_iter()
+                        .map(|(name, value)| (name.to_owned(), value)),
+                );
+                Cow::Owned(headers)
+            }),
+        None => Ok(Cow::Borrowed(&parameters.headers)),
+    };
+
+    let (send_result, request_headers) = match headers {
+        Ok(headers) => {
+            let send_result = send_webhook(
+                http_client,
+                &parameters.method,
+                &parameters.url,
+                &headers,
+                parameters.body.clone(),
+            )
+            .await;
+            (send_result, Some(headers))
+        }
+        Err(e) => (Err(WebhookError::Signing(e)), None),
+    };
+
+    if let (Some(circuit_breaker), Some(healthy)) = (circuit_breaker, target_health(&send_result)) {
+        circuit_breaker
+            .record(&webhook_job.job().target, &admission, healthy, app.as_ref())
+            .await;
+    }
+
+    if let (Some((delivery_log, metadata)), Some(request_headers), Err(WebhookError::Request(e))) =
+        (&delivery_log, &request_headers, &send_result)
+    {
+        record_delivery(
+            delivery_log,
+            NewDelivery {
+                job_id: webhook_job.job().id,
+                attempt: webhook_job.attempt(),
+                max_attempts: webhook_job.job().max_attempts,
+                metadata,
+                parameters,
+                request_headers,
+                status: e.status().map(|status| status.as_u16()),
+                response_headers: e.response_headers().map(header_map_to_hash_map),
+                response_body: e.response_body(),
+                error: Some(e.to_string()),
+                duration: now.elapsed(),
+            },
+        )
+        .await;
+    }
 
     match send_result {
         Ok(response) => {
             let status = response.status();
+            let response_headers = delivery_log
+                .as_ref()
+                .map(|_| header_map_to_hash_map(response.headers()));
             // First, read the body if needed so that the read time is included in `duration`.
-            let body = if read_body {
+            let body = if read_body || delivery_log.is_some() {
                 match first_n_bytes_of_response(response, MAX_RESPONSE_BODY).await {
                     Ok(body) => Some(body), // Once told me...
+                    // We only wanted the body for the delivery log, which can do without it.
+                    Err(_) if !read_body => None,
                     Err(_) => {
                         // TODO: Consolidate this retry-or-fail logic which is mostly repeated below.
                         let retry_interval =
@@ -515,6 +770,29 @@ async fn process_webhook_job<W: WebhookJob>(
 
             let duration = now.elapsed();
 
+            if let (Some((delivery_log, metadata)), Some(request_headers)) =
+                (&delivery_log, &request_headers)
+            {
+                record_delivery(
+                    delivery_log,
+                    NewDelivery {
+                        job_id: webhook_job.job().id,
+                        attempt: webhook_job.attempt(),
+                        max_attempts: webhook_job.job().max_attempts,
+                        metadata,
+                        parameters,
+                        request_headers,
+                        status: Some(status.as_u16()),
+                        response_headers,
+                        response_body: body.as_deref(),
+                        error: None,
+                        duration,
+                    },
+                )
+                .await;
+            }
+            let body = body.filter(|_| read_body);
+
             let created_at = webhook_job.job().created_at;
             let retries = webhook_job.job().attempt - 1;
             let labels_with_retries = [
@@ -578,6 +856,20 @@ async fn process_webhook_job<W: WebhookJob>(
 
             Ok(WebhookResult::Error(e.to_string()))
         }
+        Err(WebhookError::Signing(e)) => {
+            // Signing fails because of missing or invalid secrets, which retrying won't fix.
+            webhook_job
+                .fail(WebhookJobError::new_signing(&e.to_string()))
+                .await
+                .inspect_err(|_| {
+                    metrics::counter!("webhook_jobs_database_error", &labels).increment(1)
+                })?;
+
+            metrics::counter!("webhook_jobs_failed", &labels).increment(1);
+            metrics::counter!("webhook_jobs_signing_failed", &labels).increment(1);
+
+            Ok(WebhookResult::Error(e.to_string()))
+        }
         Err(WebhookError::Request(request_error)) => {
             let webhook_job_error = WebhookJobError::from(&request_error);
 
@@ -654,139 +946,621 @@ async fn process_webhook_job<W: WebhookJob>(
     }
 }
 
-/// Make an HTTP request to a webhook endpoint.
+/// Process a webhook job that is delivered over a transport other than HTTP, transitioning it as
+/// `process_webhook_job` would: completed if it was delivered, retryable if it wasn't but trying
+/// again could help and it has attempts remaining, or failed otherwise.
+///
+/// There's no HTTP response to report to Hog, so a delivered webhook is reported as a 200 without
+/// a body.
 ///
 /// # Arguments
 ///
-/// * `client`: An HTTP client to execute the HTTP request.
-/// * `method`: The HTTP method to use in the HTTP request.
-/// * `url`: The URL we are targetting with our request. Parsing this URL fail.
-/// * `headers`: Key, value pairs of HTTP headers in a `std::collections::HashMap`. Can fail if headers are not valid.
-/// * `body`: The body of the request. Ownership is required.
-async fn send_webhook(
-    client: reqwest::Client,
-    method: &HttpMethod,
-    url: &str,
-    headers: &collections::HashMap<String, String>,
-    body: String,
-) -> Result<reqwest::Response, WebhookError> {
-    let method: http::Method = method.into();
-    let url: reqwest::Url = (url).parse().map_err(WebhookParseError::ParseUrlError)?;
-    let headers: reqwest::header::HeaderMap = (headers)
-        .try_into()
-        .map_err(WebhookParseError::ParseHeadersError)?;
-    let body = reqwest::Body::from(body);
+/// * `webhook_job`: The webhook job to process as dequeued from `hook_common::pgqueue::PgQueue`.
+/// * `retry_policy`: The retry policy used to set retry parameters if a job fails and has remaining attempts.
+/// * `signed`: Whether the webhook should have been signed, which no transport supports.
+/// * `circuit_breaker`: Tracks the health of targets, if enabled.
+/// * `admission`: How the circuit breaker admitted this job.
+/// * `app`: The app the job is for, which the circuit breaker reports probes against.
+/// * `delivery_log`: Where to record this attempt and the plugin it was for, if it should be recorded.
+/// * `transports`: Delivers the webhook over the transport it selects.
+#[allow(clippy::too_many_arguments)]
+async fn process_transport_job<W: WebhookJob>(
+    webhook_job: W,
+    retry_policy: &RetryPolicy,
+    signed: bool,
+    circuit_breaker: Option<&CircuitBreaker>,
+    admission: Admission,
+    app: Option<App>,
+    delivery_log: Option<(&DeliveryLog, WebhookJobMetadata)>,
+    transports: &Transports,
+) -> Result<WebhookResult, WorkerError> {
+    let labels = [("queue", webhook_job.queue())];
+    let labels_with_transport = [
+        ("queue", webhook_job.queue()),
+        ("transport", webhook_job.parameters().transport.to_string()),
+    ];
 
-    let response = client
-        .request(method, url)
-        .headers(headers)
-        .body(body)
-        .send()
-        .await
-        .map_err(|e| {
-            if is_error_source::<NoPublicIPv4Error>(&e) {
-                WebhookRequestError::NonRetryableRetryableRequestError {
-                    error: e,
-                    status: None,
-                    response: None,
-                }
-            } else {
-                WebhookRequestError::RetryableRequestError {
-                    error: e,
-                    status: None,
-                    response: None,
-                    retry_after: None,
-                }
+    metrics::counter!("webhook_jobs_total", &labels).increment(1);
+    metrics::counter!("webhook_transport_jobs_total", &labels_with_transport).increment(1);
+
+    let now = tokio::time::Instant::now();
+    // Consumers expecting signed webhooks mustn't be sent unsigned ones, so these fail outright.
+    let send_result = if signed {
+        Err(TransportError::SigningUnsupported(
+            webhook_job.parameters().transport,
+        ))
+    } else {
+        transports.send(webhook_job.parameters()).await
+    };
+    let duration = now.elapsed();
+
+    if let Some(circuit_breaker) = circuit_breaker {
+        // Only failures that may be temporary say anything about the target's health.
+        let healthy = match &send_result {
+            Ok(_) => Some(true),
+            Err(e) if e.is_retryable() => Some(false),
+            Err(_) => None,
+        };
+        if let Some(healthy) = healthy {
+            circuit_breaker
+                .record(&webhook_job.job().target, &admission, healthy, app.as_ref())
+                .await;
+        }
+    }
+
+    // There's no response, so only the outcome is recorded.
+    if let Some((delivery_log, metadata)) = &delivery_log {
+        let parameters = webhook_job.parameters();
+        record_delivery(
+            delivery_log,
+            NewDelivery {
+                job_id: webhook_job.job().id,
+                attempt: webhook_job.attempt(),
+                max_attempts: webhook_job.job().max_attempts,
+                metadata,
+                parameters,
+                request_headers: &parameters.headers,
+                status: None,
+                response_headers: None,
+                response_body: None,
+                error: send_result.as_ref().err().map(ToString::to_string),
+                duration,
+            },
+        )
+        .await;
+    }
+
+    let error = match send_result {
+        Ok(_) => {
+            let created_at = webhook_job.job().created_at;
+            let retries = webhook_job.job().attempt - 1;
+            let labels_with_retries = [
+                ("queue", webhook_job.queue()),
+                ("retries", retries.to_string()),
+            ];
+
+            webhook_job.complete().await.inspect_err(|_| {
+                metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+            })?;
+
+            let insert_to_complete_duration = Utc::now() - created_at;
+            metrics::histogram!(
+                "webhook_jobs_insert_to_complete_duration_seconds",
+                &labels_with_retries
+            )
+            .record((insert_to_complete_duration.num_milliseconds() as f64) / 1_000_f64);
+            metrics::counter!("webhook_jobs_completed", &labels).increment(1);
+            metrics::histogram!("webhook_jobs_processing_duration_seconds", &labels)
+                .record(duration.as_secs_f64());
+
+            return Ok(WebhookResult::Success(WebhookResponse {
+                duration,
+                status_code: StatusCode::OK,
+                body: None,
+            }));
+        }
+        Err(e) => e,
+    };
+
+    metrics::counter!("webhook_transport_errors", &labels_with_transport).increment(1);
+
+    let webhook_job = if error.is_retryable() {
+        let retry_interval = retry_policy.retry_interval(webhook_job.attempt() as u32, None);
+        let current_queue = webhook_job.queue();
+        let retry_queue = retry_policy.retry_queue(&current_queue);
+
+        match webhook_job
+            .retry(WebhookJobError::from(&error), retry_interval, retry_queue)
+            .await
+        {
+            Ok(_) => {
+                metrics::counter!("webhook_jobs_retried", &labels).increment(1);
+
+                return Ok(WebhookResult::WillRetry);
+            }
+            Err(RetryError::RetryInvalidError(RetryInvalidError {
+                job: webhook_job, ..
+            })) => *webhook_job,
+            Err(RetryError::DatabaseError(job_error)) => {
+                metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+                return Err(WorkerError::from(job_error));
             }
+        }
+    } else {
+        webhook_job
+    };
+
+    webhook_job
+        .fail(WebhookJobError::from(&error))
+        .await
+        .inspect_err(|_| {
+            metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
         })?;
 
-    let retry_after = parse_retry_after_header(response.headers());
+    metrics::counter!("webhook_jobs_failed", &labels).increment(1);
 
-    match response.error_for_status_ref() {
-        Ok(_) => Ok(response),
-        Err(err) => {
-            if is_retryable_status(
-                err.status()
-                    .expect("status code is set as error is generated from a response"),
-            ) {
-                Err(WebhookError::Request(
-                    WebhookRequestError::RetryableRequestError {
-                        error: err,
-                        status: Some(response.status()),
-                        response: first_n_bytes_of_response(response, MAX_RESPONSE_BODY)
-                            .await
-                            .ok(),
-                        retry_after,
-                    },
-                ))
+    Ok(WebhookResult::Error(error.to_string()))
+}
+
+/// Group jobs that can be sent in a single request, up to `max_jobs` per group. Jobs that can't
+/// be batched, or have nothing to be batched with, are returned to be processed on their own.
+///
+/// Only webhooks whose metadata opts in to batching are batched. Even then, signed webhooks never
+/// are, as a signature's message id identifies a single job, and neither are webhooks delivered
+/// over other transports than HTTP.
+#[allow(clippy::type_complexity)]
+fn group_jobs<W: WebhookJob>(
+    jobs: Vec<W>,
+    batching: &Batching,
+) -> (Vec<Vec<(W, WebhookJobMetadata)>>, Vec<W>) {
+    let mut groups: collections::HashMap<BatchKey, Vec<(W, WebhookJobMetadata)>> =
+        collections::HashMap::new();
+    let mut singles = Vec::new();
+
+    for job in jobs {
+        let metadata = serde_json::from_value::<WebhookJobMetadata>(job.job().metadata.0.clone());
+        match metadata {
+            Ok(metadata)
+                if metadata.batch
+                    && metadata.signing_secret_ref.is_none()
+                    && job.parameters().transport.is_http()
+                    && BatchEnvelope::can_wrap(&job.parameters().body) =>
+            {
+                let key = BatchKey::new(&job.job().target, &metadata, job.parameters());
+                groups.entry(key).or_default().push((job, metadata));
+            }
+            _ => singles.push(job),
+        }
+    }
+
+    let mut batches = Vec::new();
+    for mut group in groups.into_values() {
+        while !group.is_empty() {
+            let rest = group.split_off(group.len().min(batching.max_jobs.max(1)));
+            if group.len() == 1 {
+                singles.extend(group.into_iter().map(|(job, _)| job));
             } else {
-                Err(WebhookError::Request(
-                    WebhookRequestError::NonRetryableRetryableRequestError {
-                        error: err,
-                        status: Some(response.status()),
-                        response: first_n_bytes_of_response(response, MAX_RESPONSE_BODY)
-                            .await
-                            .ok(),
-                    },
-                ))
+                batches.push(group);
             }
+            group = rest;
         }
     }
-}
 
-fn is_retryable_status(status: StatusCode) -> bool {
-    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+    (batches, singles)
 }
 
-/// Attempt to parse a chrono::Duration from a Retry-After header, returning None if not possible.
-/// Retry-After header can specify a date in RFC2822 or a number of seconds; we try to parse both.
-/// If a Retry-After header is not present in the provided `header_map`, `None` is returned.
+/// Process webhook jobs for the same destination by sending them in a single request, with their
+/// bodies wrapped in `envelope`. Every job is then transitioned as `process_webhook_job` would
+/// have done for the outcome of that request.
 ///
 /// # Arguments
 ///
-/// * `header_map`: A `&reqwest::HeaderMap` of response headers that could contain Retry-After.
-fn parse_retry_after_header(header_map: &reqwest::header::HeaderMap) -> Option<time::Duration> {
-    let retry_after_header = header_map.get(reqwest::header::RETRY_AFTER);
+/// * `client`: An HTTP client to execute the request.
+/// * `webhook_jobs`: The jobs to send, which must share a `BatchKey`, and their metadata.
+/// * `retry_policy`: The retry policy used to set retry parameters if the request fails.
+/// * `envelope`: How the bodies of the jobs are combined.
+/// * `circuit_breaker`: Tracks the health of targets, if enabled. Jobs for unhealthy targets are deferred.
+/// * `delivery_log`: Where to record this attempt for each job, if it should be recorded.
+async fn process_webhook_batch<W: WebhookJob>(
+    http_client: reqwest::Client,
+    webhook_jobs: Vec<(W, WebhookJobMetadata)>,
+    retry_policy: &RetryPolicy,
+    envelope: BatchEnvelope,
+    circuit_breaker: Option<&CircuitBreaker>,
+    delivery_log: Option<&DeliveryLog>,
+) -> Result<(), WorkerError> {
+    let (first_job, _) = &webhook_jobs[0];
+    let labels = [("queue", first_job.queue())];
+    let target = first_job.job().target.to_owned();
+    let parameters = first_job.parameters().clone();
+    let batch_size = webhook_jobs.len() as u64;
+
+    let admission = match circuit_breaker {
+        Some(circuit_breaker) => circuit_breaker.admit(&target).await,
+        None => Admission::Allowed,
+    };
 
-    let retry_after = match retry_after_header {
-        Some(header_value) => match header_value.to_str() {
-            Ok(s) => s,
-            Err(_) => {
-                return None;
+    // Transition every job, even if one fails to, and report the last error.
+    let mut result = Ok(());
+
+    if let Admission::Deferred(defer_interval) = admission {
+        for (webhook_job, metadata) in webhook_jobs {
+            let current_queue = webhook_job.queue();
+            let retry_queue = retry_policy.retry_queue(&current_queue);
+
+            if let Err(e) = webhook_job.defer(defer_interval, retry_queue).await {
+                metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+                result = Err(WorkerError::from(e));
+            } else if let Some(circuit_breaker) = circuit_breaker {
+                circuit_breaker.record_deferred(Some(&App::from_plugin(&metadata)));
             }
-        },
-        None => {
-            return None;
         }
-    };
 
-    if let Ok(u) = retry_after.parse::<u64>() {
-        let duration = time::Duration::from_secs(u);
-        return Some(duration);
-    }
-
-    if let Ok(dt) = chrono::DateTime::parse_from_rfc2822(retry_after) {
-        let duration =
-            chrono::DateTime::<chrono::offset::Utc>::from(dt) - chrono::offset::Utc::now();
+        metrics::counter!("webhook_jobs_deferred", &labels).increment(batch_size);
 
-        // This can only fail when negative, in which case we return None.
-        return duration.to_std().ok();
+        return result;
     }
 
-    None
-}
+    metrics::counter!("webhook_jobs_total", &labels).increment(batch_size);
+    metrics::counter!("webhook_batches_total", &labels).increment(1);
+    metrics::histogram!("webhook_batch_size").record(batch_size as f64);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::time::Duration;
+    let now = tokio::time::Instant::now();
+
+    let headers = batching::batch_headers(&parameters, envelope);
+    let body = envelope.wrap(
+        webhook_jobs
+            .iter()
+            .map(|(webhook_job, _)| webhook_job.parameters().body.as_str()),
+    );
+    let send_result = send_webhook(
+        http_client,
+        &parameters.method,
+        &parameters.url,
+        &headers,
+        body,
+    )
+    .await;
+
+    if let (Some(circuit_breaker), Some(healthy)) = (circuit_breaker, target_health(&send_result)) {
+        // There is a single request, so a probe is reported against the first job's app.
+        let app = App::from_plugin(&webhook_jobs[0].1);
+        circuit_breaker
+            .record(&target, &admission, healthy, Some(&app))
+            .await;
+    }
+
+    match send_result {
+        Ok(response) => {
+            let status = response.status();
+            let response_headers = delivery_log.map(|_| header_map_to_hash_map(response.headers()));
+            // The body is only needed for the delivery log, which can do without it.
+            let body = match delivery_log {
+                Some(_) => first_n_bytes_of_response(response, MAX_RESPONSE_BODY)
+                    .await
+                    .ok(),
+                None => None,
+            };
+            let duration = now.elapsed();
+
+            for (webhook_job, metadata) in webhook_jobs {
+                if let Some(delivery_log) = delivery_log {
+                    record_delivery(
+                        delivery_log,
+                        NewDelivery {
+                            job_id: webhook_job.job().id,
+                            attempt: webhook_job.attempt(),
+                            max_attempts: webhook_job.job().max_attempts,
+                            metadata: &metadata,
+                            parameters: webhook_job.parameters(),
+                            request_headers: &headers,
+                            status: Some(status.as_u16()),
+                            response_headers: response_headers.clone(),
+                            response_body: body.as_deref(),
+                            error: None,
+                            duration,
+                        },
+                    )
+                    .await;
+                }
+
+                let created_at = webhook_job.job().created_at;
+                let retries = webhook_job.job().attempt - 1;
+                let labels_with_retries = [
+                    ("queue", webhook_job.queue()),
+                    ("retries", retries.to_string()),
+                ];
+
+                if let Err(e) = webhook_job.complete().await {
+                    metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+                    result = Err(WorkerError::from(e));
+                    continue;
+                }
+
+                let insert_to_complete_duration = Utc::now() - created_at;
+                metrics::histogram!(
+                    "webhook_jobs_insert_to_complete_duration_seconds",
+                    &labels_with_retries
+                )
+                .record((insert_to_complete_duration.num_milliseconds() as f64) / 1_000_f64);
+                metrics::counter!("webhook_jobs_completed", &labels).increment(1);
+            }
+
+            metrics::histogram!("webhook_jobs_processing_duration_seconds", &labels)
+                .record(duration.as_secs_f64());
+        }
+        Err(WebhookError::Request(request_error)) => {
+            let duration = now.elapsed();
+            let retry_after = match &request_error {
+                WebhookRequestError::RetryableRequestError { retry_after, .. } => {
+                    Some(*retry_after)
+                }
+                WebhookRequestError::NonRetryableRetryableRequestError { .. } => None,
+            };
+
+            for (webhook_job, metadata) in webhook_jobs {
+                if let Some(delivery_log) = delivery_log {
+                    record_delivery(
+                        delivery_log,
+                        NewDelivery {
+                            job_id: webhook_job.job().id,
+                            attempt: webhook_job.attempt(),
+                            max_attempts: webhook_job.job().max_attempts,
+                            metadata: &metadata,
+                            parameters: webhook_job.parameters(),
+                            request_headers: &headers,
+                            status: request_error.status().map(|status| status.as_u16()),
+                            response_headers: request_error
+                                .response_headers()
+                                .map(header_map_to_hash_map),
+                            response_body: request_error.response_body(),
+                            error: Some(request_error.to_string()),
+                            duration,
+                        },
+                    )
+                    .await;
+                }
+
+                let webhook_job = match retry_after {
+                    Some(retry_after) => {
+                        let retry_interval =
+                            retry_policy.retry_interval(webhook_job.attempt() as u32, retry_after);
+                        let current_queue = webhook_job.queue();
+                        let retry_queue = retry_policy.retry_queue(&current_queue);
+
+                        match webhook_job
+                            .retry(
+                                WebhookJobError::from(&request_error),
+                                retry_interval,
+                                retry_queue,
+                            )
+                            .await
+                        {
+                            Ok(_) => {
+                                metrics::counter!("webhook_jobs_retried", &labels).increment(1);
+                                continue;
+                            }
+                            Err(RetryError::RetryInvalidError(RetryInvalidError {
+                                job: webhook_job,
+                                ..
+                            })) => *webhook_job,
+                            Err(RetryError::DatabaseError(job_error)) => {
+                                metrics::counter!("webhook_jobs_database_error", &labels)
+                                    .increment(1);
+                                result = Err(WorkerError::from(job_error));
+                                continue;
+                            }
+                        }
+                    }
+                    None => webhook_job,
+                };
+
+                if let Err(e) = webhook_job
+                    .fail(WebhookJobError::from(&request_error))
+                    .await
+                {
+                    metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+                    result = Err(WorkerError::from(e));
+                    continue;
+                }
+
+                metrics::counter!("webhook_jobs_failed", &labels).increment(1);
+            }
+        }
+        Err(e) => {
+            // The request couldn't be built, which retrying won't fix.
+            for (webhook_job, _) in webhook_jobs {
+                if let Err(job_error) = webhook_job
+                    .fail(WebhookJobError::new_parse(&e.to_string()))
+                    .await
+                {
+                    metrics::counter!("webhook_jobs_database_error", &labels).increment(1);
+                    result = Err(WorkerError::from(job_error));
+                    continue;
+                }
+
+                metrics::counter!("webhook_jobs_failed", &labels).increment(1);
+            }
+        }
+    }
+
+    result
+}
+
+/// Make an HTTP request to a webhook endpoint.
+///
+/// # Arguments
+///
+/// * `client`: An HTTP client to execute the HTTP request.
+/// * `method`: The HTTP method to use in the HTTP request.
+/// * `url`: The URL we are targetting with our request. Parsing this URL fail.
+/// * `headers`: Key, value pairs of HTTP headers in a `std::collections::HashMap`. Can fail if headers are not valid.
+/// * `body`: The body of the request. Ownership is required.
+async fn send_webhook(
+    client: reqwest::Client,
+    method: &HttpMethod,
+    url: &str,
+    headers: &collections::HashMap<String, String>,
+    body: String,
+) -> Result<reqwest::Response, WebhookError> {
+    let method: http::Method = method.into();
+    let url: reqwest::Url = (url).parse().map_err(WebhookParseError::ParseUrlError)?;
+    let headers: reqwest::header::HeaderMap = (headers)
+        .try_into()
+        .map_err(WebhookParseError::ParseHeadersError)?;
+    let body = reqwest::Body::from(body);
+
+    let response = client
+        .request(method, url)
+        .headers(headers)
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            if is_error_source::<NoPublicIPv4Error>(&e) {
+                WebhookRequestError::NonRetryableRetryableRequestError {
+                    error: e,
+                    status: None,
+                    headers: None,
+                    response: None,
+                }
+            } else {
+                WebhookRequestError::RetryableRequestError {
+                    error: e,
+                    status: None,
+                    headers: None,
+                    response: None,
+                    retry_after: None,
+                }
+            }
+        })?;
+
+    let retry_after = parse_retry_after_header(response.headers());
+
+    match response.error_for_status_ref() {
+        Ok(_) => Ok(response),
+        Err(err) => {
+            if is_retryable_status(
+                err.status()
+                    .expect("status code is set as error is generated from a response"),
+            ) {
+                Err(WebhookError::Request(
+                    WebhookRequestError::RetryableRequestError {
+                        error: err,
+                        status: Some(response.status()),
+                        headers: Some(response.headers().clone()),
+                        response: first_n_bytes_of_response(response, MAX_RESPONSE_BODY)
+                            .await
+                            .ok(),
+                        retry_after,
+                    },
+                ))
+            } else {
+                Err(WebhookError::Request(
+                    WebhookRequestError::NonRetryableRetryableRequestError {
+                        error: err,
+                        status: Some(response.status()),
+                        headers: Some(response.headers().clone()),
+                        response: first_n_bytes_of_response(response, MAX_RESPONSE_BODY)
+                            .await
+                            .ok(),
+                    },
+                ))
+            }
+        }
+    }
+}
+
+/// Record a delivery attempt. Failing to do so is logged, but otherwise doesn't affect the job.
+async fn record_delivery(delivery_log: &DeliveryLog, delivery: NewDelivery<'_>) {
+    if let Err(e) = delivery_log.record(delivery).await {
+        metrics::counter!("webhook_delivery_log_errors").increment(1);
+        error!("error recording webhook delivery: {}", e);
+    }
+}
+
+fn header_map_to_hash_map(headers: &header::HeaderMap) -> collections::HashMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Whether the result of sending a webhook says the target is healthy, or `None` if the request
+/// never reached the target.
+fn target_health(send_result: &Result<reqwest::Response, WebhookError>) -> Option<bool> {
+    match send_result {
+        Ok(_) => Some(true),
+        Err(WebhookError::Request(WebhookRequestError::RetryableRequestError { .. })) => {
+            Some(false)
+        }
+        // The target responded, we just didn't like what it said.
+        Err(WebhookError::Request(WebhookRequestError::NonRetryableRetryableRequestError {
+            status: Some(_),
+            ..
+        })) => Some(true),
+        Err(_) => None,
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Attempt to parse a chrono::Duration from a Retry-After header, returning None if not possible.
+/// Retry-After header can specify a date in RFC2822 or a number of seconds; we try to parse both.
+/// If a Retry-After header is not present in the provided `header_map`, `None` is returned.
+///
+/// # Arguments
+///
+/// * `header_map`: A `&reqwest::HeaderMap` of response headers that could contain Retry-After.
+fn parse_retry_after_header(header_map: &reqwest::header::HeaderMap) -> Option<time::Duration> {
+    let retry_after_header = header_map.get(reqwest::header::RETRY_AFTER);
+
+    let retry_after = match retry_after_header {
+        Some(header_value) => match header_value.to_str() {
+            Ok(s) => s,
+            Err(_) => {
+                return None;
+            }
+        },
+        None => {
+            return None;
+        }
+    };
+
+    if let Ok(u) = retry_after.parse::<u64>() {
+        let duration = time::Duration::from_secs(u);
+        return Some(duration);
+    }
+
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc2822(retry_after) {
+        let duration =
+            chrono::DateTime::<chrono::offset::Utc>::from(dt) - chrono::offset::Utc::now();
+
+        // This can only fail when negative, in which case we return None.
+        return duration.to_std().ok();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
     // Note we are ignoring some warnings in this module.
     // This is due to a long-standing cargo bug that reports imports and helper functions as unused.
     // See: https://github.com/rust-lang/rust/issues/46379.
     use common_kafka::test::create_mock_kafka;
     use health::HealthRegistry;
     use hook_common::pgqueue::{DatabaseError, NewJob};
-    use hook_common::webhook::WebhookJobMetadata;
+    use hook_common::webhook::{WebhookJobMetadata, WebhookTransport};
     use sqlx::PgPool;
 
     /// Use process id as a worker id for tests.
@@ -852,11 +1626,14 @@ mod tests {
             headers: collections::HashMap::new(),
             method: HttpMethod::POST,
             url: "localhost".to_owned(),
+            transport: WebhookTransport::Http,
         };
         let webhook_job_metadata = WebhookJobMetadata {
             team_id: 1,
             plugin_id: 2,
             plugin_config_id: 3,
+            signing_secret_ref: None,
+            batch: false,
         };
         let registry = HealthRegistry::new("liveness");
         let liveness = registry
@@ -962,6 +1739,7 @@ mod tests {
             headers: collections::HashMap::new(),
             method: HttpMethod::POST,
             url: server.url("/200"),
+            transport: WebhookTransport::Http,
         };
 
         enqueue_job(
@@ -982,6 +1760,11 @@ mod tests {
             worker.kafka_producer.clone(),
             worker.cdp_function_callbacks_topic,
             hog_mode,
+            worker.signer.clone(),
+            worker.circuit_breaker.clone(),
+            worker.delivery_log.clone(),
+            worker.batching,
+            worker.transports.clone(),
         )
         .await;
 
@@ -1078,6 +1861,7 @@ mod tests {
             headers: collections::HashMap::new(),
             method: HttpMethod::POST,
             url: server.url("/500"),
+            transport: WebhookTransport::Http,
         };
 
         enqueue_job(
@@ -1098,6 +1882,11 @@ mod tests {
             worker.kafka_producer.clone(),
             worker.cdp_function_callbacks_topic,
             hog_mode,
+            worker.signer.clone(),
+            worker.circuit_breaker.clone(),
+            worker.delivery_log.clone(),
+            worker.batching,
+            worker.transports.clone(),
         )
         .await;
 
@@ -1170,6 +1959,7 @@ mod tests {
             headers: collections::HashMap::new(),
             method: HttpMethod::POST,
             url: mock_url,
+            transport: WebhookTransport::Http,
         };
 
         let webhook_job_metadata = json!({"hugeField": "a".repeat(2 * 1024 * 1024)});
@@ -1214,10 +2004,643 @@ mod tests {
             worker.kafka_producer,
             worker.cdp_function_callbacks_topic,
             hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports.clone(),
         )
         .await;
     }
 
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_signs_webhooks_with_secret_ref(db: PgPool) {
+        use httpmock::prelude::*;
+
+        let worker_id = worker_id();
+        let queue_name = "test_signs_webhooks_with_secret_ref".to_string();
+        let queue = PgQueue::new_from_pool(&queue_name, db).await;
+
+        let secrets_dir = std::env::temp_dir().join(format!("hook-worker-{}", queue_name));
+        tokio::fs::create_dir_all(secrets_dir.join("1"))
+            .await
+            .unwrap();
+        tokio::fs::write(
+            secrets_dir.join("1").join("plugin-config-3"),
+            "whsec_MfKQ9r8GKYqrTwjUPD8ILPZIo2LaLaSw\n",
+        )
+        .await
+        .unwrap();
+
+        let server = MockServer::start();
+
+        let webhook_job_parameters = WebhookJobParameters {
+            body: r#"{"a": "b"}"#.to_owned(),
+            headers: collections::HashMap::new(),
+            method: HttpMethod::POST,
+            url: server.url("/"),
+            transport: WebhookTransport::Http,
+        };
+        let webhook_job_metadata = WebhookJobMetadata {
+            team_id: 1,
+            plugin_id: 2,
+            plugin_config_id: 3,
+            signing_secret_ref: Some("plugin-config-3".to_owned()),
+            batch: false,
+        };
+
+        enqueue_job(
+            &queue,
+            1,
+            webhook_job_parameters,
+            serde_json::to_value(webhook_job_metadata).unwrap(),
+        )
+        .await
+        .expect("failed to enqueue job");
+
+        let registry = HealthRegistry::new("liveness");
+        let liveness = registry
+            .register("worker".to_string(), ::time::Duration::seconds(30))
+            .await;
+
+        let (_, mock_producer) = create_mock_kafka().await;
+        let hog_mode = false;
+        let worker = WebhookWorker::new(
+            &worker_id,
+            &queue,
+            1,
+            time::Duration::from_millis(100),
+            time::Duration::from_millis(5000),
+            10,
+            RetryPolicy::default(),
+            true,
+            mock_producer,
+            "cdp_function_callbacks".to_string(),
+            hog_mode,
+            liveness,
+        )
+        .with_signer(WebhookSigner::new(Some(secrets_dir.clone())));
+
+        let batch = worker.wait_for_jobs_tx().await;
+        let job_id = batch.jobs[0].job.id;
+
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header(signing::WEBHOOK_ID_HEADER, format!("msg_{}", job_id))
+                .header_exists(signing::WEBHOOK_TIMESTAMP_HEADER)
+                .header_exists(signing::WEBHOOK_SIGNATURE_HEADER);
+            then.status(200);
+        });
+
+        process_batch(
+            batch,
+            worker.http_client,
+            worker.retry_policy,
+            worker.kafka_producer,
+            worker.cdp_function_callbacks_topic,
+            hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports.clone(),
+        )
+        .await;
+
+        mock.assert();
+
+        tokio::fs::remove_dir_all(&secrets_dir).await.unwrap();
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_defers_jobs_for_open_circuits(db: PgPool) {
+        use crate::config::{CircuitBreakerConfig, EnvMsDuration};
+        use httpmock::prelude::*;
+
+        let worker_id = worker_id();
+        let queue_name = "test_defers_jobs_for_open_circuits".to_string();
+        let queue = PgQueue::new_from_pool(&queue_name, db.clone()).await;
+
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.status(200);
+        });
+
+        let webhook_job_parameters = WebhookJobParameters {
+            body: "".to_owned(),
+            headers: collections::HashMap::new(),
+            method: HttpMethod::POST,
+            url: server.url("/"),
+            transport: WebhookTransport::Http,
+        };
+        let target = webhook_job_parameters.url.clone();
+
+        enqueue_job(&queue, 1, webhook_job_parameters, json!({}))
+            .await
+            .expect("failed to enqueue job");
+
+        let circuit_breaker = CircuitBreaker::new(
+            db.clone(),
+            CircuitBreakerConfig {
+                circuit_breaker_enabled: true,
+                circuit_breaker_failure_rate: 0.5,
+                circuit_breaker_min_requests: 1,
+                circuit_breaker_window: EnvMsDuration(time::Duration::from_secs(60)),
+                circuit_breaker_open_duration: EnvMsDuration(time::Duration::from_secs(60)),
+                circuit_breaker_sync_interval: EnvMsDuration(time::Duration::from_secs(1)),
+            },
+        );
+        circuit_breaker
+            .record(&target, &Admission::Allowed, false, None)
+            .await;
+        circuit_breaker.sync().await.expect("failed to sync");
+
+        let registry = HealthRegistry::new("liveness");
+        let liveness = registry
+            .register("worker".to_string(), ::time::Duration::seconds(30))
+            .await;
+
+        let (_, mock_producer) = create_mock_kafka().await;
+        let hog_mode = false;
+        let worker = WebhookWorker::new(
+            &worker_id,
+            &queue,
+            1,
+            time::Duration::from_millis(100),
+            time::Duration::from_millis(5000),
+            10,
+            RetryPolicy::default(),
+            true,
+            mock_producer,
+            "cdp_function_callbacks".to_string(),
+            hog_mode,
+            liveness,
+        )
+        .with_circuit_breaker(circuit_breaker);
+
+        let batch = worker.wait_for_jobs_tx().await;
+
+        process_batch(
+            batch,
+            worker.http_client,
+            worker.retry_policy,
+            worker.kafka_producer,
+            worker.cdp_function_callbacks_topic,
+            hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports.clone(),
+        )
+        .await;
+
+        mock.assert_hits(0);
+
+        // The job is still available, and the attempt we dequeued it for wasn't counted.
+        let (attempt, status): (i32, String) =
+            sqlx::query_as("SELECT attempt, status::text FROM job_queue WHERE queue = $1")
+                .bind(&queue_name)
+                .fetch_one(&db)
+                .await
+                .expect("failed to fetch job");
+        assert_eq!(attempt, 0);
+        assert_eq!(status, "available");
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_records_deliveries(db: PgPool) {
+        use httpmock::prelude::*;
+
+        let worker_id = worker_id();
+        let queue_name = "test_records_deliveries".to_string();
+        let queue = PgQueue::new_from_pool(&queue_name, db.clone()).await;
+
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/");
+            then.status(500)
+                .header("x-request-id", "abc")
+                .body("something went wrong");
+        });
+
+        let webhook_job_parameters = WebhookJobParameters {
+            body: r#"{"a": "b"}"#.to_owned(),
+            headers: collections::HashMap::from([("x-test".to_owned(), "1".to_owned())]),
+            method: HttpMethod::POST,
+            url: server.url("/"),
+            transport: WebhookTransport::Http,
+        };
+        let webhook_job_metadata = WebhookJobMetadata {
+            team_id: 1,
+            plugin_id: 2,
+            plugin_config_id: 3,
+            signing_secret_ref: None,
+            batch: false,
+        };
+
+        enqueue_job(
+            &queue,
+            1,
+            webhook_job_parameters,
+            serde_json::to_value(webhook_job_metadata).unwrap(),
+        )
+        .await
+        .expect("failed to enqueue job");
+
+        let registry = HealthRegistry::new("liveness");
+        let liveness = registry
+            .register("worker".to_string(), ::time::Duration::seconds(30))
+            .await;
+
+        let (_, mock_producer) = create_mock_kafka().await;
+        let hog_mode = false;
+        let delivery_log = DeliveryLog::new(db);
+        let worker = WebhookWorker::new(
+            &worker_id,
+            &queue,
+            1,
+            time::Duration::from_millis(100),
+            time::Duration::from_millis(5000),
+            10,
+            RetryPolicy::default(),
+            true,
+            mock_producer,
+            "cdp_function_callbacks".to_string(),
+            hog_mode,
+            liveness,
+        )
+        .with_delivery_log(delivery_log.clone());
+
+        let batch = worker.wait_for_jobs_tx().await;
+        let job_id = batch.jobs[0].job.id;
+
+        process_batch(
+            batch,
+            worker.http_client,
+            worker.retry_policy,
+            worker.kafka_producer,
+            worker.cdp_function_callbacks_topic,
+            hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports.clone(),
+        )
+        .await;
+
+        let deliveries = delivery_log
+            .list(3, None, 10)
+            .await
+            .expect("failed to list deliveries");
+        assert_eq!(deliveries.len(), 1);
+
+        let delivery = &deliveries[0];
+        assert_eq!(delivery.job_id, job_id);
+        assert_eq!(delivery.attempt, 1);
+        assert_eq!(delivery.status, Some(500));
+        assert_eq!(delivery.request_body, r#"{"a": "b"}"#);
+        assert_eq!(delivery.request_headers.0.get("x-test").unwrap(), "1");
+        assert_eq!(
+            delivery
+                .response_headers
+                .as_ref()
+                .unwrap()
+                .0
+                .get("x-request-id")
+                .unwrap(),
+            "abc"
+        );
+        assert_eq!(
+            delivery.response_body.as_deref(),
+            Some("something went wrong")
+        );
+        assert!(delivery.error.is_some());
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_batches_webhooks_for_same_destination(db: PgPool) {
+        use httpmock::prelude::*;
+
+        let worker_id = worker_id();
+        let queue_name = "test_batches_webhooks_for_same_destination".to_string();
+        let queue = PgQueue::new_from_pool(&queue_name, db.clone()).await;
+
+        let server = MockServer::start();
+        let batch_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header("content-type", "application/x-ndjson")
+                .body_contains("{\"n\": 2}\n");
+            then.status(200);
+        });
+        let single_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/")
+                .header("content-type", "application/json");
+            then.status(200);
+        });
+
+        for (n, plugin_config_id, batch) in
+            [(1, 3, true), (2, 3, true), (3, 3, false), (4, 4, true)]
+        {
+            let webhook_job_parameters = WebhookJobParameters {
+                body: format!("{{\"n\": {}}}", n),
+                headers: collections::HashMap::new(),
+                method: HttpMethod::POST,
+                url: server.url("/"),
+                transport: WebhookTransport::Http,
+            };
+            let webhook_job_metadata = WebhookJobMetadata {
+                team_id: 1,
+                plugin_id: 2,
+                plugin_config_id,
+                signing_secret_ref: None,
+                batch,
+            };
+
+            enqueue_job(
+                &queue,
+                1,
+                webhook_job_parameters,
+                serde_json::to_value(webhook_job_metadata).unwrap(),
+            )
+            .await
+            .expect("failed to enqueue job");
+        }
+
+        let registry = HealthRegistry::new("liveness");
+        let liveness = registry
+            .register("worker".to_string(), ::time::Duration::seconds(30))
+            .await;
+
+        let (_, mock_producer) = create_mock_kafka().await;
+        let hog_mode = false;
+        let worker = WebhookWorker::new(
+            &worker_id,
+            &queue,
+            10,
+            time::Duration::from_millis(100),
+            time::Duration::from_millis(5000),
+            10,
+            RetryPolicy::default(),
+            true,
+            mock_producer,
+            "cdp_function_callbacks".to_string(),
+            hog_mode,
+            liveness,
+        )
+        .with_batching(Batching {
+            envelope: BatchEnvelope::Ndjson,
+            max_jobs: 500,
+        });
+
+        let batch = worker.wait_for_jobs_tx().await;
+        assert_eq!(batch.jobs.len(), 4);
+
+        process_batch(
+            batch,
+            worker.http_client,
+            worker.retry_policy,
+            worker.kafka_producer,
+            worker.cdp_function_callbacks_topic,
+            hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports.clone(),
+        )
+        .await;
+
+        // The two jobs for the same plugin config that opted in were sent together, the others on
+        // their own.
+        batch_mock.assert_hits(1);
+        single_mock.assert_hits(2);
+
+        let statuses: Vec<String> =
+            sqlx::query_scalar("SELECT status::text FROM job_queue WHERE queue = $1")
+                .bind(&queue_name)
+                .fetch_all(&db)
+                .await
+                .expect("failed to fetch jobs");
+        assert_eq!(statuses, vec!["completed"; 4]);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_delivers_webhooks_over_kafka_transport(db: PgPool) {
+        use rdkafka::consumer::{Consumer, StreamConsumer};
+        use rdkafka::message::Headers;
+        use rdkafka::{ClientConfig, Message};
+
+        let worker_id = worker_id();
+        let queue_name = "test_delivers_webhooks_over_kafka_transport".to_string();
+        let queue = PgQueue::new_from_pool(&queue_name, db.clone()).await;
+        let topic = "webhook_events";
+
+        let (_, mock_producer) = create_mock_kafka().await;
+        // Webhooks are produced to their own cluster, not the one the worker reports to.
+        let (mock_cluster, transport_producer) = create_mock_kafka().await;
+        mock_cluster
+            .create_topic(topic, 1, 1)
+            .expect("failed to create mock topic");
+
+        let webhook_job_parameters = WebhookJobParameters {
+            body: r#"{"event": "signed_up"}"#.to_owned(),
+            headers: collections::HashMap::from([("x-team".to_owned(), "1".to_owned())]),
+            method: HttpMethod::POST,
+            url: format!("kafka://{}", topic),
+            transport: WebhookTransport::Kafka,
+        };
+        enqueue_job(
+            &queue,
+            1,
+            webhook_job_parameters,
+            serde_json::to_value(WebhookJobMetadata {
+                team_id: 1,
+                plugin_id: 2,
+                plugin_config_id: 3,
+                signing_secret_ref: None,
+                batch: false,
+            })
+            .unwrap(),
+        )
+        .await
+        .expect("failed to enqueue job");
+
+        let registry = HealthRegistry::new("liveness");
+        let liveness = registry
+            .register("worker".to_string(), ::time::Duration::seconds(30))
+            .await;
+
+        let hog_mode = false;
+        let worker = WebhookWorker::new(
+            &worker_id,
+            &queue,
+            1,
+            time::Duration::from_millis(100),
+            time::Duration::from_millis(5000),
+            10,
+            RetryPolicy::default(),
+            false,
+            mock_producer,
+            "cdp_function_callbacks".to_string(),
+            hog_mode,
+            liveness,
+        )
+        .with_kafka_transport(KafkaTransport::new(
+            transport_producer,
+            time::Duration::from_millis(5000),
+            collections::HashSet::from([topic.to_owned()]),
+        ))
+        .with_delivery_log(DeliveryLog::new(db.clone()));
+
+        let batch = worker.wait_for_jobs_tx().await;
+
+        process_batch(
+            batch,
+            worker.http_client,
+            worker.retry_policy,
+            worker.kafka_producer,
+            worker.cdp_function_callbacks_topic,
+            hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports,
+        )
+        .await;
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", mock_cluster.bootstrap_servers())
+            .set("group.id", "mock")
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .expect("failed to create mock consumer");
+        consumer.subscribe(&[topic]).unwrap();
+
+        let kafka_msg = consumer.recv().await.unwrap();
+        assert_eq!(
+            kafka_msg.payload().unwrap(),
+            r#"{"event": "signed_up"}"#.as_bytes()
+        );
+        let header = kafka_msg.headers().unwrap().get(0);
+        assert_eq!(header.key, "x-team");
+        assert_eq!(header.value, Some("1".as_bytes()));
+
+        let status: String =
+            sqlx::query_scalar("SELECT status::text FROM job_queue WHERE queue = $1")
+                .bind(&queue_name)
+                .fetch_one(&db)
+                .await
+                .expect("failed to fetch job");
+        assert_eq!(status, "completed");
+
+        let deliveries = DeliveryLog::new(db)
+            .list(3, None, 10)
+            .await
+            .expect("failed to list deliveries");
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].url, format!("kafka://{}", topic));
+        assert_eq!(deliveries[0].status, None);
+        assert_eq!(deliveries[0].error, None);
+    }
+
+    #[sqlx::test(migrations = "../migrations")]
+    async fn test_fails_signed_webhooks_over_transports(db: PgPool) {
+        use common_kafka::kafka_messages::app_metrics::ErrorType;
+
+        let worker_id = worker_id();
+        let queue_name = "test_fails_signed_webhooks_over_transports".to_string();
+        let queue = PgQueue::new_from_pool(&queue_name, db.clone()).await;
+        let topic = "webhook_events";
+
+        let (_, mock_producer) = create_mock_kafka().await;
+        let (_transport_cluster, transport_producer) = create_mock_kafka().await;
+
+        let webhook_job_parameters = WebhookJobParameters {
+            body: r#"{"event": "signed_up"}"#.to_owned(),
+            headers: collections::HashMap::new(),
+            method: HttpMethod::POST,
+            url: format!("kafka://{}", topic),
+            transport: WebhookTransport::Kafka,
+        };
+        enqueue_job(
+            &queue,
+            3,
+            webhook_job_parameters,
+            serde_json::to_value(WebhookJobMetadata {
+                team_id: 1,
+                plugin_id: 2,
+                plugin_config_id: 3,
+                signing_secret_ref: Some("plugin-config-3".to_owned()),
+                batch: false,
+            })
+            .unwrap(),
+        )
+        .await
+        .expect("failed to enqueue job");
+
+        let registry = HealthRegistry::new("liveness");
+        let liveness = registry
+            .register("worker".to_string(), ::time::Duration::seconds(30))
+            .await;
+
+        let hog_mode = false;
+        let worker = WebhookWorker::new(
+            &worker_id,
+            &queue,
+            1,
+            time::Duration::from_millis(100),
+            time::Duration::from_millis(5000),
+            10,
+            RetryPolicy::default(),
+            false,
+            mock_producer,
+            "cdp_function_callbacks".to_string(),
+            hog_mode,
+            liveness,
+        )
+        .with_kafka_transport(KafkaTransport::new(
+            transport_producer,
+            time::Duration::from_millis(5000),
+            collections::HashSet::from([topic.to_owned()]),
+        ));
+
+        let batch = worker.wait_for_jobs_tx().await;
+
+        process_batch(
+            batch,
+            worker.http_client,
+            worker.retry_policy,
+            worker.kafka_producer,
+            worker.cdp_function_callbacks_topic,
+            hog_mode,
+            worker.signer,
+            worker.circuit_breaker,
+            worker.delivery_log,
+            worker.batching,
+            worker.transports,
+        )
+        .await;
+
+        // The job fails without being retried, as it would never be delivered signed.
+        let (status, error): (String, serde_json::Value) = sqlx::query_as(
+            "SELECT status::text, errors[array_upper(errors, 1)] FROM job_queue WHERE queue = $1",
+        )
+        .bind(&queue_name)
+        .fetch_one(&db)
+        .await
+        .expect("failed to fetch job");
+        assert_eq!(status, "failed");
+        let error: WebhookJobError = serde_json::from_value(error).unwrap();
+        assert!(matches!(error.r#type, ErrorType::SigningError));
+    }
+
     #[tokio::test]
     async fn test_send_webhook() {
         let method = HttpMethod::POST;
@@ -0,0 +1,20 @@
+/*
+Circuit breakers for webhook targets, shared across all workers.
+
+While a target is closed, workers periodically add the outcomes they have seen to the counters of
+the current window, and open the circuit if the failure rate is too high. While a target is open,
+jobs for it are deferred until `probe_at`, when a single worker claims a half-open probe to check
+whether the target has recovered.
+*/
+CREATE TABLE target_circuit_breakers(
+    target TEXT PRIMARY KEY,
+    state TEXT NOT NULL DEFAULT 'closed' :: text,
+    window_started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    successes INT NOT NULL DEFAULT 0,
+    failures INT NOT NULL DEFAULT 0,
+    opened_at TIMESTAMPTZ DEFAULT NULL,
+    probe_at TIMESTAMPTZ DEFAULT NULL
+);
+
+-- Needed for workers to load all circuits that are not closed
+CREATE INDEX idx_target_circuit_breakers_state ON target_circuit_breakers(state) WHERE state <> 'closed' :: text;
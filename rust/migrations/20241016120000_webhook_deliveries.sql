@@ -0,0 +1,35 @@
+/*
+A log of every attempt to deliver a plugin webhook, for showing customers what we sent and what
+their server said. Bodies are truncated, but the full job parameters and metadata are kept so that
+a delivery can be re-enqueued after the job itself has been cleaned up. Rows are removed by the
+janitor once they are older than the retention period.
+*/
+CREATE TABLE webhook_deliveries(
+    id BIGSERIAL PRIMARY KEY,
+    job_id BIGINT NOT NULL,
+    attempt INT NOT NULL,
+    max_attempts INT NOT NULL,
+    team_id INT NOT NULL,
+    plugin_config_id INT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    method TEXT NOT NULL,
+    url TEXT NOT NULL,
+    request_headers JSONB NOT NULL,
+    request_body TEXT NOT NULL,
+    status INT DEFAULT NULL,
+    response_headers JSONB DEFAULT NULL,
+    response_body TEXT DEFAULT NULL,
+    error TEXT DEFAULT NULL,
+    duration_ms INT NOT NULL,
+    parameters JSONB NOT NULL,
+    metadata JSONB NOT NULL
+);
+
+-- Needed for listing the most recent deliveries of a plugin config
+CREATE INDEX idx_webhook_deliveries_plugin_config ON webhook_deliveries(plugin_config_id, id DESC);
+
+-- Needed for finding the deliveries of a job to redeliver
+CREATE INDEX idx_webhook_deliveries_job ON webhook_deliveries(job_id);
+
+-- Needed for deleting deliveries past retention
+CREATE INDEX idx_webhook_deliveries_created_at ON webhook_deliveries(created_at);
@@ -0,0 +1,7 @@
+ALTER TABLE job_queue ADD COLUMN ordering_key TEXT DEFAULT NULL;
+
+/*
+Partial index used when dequeuing to check whether an earlier job with the same ordering key is
+still pending. Jobs without an ordering key never need this check, so they are left out.
+*/
+CREATE INDEX idx_queue_ordering_key ON job_queue(ordering_key, id) WHERE status = 'available' :: job_status AND ordering_key IS NOT NULL;
@@ -0,0 +1,6 @@
+/*
+The job parameters and metadata needed to redeliver a job are the same for every attempt, so they
+are now only kept on the first recorded attempt of each job rather than duplicated on all of them.
+*/
+ALTER TABLE webhook_deliveries ALTER COLUMN parameters DROP NOT NULL;
+ALTER TABLE webhook_deliveries ALTER COLUMN metadata DROP NOT NULL;
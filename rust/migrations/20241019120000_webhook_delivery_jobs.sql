@@ -0,0 +1,22 @@
+/*
+What is needed to redeliver a job is kept once per job, with its own retention, rather than on the
+job's first recorded attempt, where it was removed along with that attempt and included any
+credentials the job was sent with. Sensitive headers are stripped before a payload is stored here,
+and a job that still exists is redelivered from the job queue instead, credentials and all.
+
+Existing payloads are dropped rather than copied, as they may hold credentials.
+*/
+CREATE TABLE webhook_delivery_jobs(
+    job_id BIGINT PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    max_attempts INT NOT NULL,
+    parameters JSONB NOT NULL,
+    metadata JSONB NOT NULL
+);
+
+-- Needed for deleting payloads past retention
+CREATE INDEX idx_webhook_delivery_jobs_created_at ON webhook_delivery_jobs(created_at);
+
+DROP INDEX idx_webhook_deliveries_job;
+ALTER TABLE webhook_deliveries DROP COLUMN parameters;
+ALTER TABLE webhook_deliveries DROP COLUMN metadata;